@@ -0,0 +1,108 @@
+#![feature(test)]
+//! Parsing a mix of valid and invalid `wikipedia` tags, like those in OSM.
+//!
+//! Allocations are counted with a global allocator, and printed for each kind of tag before the benchmarks run.
+//! Tags rejected by the cheap checks of [Title::from_title] should not allocate at all.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+extern crate om_wikiparser;
+extern crate test;
+
+use om_wikiparser::wm::Title;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const VALID: &[&str] = &[
+    "en:Article Title",
+    "de:Köln",
+    "fr:Tour Eiffel",
+    "https://en.wikipedia.org/wiki/Article_Title",
+];
+
+/// Invalid tags that are rejected before allocating.
+const CHEAP_INVALID: &[&str] = &[
+    "",
+    "   ",
+    "Article Title",
+    "en:",
+    ":Article Title",
+    "e n:Article Title",
+    "en_US:Article Title",
+    "en_US:Caf%C3%A9",
+    "en:///",
+];
+
+/// Invalid tags that still allocate, for an error message or to parse a url.
+const INVALID: &[&str] = &[
+    "en:Category:Lighthouses",
+    "https://wikidata.org/wiki/Q12345",
+    "en:https://en.wikipedia.org/not_a_wiki_page",
+];
+
+fn parse_all(tags: &[&str]) -> usize {
+    tags.iter()
+        .filter(|tag| Title::from_osm_tag(tag).is_ok())
+        .count()
+}
+
+fn allocations(tags: &[&str]) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    test::black_box(parse_all(test::black_box(tags)));
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn mixed() -> Vec<&'static str> {
+    let mut tags = Vec::new();
+    tags.extend(VALID);
+    tags.extend(CHEAP_INVALID);
+    tags.extend(INVALID);
+    tags
+}
+
+#[bench]
+fn parse_mixed_tags(b: &mut test::Bencher) {
+    for (kind, tags) in [
+        ("valid", VALID),
+        ("cheaply rejected", CHEAP_INVALID),
+        ("other invalid", INVALID),
+    ] {
+        eprintln!(
+            "{} allocations to parse {} {kind} tags",
+            allocations(tags),
+            tags.len()
+        );
+    }
+    assert_eq!(0, allocations(CHEAP_INVALID));
+
+    let tags = mixed();
+    b.iter(|| parse_all(test::black_box(&tags)));
+}
+
+#[bench]
+fn parse_cheap_invalid_tags(b: &mut test::Bencher) {
+    b.iter(|| parse_all(test::black_box(CHEAP_INVALID)));
+}
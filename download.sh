@@ -24,13 +24,24 @@ Environment Variables:
                 list of available mirrors, note that many do not include the
                 required Enterprise HTML dumps.
                 For example: MIRROR=https://mirror.accum.se/mirror/wikimedia.org
+    USER_AGENT  Required. The User-Agent to identify the downloads with, which
+                must include a way to contact you, e.g.
+                'MyMapsBot/1.0 (https://example.org; maps@example.org)'.
+                All requests use it, followed by the name of the download tool.
+                See <https://meta.wikimedia.org/wiki/User-Agent_policy>.
+    TRIES       Number of times to try each request before giving up.
+                Forbidden requests are not retried. Defaults to 3.
+    WAIT        Seconds to wait between requests, and the longest wait between
+                retries of a failed request. Defaults to 1.
 
 Exit codes:
     0   The latest dumps are already present or were downloaded successfully.
-    1   Argument error.
+    1   Argument error, including a missing USER_AGENT.
     16  Some of languages were not available to download. The latest dump may
         be in progress, some of the specified languages may not exist, or the
         chosen mirror may not host the files.
+    17  A request was forbidden, which Wikimedia servers do for User-Agents
+        that don't follow their policy.
     _   Subprocess error.
 "
 
@@ -41,11 +52,17 @@ build_user_agent() {
     # While the dump websites are not part of the API, it's still polite to identify yourself.
     # See https://meta.wikimedia.org/wiki/User-Agent_policy
     subcommand=$1
-    name="OrganicMapsWikiparserDownloaderBot"
-    version="1.0"
-    url="https://github.com/organicmaps/wikiparser"
-    email="hello@organicmaps.app"
-    echo -n "$name/$version ($url; $email) $subcommand"
+    echo -n "$USER_AGENT $subcommand"
+}
+
+# Exit with a targeted message if the output of a failed wget request shows it was forbidden.
+check_forbidden() {
+    output=$1
+    url=$2
+    if grep -qF "ERROR 403" <<< "$output"; then
+        log "Request for '$url' was forbidden, check that USER_AGENT '$USER_AGENT' follows https://meta.wikimedia.org/wiki/User-Agent_policy"
+        exit 17
+    fi
 }
 
 # Parse options.
@@ -83,6 +100,21 @@ if [ ! -d "$DUMP_DIR" ]; then
     exit 1
 fi
 
+if [ -z "${USER_AGENT:-}" ]; then
+    echo "USER_AGENT is required, set it to identify yourself with a way to contact you" >&2
+    echo "See https://meta.wikimedia.org/wiki/User-Agent_policy" >&2
+    exit 1
+fi
+
+TRIES=${TRIES:-3}
+WAIT=${WAIT:-1}
+if [ ! "$TRIES" -ge 1 ] || [ ! "$WAIT" -ge 0 ]; then
+    echo "TRIES must be >= 1 and WAIT must be >= 0" >&2
+    exit 1
+fi
+# Options of wget and wget2 for the retries and wait between requests.
+RETRY_OPTIONS=(--tries "$TRIES" --wait "$WAIT" --waitretry "$WAIT")
+
 if [ -n "$CONCURRENT_DOWNLOADS" ]; then
     if [ ! "$CONCURRENT_DOWNLOADS" -ge 1 ]; then
         echo "Number of concurrent downloads (-n) must be >= 1" >&2
@@ -121,9 +153,17 @@ fi
 log "Selected languages:" $LANGUAGES
 
 log "Fetching run index"
+RUNS_URL="$BASE_URL/other/enterprise_html/runs/"
+RUNS_LOG=$(mktemp)
+trap 'rm -f "$RUNS_LOG"' EXIT
+if ! RUNS=$(wget "$RUNS_URL" --no-verbose "${RETRY_OPTIONS[@]}" --user-agent "$(build_user_agent wget)" -O - 2> "$RUNS_LOG"); then
+    cat "$RUNS_LOG" >&2
+    check_forbidden "$(cat "$RUNS_LOG")" "$RUNS_URL"
+    log "Unable to fetch run index '$RUNS_URL'"
+    exit 1
+fi
 # The date of the latest dump, YYYYMMDD.
-LATEST_DUMP=$(wget "$BASE_URL/other/enterprise_html/runs/" --no-verbose -O - \
-            | grep -Po '(?<=href=")[^"]*' | grep -P '\d{8}' | sort -r | head -n1)
+LATEST_DUMP=$(grep -Po '(?<=href=")[^"]*' <<< "$RUNS" | grep -P '\d{8}' | sort -r | head -n1)
 LATEST_DUMP="${LATEST_DUMP%/}"
 
 log "Checking latest dump $LATEST_DUMP"
@@ -132,7 +172,9 @@ URLS=
 MISSING_DUMPS=0
 for lang in $LANGUAGES; do
     url="$BASE_URL/other/enterprise_html/runs/${LATEST_DUMP}/${lang}wiki-NS0-${LATEST_DUMP}-ENTERPRISE-HTML.json.tar.gz"
-    if ! wget --no-verbose --method=HEAD "$url"; then
+    if ! output=$(wget --no-verbose --method=HEAD "${RETRY_OPTIONS[@]}" --user-agent "$(build_user_agent wget)" "$url" 2>&1); then
+        echo "$output" >&2
+        check_forbidden "$output" "$url"
         MISSING_DUMPS=$(( MISSING_DUMPS + 1 ))
         log "Dump for '$lang' does not exist at '$url'"
         continue
@@ -156,6 +198,7 @@ if type wget2 > /dev/null; then
     # shellcheck disable=SC2086 # URLS should be expanded on spaces.
     wget2 --verbose --progress=bar --continue \
         --user-agent "$(build_user_agent wget2)" \
+        "${RETRY_OPTIONS[@]}" \
         --max-threads "${CONCURRENT_DOWNLOADS:-2}" \
         --directory-prefix "$DOWNLOAD_DIR" \
         $URLS
@@ -164,6 +207,7 @@ else
     # shellcheck disable=SC2086 # URLS should be expanded on spaces.
     wget --continue \
         --user-agent "$(build_user_agent wget)" \
+        "${RETRY_OPTIONS[@]}" \
         --directory-prefix "$DOWNLOAD_DIR" \
         $URLS
 fi
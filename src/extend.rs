@@ -25,6 +25,38 @@ impl<Item, F: FnMut(Item)> Extend<Item> for FromFn<F> {
     }
 }
 
+/// Counts the items added to `inner`.
+///
+/// ```
+/// # use om_wikiparser::extend;
+/// let mut items = Vec::new();
+/// let mut counted = extend::counted(&mut items);
+/// counted.extend([1, 2, 3]);
+/// assert_eq!(counted.count(), 3);
+/// assert_eq!(items, [1, 2, 3]);
+/// ```
+pub fn counted<E>(inner: &mut E) -> Counted<'_, E> {
+    Counted { inner, count: 0 }
+}
+
+pub struct Counted<'a, E> {
+    inner: &'a mut E,
+    count: usize,
+}
+
+impl<E> Counted<'_, E> {
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl<Item, E: Extend<Item>> Extend<Item> for Counted<'_, E> {
+    fn extend<T: IntoIterator<Item = Item>>(&mut self, iter: T) {
+        let count = &mut self.count;
+        self.inner.extend(iter.into_iter().inspect(|_| *count += 1));
+    }
+}
+
 /// Iterates but drops each `Item`.
 pub fn sink() -> Sink {
     Sink(())
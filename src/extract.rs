@@ -0,0 +1,3144 @@
+//! Extraction of requested articles from Wikipedia Enterprise HTML dumps.
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    os::unix::{self, fs::MetadataExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context};
+use flate2::write::GzEncoder;
+use scraper::Html;
+use tracing::{debug, error, info, info_span, warn};
+
+use crate::{
+    extend,
+    html::{self, HtmlError, SimplifyConfig},
+    limits::{read_line_capped, CappedLine},
+    osm, parse_osm_tag_file, parse_osm_tag_file_raw, parse_wikidata_file_with,
+    parse_wikipedia_file_raw, parse_wikipedia_file_with,
+    wm::{
+        descriptions::Descriptions,
+        output::{
+            relative_target, sidecar_path, write_link_index, write_qid_langs, AltName,
+            AltNameSource, LinkEntry, ManifestEntry, MatchedBy, Sidecar, ALTNAMES_FILE, LINKS_FILE,
+            MANIFEST_FILE, SIDECAR_SCHEMA_VERSION,
+        },
+        LanguageMap, Layout, Page, Project, Qid, Title,
+    },
+    ListFileOptions, ParseLineError, TagFileOptions,
+};
+
+mod budget;
+mod dedup;
+mod near_miss;
+mod post_process;
+
+use budget::{Budget, Held, Selected};
+use dedup::{HandledArticles, Occurrence, PageKey};
+use near_miss::{DeferredNearMisses, Skeletons};
+use post_process::post_process;
+
+#[derive(Debug, clap::ValueEnum, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArticleFilter {
+    /// All articles that match on title/QID
+    Match,
+    /// Articles that cannot be simplified
+    Error,
+    /// Articles that cause panics when simplified
+    Panic, // FIXME: move panic dumping to this
+}
+
+/// What to do when a page is matched again after the same article was already handled.
+#[derive(
+    Debug,
+    Default,
+    clap::ValueEnum,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Skip later occurrences.
+    #[default]
+    KeepFirst,
+    /// Replace the article if the later occurrence has a newer revision (`version.identifier`).
+    ///
+    /// Pages without a revision are never newer.
+    KeepLatest,
+    /// Stop processing with an error.
+    Error,
+}
+
+/// Which matched articles [Options::max_total_bytes] keeps when they don't all fit.
+#[derive(
+    Debug,
+    Default,
+    clap::ValueEnum,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetOrder {
+    /// Write articles in the order of the dump, until the next one doesn't fit.
+    #[default]
+    Dump,
+    /// Prefer articles with lower QIDs, which are usually the more notable ones, and write articles without a QID last.
+    ///
+    /// The articles are held back until the whole dump is read, like the pages of [Options::near_miss_titles].
+    /// Their pages and html are kept in a temporary file meanwhile, so only a few bytes of each stay in memory.
+    Qid,
+}
+
+/// Compression of the html files written by [run].
+#[derive(
+    Debug, clap::ValueEnum, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum HtmlCompression {
+    /// Gzip compressed `lang.html.gz` files.
+    Gzip,
+}
+
+impl HtmlCompression {
+    /// Extension of the compressed html files.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            HtmlCompression::Gzip => "html.gz",
+        }
+    }
+}
+
+/// Which way the links between the QID and title directories of an article point.
+#[derive(
+    Debug,
+    Default,
+    clap::ValueEnum,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkDirection {
+    /// Articles are written to their QID directory, and each title directory links to it.
+    #[default]
+    TitleToQid,
+    /// Articles are written to the directory of their first title, and their file in the QID directory links to it.
+    ///
+    /// The QID directory has the articles of all languages, so only the file is a link.
+    /// Other titles of the article link to the directory of the first title.
+    QidToTitle,
+}
+
+/// What [run] does when publishing to an [Options::output_dir] that already exists, see [Options::publish].
+#[derive(
+    Debug, clap::ValueEnum, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Publish {
+    /// Fail before reading the dump.
+    Error,
+    /// Replace the existing directory with the new one once the run succeeds.
+    ///
+    /// The old directory is renamed away just before the new one is renamed into place,
+    /// so the output directory is briefly missing, but never partially written.
+    Swap,
+}
+
+/// Which of the QID and title rules must match for [run] to extract a page.
+#[derive(
+    Debug,
+    Default,
+    clap::ValueEnum,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchPolicy {
+    /// Pages matched by QID or title.
+    #[default]
+    Either,
+    /// Only pages matched by a requested QID.
+    QidOnly,
+    /// Only pages matched by title, including redirects, loose and near-miss titles, and the QIDs of other articles matched by title.
+    TitleOnly,
+    /// Only pages matched by both a requested QID and a title, for high precision.
+    Both,
+}
+
+impl MatchPolicy {
+    pub const ALL: &'static [MatchPolicy] = &[
+        MatchPolicy::Either,
+        MatchPolicy::QidOnly,
+        MatchPolicy::TitleOnly,
+        MatchPolicy::Both,
+    ];
+
+    /// Whether a page matched by the QID and title rules as given is extracted.
+    pub fn accepts(&self, qid: bool, title: bool) -> bool {
+        match self {
+            MatchPolicy::Either => qid || title,
+            MatchPolicy::QidOnly => qid,
+            MatchPolicy::TitleOnly => title,
+            MatchPolicy::Both => qid && title,
+        }
+    }
+}
+
+/// The QIDs and titles of the articles to extract with [run].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Matcher {
+    pub qids: HashSet<Qid>,
+    pub titles: HashSet<Title>,
+    /// The input text of each title, if captured with [Matcher::from_files_raw].
+    ///
+    /// Used to list how missing titles were written in the [Options::write_missing] report.
+    pub raw_titles: Option<RawTitles>,
+}
+
+/// Counts of the articles requested by a [Matcher], see [Matcher::stats].
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct MatcherStats {
+    pub qids: usize,
+    pub titles: usize,
+    /// Requested titles in each language.
+    ///
+    /// Languages of other projects are prefixed like their titles, e.g. `voy:en`.
+    pub titles_by_lang: BTreeMap<String, usize>,
+}
+
+impl MatcherStats {
+    /// The distinct languages of the requested titles, in order.
+    pub fn langs(&self) -> impl Iterator<Item = &str> {
+        self.titles_by_lang.keys().map(String::as_str)
+    }
+}
+
+/// The distinct spellings that titles were parsed from, e.g. a mobile url and a `lang:Title` tag of the same article.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RawTitles(HashMap<Title, BTreeMap<String, usize>>);
+
+impl RawTitles {
+    /// The spellings of `title` and the number of times each was read.
+    pub fn get(&self, title: &Title) -> Option<&BTreeMap<String, usize>> {
+        self.0.get(title)
+    }
+}
+
+impl Extend<(Title, String)> for RawTitles {
+    fn extend<T: IntoIterator<Item = (Title, String)>>(&mut self, iter: T) {
+        for (title, raw) in iter {
+            *self.0.entry(title).or_default().entry(raw).or_default() += 1;
+        }
+    }
+}
+
+/// A line that could not be parsed in an input file of [Matcher::from_files].
+#[derive(Debug)]
+pub struct FileParseError {
+    pub path: PathBuf,
+    pub error: ParseLineError,
+}
+
+impl Display for FileParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+impl Matcher {
+    /// Load and combine the articles listed in all of the input files.
+    ///
+    /// - `qid_paths` are read with [parse_wikidata_file_with].
+    /// - `title_paths` are read with [parse_wikipedia_file_with].
+    /// - `tag_paths` are read with [parse_osm_tag_file].
+    ///
+    /// Lines that cannot be parsed are skipped and returned with the matcher.
+    /// Errors opening or reading the files are returned immediately.
+    pub fn from_files(
+        qid_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        title_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        tag_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> anyhow::Result<(Self, Vec<FileParseError>)> {
+        Self::load(qid_paths, title_paths, tag_paths, false)
+    }
+
+    /// Like [Matcher::from_files], also capturing the [Matcher::raw_titles].
+    pub fn from_files_raw(
+        qid_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        title_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        tag_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> anyhow::Result<(Self, Vec<FileParseError>)> {
+        Self::load(qid_paths, title_paths, tag_paths, true)
+    }
+
+    fn load(
+        qid_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        title_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        tag_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        capture_raw: bool,
+    ) -> anyhow::Result<(Self, Vec<FileParseError>)> {
+        let mut matcher = Self {
+            raw_titles: capture_raw.then(RawTitles::default),
+            ..Default::default()
+        };
+        let Matcher {
+            qids,
+            titles,
+            raw_titles,
+        } = &mut matcher;
+        let mut errors = Vec::new();
+
+        for path in qid_paths {
+            let path = path.as_ref();
+            let mut line_errors = Vec::new();
+            parse_wikidata_file_with(
+                open(path)?,
+                qids,
+                &mut line_errors,
+                &ListFileOptions::default(),
+            )
+            .with_context(|| format!("reading QID file {:?}", path))?;
+            errors.extend(line_errors.into_iter().map(|error| FileParseError {
+                path: path.to_owned(),
+                error,
+            }));
+        }
+
+        for path in title_paths {
+            let path = path.as_ref();
+            let mut line_errors = Vec::new();
+            match raw_titles {
+                Some(raw_titles) => parse_wikipedia_file_raw(
+                    open(path)?,
+                    &mut capture_raw_titles(titles, raw_titles),
+                    &mut line_errors,
+                    &ListFileOptions::default(),
+                ),
+                None => parse_wikipedia_file_with(
+                    open(path)?,
+                    titles,
+                    &mut line_errors,
+                    &ListFileOptions::default(),
+                ),
+            }
+            .with_context(|| format!("reading title file {:?}", path))?;
+            errors.extend(line_errors.into_iter().map(|error| FileParseError {
+                path: path.to_owned(),
+                error,
+            }));
+        }
+
+        for path in tag_paths {
+            let path = path.as_ref();
+            let mut line_errors = Vec::new();
+            match raw_titles {
+                Some(raw_titles) => parse_osm_tag_file_raw(
+                    open(path)?,
+                    qids,
+                    &mut capture_raw_titles(titles, raw_titles),
+                    &mut line_errors,
+                    &TagFileOptions::default(),
+                ),
+                None => parse_osm_tag_file(open(path)?, qids, titles, &mut line_errors),
+            }
+            .with_context(|| format!("reading osm tag file {:?}", path))?;
+            errors.extend(line_errors.into_iter().map(|error| FileParseError {
+                path: path.to_owned(),
+                error,
+            }));
+        }
+
+        Ok((matcher, errors))
+    }
+
+    /// Count the requested QIDs and titles, e.g. to check the input files before a run.
+    ///
+    /// ```
+    /// use om_wikiparser::{extract::Matcher, wm::{Qid, Title}};
+    ///
+    /// let matcher = Matcher {
+    ///     qids: ["Q64".parse::<Qid>().unwrap()].into(),
+    ///     titles: ["en:Berlin", "de:Köln", "de:Berlin", "voy:en:Berlin"]
+    ///         .map(|tag| Title::from_osm_tag(tag).unwrap())
+    ///         .into(),
+    ///     ..Default::default()
+    /// };
+    /// let stats = matcher.stats();
+    /// assert_eq!((1, 4), (stats.qids, stats.titles));
+    /// assert_eq!(vec!["de", "en", "voy:en"], stats.langs().collect::<Vec<_>>());
+    /// assert_eq!(Some(&2), stats.titles_by_lang.get("de"));
+    /// ```
+    pub fn stats(&self) -> MatcherStats {
+        let mut titles_by_lang = BTreeMap::new();
+        for title in &self.titles {
+            let lang = match title.project().prefix() {
+                Some(prefix) => format!("{prefix}:{}", title.lang()),
+                None => title.lang().to_owned(),
+            };
+            *titles_by_lang.entry(lang).or_default() += 1;
+        }
+        MatcherStats {
+            qids: self.qids.len(),
+            titles: self.titles.len(),
+            titles_by_lang,
+        }
+    }
+}
+
+/// Add parsed titles to `titles`, and their raw text to `raw_titles`.
+pub fn capture_raw_titles<'a>(
+    titles: &'a mut HashSet<Title>,
+    raw_titles: &'a mut RawTitles,
+) -> impl Extend<(Title, String)> + 'a {
+    extend::from_fn(move |(title, raw): (Title, String)| {
+        raw_titles.extend(Some((title.clone(), raw)));
+        titles.insert(title);
+    })
+}
+
+fn open(path: &Path) -> anyhow::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .with_context(|| format!("opening {:?}", path))
+}
+
+/// Configuration of [run].
+///
+/// The defaults match those of the `get-articles` command.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Directory to write the extracted articles to.
+    ///
+    /// If `None`, articles are only processed, e.g. for use with `passthrough`.
+    pub output_dir: Option<PathBuf>,
+    /// Copy input article JSON to the passthrough writer if it matches certain criteria.
+    pub passthrough: Option<ArticleFilter>,
+    /// Append the QIDs of articles matched by title but not QID to this file.
+    pub write_new_qids: Option<PathBuf>,
+    /// Write the requested QIDs and titles that did not match any article to this directory.
+    pub write_missing: Option<PathBuf>,
+    /// Append the written articles to the manifest file in `output_dir`.
+    pub write_manifest: bool,
+    /// Directory structure to write the extracted articles in.
+    pub layout: Layout,
+    /// Language codes to write the articles as in the output directory.
+    pub language_map: LanguageMap,
+    /// Which way the links between the QID and title directories of an article point.
+    pub link_direction: LinkDirection,
+    /// Create links with targets relative to the link, so the output directory can be moved.
+    ///
+    /// Otherwise the targets are within [Options::output_dir] as given.
+    pub relative_links: bool,
+    /// Write the articles to a [staging_dir] next to [Options::output_dir], and only rename it to `output_dir` if the run succeeds.
+    ///
+    /// Consumers of `output_dir` never see a partially written tree, and a failed or cancelled run leaves the previous output untouched.
+    /// The staging directory starts empty, so the published directory only has the articles of this run, and `output_dir` doesn't need to exist.
+    /// Links are always relative, as absolute links would point into the staging directory.
+    pub publish: Option<Publish>,
+    /// Which of the QID and title rules must match for a page to be extracted.
+    pub match_policy: MatchPolicy,
+    /// Don't extract articles with the QIDs of articles that were matched by title earlier in the dump.
+    pub no_expand_titles: bool,
+    /// Don't write articles that are marked as stubs.
+    pub skip_stubs: bool,
+    /// Don't write articles with fewer characters of text than this, see [html::substance].
+    ///
+    /// Like [Options::min_paragraphs], this is measured on the simplified html, before [Options::post_processor] or any truncation.
+    /// Text in infoboxes is not counted, so an article with only an infobox left is skipped by any threshold above zero.
+    pub min_text_chars: Option<usize>,
+    /// Don't write articles with fewer paragraphs of text than this, see [html::substance].
+    pub min_paragraphs: Option<usize>,
+    /// Don't process extracted HTML; write the original text to disk.
+    pub no_simplify: bool,
+    /// CSS selectors of elements to keep when simplifying HTML, see [SimplifyConfig::protect_selectors].
+    pub protect_selectors: Vec<String>,
+    /// Compress each html file, e.g. for static hosts to serve with a `Content-Encoding`.
+    ///
+    /// If `None`, the files are written uncompressed as `lang.html`.
+    pub compress_html: Option<HtmlCompression>,
+    /// How to handle an article that appears more than once in the dump.
+    pub duplicates: DuplicatePolicy,
+    /// Write the `lang:Title` of each duplicate page to this file.
+    pub write_duplicates: Option<PathBuf>,
+    /// Append an [AuditEntry] for each matched page that is not written to this file.
+    pub write_audit: Option<PathBuf>,
+    /// Match requested titles that no page matches exactly to pages with the same [Title::skeleton].
+    ///
+    /// Pages that only match this way are processed after the rest of the dump, so they never replace an exact match.
+    pub near_miss_titles: bool,
+    /// Match requested titles to pages and redirects whose titles only differ in case and whitespace, see [Title::loose_key].
+    ///
+    /// Unlike [Options::near_miss_titles], these matches are made as the page is read.
+    pub loose_titles: bool,
+    /// Skip corrupt regions of the dump that can't be parsed as pages, instead of failing.
+    ///
+    /// Parsing continues at the next plausible start of a page, see [SkippedRegion].
+    /// The dump is decompressed before it is read, so a corrupt compressed region must be tolerated by the decompressor to reach this.
+    pub recover_corrupt: bool,
+    /// Skip pages of the dump longer than this many bytes, without reading them into memory.
+    ///
+    /// If `None`, [DEFAULT_MAX_PAGE_BYTES] is used.
+    pub max_page_bytes: Option<usize>,
+    /// Stop writing articles once their html would add up to more than this many bytes.
+    ///
+    /// The size is measured like [LangStats::bytes], before [Options::compress_html] and without the files written alongside the articles.
+    /// The articles that don't fit are counted in [DumpStats::over_budget].
+    pub max_total_bytes: Option<usize>,
+    /// Which articles to write first with [Options::max_total_bytes].
+    pub budget_order: BudgetOrder,
+    /// Write a [Sidecar] with the provenance of each article next to its html file.
+    pub write_sidecars: bool,
+    /// Append the redirects of each written article and its titles in other languages to [ALTNAMES_FILE] in `output_dir`.
+    ///
+    /// Titles in other languages are only known for articles matched by the QID of an article matched by title, see [Article::other_langs].
+    pub write_altnames: bool,
+    /// Maximum rows of each article in [Options::write_altnames].
+    ///
+    /// If `None`, [DEFAULT_MAX_ALTNAMES] is used.
+    pub max_altnames: Option<usize>,
+    /// Wikidata JSON dump to read the descriptions of the written articles' QIDs from, for [ManifestEntry::descriptions] and [Sidecar::descriptions].
+    ///
+    /// See [Descriptions::read].
+    pub wikidata_descriptions: Option<PathBuf>,
+    /// Languages of the descriptions to read from [Options::wikidata_descriptions], or all languages if empty.
+    pub description_langs: Vec<String>,
+    /// After the run, write the languages of the articles written for each QID to [QID_LANGS_FILE](crate::wm::output::QID_LANGS_FILE) in `output_dir`, from the manifest.
+    ///
+    /// Requires [Options::write_manifest], see [write_qid_langs].
+    pub write_qid_langs: bool,
+    /// After the run, add the title and redirects of each written article to [LINK_INDEX_FILE](crate::wm::output::LINK_INDEX_FILE) in `output_dir`, with the path of its html file.
+    ///
+    /// This resolves the internal links of articles offline, see [write_link_index].
+    pub write_link_index: bool,
+    /// Infer the [ManifestEntry::dir] of articles without a `dir` attribute from their language, see [Page::text_direction_or_lang].
+    pub infer_text_direction: bool,
+    /// Soft limit on the memory used by [run], in bytes.
+    ///
+    /// When the estimated usage approaches the limit, memory is traded for speed instead of failing, see [Degradation].
+    /// The estimate only includes the largest consumers, at a fixed size for each entry of the sets of titles and QIDs.
+    pub memory_ceiling: Option<usize>,
+    /// Time the processing of each matched page, and keep this many of the slowest in [DumpStats::slowest].
+    pub profile: Option<usize>,
+    /// Hook to change the html of each article after it is simplified and before it is written.
+    ///
+    /// Not part of the serialized options, so it must be set again on options that are read back, e.g. from a [RunPlan](crate::wm::plan::RunPlan).
+    #[serde(skip)]
+    pub post_processor: Option<Arc<dyn PostProcessor>>,
+    /// Custom rule that each matched page must also pass to be written, after the built-in filters like [Options::skip_stubs].
+    ///
+    /// Like [Options::post_processor], it is not part of the serialized options.
+    #[serde(skip)]
+    pub page_filter: Option<Arc<dyn PageFilter>>,
+}
+
+impl Options {
+    /// The default options, except that duplicate pages fail the run with [DuplicatePolicy::Error].
+    ///
+    /// Use it with [ListFileOptions::strict()] and [TagFileOptions::strict()] to check that a dump and the input files are clean.
+    pub fn strict() -> Self {
+        Self {
+            duplicates: DuplicatePolicy::Error,
+            ..Self::default()
+        }
+    }
+
+    /// The default options, except that corrupt regions of the dump are skipped with [Options::recover_corrupt],
+    /// and duplicate pages are replaced by newer revisions with [DuplicatePolicy::KeepLatest].
+    ///
+    /// Use it with [TagFileOptions::production_extract] and [ParseOptions::production_extract](crate::wm::ParseOptions::production_extract).
+    pub fn production_extract() -> Self {
+        Self {
+            recover_corrupt: true,
+            duplicates: DuplicatePolicy::KeepLatest,
+            ..Self::default()
+        }
+    }
+}
+
+/// Default of [Options::max_altnames].
+pub const DEFAULT_MAX_ALTNAMES: usize = 32;
+
+/// Default of [Options::max_page_bytes].
+///
+/// The largest articles in the dumps are around 10 MiB.
+pub const DEFAULT_MAX_PAGE_BYTES: usize = 64 << 20;
+
+/// Consecutive corrupt lines [Options::recover_corrupt] skips before giving up on the dump.
+pub const MAX_CONSECUTIVE_RECOVERIES: usize = 64;
+
+/// Start of each page's JSON in the dumps, where parsing resumes after a corrupt region.
+const PAGE_START: &[u8] = br#"{"name""#;
+
+/// A corrupt region of the dump that was skipped with [Options::recover_corrupt].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SkippedRegion {
+    /// Line of the dump the region starts on.
+    pub line: usize,
+    /// Byte offset of the start of the region, starting at 1 like [Article::byte].
+    pub start: usize,
+    /// Byte offset after the end of the region.
+    pub end: usize,
+    /// Why the region could not be parsed.
+    pub reason: String,
+}
+
+/// A matched page that was not written because its html file was already written by a different page in the same run.
+///
+/// Titles that differ can still have the same path, e.g. when they only differ in a trailing `/`, or in case on a case-insensitive filesystem.
+/// The first page written to the path is kept.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, thiserror::Error)]
+#[error("html file {path:?} of page {skipped:?} was already written by page {kept:?}")]
+pub struct PathCollision {
+    /// Path of the html file, relative to the output directory.
+    pub path: PathBuf,
+    /// `lang:Title` of the page that was written to the path, as it appears in the dump.
+    pub kept: String,
+    /// Page id of the kept page, see [Page::identifier].
+    pub kept_id: Option<u64>,
+    /// `lang:Title` of the page that was not written.
+    pub skipped: String,
+    /// Page id of the skipped page.
+    pub skipped_id: Option<u64>,
+    /// Line of the skipped page in the dump.
+    pub line: usize,
+}
+
+/// Longest path in bytes the OS can open, excluding the terminating NUL of `PATH_MAX`.
+#[cfg(target_os = "macos")]
+pub const MAX_PATH_BYTES: usize = 1023;
+/// Longest path in bytes the OS can open, excluding the terminating NUL of `PATH_MAX`.
+#[cfg(not(target_os = "macos"))]
+pub const MAX_PATH_BYTES: usize = 4095;
+
+/// The html file of a matched page would have a longer path than [MAX_PATH_BYTES], so it was not written.
+///
+/// Titles with many subpages are the longest, but the limit is usually only reached with a long output directory.
+/// Links from the page's other titles with paths that are too long are skipped instead.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("path of {title:?} in the output directory is {len} bytes, longer than the limit of {max} bytes")]
+pub struct PathTooLong {
+    /// `lang:Title` of the page, like [PathCollision::skipped].
+    pub title: String,
+    /// The full path, including the output directory.
+    pub path: PathBuf,
+    pub len: usize,
+    pub max: usize,
+}
+
+impl PathTooLong {
+    /// Check the length of `path`, which is written for `title`.
+    fn check(path: &Path, title: impl FnOnce() -> String) -> Result<(), Self> {
+        let len = path.as_os_str().len();
+        if len <= MAX_PATH_BYTES {
+            return Ok(());
+        }
+        Err(Self {
+            title: title(),
+            path: path.to_owned(),
+            len,
+            max: MAX_PATH_BYTES,
+        })
+    }
+}
+
+/// A way [run] reduces its memory use when it approaches [Options::memory_ceiling].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Degradation {
+    /// Pages deferred for [Options::near_miss_titles] are moved to a temporary file.
+    SpillNearMissPages,
+    /// The buffer of the page being read is shrunk after a large page, instead of keeping its capacity for the next one.
+    ShrinkPageBuffer,
+}
+
+/// When a [Degradation] first took effect.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Degraded {
+    pub degradation: Degradation,
+    /// Line of the dump that was read next.
+    pub line: usize,
+    /// The estimated memory in bytes at the time.
+    pub estimated_bytes: usize,
+}
+
+/// Counts of pages and requested articles encountered while processing a dump.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct DumpStats {
+    /// Pages read from the dump.
+    pub pages: usize,
+    /// Pages matched by QID.
+    pub qid_matches: usize,
+    /// Pages matched by title or redirect, but not QID.
+    pub title_matches: usize,
+    /// Pages matched by the QID of an article matched by title earlier in the input.
+    pub expanded_matches: usize,
+    /// Pages matched by a near-miss of a requested title with [Options::near_miss_titles].
+    pub near_miss_matches: usize,
+    /// Requested titles that were matched by a near-miss, and the title of the page they matched.
+    pub near_misses: BTreeMap<String, String>,
+    /// Matched pages that were skipped or replaced because the same article was already handled.
+    pub duplicates: usize,
+    /// Duplicate pages that replaced an older revision with [DuplicatePolicy::KeepLatest].
+    pub replaced: usize,
+    /// Matched pages that were skipped because they are stubs.
+    pub stubs: usize,
+    /// Matched pages that were skipped because they are below [Options::min_text_chars] or [Options::min_paragraphs].
+    ///
+    /// Each is listed in the [Options::write_audit] file with its [html::Substance].
+    pub insubstantial: usize,
+    /// Matched pages that were skipped because [Options::page_filter] rejected them.
+    pub filtered: usize,
+    /// Articles written to the output directory.
+    pub written: usize,
+    /// Written articles with a blank or unparseable title, which are only in their QID directory.
+    pub untitled: usize,
+    /// Articles that failed simplification or writing.
+    pub errors: usize,
+    /// Pages that were skipped because they are longer than [Options::max_page_bytes].
+    pub oversized_pages: usize,
+    /// Matched articles that were not written because they didn't fit in [Options::max_total_bytes].
+    pub over_budget: usize,
+    /// `lang:Title` of each matched page that [Options::post_processor] failed on, like [PathCollision::skipped], and the error.
+    ///
+    /// These pages are also counted in `errors`.
+    pub post_process_errors: BTreeMap<String, String>,
+    /// Corrupt regions of the dump that were skipped with [Options::recover_corrupt].
+    pub skipped_regions: Vec<SkippedRegion>,
+    /// Matched pages that were not written because a different page was already written to the same path.
+    pub path_collisions: Vec<PathCollision>,
+    /// Requested QIDs that did not match any page.
+    pub missing_qids: usize,
+    /// Requested titles that did not match any page or redirect.
+    pub missing_titles: usize,
+    /// Processing was stopped early by the cancellation token passed to [run].
+    pub cancelled: bool,
+    /// Pages that each [MatchPolicy] would extract, whichever is used, counting duplicates.
+    ///
+    /// Pages matched by the QID of an article matched by title are only counted if that article was extracted with the policy that is used.
+    pub policy_matches: BTreeMap<MatchPolicy, usize>,
+    /// Memory reductions made to stay under [Options::memory_ceiling].
+    pub degradations: Vec<Degraded>,
+    /// Time spent in each stage of processing the dump.
+    pub timings: Timings,
+    /// The matched pages that took the longest to process with [Options::profile], slowest first.
+    pub slowest: Vec<SlowArticle>,
+    /// Breakdown of the above by language, for Wikipedia articles.
+    pub langs: BTreeMap<String, LangStats>,
+    /// Breakdown of the above by language, for the articles of other projects.
+    pub other_projects: BTreeMap<Project, BTreeMap<String, LangStats>>,
+}
+
+impl DumpStats {
+    fn lang(&mut self, project: Project, lang: &str) -> &mut LangStats {
+        let langs = match project {
+            Project::Wikipedia => &mut self.langs,
+            project => self.other_projects.entry(project).or_default(),
+        };
+        langs.entry(lang.to_owned()).or_default()
+    }
+
+    /// Count an article that failed in `errors` and those of its language.
+    fn count_error(&mut self, project: Project, lang: &str) {
+        self.errors += 1;
+        self.lang(project, lang).errors += 1;
+    }
+
+    /// The [LangStats] of each language, sorted by descending article count.
+    ///
+    /// Languages of other projects are prefixed like their titles, e.g. `voy:en`.
+    pub fn lang_rows(&self) -> Vec<(Cow<'_, str>, &LangStats)> {
+        let mut rows: Vec<(Cow<str>, &LangStats)> = self
+            .langs
+            .iter()
+            .map(|(lang, s)| (Cow::Borrowed(lang.as_str()), s))
+            .collect();
+        for (project, langs) in &self.other_projects {
+            let prefix = project.prefix().unwrap_or_default();
+            rows.extend(
+                langs
+                    .iter()
+                    .map(|(lang, s)| (Cow::Owned(format!("{prefix}:{lang}")), s)),
+            );
+        }
+        rows.sort_by_key(|(_, s)| Reverse(s.matches));
+        rows
+    }
+}
+
+/// Wall time in seconds spent in each stage of [run], from [DumpStats::timings], and the CPU time of them in [Timings::cpu].
+///
+/// Each page is timed as a whole at the boundaries of the stages, which is negligible next to parsing it.
+/// The dump is decompressed before it is read, so the time spent waiting for the decompressor is part of `read`.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+pub struct Timings {
+    /// Reading the lines of pages from the dump, and of deferred pages from [Options::near_miss_titles].
+    pub read: f64,
+    /// Deserializing the JSON of pages.
+    pub parse: f64,
+    /// Matching pages against the requested QIDs and titles, including checks for duplicates and stubs.
+    pub matching: f64,
+    /// Simplifying the html of matched pages, and running [Options::post_processor] on it.
+    pub simplify: f64,
+    /// Writing the articles and the files alongside them.
+    pub write: f64,
+    /// Total time from reading the first page to writing the last one.
+    ///
+    /// Slightly more than the sum of the stages, which don't include e.g. logging.
+    pub total: f64,
+    /// Pages read from the dump each second of `total`.
+    pub pages_per_second: f64,
+    /// CPU time of the thread running [run] in each stage.
+    pub cpu: CpuTimings,
+}
+
+impl Timings {
+    /// Sum of the stages, excluding `total`.
+    pub fn stages(&self) -> f64 {
+        self.read + self.parse + self.matching + self.simplify + self.write
+    }
+
+    /// Add the time of another run over a different part of the input, e.g. from another process.
+    pub fn merge(&mut self, other: &Timings) {
+        self.read += other.read;
+        self.parse += other.parse;
+        self.matching += other.matching;
+        self.simplify += other.simplify;
+        self.write += other.write;
+        self.cpu.merge(&other.cpu);
+        let pages = self.pages_per_second * self.total + other.pages_per_second * other.total;
+        self.total += other.total;
+        self.pages_per_second = if self.total > 0.0 {
+            pages / self.total
+        } else {
+            0.0
+        };
+    }
+}
+
+/// CPU time in seconds spent in each stage of [run], from [Timings::cpu].
+///
+/// Only the CPU time of the thread running [run] is measured, so a decompressor in another process or thread is not included.
+/// Time spent waiting, e.g. for the dump to be read or the articles to be written, is only part of the wall time,
+/// so a stage with much less CPU than wall time is limited by IO.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+pub struct CpuTimings {
+    pub read: f64,
+    pub parse: f64,
+    pub matching: f64,
+    pub simplify: f64,
+    pub write: f64,
+    /// Total CPU time, like [Timings::total].
+    pub total: f64,
+}
+
+impl CpuTimings {
+    /// Sum of the stages, excluding `total`.
+    pub fn stages(&self) -> f64 {
+        self.read + self.parse + self.matching + self.simplify + self.write
+    }
+
+    /// Add the time of another run, like [Timings::merge].
+    pub fn merge(&mut self, other: &CpuTimings) {
+        self.read += other.read;
+        self.parse += other.parse;
+        self.matching += other.matching;
+        self.simplify += other.simplify;
+        self.write += other.write;
+        self.total += other.total;
+    }
+}
+
+/// A page from [DumpStats::slowest].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SlowArticle {
+    /// `lang:Title` of the page, like [PathCollision::skipped].
+    pub title: String,
+    /// Wall time from parsing the page to writing it, or skipping it after it failed.
+    pub seconds: f64,
+    /// Length of the page's JSON in the dump.
+    pub bytes: usize,
+    /// Line of the page in the dump.
+    pub line: usize,
+}
+
+/// Keeps the slowest pages for [Options::profile].
+struct Profile {
+    max: usize,
+    slowest: BinaryHeap<Reverse<(Duration, usize, String, usize)>>,
+    /// The start, line, title, and bytes of the page being processed.
+    current: Option<(Instant, usize, String, usize)>,
+}
+
+impl Profile {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            slowest: BinaryHeap::with_capacity(max + 1),
+            current: None,
+        }
+    }
+
+    fn start(&mut self, started: Instant, line: usize, page: &Page, bytes: usize) {
+        self.current = Some((started, line, WrittenPage::new(page).tag, bytes));
+    }
+
+    /// Record the time of the page being processed, if any.
+    fn end(&mut self) {
+        let Some((started, line, title, bytes)) = self.current.take() else {
+            return;
+        };
+        self.slowest
+            .push(Reverse((started.elapsed(), line, title, bytes)));
+        if self.slowest.len() > self.max {
+            self.slowest.pop();
+        }
+    }
+
+    fn finish(mut self) -> Vec<SlowArticle> {
+        self.end();
+        self.slowest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((elapsed, line, title, bytes))| SlowArticle {
+                title,
+                seconds: elapsed.as_secs_f64(),
+                bytes,
+                line,
+            })
+            .collect()
+    }
+}
+
+/// A stage of [Timings].
+#[derive(Clone, Copy)]
+enum Stage {
+    Read,
+    Parse,
+    Matching,
+    Simplify,
+    Write,
+}
+
+/// Measures the wall and CPU time between consecutive [Lap::split]s.
+struct Lap {
+    wall: Instant,
+    cpu: f64,
+}
+
+impl Lap {
+    fn start() -> Self {
+        Self {
+            wall: Instant::now(),
+            cpu: thread_cpu_time(),
+        }
+    }
+
+    /// Add the time since the previous split to `stage` of `timings`.
+    fn split(&mut self, timings: &mut Timings, stage: Stage) {
+        let now = Instant::now();
+        let wall = now.duration_since(self.wall).as_secs_f64();
+        self.wall = now;
+        let cpu_now = thread_cpu_time();
+        // The clock is monotonic, but don't let an error make a stage negative.
+        let cpu = (cpu_now - self.cpu).max(0.0);
+        self.cpu = cpu_now;
+
+        let (wall_total, cpu_total) = match stage {
+            Stage::Read => (&mut timings.read, &mut timings.cpu.read),
+            Stage::Parse => (&mut timings.parse, &mut timings.cpu.parse),
+            Stage::Matching => (&mut timings.matching, &mut timings.cpu.matching),
+            Stage::Simplify => (&mut timings.simplify, &mut timings.cpu.simplify),
+            Stage::Write => (&mut timings.write, &mut timings.cpu.write),
+        };
+        *wall_total += wall;
+        *cpu_total += cpu;
+    }
+}
+
+/// CPU time of the current thread in seconds, or 0 if it can't be read.
+fn thread_cpu_time() -> f64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is a valid timespec for the call to write to.
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } != 0 {
+        return 0.0;
+    }
+    time.tv_sec as f64 + time.tv_nsec as f64 * 1e-9
+}
+
+/// Per-language counts of [DumpStats].
+#[derive(Debug, Default, serde::Serialize)]
+pub struct LangStats {
+    /// Requested titles in this language.
+    pub titles: usize,
+    /// Pages matched by QID or title.
+    pub matches: usize,
+    /// Articles written to the output directory.
+    pub written: usize,
+    /// Bytes of html written to the output directory.
+    pub bytes: usize,
+    /// Articles that failed simplification or writing.
+    pub errors: usize,
+}
+
+/// Table of [DumpStats::lang_rows].
+impl Display for DumpStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<8} {:>10} {:>10} {:>10} {:>14} {:>10}",
+            "lang", "titles", "matches", "written", "bytes", "errors"
+        )?;
+        for (lang, s) in self.lang_rows() {
+            writeln!(
+                f,
+                "{:<8} {:>10} {:>10} {:>10} {:>14} {:>10}",
+                lang, s.titles, s.matches, s.written, s.bytes, s.errors
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Extract the articles matching the QIDs or titles of `matcher` from the newline-delimited JSON `dump`.
+///
+/// Processing stops early if `cancel` is set, which is checked before each page.
+/// Articles, new QIDs, and manifest entries are written as each page is processed, so the output is left in a consistent state.
+/// The returned stats and any missing report then only cover the pages read before cancellation.
+///
+/// Articles are written to [Options::output_dir] with a [DirectoryWriter], see [run_with] to write them elsewhere.
+pub fn run(
+    dump: impl BufRead,
+    passthrough: impl Write,
+    matcher: &Matcher,
+    options: &Options,
+    cancel: &AtomicBool,
+) -> anyhow::Result<DumpStats> {
+    if let (Some(dir), Some(publish)) = (&options.output_dir, options.publish) {
+        return run_staged(dump, passthrough, matcher, options, dir, publish, cancel);
+    }
+    let writer = options
+        .output_dir
+        .as_ref()
+        .map(|dir| DirectoryWriter::new(dir, options))
+        .transpose()?;
+    run_inner(dump, passthrough, matcher, options, writer, cancel)
+}
+
+/// The directory that [Options::publish] writes the articles of `output_dir` to before renaming it, e.g. `.articles.staging` for `articles`.
+///
+/// It is next to `output_dir`, so it can be renamed within the same filesystem.
+pub fn staging_dir(output_dir: &Path) -> PathBuf {
+    sibling_dir(output_dir, "staging")
+}
+
+/// The hidden directory next to `dir` with the `suffix`.
+fn sibling_dir(dir: &Path, suffix: &str) -> PathBuf {
+    let name = dir.file_name().unwrap_or(dir.as_os_str()).to_string_lossy();
+    dir.with_file_name(format!(".{name}.{suffix}"))
+}
+
+/// Remove a directory left by an earlier run that did not finish.
+fn remove_leftover(dir: &Path) -> anyhow::Result<()> {
+    if dir.exists() {
+        warn!("Removing {:?} left by an earlier run", dir);
+        fs::remove_dir_all(dir).with_context(|| format!("removing {:?}", dir))?;
+    }
+    Ok(())
+}
+
+/// [run] with [Options::publish], writing to the [staging_dir] of `output_dir` and renaming it on success.
+fn run_staged(
+    dump: impl BufRead,
+    passthrough: impl Write,
+    matcher: &Matcher,
+    options: &Options,
+    output_dir: &Path,
+    publish: Publish,
+    cancel: &AtomicBool,
+) -> anyhow::Result<DumpStats> {
+    if publish == Publish::Error && output_dir.exists() {
+        bail!("output dir {:?} already exists", output_dir);
+    }
+    let staging = staging_dir(output_dir);
+    remove_leftover(&staging)?;
+    fs::create_dir(&staging).with_context(|| format!("creating staging dir {:?}", staging))?;
+
+    let staged = Options {
+        output_dir: Some(staging.clone()),
+        publish: None,
+        relative_links: true,
+        ..options.clone()
+    };
+    let stats = run(dump, passthrough, matcher, &staged, cancel)
+        .with_context(|| format!("partial output is left in {:?}", staging))?;
+    if stats.cancelled {
+        warn!(
+            "Not publishing the output of a cancelled run, partial output is left in {:?}",
+            staging
+        );
+        return Ok(stats);
+    }
+
+    let old = sibling_dir(output_dir, "old");
+    let replacing = output_dir.exists();
+    if replacing {
+        remove_leftover(&old)?;
+        fs::rename(output_dir, &old)
+            .with_context(|| format!("moving old output dir {:?} to {:?}", output_dir, old))?;
+    }
+    if let Err(e) = fs::rename(&staging, output_dir) {
+        if replacing {
+            // Put the old output back, so the failed run leaves it in place.
+            fs::rename(&old, output_dir)
+                .with_context(|| format!("restoring old output dir {:?}", output_dir))?;
+        }
+        return Err(e).with_context(|| format!("publishing {:?} to {:?}", staging, output_dir));
+    }
+    info!("Published output to {:?}", output_dir);
+    if replacing {
+        fs::remove_dir_all(&old).with_context(|| format!("removing old output dir {:?}", old))?;
+    }
+    Ok(stats)
+}
+
+/// Like [run], writing the extracted articles to `writer` instead of [Options::output_dir].
+///
+/// [Options::output_dir] and the options of the files written to it, like [Options::layout] and [Options::write_manifest], are ignored.
+pub fn run_with(
+    dump: impl BufRead,
+    passthrough: impl Write,
+    matcher: &Matcher,
+    options: &Options,
+    writer: impl PageWriter,
+    cancel: &AtomicBool,
+) -> anyhow::Result<DumpStats> {
+    run_inner(dump, passthrough, matcher, options, Some(writer), cancel)
+}
+
+fn run_inner(
+    mut dump: impl BufRead,
+    mut passthrough: impl Write,
+    matcher: &Matcher,
+    options: &Options,
+    mut writer: Option<impl PageWriter>,
+    cancel: &AtomicBool,
+) -> anyhow::Result<DumpStats> {
+    let mut run = Run::new(matcher, options)?;
+
+    info!("Processing dump");
+
+    let max_page_bytes = options.max_page_bytes.unwrap_or(DEFAULT_MAX_PAGE_BYTES);
+    let mut buffer = Vec::new();
+    let mut consumed = 0;
+    let mut line = 0;
+    let mut byte = 1;
+    let mut consecutive_recoveries = 0;
+    loop {
+        // Pages that aren't extracted stop in the matching stage.
+        run.split(Stage::Matching);
+        if let Some(profile) = &mut run.profile {
+            profile.end();
+        }
+        if !run.deferred_near_misses.is_replaying() {
+            line += 1;
+            byte += consumed;
+            buffer.clear();
+            run.check_memory(line, &mut buffer)?;
+
+            let read =
+                read_line_capped(&mut dump, &mut buffer, max_page_bytes).context("reading dump")?;
+            match read {
+                CappedLine::Line(len) => consumed = len,
+                CappedLine::TooLong(len) => {
+                    consumed = len;
+                    warn!(
+                        line,
+                        byte,
+                        len,
+                        max = max_page_bytes,
+                        "Skipping page longer than the limit"
+                    );
+                    run.stats.oversized_pages += 1;
+                    continue;
+                }
+                CappedLine::Eof => run
+                    .deferred_near_misses
+                    .replay()
+                    .context("reading near-miss pages")?,
+            }
+        }
+        if run.deferred_near_misses.is_replaying() {
+            let Some(next) = run.deferred_near_misses.next_page() else {
+                break;
+            };
+            (line, byte, buffer) = next.context("reading near-miss pages")?;
+        }
+        run.split(Stage::Read);
+        let page_started = Instant::now();
+
+        if cancel.load(Ordering::Relaxed) {
+            info!(line, "Cancelled processing dump");
+            run.stats.cancelled = true;
+            break;
+        }
+
+        // TODO: Compare different deserialization methods.
+        // The docs warn against using a reader directly, and it's slower than tar can decompress the dump.
+        // let stream = serde_json::Deserializer::from_reader(dump).into_iter::<Page>();
+        let parsed = parse_page(
+            &buffer,
+            line,
+            byte,
+            options.recover_corrupt,
+            &mut consecutive_recoveries,
+            &mut run.stats,
+            &mut run.audit,
+        )?;
+        run.split(Stage::Parse);
+        let Some((offset, page)) = parsed else {
+            continue;
+        };
+        if offset > 0 {
+            // Keep only the recovered page for passthrough and near-miss matching.
+            buffer.drain(..offset);
+            byte += offset;
+            consumed -= offset;
+        }
+        run.stats.pages += 1;
+
+        let span = info_span!(
+            "page",
+            lang = page.in_language.identifier,
+            title = page.name,
+            url = page.url,
+            qid = page.main_entity.as_ref().map(|w| &w.identifier),
+            line,
+            byte,
+        );
+        let _handle = span.enter();
+
+        let read = DumpPage {
+            page,
+            json: &buffer,
+            line,
+            byte,
+        };
+        run.process(&read, page_started, &mut passthrough, writer.as_mut())?;
+    }
+
+    run.finish(writer)
+}
+
+/// A page read from the dump, with its JSON and position.
+struct DumpPage<'a> {
+    page: Page,
+    json: &'a [u8],
+    /// Line of the page in the dump.
+    line: usize,
+    /// Byte offset of the page in the dump, starting at 1.
+    byte: usize,
+}
+
+/// How a page matched the requested articles, from [Run::match_page].
+struct PageMatch {
+    qid: Option<Qid>,
+    project: Project,
+    is_wikidata_match: bool,
+    is_near_miss: bool,
+    /// Requested titles that matched the page or its redirects, or its near-miss titles.
+    ///
+    /// Empty for pages only matched by QID or by the QID of an article matched by title.
+    titles: Vec<Title>,
+}
+
+/// QIDs of articles matched by title, and the titles they were matched by.
+type Expansions = HashMap<(Project, Qid), Vec<Title>>;
+
+impl PageMatch {
+    fn matched_by(&self, page: &Page) -> MatchedBy {
+        if self.is_wikidata_match {
+            MatchedBy::Qid
+        } else if self.is_near_miss {
+            MatchedBy::NearMiss
+        } else if self.titles.is_empty() {
+            MatchedBy::Expanded
+        } else if page.title().is_ok_and(|title| self.titles.contains(&title)) {
+            MatchedBy::Title
+        } else {
+            MatchedBy::Redirect
+        }
+    }
+
+    /// The requested QID and titles that selected the article, see [Article::requesters].
+    fn requesters(&self, matched_by: MatchedBy, expansions: &Expansions) -> Vec<String> {
+        match (matched_by, self.qid) {
+            (MatchedBy::Expanded, Some(qid)) => expansions[&(self.project, qid)]
+                .iter()
+                .map(Title::to_string)
+                .collect(),
+            _ => self
+                .qid
+                .filter(|_| self.is_wikidata_match)
+                .map(|qid| qid.to_string())
+                .into_iter()
+                .chain(self.titles.iter().map(Title::to_string))
+                .collect(),
+        }
+    }
+
+    /// See [Article::other_langs].
+    fn other_langs(&self, page: &Page, expansions: &Expansions) -> Vec<Title> {
+        self.qid
+            .and_then(|qid| expansions.get(&(self.project, qid)))
+            .into_iter()
+            .flatten()
+            .filter(|title| title.lang() != page.in_language.identifier)
+            .cloned()
+            .collect()
+    }
+}
+
+/// The state of [run] carried from one page of the dump to the next.
+struct Run<'a> {
+    matcher: &'a Matcher,
+    options: &'a Options,
+    simplify_configs: HashMap<Project, SimplifyConfig>,
+    /// Whether [Options::min_text_chars] or [Options::min_paragraphs] need the [html::Substance] of articles.
+    measure_substance: bool,
+    skeletons: Skeletons<'a>,
+    /// Requested titles by their loose key.
+    loose_titles: HashMap<String, Vec<&'a Title>>,
+    stats: DumpStats,
+    found_qids: HashSet<Qid>,
+    found_titles: HashSet<Title>,
+    handled: HandledArticles,
+    /// Only pages of the same project are expanded to.
+    expansions: Expansions,
+    deferred_near_misses: DeferredNearMisses,
+    budget: Budget,
+    write_new_qids: Option<File>,
+    audit: Option<AuditLog>,
+    write_duplicates: Option<BufWriter<File>>,
+    started: Instant,
+    started_cpu: f64,
+    lap: Lap,
+    profile: Option<Profile>,
+}
+
+impl<'a> Run<'a> {
+    fn new(matcher: &'a Matcher, options: &'a Options) -> anyhow::Result<Self> {
+        // NOTE: For atomic writes to the same file across threads/processes:
+        // - The file needs to be opened in APPEND mode (`.append(true)`).
+        // - Each write needs to be a single syscall (for Rust, use `format!` for formatting before calling `write!`, or `write!` to a `String` first).
+        // - Each write needs to be under `PIPE_BUF` size (see `man write(3)`), usually 4kb on Linux.
+        //
+        // For more information, see:
+        // - `man write(3posix)`: https://www.man7.org/linux/man-pages/man3/write.3p.html
+        // - `std::fs::OpenOptions::append`: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html#method.append
+        // - https://stackoverflow.com/questions/1154446/is-file-append-atomic-in-unix
+        let write_new_qids = options
+            .write_new_qids
+            .as_ref()
+            .map(|p| File::options().create(true).append(true).open(p))
+            .transpose()?;
+
+        let audit = options
+            .write_audit
+            .as_ref()
+            .map(AuditLog::append)
+            .transpose()?;
+
+        let write_duplicates = options
+            .write_duplicates
+            .as_ref()
+            .map(|p| {
+                File::create(p)
+                    .map(BufWriter::new)
+                    .with_context(|| format!("creating duplicates file {:?}", p))
+            })
+            .transpose()?;
+
+        if let Some(dir) = &options.write_missing {
+            if !dir.is_dir() {
+                bail!("missing report dir {:?} does not exist", dir);
+            }
+        }
+
+        let simplify_configs = simplify_configs(options)?;
+
+        let mut stats = DumpStats::default();
+        for title in &matcher.titles {
+            stats.lang(title.project(), title.lang()).titles += 1;
+        }
+
+        let skeletons = if options.near_miss_titles {
+            Skeletons::new(&matcher.titles)
+        } else {
+            Skeletons::default()
+        };
+        let mut loose_titles: HashMap<String, Vec<&Title>> = HashMap::new();
+        if options.loose_titles {
+            for title in &matcher.titles {
+                loose_titles
+                    .entry(title.loose_key())
+                    .or_default()
+                    .push(title);
+            }
+        }
+
+        Ok(Self {
+            matcher,
+            options,
+            simplify_configs,
+            measure_substance: options.min_text_chars.is_some() || options.min_paragraphs.is_some(),
+            skeletons,
+            loose_titles,
+            stats,
+            found_qids: HashSet::new(),
+            found_titles: HashSet::new(),
+            handled: HandledArticles::default(),
+            expansions: HashMap::new(),
+            deferred_near_misses: DeferredNearMisses::default(),
+            budget: Budget::new(options.max_total_bytes, options.budget_order),
+            write_new_qids,
+            audit,
+            write_duplicates,
+            started: Instant::now(),
+            started_cpu: thread_cpu_time(),
+            lap: Lap::start(),
+            profile: options.profile.map(Profile::new),
+        })
+    }
+
+    /// Add the time since the previous split to `stage`, see [Lap::split].
+    fn split(&mut self, stage: Stage) {
+        self.lap.split(&mut self.stats.timings, stage);
+    }
+
+    /// Make the [Degradation]s needed to stay under [Options::memory_ceiling] before reading `line` into `buffer`.
+    fn check_memory(&mut self, line: usize, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        let Some(ceiling) = self.options.memory_ceiling else {
+            return Ok(());
+        };
+        let estimated_bytes = estimate_memory(&[
+            (
+                self.matcher.qids.len() + self.found_qids.len(),
+                QID_ENTRY_BYTES,
+            ),
+            (
+                self.matcher.titles.len()
+                    + self.found_titles.len()
+                    + self.handled.len()
+                    + self.expansions.len()
+                    + self.skeletons.len()
+                    + self.loose_titles.len(),
+                TITLE_ENTRY_BYTES,
+            ),
+            (
+                self.deferred_near_misses.memory_bytes() + buffer.capacity(),
+                1,
+            ),
+        ]);
+        if estimated_bytes < ceiling / 10 * 9 {
+            return Ok(());
+        }
+        let stats = &mut self.stats;
+        let mut degrade = |degradation| {
+            if !stats
+                .degradations
+                .iter()
+                .any(|d| d.degradation == degradation)
+            {
+                warn!(
+                    line,
+                    estimated_bytes,
+                    ceiling,
+                    ?degradation,
+                    "Approaching memory ceiling"
+                );
+                stats.degradations.push(Degraded {
+                    degradation,
+                    line,
+                    estimated_bytes,
+                });
+            }
+        };
+        if self.options.near_miss_titles && !self.deferred_near_misses.is_spilled() {
+            self.deferred_near_misses
+                .spill()
+                .context("spilling near-miss pages to a temporary file")?;
+            degrade(Degradation::SpillNearMissPages);
+        }
+        if buffer.capacity() > SHRUNK_BUFFER_BYTES {
+            buffer.shrink_to(SHRUNK_BUFFER_BYTES);
+            degrade(Degradation::ShrinkPageBuffer);
+        }
+        Ok(())
+    }
+
+    /// Match, simplify, and write the article of `read`, or record why it was skipped.
+    ///
+    /// `started` is when the page was read, for [Options::profile].
+    fn process<W: PageWriter>(
+        &mut self,
+        read: &DumpPage,
+        started: Instant,
+        passthrough: &mut impl Write,
+        writer: Option<&mut W>,
+    ) -> anyhow::Result<()> {
+        let Some(matched) = self.match_page(read)? else {
+            return Ok(());
+        };
+        let Some((key, replacing)) = self.dedup(read, &matched)? else {
+            return Ok(());
+        };
+        self.count_match(&read.page, &matched, replacing)?;
+        if !self.accepts(read)? {
+            return Ok(());
+        }
+
+        // Always write regardless of later errors.
+        if let Some(ArticleFilter::Match) = self.options.passthrough {
+            passthrough.write_all(read.json)?;
+        }
+
+        let page = &read.page;
+        if self.budget.is_exhausted() {
+            debug!("Skipping article after the output budget was reached");
+            self.stats.over_budget += 1;
+            audit_skip(&mut self.audit, read.line, page, "over budget")?;
+            return Ok(());
+        }
+
+        self.split(Stage::Matching);
+        if let Some(profile) = &mut self.profile {
+            profile.start(started, read.line, page, read.json.len());
+        }
+        let (article_output, substance) = self.simplify(page, matched.project);
+        self.split(Stage::Simplify);
+
+        let html = match article_output {
+            Ok(html) => html,
+            Err(e) => {
+                error!("Error processing article: {:#}", e);
+                audit_skip(
+                    &mut self.audit,
+                    read.line,
+                    page,
+                    &format!("processing: {e}"),
+                )?;
+                self.stats
+                    .count_error(matched.project, &page.in_language.identifier);
+                if let Some(filter) = self.options.passthrough {
+                    match (e, filter) {
+                        (_, ArticleFilter::Error) | (HtmlError::Panic(_), ArticleFilter::Panic) => {
+                            passthrough.write_all(read.json)?
+                        }
+                        _ => {}
+                    }
+                }
+                return Ok(());
+            }
+        };
+        if let Some(reason) = substance.and_then(|s| insubstantial(self.options, &s)) {
+            debug!("Skipping article that is {reason}");
+            self.stats.insubstantial += 1;
+            audit_skip(&mut self.audit, read.line, page, &reason)?;
+            return Ok(());
+        }
+        let Some(writer) = writer else {
+            return Ok(());
+        };
+        let processed = post_process(self.options.post_processor.as_deref(), page, html);
+        self.split(Stage::Simplify);
+        let html = match processed {
+            Ok(html) => html,
+            Err(e) => {
+                error!("Error post-processing article: {:#}", e);
+                audit_skip(
+                    &mut self.audit,
+                    read.line,
+                    page,
+                    &format!("post-processing: {e:#}"),
+                )?;
+                self.stats
+                    .count_error(matched.project, &page.in_language.identifier);
+                self.stats
+                    .post_process_errors
+                    .insert(WrittenPage::new(page).tag, format!("{e:#}"));
+                return Ok(());
+            }
+        };
+        self.write(writer, read, matched, key, replacing, &html)
+    }
+
+    /// How `read` matches the requested articles.
+    ///
+    /// `None` if it doesn't, if [Options::match_policy] rejects it, or if it was deferred to match near-miss titles after the rest of the dump.
+    fn match_page(&mut self, read: &DumpPage) -> anyhow::Result<Option<PageMatch>> {
+        let page = &read.page;
+        let qid = page.wikidata();
+        let project = page.project();
+        let mut is_wikidata_match = qid
+            .as_ref()
+            .map(|qid| self.matcher.qids.contains(qid))
+            .unwrap_or_default();
+
+        let mut matching_titles = matching_titles(page, &self.matcher.titles, &self.loose_titles);
+
+        let mut is_expanded_match = !is_wikidata_match
+            && qid
+                .as_ref()
+                .map(|&qid| self.expansions.contains_key(&(project, qid)))
+                .unwrap_or_default();
+
+        let mut is_near_miss = false;
+        if !is_wikidata_match
+            && !is_expanded_match
+            && matching_titles.is_empty()
+            && !self.skeletons.is_empty()
+        {
+            let near_misses = self
+                .skeletons
+                .near_misses(page.all_titles().filter_map(Result::ok), &self.found_titles);
+            if !near_misses.is_empty() {
+                if !self.deferred_near_misses.is_replaying() {
+                    debug!(titles = ?near_misses, "Deferring near-miss title match");
+                    self.deferred_near_misses
+                        .defer(read.line, read.byte, read.json)
+                        .context("deferring near-miss page")?;
+                    return Ok(None);
+                }
+                let page_title = page
+                    .title()
+                    .map_or_else(|_| page.name.clone(), |t| t.to_string());
+                for requested in &near_misses {
+                    info!(requested = %requested, "Matched near-miss title");
+                    self.stats
+                        .near_misses
+                        .insert(requested.to_string(), page_title.clone());
+                }
+                matching_titles = near_misses;
+                is_near_miss = true;
+            }
+        }
+
+        let is_title_match = is_expanded_match || !matching_titles.is_empty();
+        for policy in MatchPolicy::ALL {
+            if policy.accepts(is_wikidata_match, is_title_match) {
+                *self.stats.policy_matches.entry(*policy).or_default() += 1;
+            }
+        }
+        match self.options.match_policy {
+            MatchPolicy::Either => {}
+            MatchPolicy::QidOnly => {
+                matching_titles.clear();
+                is_expanded_match = false;
+                is_near_miss = false;
+            }
+            MatchPolicy::TitleOnly => is_wikidata_match = false,
+            MatchPolicy::Both if !is_wikidata_match || !is_title_match => return Ok(None),
+            MatchPolicy::Both => {}
+        }
+
+        if !is_wikidata_match && !is_expanded_match && matching_titles.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(PageMatch {
+            qid,
+            project,
+            is_wikidata_match,
+            is_near_miss,
+            titles: matching_titles,
+        }))
+    }
+
+    /// The key of the article of `read`, and whether it replaces an older revision, see [Options::duplicates].
+    ///
+    /// `None` if the article was already handled.
+    fn dedup(
+        &mut self,
+        read: &DumpPage,
+        matched: &PageMatch,
+    ) -> anyhow::Result<Option<(PageKey, bool)>> {
+        let page = &read.page;
+        // A page may be listed by both QID and title, or appear more than once in the input.
+        // Handle each article only once.
+        let key = match matched.qid {
+            Some(qid) => PageKey::Qid(qid, matched.project, page.in_language.identifier.clone()),
+            None => PageKey::Title(matched.titles[0].clone()),
+        };
+        let revision = page.revision();
+        let occurrence = self.handled.see(&key, revision, self.options.duplicates);
+        if occurrence != Occurrence::First {
+            self.stats.duplicates += 1;
+            if let Some(f) = &mut self.write_duplicates {
+                writeln!(f, "{}:{}", page.in_language.identifier, page.name).with_context(
+                    || {
+                        format!(
+                            "writing duplicates file {:?}",
+                            self.options.write_duplicates.as_ref().unwrap()
+                        )
+                    },
+                )?;
+            }
+        }
+        let replacing = match occurrence {
+            Occurrence::First => false,
+            Occurrence::Newer { previous } => {
+                debug!(revision, previous, "Replacing older revision of article");
+                self.stats.replaced += 1;
+                true
+            }
+            Occurrence::Duplicate if self.options.duplicates == DuplicatePolicy::Error => {
+                bail!("page {:?} duplicates an already handled article", page.name)
+            }
+            Occurrence::Duplicate => {
+                debug!("Skipping already handled article");
+                audit_skip(&mut self.audit, read.line, page, "duplicate")?;
+                return Ok(None);
+            }
+        };
+        Ok(Some((key, replacing)))
+    }
+
+    /// Count the match of `page` in the stats, and remember what it was found by for the rest of the dump.
+    fn count_match(
+        &mut self,
+        page: &Page,
+        matched: &PageMatch,
+        replacing: bool,
+    ) -> anyhow::Result<()> {
+        let PageMatch {
+            qid,
+            project,
+            is_wikidata_match,
+            is_near_miss,
+            titles,
+        } = matched;
+        if replacing {
+            // Already counted as a match.
+        } else if *is_wikidata_match {
+            self.stats.qid_matches += 1;
+            self.found_qids.extend(qid);
+        } else if !titles.is_empty() {
+            if *is_near_miss {
+                self.stats.near_miss_matches += 1;
+            } else {
+                self.stats.title_matches += 1;
+            }
+            if let (false, Some(qid)) = (self.options.no_expand_titles, qid) {
+                self.expansions
+                    .entry((*project, *qid))
+                    .or_default()
+                    .extend(titles.iter().cloned());
+            }
+        } else {
+            debug!(
+                "Matched QID of titles {:?} from earlier in the input",
+                self.expansions[&(*project, qid.unwrap())]
+            );
+            self.stats.expanded_matches += 1;
+        }
+        if !replacing {
+            self.stats
+                .lang(*project, &page.in_language.identifier)
+                .matches += 1;
+        }
+        // Redirects are included, so the originally requested title is marked as found.
+        self.found_titles.extend(titles.iter().cloned());
+
+        // Write matched new QIDs back to file.
+        if let (Some(f), Some(qid)) = (&mut self.write_new_qids, qid) {
+            if !replacing && !is_wikidata_match && !titles.is_empty() {
+                debug!("Writing new id {}", qid);
+                // NOTE: Write to string buffer first to have a single atomic write syscall.
+                // See `write_new_qids` for more info.
+                let line = format!("{}\n", qid);
+                write!(f, "{}", line).with_context(|| {
+                    format!(
+                        "writing new QID to file {:?}",
+                        self.options.write_new_qids.as_ref().unwrap()
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the article of `read` passes [Options::skip_stubs] and [Options::page_filter], recording why it doesn't.
+    fn accepts(&mut self, read: &DumpPage) -> anyhow::Result<bool> {
+        let page = &read.page;
+        if self.options.skip_stubs && page.is_stub() {
+            debug!("Skipping stub article");
+            self.stats.stubs += 1;
+            audit_skip(&mut self.audit, read.line, page, "stub")?;
+            return Ok(false);
+        }
+
+        if let Some(filter) = &self.options.page_filter {
+            if !filter.accepts(page) {
+                debug!("Skipping article rejected by the page filter");
+                self.stats.filtered += 1;
+                audit_skip(&mut self.audit, read.line, page, "filtered")?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The simplified html of `page`, or its original html with [Options::no_simplify], and its substance if it's measured.
+    fn simplify<'p>(
+        &self,
+        page: &'p Page,
+        project: Project,
+    ) -> (Result<Cow<'p, str>, HtmlError>, Option<html::Substance>) {
+        let mut substance = None;
+        let article_output = if self.options.no_simplify {
+            if self.measure_substance {
+                substance = Some(html::substance(&Html::parse_document(
+                    &page.article_body.html,
+                )));
+            }
+            Ok(Cow::Borrowed(page.article_body.html.as_str()))
+        } else {
+            html::process_with(
+                Html::parse_document(&page.article_body.html),
+                &page.in_language.identifier,
+                &self.simplify_configs[&project],
+            )
+            .map(|document| {
+                if self.measure_substance {
+                    substance = Some(html::substance(&document));
+                }
+                Cow::Owned(document.html())
+            })
+        };
+        (article_output, substance)
+    }
+
+    /// Write the processed `html` of the article of `read` if it fits in the [Budget], or hold it back for it.
+    fn write(
+        &mut self,
+        writer: &mut impl PageWriter,
+        read: &DumpPage,
+        matched: PageMatch,
+        key: PageKey,
+        replacing: bool,
+        html: &str,
+    ) -> anyhow::Result<()> {
+        let page = &read.page;
+        let matched_by = matched.matched_by(page);
+        let requesters = matched.requesters(matched_by, &self.expansions);
+        let other_langs = matched.other_langs(page, &self.expansions);
+        if self.budget.holds_back() {
+            let held = Held {
+                qid: matched.qid,
+                key,
+                replacing,
+                bytes: html.len(),
+                titles: matched.titles,
+                other_langs,
+                matched_by,
+                requesters,
+            };
+            return self
+                .budget
+                .hold(read.line, read.byte, read.json, html, held)
+                .context("holding back article for the output budget");
+        }
+        let freed = self.handled.written_bytes(&key);
+        if !self.budget.fits(html.len(), freed) {
+            self.stats.over_budget += 1;
+            audit_skip(&mut self.audit, read.line, page, "over budget")?;
+            return Ok(());
+        }
+        let article = Article {
+            page,
+            html,
+            titles: &matched.titles,
+            other_langs: &other_langs,
+            matched_by,
+            requesters,
+            line: read.line,
+            byte: read.byte,
+        };
+        write_article(
+            writer,
+            &article,
+            &key,
+            replacing,
+            &mut self.handled,
+            &mut self.stats,
+            &mut self.audit,
+        )?;
+        self.budget.wrote(self.handled.written_bytes(&key), freed);
+        self.split(Stage::Write);
+        Ok(())
+    }
+
+    /// Write the articles held back for the [Budget] and the reports of missing articles, and return the stats of the run.
+    fn finish(self, writer: Option<impl PageWriter>) -> anyhow::Result<DumpStats> {
+        let Self {
+            matcher,
+            options,
+            mut stats,
+            found_qids,
+            found_titles,
+            mut handled,
+            budget,
+            mut audit,
+            write_duplicates,
+            started,
+            started_cpu,
+            mut lap,
+            profile,
+            ..
+        } = self;
+        if let Some(mut writer) = writer {
+            // The end of the dump was the last thing read.
+            lap.split(&mut stats.timings, Stage::Read);
+            if budget.held() > 0 {
+                info!(
+                    articles = budget.held(),
+                    "Writing articles in the order of the output budget"
+                );
+                write_held(&mut writer, budget, &mut handled, &mut stats, &mut audit)?;
+            }
+            writer.finish()?;
+            lap.split(&mut stats.timings, Stage::Write);
+        }
+        if let Some(profile) = profile {
+            stats.slowest = profile.finish();
+        }
+        stats.timings.total = started.elapsed().as_secs_f64();
+        stats.timings.cpu.total = (thread_cpu_time() - started_cpu).max(0.0);
+        if stats.timings.total > 0.0 {
+            stats.timings.pages_per_second = stats.pages as f64 / stats.timings.total;
+        }
+
+        if let Some(audit) = audit {
+            audit.finish()?;
+        }
+
+        if let Some(mut f) = write_duplicates {
+            f.flush().with_context(|| {
+                format!(
+                    "writing duplicates file {:?}",
+                    options.write_duplicates.as_ref().unwrap()
+                )
+            })?;
+        }
+
+        let mut missing_qids: Vec<_> = matcher.qids.difference(&found_qids).collect();
+        let mut missing_titles: Vec<_> = matcher.titles.difference(&found_titles).collect();
+        stats.missing_qids = missing_qids.len();
+        stats.missing_titles = missing_titles.len();
+
+        if let Some(dir) = &options.write_missing {
+            missing_qids.sort_unstable();
+            missing_titles.sort_unstable();
+            write_missing(
+                dir,
+                &missing_qids,
+                &missing_titles,
+                matcher.raw_titles.as_ref(),
+            )?;
+        }
+
+        Ok(stats)
+    }
+}
+
+/// The first page in `buffer` that starts after a corrupt region at its start, and its offset.
+fn recover_page(buffer: &[u8]) -> Option<(usize, Page)> {
+    let mut offset = 0;
+    loop {
+        offset += 1 + buffer
+            .get(offset + 1..)?
+            .windows(PAGE_START.len())
+            .position(|w| w == PAGE_START)?;
+        if let Ok(page) = serde_json::from_slice(&buffer[offset..]) {
+            return Some((offset, page));
+        }
+    }
+}
+
+/// Estimated bytes of an entry in a set or map of QIDs, for [Options::memory_ceiling].
+const QID_ENTRY_BYTES: usize = 16;
+/// Estimated bytes of an entry in a set or map of titles, including a typical title and the hash table overhead.
+const TITLE_ENTRY_BYTES: usize = 128;
+/// Capacity the page buffer is shrunk to with [Degradation::ShrinkPageBuffer].
+const SHRUNK_BUFFER_BYTES: usize = 1 << 20;
+
+/// Sum of (count, bytes of each) pairs.
+fn estimate_memory(consumers: &[(usize, usize)]) -> usize {
+    consumers
+        .iter()
+        .map(|(count, bytes)| count.saturating_mul(*bytes))
+        .fold(0, usize::saturating_add)
+}
+
+/// Entry of the newline-delimited JSON log written with [Options::write_audit].
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEntry<'a> {
+    /// A line of an input file that could not be parsed.
+    ParseError {
+        file: &'a Path,
+        line: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        osm_id: Option<osm::Id>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        osm_type: Option<&'static str>,
+        /// What was being parsed, see [crate::ParseErrorKind].
+        kind: String,
+        text: &'a str,
+        reason: String,
+    },
+    /// A matched page that was not written.
+    Skip {
+        /// Line of the page in the dump.
+        line: usize,
+        lang: &'a str,
+        title: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        qid: Option<Qid>,
+        reason: &'a str,
+    },
+    /// A corrupt region of the dump skipped with [Options::recover_corrupt], see [SkippedRegion].
+    SkippedRegion {
+        line: usize,
+        start: usize,
+        end: usize,
+        reason: &'a str,
+    },
+}
+
+impl<'a> AuditEntry<'a> {
+    pub fn parse_error(file: &'a Path, error: &'a ParseLineError) -> Self {
+        let mut reason = Vec::new();
+        let mut source = std::error::Error::source(&error.kind);
+        while let Some(e) = source {
+            reason.push(e.to_string());
+            source = e.source();
+        }
+        AuditEntry::ParseError {
+            file,
+            line: error.line,
+            osm_id: error.osm_id,
+            osm_type: error.osm_type.as_ref().map(osm::Kind::oname),
+            kind: error.kind.to_string(),
+            text: &error.text,
+            reason: reason.join(": "),
+        }
+    }
+}
+
+/// Writer of [AuditEntry]s.
+pub struct AuditLog {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl AuditLog {
+    /// Create or truncate the log at `path`.
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("creating audit log {:?}", path))?;
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Append to the log at `path`, creating it if necessary.
+    pub fn append(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening audit log {:?}", path))?;
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, entry: &AuditEntry) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.file, entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(self.file.write_all(b"\n")?))
+            .with_context(|| format!("writing audit log {:?}", self.path))
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.file
+            .flush()
+            .with_context(|| format!("writing audit log {:?}", self.path))
+    }
+}
+
+fn audit_skip(
+    audit: &mut Option<AuditLog>,
+    line: usize,
+    page: &Page,
+    reason: &str,
+) -> anyhow::Result<()> {
+    let Some(audit) = audit else {
+        return Ok(());
+    };
+    audit.record(&AuditEntry::Skip {
+        line,
+        lang: &page.in_language.identifier,
+        title: &page.name,
+        qid: page.wikidata(),
+        reason,
+    })
+}
+
+/// Parse the page in `buffer`, read from `line` and `byte` of the dump, and return it with its offset in `buffer`.
+///
+/// With `recover_corrupt`, a corrupt region before the next page in the line is skipped and recorded in `stats` and `audit`,
+/// and `None` is returned if there is no page after it.
+/// `consecutive_recoveries` counts the lines that needed it in a row, to fail after [MAX_CONSECUTIVE_RECOVERIES].
+fn parse_page(
+    buffer: &[u8],
+    line: usize,
+    byte: usize,
+    recover_corrupt: bool,
+    consecutive_recoveries: &mut usize,
+    stats: &mut DumpStats,
+    audit: &mut Option<AuditLog>,
+) -> anyhow::Result<Option<(usize, Page)>> {
+    let e = match serde_json::from_slice(buffer) {
+        Ok(page) => {
+            *consecutive_recoveries = 0;
+            return Ok(Some((0, page)));
+        }
+        Err(e) if !recover_corrupt => {
+            return Err(e).with_context(|| format!("deserializing json on line {line}"))
+        }
+        Err(e) => e,
+    };
+    *consecutive_recoveries += 1;
+    if *consecutive_recoveries > MAX_CONSECUTIVE_RECOVERIES {
+        return Err(e).with_context(|| {
+            format!(
+                "deserializing json on line {line}, after {MAX_CONSECUTIVE_RECOVERIES} consecutive corrupt lines"
+            )
+        });
+    }
+    let recovered = recover_page(buffer);
+    let skipped = recovered
+        .as_ref()
+        .map_or(buffer.len(), |(offset, _)| *offset);
+    let region = SkippedRegion {
+        line,
+        start: byte,
+        end: byte + skipped,
+        reason: e.to_string(),
+    };
+    warn!(
+        line,
+        start = region.start,
+        end = region.end,
+        "Skipping corrupt region of dump: {}",
+        region.reason
+    );
+    if let Some(audit) = audit {
+        audit.record(&AuditEntry::SkippedRegion {
+            line,
+            start: region.start,
+            end: region.end,
+            reason: &region.reason,
+        })?;
+    }
+    stats.skipped_regions.push(region);
+    Ok(recovered)
+}
+
+/// The configs to simplify the articles of each project with, protecting the [Options::protect_selectors].
+fn simplify_configs(options: &Options) -> Result<HashMap<Project, SimplifyConfig>, HtmlError> {
+    Project::ALL
+        .iter()
+        .map(|&project| {
+            let mut config = match project {
+                Project::Wikipedia => SimplifyConfig::default(),
+                Project::Wikivoyage => SimplifyConfig::wikivoyage(),
+            };
+            config
+                .protect_selectors
+                .extend(options.protect_selectors.iter().cloned());
+            config.validate()?;
+            Ok((project, config))
+        })
+        .collect()
+}
+
+/// The `requested` titles that match the title or a redirect of `page`.
+///
+/// If none do, those that match by the [Title::loose_key]s in `loose_titles` instead.
+fn matching_titles(
+    page: &Page,
+    requested: &HashSet<Title>,
+    loose_titles: &HashMap<String, Vec<&Title>>,
+) -> Vec<Title> {
+    let mut matching_titles = if requested.is_empty() {
+        Vec::new()
+    } else {
+        page.all_titles()
+            .filter_map(|r| {
+                r.map(Some).unwrap_or_else(|e| {
+                    warn!("Could not parse title: {:#}", e);
+                    None
+                })
+            })
+            .filter(|t| requested.contains(t))
+            .collect()
+    };
+
+    if matching_titles.is_empty() && !loose_titles.is_empty() {
+        for title in page.all_titles().filter_map(Result::ok) {
+            for &requested in loose_titles.get(&title.loose_key()).into_iter().flatten() {
+                if !matching_titles.contains(requested) {
+                    debug!(requested = %requested, matched = %title, "Matched loose title");
+                    matching_titles.push(requested.clone());
+                }
+            }
+        }
+    }
+    matching_titles
+}
+
+/// Why an article with `substance` is below [Options::min_text_chars] or [Options::min_paragraphs], if it is.
+fn insubstantial(options: &Options, substance: &html::Substance) -> Option<String> {
+    let too_short = options
+        .min_text_chars
+        .is_some_and(|min| substance.text_chars < min);
+    let too_few = options
+        .min_paragraphs
+        .is_some_and(|min| substance.paragraphs < min);
+    if !too_short && !too_few {
+        return None;
+    }
+    Some(format!(
+        "insubstantial: {} characters, {} paragraphs{}",
+        substance.text_chars,
+        substance.paragraphs,
+        if substance.only_infobox() {
+            ", only an infobox"
+        } else {
+            ""
+        }
+    ))
+}
+
+/// Write `article` and count it in `stats`, or record why it could not be written.
+fn write_article(
+    writer: &mut impl PageWriter,
+    article: &Article,
+    key: &PageKey,
+    replacing: bool,
+    handled: &mut HandledArticles,
+    stats: &mut DumpStats,
+    audit: &mut Option<AuditLog>,
+) -> anyhow::Result<()> {
+    let page = article.page;
+    let project = page.project();
+    match writer.write(article) {
+        Ok(()) => {
+            let previous = handled.set_written_bytes(key, article.html.len());
+            let rewritten = replacing && previous != 0;
+            if !rewritten {
+                stats.written += 1;
+                if page.title().is_err() {
+                    info!(name = page.name, "Wrote article without a usable title");
+                    stats.untitled += 1;
+                }
+            }
+            let lang_stats = stats.lang(project, &page.in_language.identifier);
+            if rewritten {
+                lang_stats.bytes -= previous;
+            } else {
+                lang_stats.written += 1;
+            }
+            lang_stats.bytes += article.html.len();
+        }
+        Err(e) if e.is::<PathCollision>() => {
+            let collision = e.downcast::<PathCollision>().unwrap();
+            warn!(
+                path = ?collision.path,
+                kept = collision.kept,
+                kept_id = collision.kept_id,
+                skipped_id = collision.skipped_id,
+                "Skipping page with the same path as an already written page"
+            );
+            audit_skip(
+                audit,
+                article.line,
+                page,
+                &format!("path collision with {}", collision.kept),
+            )?;
+            stats.path_collisions.push(collision);
+        }
+        Err(e) => {
+            error!("Error writing article: {:#}", e);
+            audit_skip(audit, article.line, page, &format!("writing: {e:#}"))?;
+            stats.count_error(project, &page.in_language.identifier);
+        }
+    }
+    Ok(())
+}
+
+/// Write the articles held back by `budget` that it selects, and count the rest as [DumpStats::over_budget].
+fn write_held(
+    writer: &mut impl PageWriter,
+    budget: Budget,
+    handled: &mut HandledArticles,
+    stats: &mut DumpStats,
+    audit: &mut Option<AuditLog>,
+) -> anyhow::Result<()> {
+    let selected = budget.select();
+    let (held, mut pages) = budget.into_held().context("reading held articles")?;
+    for (article, selected) in held.into_iter().zip(selected) {
+        let (Some(json), Some(html)) = (pages.next(), pages.next()) else {
+            bail!("held articles ended early");
+        };
+        let (line, byte, json) = json.context("reading held articles")?;
+        if selected == Selected::Replaced {
+            continue;
+        }
+        let page: Page = serde_json::from_slice(&json)
+            .with_context(|| format!("deserializing json on line {line}"))?;
+        if selected == Selected::OverBudget {
+            debug!(
+                title = page.name,
+                line, "Skipping article over the output budget"
+            );
+            stats.over_budget += 1;
+            audit_skip(audit, line, &page, "over budget")?;
+            continue;
+        }
+        let html = String::from_utf8(html.context("reading held articles")?.2)
+            .context("reading held articles")?;
+        let Held {
+            key,
+            replacing,
+            titles,
+            other_langs,
+            matched_by,
+            requesters,
+            ..
+        } = article;
+        let article = Article {
+            page: &page,
+            html: &html,
+            titles: &titles,
+            other_langs: &other_langs,
+            matched_by,
+            requesters,
+            line,
+            byte,
+        };
+        write_article(writer, &article, &key, replacing, handled, stats, audit)?;
+    }
+    Ok(())
+}
+
+/// Write requested articles that were not found to `missing_qids.txt` and `missing_titles.txt` in `dir`.
+///
+/// The files use the formats expected by [crate::parse_wikidata_file] and [crate::parse_wikipedia_file].
+fn write_missing(
+    dir: &Path,
+    qids: &[&Qid],
+    titles: &[&Title],
+    raw_titles: Option<&RawTitles>,
+) -> anyhow::Result<()> {
+    let path = dir.join("missing_qids.txt");
+    info!("Writing {} missing QIDs to {:?}", qids.len(), path);
+    let mut file = BufWriter::new(
+        File::create(&path).with_context(|| format!("creating missing QID file {:?}", path))?,
+    );
+    for qid in qids {
+        writeln!(file, "{}", qid)?;
+    }
+    file.flush()
+        .with_context(|| format!("writing missing QID file {:?}", path))?;
+
+    let path = dir.join("missing_titles.txt");
+    info!("Writing {} missing titles to {:?}", titles.len(), path);
+    let mut file = BufWriter::new(
+        File::create(&path).with_context(|| format!("creating missing title file {:?}", path))?,
+    );
+    for title in titles {
+        writeln!(file, "{}", title)?;
+    }
+    file.flush()
+        .with_context(|| format!("writing missing title file {:?}", path))?;
+
+    if let Some(raw_titles) = raw_titles {
+        let path = dir.join("missing_titles_raw.jsonl");
+        info!("Writing spellings of missing titles to {:?}", path);
+        let mut file = BufWriter::new(
+            File::create(&path)
+                .with_context(|| format!("creating missing title file {:?}", path))?,
+        );
+        let no_raw = BTreeMap::new();
+        for title in titles {
+            let raw = raw_titles.get(title).unwrap_or(&no_raw);
+            let entry = MissingTitle {
+                title: title.to_string(),
+                count: raw.values().sum(),
+                raw,
+            };
+            serde_json::to_writer(&mut file, &entry)?;
+            writeln!(file)?;
+        }
+        file.flush()
+            .with_context(|| format!("writing missing title file {:?}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Line of the `missing_titles_raw.jsonl` report, grouping the spellings of a missing title.
+#[derive(serde::Serialize)]
+struct MissingTitle<'a> {
+    title: String,
+    /// Number of times the title was read, in any spelling.
+    count: usize,
+    /// Number of times each spelling was read.
+    raw: &'a BTreeMap<String, usize>,
+}
+
+/// A matched and processed page to write with a [PageWriter].
+pub struct Article<'a> {
+    pub page: &'a Page,
+    /// Processed html of the page.
+    pub html: &'a str,
+    /// Requested titles that matched the page or its redirects.
+    ///
+    /// Empty for pages only matched by QID.
+    pub titles: &'a [Title],
+    /// Requested titles in other languages of articles with the same QID that were matched by title earlier in the dump.
+    ///
+    /// Empty with [Options::no_expand_titles].
+    pub other_langs: &'a [Title],
+    pub matched_by: MatchedBy,
+    /// The requested QID and titles that selected the article, see [ManifestEntry::requesters].
+    pub requesters: Vec<String>,
+    /// Line of the page in the dump.
+    pub line: usize,
+    /// Byte offset of the page in the dump, starting at 1.
+    pub byte: usize,
+}
+
+/// Hook of [Options::post_processor] to change the html of each article before it is written, e.g. to add markup.
+///
+/// The hook always runs after the html is simplified, unless [Options::no_simplify] is set, and its output is written as is.
+/// Anything it adds is therefore not removed by simplification, and it is responsible for the output being safe to serve.
+///
+/// An error skips the article like an error simplifying it, and is recorded in [DumpStats::post_process_errors].
+/// Closures with the same signature as [PostProcessor::process] implement the trait.
+pub trait PostProcessor: Send + Sync {
+    fn process(&self, title: &Title, html: String) -> anyhow::Result<String>;
+}
+
+impl<F> PostProcessor for F
+where
+    F: Fn(&Title, String) -> anyhow::Result<String> + Send + Sync,
+{
+    fn process(&self, title: &Title, html: String) -> anyhow::Result<String> {
+        self(title, html)
+    }
+}
+
+impl std::fmt::Debug for dyn PostProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PostProcessor")
+    }
+}
+
+/// Hook of [Options::page_filter] to skip matched pages by custom rules, e.g. a minimum number of words.
+///
+/// It only runs on pages that match the requested QIDs and titles, so it may be expensive, e.g. parse the html.
+/// Skipped pages are counted in [DumpStats::filtered], and still count as found for [Options::write_missing].
+/// Closures with the same signature as [PageFilter::accepts] implement the trait.
+pub trait PageFilter: Send + Sync {
+    /// Whether to write `page`.
+    fn accepts(&self, page: &Page) -> bool;
+}
+
+impl<F> PageFilter for F
+where
+    F: Fn(&Page) -> bool + Send + Sync,
+{
+    fn accepts(&self, page: &Page) -> bool {
+        self(page)
+    }
+}
+
+impl std::fmt::Debug for dyn PageFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PageFilter")
+    }
+}
+
+/// Backend of [run_with] that stores the extracted articles.
+///
+/// With [DuplicatePolicy::KeepLatest], the same article can be written again with a newer revision, which should replace the earlier one.
+pub trait PageWriter {
+    fn write(&mut self, article: &Article) -> anyhow::Result<()>;
+
+    /// Called once after the last article of the dump is written.
+    fn finish(self) -> anyhow::Result<()>;
+}
+
+/// The [PageWriter] of [run], writing articles to [Options::output_dir] in its [Layout].
+///
+/// Also writes the manifest and sidecars, if enabled in the [Options].
+pub struct DirectoryWriter {
+    base: PathBuf,
+    layout: Layout,
+    languages: LanguageMap,
+    linker: Linker,
+    link_direction: LinkDirection,
+    compression: Option<HtmlCompression>,
+    manifest: Option<(PathBuf, File)>,
+    altnames: Option<(PathBuf, File)>,
+    max_altnames: usize,
+    descriptions: Option<Descriptions>,
+    infer_text_direction: bool,
+    write_sidecars: bool,
+    write_qid_langs: bool,
+    /// Titles of the articles written by this run and their html files, if writing the link index.
+    link_index: Option<BTreeMap<String, PathBuf>>,
+    /// Pages written by this run, by the device and inode of their html file.
+    ///
+    /// The inode identifies the file however the filesystem folds the names leading to it.
+    written: HashMap<(u64, u64), WrittenPage>,
+}
+
+/// The page an html file was written from, see [PathCollision].
+struct WrittenPage {
+    tag: String,
+    id: Option<u64>,
+}
+
+impl WrittenPage {
+    fn new(page: &Page) -> Self {
+        let tag = match page.project().prefix() {
+            Some(prefix) => format!("{prefix}:{}:{}", page.in_language.identifier, page.name),
+            None => format!("{}:{}", page.in_language.identifier, page.name),
+        };
+        Self {
+            tag,
+            id: page.identifier,
+        }
+    }
+
+    /// Pages are identified by their id, or by their title if either has none.
+    fn is_same(&self, other: &Self) -> bool {
+        match (self.id, other.id) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.tag == other.tag,
+        }
+    }
+}
+
+impl DirectoryWriter {
+    /// Write to the existing directory `base` with the output options of `options`.
+    pub fn new(base: impl AsRef<Path>, options: &Options) -> anyhow::Result<Self> {
+        let base = base.as_ref();
+        if !base.is_dir() {
+            bail!("output dir {:?} does not exist", base);
+        }
+
+        // NOTE: Appended to in the same way as `write_new_qids` in [run].
+        let manifest = if options.write_manifest {
+            let path = base.join(MANIFEST_FILE);
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("opening manifest {:?}", path))?;
+            Some((path, file))
+        } else {
+            None
+        };
+
+        let altnames = if options.write_altnames {
+            let path = base.join(ALTNAMES_FILE);
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("opening altnames file {:?}", path))?;
+            Some((path, file))
+        } else {
+            None
+        };
+
+        if options.write_qid_langs && !options.write_manifest {
+            bail!("writing the languages of each QID requires writing the manifest");
+        }
+
+        let descriptions = options
+            .wikidata_descriptions
+            .as_ref()
+            .map(|path| {
+                info!("Loading wikidata descriptions from {:?}", path);
+                let descriptions = Descriptions::read(path, &options.description_langs)?;
+                info!("Loaded descriptions of {} items", descriptions.len());
+                anyhow::Ok(descriptions)
+            })
+            .transpose()?;
+
+        Ok(Self {
+            base: base.to_owned(),
+            layout: options.layout,
+            languages: options.language_map.clone(),
+            linker: Linker {
+                base: base.to_owned(),
+                relative: options.relative_links,
+                fallback: None,
+            },
+            link_direction: options.link_direction,
+            compression: options.compress_html,
+            manifest,
+            altnames,
+            max_altnames: options.max_altnames.unwrap_or(DEFAULT_MAX_ALTNAMES),
+            descriptions,
+            infer_text_direction: options.infer_text_direction,
+            write_sidecars: options.write_sidecars,
+            write_qid_langs: options.write_qid_langs,
+            link_index: options.write_link_index.then(BTreeMap::new),
+            written: HashMap::new(),
+        })
+    }
+}
+
+impl DirectoryWriter {
+    fn text_direction(&self, page: &Page) -> html::Direction {
+        if self.infer_text_direction {
+            page.text_direction_or_lang()
+        } else {
+            page.text_direction()
+        }
+    }
+}
+
+impl PageWriter for DirectoryWriter {
+    fn write(&mut self, article: &Article) -> anyhow::Result<()> {
+        let page = article.page;
+        let written = WrittenPage::new(page);
+        let main_dir = main_dir(
+            &self.base,
+            self.layout,
+            &self.languages,
+            self.link_direction,
+            page,
+            article.titles.first().cloned(),
+        )?;
+        let file = html_file(main_dir, page, &self.languages, self.compression);
+        let longest = if self.write_sidecars {
+            sidecar_path(&file)
+        } else {
+            file.clone()
+        };
+        PathTooLong::check(&longest, || written.tag.clone())?;
+        let inode = |path: &Path| fs::metadata(path).ok().map(|m| (m.dev(), m.ino()));
+        if let Some(kept) = inode(&file).and_then(|inode| self.written.get(&inode)) {
+            if !kept.is_same(&written) {
+                return Err(PathCollision {
+                    path: file.strip_prefix(&self.base).unwrap_or(&file).to_owned(),
+                    kept: kept.tag.clone(),
+                    kept_id: kept.id,
+                    skipped: written.tag,
+                    skipped_id: written.id,
+                    line: article.line,
+                }
+                .into());
+            }
+        }
+
+        let path = write(
+            &mut self.linker,
+            self.layout,
+            &self.languages,
+            self.link_direction,
+            page,
+            article.titles.iter().cloned(),
+            article.html,
+            self.compression,
+        )?;
+        if let Some(inode) = inode(&path) {
+            self.written.insert(inode, written);
+        }
+
+        let descriptions = page
+            .wikidata()
+            .zip(self.descriptions.as_ref())
+            .and_then(|(qid, descriptions)| descriptions.get(qid))
+            .cloned()
+            .unwrap_or_default();
+
+        let dir = self.text_direction(page);
+
+        if self.write_sidecars {
+            let original = Html::parse_document(&page.article_body.html);
+            let sidecar = Sidecar {
+                schema_version: SIDECAR_SCHEMA_VERSION,
+                lang: page.in_language.identifier.clone(),
+                title: page.name.clone(),
+                qid: page.wikidata(),
+                matched_by: article.matched_by,
+                requesters: article.requesters.clone(),
+                line: article.line,
+                byte: article.byte,
+                revision: page.revision(),
+                written_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs()),
+                descriptions: descriptions.clone(),
+                dir,
+                word_count: html::word_count(&Html::parse_document(article.html)),
+                coordinates: html::detect_coordinates(&original),
+                short_description: html::detect_short_description(&original),
+                categories: html::detect_categories(&original),
+            };
+            write_sidecar(&path, &sidecar)?;
+        }
+
+        if let Some((manifest_path, f)) = &mut self.manifest {
+            let entry = ManifestEntry {
+                path: path.strip_prefix(&self.base).unwrap_or(&path).to_owned(),
+                lang: page.in_language.identifier.clone(),
+                title: page.name.clone(),
+                qid: page.wikidata(),
+                page_id: page.identifier,
+                requesters: article.requesters.clone(),
+                project: page.project(),
+                dir,
+                descriptions,
+            };
+            f.write_all(entry.to_line().as_bytes())
+                .with_context(|| format!("writing to manifest {:?}", manifest_path))?;
+        }
+
+        if let Some(index) = &mut self.link_index {
+            let path = path.strip_prefix(&self.base).unwrap_or(&path);
+            for title in page.all_titles().filter_map(Result::ok) {
+                index.insert(title.to_string(), path.to_owned());
+            }
+        }
+
+        if let Some((altnames_path, f)) = &mut self.altnames {
+            let path = path.strip_prefix(&self.base).unwrap_or(&path);
+            let lines: String = alt_names(article, self.max_altnames)
+                .map(|(name, source)| {
+                    AltName {
+                        path: path.to_owned(),
+                        name,
+                        source,
+                    }
+                    .to_line()
+                })
+                .collect();
+            // NOTE: Appended in a single write like the manifest.
+            f.write_all(lines.as_bytes())
+                .with_context(|| format!("writing to altnames file {:?}", altnames_path))?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        if self.write_qid_langs {
+            write_qid_langs(&self.base)?;
+        }
+        if let Some(index) = self.link_index {
+            write_link_index(&self.base, index)?;
+        }
+        Ok(())
+    }
+}
+
+/// A [PageWriter] that prints the path each article would be written to in an output directory, one on each line, without writing anything.
+///
+/// The paths are those of the html files in the [Layout] of the options, e.g. the [Title::get_dir] of an article without a QID.
+/// The links to them, and the sidecars and other files of the output directory, are not listed.
+/// Paths longer than [MAX_PATH_BYTES] fail like they would when writing the article.
+pub struct PathPrinter<W> {
+    base: PathBuf,
+    layout: Layout,
+    languages: LanguageMap,
+    link_direction: LinkDirection,
+    compression: Option<HtmlCompression>,
+    out: W,
+}
+
+impl<W: Write> PathPrinter<W> {
+    /// Print paths within `base` to `out`, which doesn't have to exist yet.
+    pub fn new(base: impl AsRef<Path>, options: &Options, out: W) -> Self {
+        Self {
+            base: base.as_ref().to_owned(),
+            layout: options.layout,
+            languages: options.language_map.clone(),
+            link_direction: options.link_direction,
+            compression: options.compress_html,
+            out,
+        }
+    }
+}
+
+impl<W: Write> PageWriter for PathPrinter<W> {
+    fn write(&mut self, article: &Article) -> anyhow::Result<()> {
+        let page = article.page;
+        let main_dir = main_dir(
+            &self.base,
+            self.layout,
+            &self.languages,
+            self.link_direction,
+            page,
+            article.titles.first().cloned(),
+        )?;
+        let file = html_file(main_dir, page, &self.languages, self.compression);
+        PathTooLong::check(&file, || WrittenPage::new(page).tag)?;
+        writeln!(self.out, "{}", file.display()).context("printing path")
+    }
+
+    fn finish(mut self) -> anyhow::Result<()> {
+        self.out.flush().context("printing paths")
+    }
+}
+
+/// The distinct names of the article other than its title, redirects first, up to `max`.
+fn alt_names<'a>(
+    article: &'a Article,
+    max: usize,
+) -> impl Iterator<Item = (String, AltNameSource)> + 'a {
+    let page = article.page;
+    let redirects = page
+        .redirects
+        .iter()
+        .map(|r| (r.name.replace('_', " "), AltNameSource::Redirect));
+    let langlinks = article.other_langs.iter().map(|title| {
+        (
+            title.original_name().replace('_', " "),
+            AltNameSource::Langlink,
+        )
+    });
+    let mut seen = HashSet::from([page.name.replace('_', " ")]);
+    redirects
+        .chain(langlinks)
+        .filter(move |(name, _)| !name.is_empty() && seen.insert(name.clone()))
+        .take(max)
+}
+
+/// Creates the links between article directories in `base`.
+///
+/// Where the filesystem doesn't support symlinks, the links are appended to [LINKS_FILE] instead.
+struct Linker {
+    base: PathBuf,
+    relative: bool,
+    fallback: Option<File>,
+}
+
+impl Linker {
+    /// Link `link` to `target`, replacing anything else at `link`.
+    ///
+    /// Both must be within `base`.
+    fn link(&mut self, link: &Path, target: &Path) -> anyhow::Result<()> {
+        let stored = if self.relative {
+            relative_target(&self.base, link, target)
+        } else {
+            target.to_owned()
+        };
+
+        // Possible states from previous run:
+        // - Does not exist (and is not a symlink)
+        // - Exists, is a directory or file
+        // - Is a symlink to the correct location
+        // - Is a symlink to an incorrect location, or with a differently formatted target
+        if link.is_symlink() {
+            if fs::read_link(link)? == stored {
+                return Ok(());
+            }
+            fs::remove_file(link).with_context(|| format!("removing old link {:?}", link))?;
+        } else if link.is_dir() {
+            fs::remove_dir_all(link)
+                .with_context(|| format!("removing old directory {:?}", link))?;
+        } else if link.exists() {
+            fs::remove_file(link).with_context(|| format!("removing old file {:?}", link))?;
+        } else {
+            // titles can contain `/`, so ensure necessary subdirs exist
+            let parent_dir = link.parent().unwrap();
+            fs::create_dir_all(parent_dir)
+                .with_context(|| format!("creating link directory {:?}", parent_dir))?;
+        }
+
+        match unix::fs::symlink(&stored, link) {
+            Ok(()) => Ok(()),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+                ) =>
+            {
+                self.record(link, target, e)
+            }
+            Err(e) => {
+                Err(e).with_context(|| format!("creating symlink from {:?} to {:?}", link, stored))
+            }
+        }
+    }
+
+    /// Append the link to [LINKS_FILE] after creating it failed with `error`.
+    fn record(&mut self, link: &Path, target: &Path, error: io::Error) -> anyhow::Result<()> {
+        let path = self.base.join(LINKS_FILE);
+        let file = match &mut self.fallback {
+            Some(file) => file,
+            None => {
+                warn!(
+                    "Unable to create symlinks in the output directory ({}), writing them to {:?}",
+                    error, path
+                );
+                // NOTE: Appended to in the same way as the manifest.
+                let file = File::options()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("opening links file {:?}", path))?;
+                self.fallback.insert(file)
+            }
+        };
+        let rel = |p: &Path| p.strip_prefix(&self.base).unwrap_or(p).to_owned();
+        let line = LinkEntry {
+            link: rel(link),
+            target: rel(target),
+        }
+        .to_line();
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("writing to links file {:?}", path))
+    }
+}
+
+/// Determine the directory to write the article contents to, create it, and create any necessary symlinks to it.
+fn create_article_dir(
+    linker: &mut Linker,
+    layout: Layout,
+    languages: &LanguageMap,
+    direction: LinkDirection,
+    page: &Page,
+    redirects: impl IntoIterator<Item = Title>,
+    compression: Option<HtmlCompression>,
+) -> anyhow::Result<PathBuf> {
+    let base = linker.base.clone();
+    let mut redirects = redirects.into_iter().peekable();
+    let main_dir = main_dir(
+        &base,
+        layout,
+        languages,
+        direction,
+        page,
+        redirects.peek().cloned(),
+    )?;
+    // Without a QID directory, the first title is the main directory itself.
+    if main_dir_qid(page, direction).is_none() {
+        redirects.next();
+    }
+
+    if main_dir.is_symlink() {
+        fs::remove_file(&main_dir)
+            .with_context(|| format!("removing old link for main directory {:?}", &main_dir))?;
+    }
+    fs::create_dir_all(&main_dir)
+        .with_context(|| format!("creating main directory {:?}", &main_dir))?;
+
+    // Write symlinks to main directory.
+    for title in redirects {
+        let wikipedia_dir = languages.title_dir(layout, base.clone(), &title);
+        // The file would not be readable through the link.
+        let through_link = html_file(wikipedia_dir.clone(), page, languages, compression);
+        if let Err(e) = PathTooLong::check(&through_link, || title.to_string()) {
+            warn!("Skipping link: {e}");
+            continue;
+        }
+        linker.link(&wikipedia_dir, &main_dir)?;
+    }
+
+    Ok(main_dir)
+}
+
+/// The directory in `base` the html file of `page` is written to, where `first` is the first of the titles it was matched by.
+fn main_dir(
+    base: &Path,
+    layout: Layout,
+    languages: &LanguageMap,
+    direction: LinkDirection,
+    page: &Page,
+    first: Option<Title>,
+) -> anyhow::Result<PathBuf> {
+    let dir = match main_dir_qid(page, direction) {
+        None => {
+            // Write to wikipedia title directory.
+            // Prefer first redirect, fall back to page title if none exist
+            if page.wikidata().is_none() {
+                info!("Page without wikidata qid");
+            }
+            first
+                .or_else(|| match page.title() {
+                    Ok(title) => Some(title),
+                    Err(e) => {
+                        warn!("Unable to parse title: {:#}", e);
+                        None
+                    }
+                })
+                // hard fail when no titles can be parsed
+                .ok_or_else(|| anyhow!("No available titles for page {:?}", page.name))
+                .map(|title| languages.title_dir(layout, base.to_owned(), &title))?
+        }
+        Some(qid) => {
+            // Otherwise use wikidata as main directory and symlink from wikipedia titles.
+            layout.qid_dir(base.to_owned(), &qid)
+        }
+    };
+    Ok(dir)
+}
+
+/// The QID of the [main_dir] of `page`, if it is a QID directory.
+fn main_dir_qid(page: &Page, direction: LinkDirection) -> Option<Qid> {
+    match direction {
+        LinkDirection::TitleToQid => qid_dir_qid(page),
+        // The QID directory is linked to the file once it is written.
+        // Pages without a usable title have no directory of their own to link to.
+        LinkDirection::QidToTitle => qid_dir_qid(page).filter(|_| page.title().is_err()),
+    }
+}
+
+/// Path of the html file of `page` in its [main_dir].
+fn html_file(
+    main_dir: PathBuf,
+    page: &Page,
+    languages: &LanguageMap,
+    compression: Option<HtmlCompression>,
+) -> PathBuf {
+    let mut filename = main_dir;
+    filename.push(languages.lang(&page.in_language.identifier));
+    filename.set_extension(compression.map_or("html", |c| c.extension()));
+    filename
+}
+
+/// The QID of the page's directory in the QID layout, if it has one.
+///
+/// Only Wikipedia articles are written to QID directories, so the articles of other projects with the same QID are kept apart in their title directories.
+fn qid_dir_qid(page: &Page) -> Option<Qid> {
+    page.wikidata().filter(|_| page.project().is_wikipedia())
+}
+
+fn write_sidecar(html: &Path, sidecar: &Sidecar) -> anyhow::Result<()> {
+    let path = sidecar_path(html);
+    let json = serde_json::to_vec_pretty(sidecar)?;
+    fs::write(&path, json).with_context(|| format!("writing sidecar {:?}", path))
+}
+
+/// Write selected article to disk, returning the path of the html file.
+///
+/// - Write page contents to wikidata page (`wikidata.org/wiki/QXXX/lang.html`).
+/// - If the page has no wikidata qid or is not a Wikipedia article, write contents to its title location (`lang.wikipedia.org/wiki/article_title/lang.html` or `lang.wikivoyage.org/wiki/article_title/lang.html`).
+/// - Create links from all wikipedia urls and redirects (`lang.wikipedia.org/wiki/a_redirect -> wikidata.org/wiki/QXXX`).
+#[allow(clippy::too_many_arguments)]
+fn write(
+    linker: &mut Linker,
+    layout: Layout,
+    languages: &LanguageMap,
+    direction: LinkDirection,
+    page: &Page,
+    redirects: impl IntoIterator<Item = Title>,
+    html: &str,
+    compression: Option<HtmlCompression>,
+) -> anyhow::Result<PathBuf> {
+    let article_dir = create_article_dir(
+        linker,
+        layout,
+        languages,
+        direction,
+        page,
+        redirects,
+        compression,
+    )?;
+
+    // Write html to determined file.
+    let filename = html_file(article_dir, page, languages, compression);
+
+    debug!(
+        file = filename.to_string_lossy().as_ref(),
+        exists = filename.exists(),
+        "Writing article"
+    );
+
+    // Don't write through a link from a run with the other link direction.
+    if filename.is_symlink() {
+        fs::remove_file(&filename).with_context(|| format!("removing old link {:?}", filename))?;
+    }
+    let mut file =
+        File::create(&filename).with_context(|| format!("creating html file {:?}", filename))?;
+    match compression {
+        None => file.write_all(html.as_bytes()),
+        Some(HtmlCompression::Gzip) => {
+            let mut encoder = GzEncoder::new(file, flate2::Compression::default());
+            encoder
+                .write_all(html.as_bytes())
+                .and_then(|_| encoder.finish().map(drop))
+        }
+    }
+    .with_context(|| format!("writing html file {:?}", filename))?;
+
+    let linked_qid = qid_dir_qid(page).filter(|_| main_dir_qid(page, direction).is_none());
+    if let (LinkDirection::QidToTitle, Some(qid)) = (direction, linked_qid) {
+        let qid_dir = layout.qid_dir(linker.base.clone(), &qid);
+        if qid_dir.is_symlink() {
+            fs::remove_file(&qid_dir)
+                .with_context(|| format!("removing old link {:?}", qid_dir))?;
+        }
+        let link = qid_dir.join(filename.file_name().unwrap());
+        linker.link(&link, &filename)?;
+    }
+
+    Ok(filename)
+}
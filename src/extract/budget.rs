@@ -0,0 +1,237 @@
+//! The cap of [Options::max_total_bytes](super::Options::max_total_bytes) on the html written by a run.
+use std::{collections::HashSet, io};
+
+use tracing::info;
+
+use super::{dedup::PageKey, BudgetOrder};
+use crate::{
+    spill::{PageBuffer, Pages},
+    wm::{output::MatchedBy, Qid, Title},
+};
+
+/// Bytes of html written against the budget, and the articles held back for [BudgetOrder::Qid].
+pub(super) struct Budget {
+    max: Option<usize>,
+    order: BudgetOrder,
+    written: usize,
+    exhausted: bool,
+    held: Vec<Held>,
+    /// The JSON of the page and the html of each held article in turn, in a temporary file from the first one held.
+    pages: PageBuffer,
+}
+
+/// A processed article held back until the whole dump is read, without its page and html.
+pub(super) struct Held {
+    pub qid: Option<Qid>,
+    pub key: PageKey,
+    pub replacing: bool,
+    /// Length of the html.
+    pub bytes: usize,
+    pub titles: Vec<Title>,
+    pub other_langs: Vec<Title>,
+    pub matched_by: MatchedBy,
+    pub requesters: Vec<String>,
+}
+
+/// What to do with a held article, from [Budget::select].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Selected {
+    Write,
+    OverBudget,
+    /// A later page of the same article was held back in its place.
+    Replaced,
+}
+
+impl Budget {
+    /// A budget of `max` bytes, or no limit if `None`.
+    pub fn new(max: Option<usize>, order: BudgetOrder) -> Self {
+        Self {
+            max,
+            order,
+            written: 0,
+            exhausted: false,
+            held: Vec::new(),
+            pages: PageBuffer::default(),
+        }
+    }
+
+    /// Whether articles are held back with [Budget::hold] to be written in the budget's order, instead of as they are read.
+    pub fn holds_back(&self) -> bool {
+        self.max.is_some() && self.order != BudgetOrder::Dump
+    }
+
+    /// Whether an article read in dump order didn't fit, so no further articles are written.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Check that `bytes` of html fit in the budget in place of the `freed` bytes already written for the same article.
+    ///
+    /// Once an article doesn't fit, none of the following ones do.
+    pub fn fits(&mut self, bytes: usize, freed: usize) -> bool {
+        let Some(max) = self.max else {
+            return true;
+        };
+        if !self.exhausted && self.written - freed + bytes <= max {
+            return true;
+        }
+        if !self.exhausted {
+            info!(
+                written_bytes = self.written,
+                max, "Reached the output budget, skipping the remaining articles"
+            );
+            self.exhausted = true;
+        }
+        false
+    }
+
+    /// Count `bytes` of html as written in place of the `freed` bytes written for the same article before.
+    pub fn wrote(&mut self, bytes: usize, freed: usize) {
+        self.written = self.written - freed + bytes;
+    }
+
+    /// Hold back an article with the JSON of its `page` and its `html`, to write once the whole dump is read.
+    ///
+    /// The page and html are written to a temporary file instead of kept in memory, see [PageBuffer::spill].
+    pub fn hold(
+        &mut self,
+        line: usize,
+        byte: usize,
+        page: &[u8],
+        html: &str,
+        article: Held,
+    ) -> io::Result<()> {
+        self.pages.spill()?;
+        self.pages.push(line, byte, page)?;
+        self.pages.push(line, byte, html.as_bytes())?;
+        self.held.push(article);
+        Ok(())
+    }
+
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+
+    /// What to do with each held article, in the order they were held.
+    ///
+    /// Only the last article held for each [PageKey] is used, as it replaced the earlier ones with a newer revision.
+    /// The others are written from the lowest QID up, with articles without a QID last, until the next one doesn't fit.
+    pub fn select(&self) -> Vec<Selected> {
+        let mut selected = vec![Selected::OverBudget; self.held.len()];
+        let mut seen = HashSet::new();
+        for (i, article) in self.held.iter().enumerate().rev() {
+            if !seen.insert(&article.key) {
+                selected[i] = Selected::Replaced;
+            }
+        }
+        let mut order: Vec<usize> = (0..self.held.len())
+            .filter(|&i| selected[i] != Selected::Replaced)
+            .collect();
+        order.sort_by_key(|&i| (self.held[i].qid.is_none(), self.held[i].qid, i));
+
+        let max = self.max.unwrap_or(usize::MAX);
+        let mut total = 0;
+        for i in order {
+            if total + self.held[i].bytes > max {
+                info!(
+                    written_bytes = total,
+                    max, "Reached the output budget, skipping the remaining articles"
+                );
+                break;
+            }
+            total += self.held[i].bytes;
+            selected[i] = Selected::Write;
+        }
+        selected
+    }
+
+    /// The held articles, and an iterator over the JSON of the page and the html of each in turn.
+    pub fn into_held(self) -> io::Result<(Vec<Held>, Pages)> {
+        Ok((self.held, self.pages.into_pages()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn held(qid: Option<&str>, key: &str, bytes: usize) -> Held {
+        Held {
+            qid: qid.map(|q| Qid::from_str(q).unwrap()),
+            key: PageKey::Title(Title::from_osm_tag(key).unwrap()),
+            replacing: false,
+            bytes,
+            titles: Vec::new(),
+            other_langs: Vec::new(),
+            matched_by: MatchedBy::Qid,
+            requesters: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dump_order() {
+        let mut budget = Budget::new(Some(100), BudgetOrder::Dump);
+        assert!(!budget.holds_back());
+        assert!(budget.fits(60, 0));
+        budget.wrote(60, 0);
+        // A replacement only counts the difference.
+        assert!(budget.fits(90, 60));
+        budget.wrote(90, 60);
+        assert!(!budget.is_exhausted());
+
+        assert!(!budget.fits(20, 0));
+        assert!(budget.is_exhausted());
+        // Nothing fits after the first article that doesn't.
+        assert!(!budget.fits(1, 0));
+
+        let mut unlimited = Budget::new(None, BudgetOrder::Qid);
+        assert!(!unlimited.holds_back());
+        assert!(unlimited.fits(usize::MAX, 0));
+    }
+
+    #[test]
+    fn qid_order() {
+        let mut budget = Budget::new(Some(100), BudgetOrder::Qid);
+        assert!(budget.holds_back());
+        for (qid, key, bytes) in [
+            (Some("Q64"), "en:Berlin", 40),
+            (None, "en:Untitled", 10),
+            (Some("Q1"), "en:Universe", 50),
+            (Some("Q365"), "de:Köln", 30),
+            // A newer revision replaces the first page of the same article.
+            (Some("Q64"), "en:Berlin", 45),
+        ] {
+            budget
+                .hold(1, 1, b"{}", "<p></p>", held(qid, key, bytes))
+                .unwrap();
+        }
+        assert_eq!(5, budget.held());
+        assert!(budget.pages.is_spilled());
+        assert_eq!(0, budget.pages.memory_bytes());
+        assert_eq!(
+            vec![
+                Selected::Replaced,
+                Selected::OverBudget,
+                Selected::Write,
+                Selected::OverBudget,
+                Selected::Write,
+            ],
+            budget.select()
+        );
+
+        let (held, pages) = budget.into_held().unwrap();
+        assert_eq!(5, held.len());
+        assert_eq!(10, pages.count());
+    }
+
+    #[test]
+    fn articles_without_qid_last() {
+        let mut budget = Budget::new(Some(60), BudgetOrder::Qid);
+        for (qid, key) in [(None, "en:Untitled"), (Some("Q42"), "en:Douglas Adams")] {
+            budget.hold(1, 1, b"{}", "", held(qid, key, 50)).unwrap();
+        }
+        assert_eq!(vec![Selected::OverBudget, Selected::Write], budget.select());
+    }
+}
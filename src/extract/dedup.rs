@@ -0,0 +1,138 @@
+//! Handling each article once, when it is matched by more than one page of the dump.
+use std::collections::HashMap;
+
+use super::DuplicatePolicy;
+use crate::wm::{Project, Qid, Title};
+
+/// Identity of an article in a dump.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) enum PageKey {
+    /// QID, project, and language of the page.
+    Qid(Qid, Project, String),
+    /// Main title or first matching title of pages without a QID.
+    Title(Title),
+}
+
+/// State of an already handled article.
+struct Handled {
+    /// Revision of the page the article was taken from.
+    revision: Option<u64>,
+    /// Size of the written html, if any.
+    bytes: usize,
+}
+
+/// How a matched page relates to the articles handled before it, from [HandledArticles::see].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Occurrence {
+    /// The first page of the article.
+    First,
+    /// A newer revision of a handled article, which replaces it with [DuplicatePolicy::KeepLatest].
+    Newer { previous: Option<u64> },
+    /// Another page of a handled article, which is not used.
+    Duplicate,
+}
+
+/// The articles handled so far in a dump.
+#[derive(Default)]
+pub(super) struct HandledArticles(HashMap<PageKey, Handled>);
+
+impl HandledArticles {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Record a page of the article `key` at `revision`, and whether it is used under `policy`.
+    ///
+    /// Pages without a revision are never newer.
+    pub fn see(
+        &mut self,
+        key: &PageKey,
+        revision: Option<u64>,
+        policy: DuplicatePolicy,
+    ) -> Occurrence {
+        let Some(prev) = self.0.get_mut(key) else {
+            self.0.insert(key.clone(), Handled { revision, bytes: 0 });
+            return Occurrence::First;
+        };
+        if policy == DuplicatePolicy::KeepLatest && revision > prev.revision {
+            let previous = prev.revision;
+            prev.revision = revision;
+            return Occurrence::Newer { previous };
+        }
+        Occurrence::Duplicate
+    }
+
+    /// Bytes of html written for the article `key`, or 0 if none were.
+    pub fn written_bytes(&self, key: &PageKey) -> usize {
+        self.0.get(key).map_or(0, |handled| handled.bytes)
+    }
+
+    /// Record that `bytes` of html were written for the handled article `key`, returning the bytes written for it before.
+    pub fn set_written_bytes(&mut self, key: &PageKey, bytes: usize) -> usize {
+        let handled = self
+            .0
+            .get_mut(key)
+            .expect("articles are handled before they are written");
+        std::mem::replace(&mut handled.bytes, bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(tag: &str) -> PageKey {
+        PageKey::Title(Title::from_osm_tag(tag).unwrap())
+    }
+
+    #[test]
+    fn first_and_duplicates() {
+        let mut handled = HandledArticles::default();
+        let berlin = key("en:Berlin");
+        assert_eq!(
+            Occurrence::First,
+            handled.see(&berlin, Some(1), DuplicatePolicy::KeepFirst)
+        );
+        assert_eq!(
+            Occurrence::Duplicate,
+            handled.see(&berlin, Some(2), DuplicatePolicy::KeepFirst)
+        );
+        assert_eq!(
+            Occurrence::First,
+            handled.see(&key("de:Berlin"), None, DuplicatePolicy::KeepFirst)
+        );
+        assert_eq!(2, handled.len());
+    }
+
+    #[test]
+    fn newer_revisions() {
+        let mut handled = HandledArticles::default();
+        let berlin = key("en:Berlin");
+        let see = |handled: &mut HandledArticles, revision| {
+            handled.see(&berlin, revision, DuplicatePolicy::KeepLatest)
+        };
+        assert_eq!(Occurrence::First, see(&mut handled, None));
+        assert_eq!(
+            Occurrence::Newer { previous: None },
+            see(&mut handled, Some(5))
+        );
+        assert_eq!(Occurrence::Duplicate, see(&mut handled, Some(5)));
+        assert_eq!(Occurrence::Duplicate, see(&mut handled, Some(3)));
+        assert_eq!(Occurrence::Duplicate, see(&mut handled, None));
+        assert_eq!(
+            Occurrence::Newer { previous: Some(5) },
+            see(&mut handled, Some(8))
+        );
+    }
+
+    #[test]
+    fn written_bytes() {
+        let mut handled = HandledArticles::default();
+        let berlin = key("en:Berlin");
+        assert_eq!(0, handled.written_bytes(&berlin));
+        handled.see(&berlin, None, DuplicatePolicy::KeepLatest);
+        assert_eq!(0, handled.set_written_bytes(&berlin, 100));
+        assert_eq!(100, handled.set_written_bytes(&berlin, 80));
+        assert_eq!(80, handled.written_bytes(&berlin));
+    }
+}
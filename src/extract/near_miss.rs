@@ -0,0 +1,153 @@
+//! Matching requested titles to pages with the same [Title::skeleton], after the exact matches of the whole dump are known.
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+use tracing::info;
+
+use crate::{
+    spill::{PageBuffer, Pages},
+    wm::Title,
+};
+
+/// Requested titles by their [Title::skeleton].
+#[derive(Default)]
+pub(super) struct Skeletons<'a>(HashMap<String, Vec<&'a Title>>);
+
+impl<'a> Skeletons<'a> {
+    pub fn new(requested: impl IntoIterator<Item = &'a Title>) -> Self {
+        let mut skeletons: HashMap<String, Vec<&Title>> = HashMap::new();
+        for title in requested {
+            skeletons.entry(title.skeleton()).or_default().push(title);
+        }
+        Self(skeletons)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The requested titles with the same skeleton as any of a page's `titles`, except those already in `found`.
+    pub fn near_misses(
+        &self,
+        titles: impl IntoIterator<Item = Title>,
+        found: &HashSet<Title>,
+    ) -> Vec<Title> {
+        let mut near_misses = Vec::new();
+        for title in titles {
+            for &requested in self.0.get(&title.skeleton()).into_iter().flatten() {
+                if !found.contains(requested) && !near_misses.contains(requested) {
+                    near_misses.push(requested.clone());
+                }
+            }
+        }
+        near_misses
+    }
+}
+
+/// Pages that only matched a near-miss, deferred while the dump is read and then replayed in the same order.
+///
+/// Replaying them after the exact matches means a near-miss never replaces an exact match of the same title.
+#[derive(Default)]
+pub(super) struct DeferredNearMisses {
+    pages: PageBuffer,
+    replaying: Option<Pages>,
+}
+
+impl DeferredNearMisses {
+    /// Whether the end of the dump was reached, and the deferred pages are being replayed.
+    pub fn is_replaying(&self) -> bool {
+        self.replaying.is_some()
+    }
+
+    /// Defer the JSON of a page at `line` and `byte` of the dump.
+    pub fn defer(&mut self, line: usize, byte: usize, page: &[u8]) -> io::Result<()> {
+        debug_assert!(!self.is_replaying());
+        self.pages.push(line, byte, page)
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.pages.memory_bytes()
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        self.pages.is_spilled()
+    }
+
+    /// Move the deferred pages to a temporary file, see [PageBuffer::spill].
+    pub fn spill(&mut self) -> io::Result<()> {
+        self.pages.spill()
+    }
+
+    /// Start replaying the deferred pages, once the whole dump is read.
+    pub fn replay(&mut self) -> io::Result<()> {
+        if !self.pages.is_empty() {
+            info!(
+                pages = self.pages.len(),
+                "Processing near-miss title matches"
+            );
+        }
+        self.replaying = Some(std::mem::take(&mut self.pages).into_pages()?);
+        Ok(())
+    }
+
+    /// The line, byte offset, and JSON of the next deferred page while replaying, or `None` after the last one.
+    pub fn next_page(&mut self) -> Option<io::Result<(usize, usize, Vec<u8>)>> {
+        self.replaying.as_mut()?.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn near_misses() {
+        let requested = ["en:King’s Cross", "en:King's Cross", "en:Berlin"]
+            .map(|tag| Title::from_osm_tag(tag).unwrap());
+        let skeletons = Skeletons::new(&requested);
+        assert_eq!(2, skeletons.len());
+
+        let page =
+            || ["en:King's  Cross", "en:Kings Cross"].map(|tag| Title::from_osm_tag(tag).unwrap());
+        assert_eq!(
+            requested[..2].to_vec(),
+            skeletons.near_misses(page(), &HashSet::new())
+        );
+        // Titles that were already found are not near misses.
+        let found = HashSet::from([requested[0].clone()]);
+        assert_eq!(
+            requested[1..2].to_vec(),
+            skeletons.near_misses(page(), &found)
+        );
+        assert!(Skeletons::new([])
+            .near_misses(page(), &HashSet::new())
+            .is_empty());
+    }
+
+    #[test]
+    fn replay_in_order() {
+        let mut deferred = DeferredNearMisses::default();
+        assert!(!deferred.is_replaying());
+        assert!(deferred.next_page().is_none());
+        deferred.defer(2, 10, b"first").unwrap();
+        deferred.spill().unwrap();
+        deferred.defer(5, 40, b"second").unwrap();
+
+        deferred.replay().unwrap();
+        assert!(deferred.is_replaying());
+        let pages: Vec<_> = std::iter::from_fn(|| deferred.next_page())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            vec![(2, 10, b"first".to_vec()), (5, 40, b"second".to_vec())],
+            pages
+        );
+        assert!(deferred.is_replaying());
+    }
+}
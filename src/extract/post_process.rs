@@ -0,0 +1,63 @@
+//! Running the [Options::post_processor](super::Options::post_processor) hook on the html of each article.
+use std::borrow::Cow;
+
+use super::PostProcessor;
+use crate::wm::Page;
+
+/// The `html` of `page` after the `hook`, or as it is without one.
+///
+/// Pages without a usable title fail, as the hook is passed the title.
+pub(super) fn post_process<'a>(
+    hook: Option<&dyn PostProcessor>,
+    page: &Page,
+    html: Cow<'a, str>,
+) -> anyhow::Result<Cow<'a, str>> {
+    let Some(hook) = hook else {
+        return Ok(html);
+    };
+    let title = page.title()?;
+    hook.process(&title, html.into_owned()).map(Cow::Owned)
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+    use crate::wm::Title;
+
+    fn page(name: &str) -> Page {
+        serde_json::from_str(&format!(
+            r#"{{"name": {name:?}, "date_modified": "", "in_language": {{"identifier": "en"}}, "article_body": {{"html": ""}}}}"#
+        ))
+        .unwrap()
+    }
+
+    fn append_title(title: &Title, html: String) -> anyhow::Result<String> {
+        if title.name() == "Broken" {
+            bail!("cannot process {title}");
+        }
+        Ok(format!("{html}<p>{title}</p>"))
+    }
+
+    #[test]
+    fn without_hook() {
+        let html = post_process(None, &page(""), Cow::Borrowed("<p></p>")).unwrap();
+        assert!(matches!(html, Cow::Borrowed("<p></p>")));
+    }
+
+    #[test]
+    fn with_hook() {
+        let hook: Option<&dyn PostProcessor> = Some(&append_title);
+        assert_eq!(
+            "<p></p><p>en:Berlin</p>",
+            post_process(hook, &page("Berlin"), Cow::Borrowed("<p></p>")).unwrap()
+        );
+
+        let e = post_process(hook, &page("Broken"), Cow::Borrowed("")).unwrap_err();
+        assert_eq!("cannot process en:Broken", e.to_string());
+        // The hook is not run on pages without a title.
+        let e = post_process(hook, &page(""), Cow::Borrowed("")).unwrap_err();
+        assert_eq!("bad title \"\"", e.to_string());
+    }
+}
@@ -0,0 +1,320 @@
+//! Suggested corrections of `wikidata` and `wikipedia` tags in an OSM tag file.
+//!
+//! Only unambiguous fixes are suggested: the object must be identified by its `@id` and `@otype`/`@oname` columns,
+//! and the corrected value must parse where the original value was confusingly formatted.
+use std::{fmt::Display, io::Read, str::FromStr};
+
+use anyhow::anyhow;
+use url::Url;
+
+use crate::{
+    osm,
+    wm::{has_percent_escape, Qid, Title},
+};
+
+/// A class of fixable tag values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum FixRule {
+    /// A lowercase `q` prefix in a `wikidata` tag, e.g. `q42`.
+    LowercaseQid,
+    /// A wikidata.org url in a `wikipedia` tag, e.g. `https://www.wikidata.org/wiki/Q42`.
+    WikidataUrl,
+    /// A percent-encoded title in a `wikipedia` tag, e.g. `de:K%C3%B6ln`.
+    EncodedTitle,
+    /// A language code that redirects to another wikipedia in a `wikipedia` tag, e.g. `nb:Oslo`.
+    LangAlias,
+}
+
+impl FixRule {
+    pub const ALL: &'static [FixRule] = &[
+        FixRule::LowercaseQid,
+        FixRule::WikidataUrl,
+        FixRule::EncodedTitle,
+        FixRule::LangAlias,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FixRule::LowercaseQid => "lowercase-qid",
+            FixRule::WikidataUrl => "wikidata-url",
+            FixRule::EncodedTitle => "encoded-title",
+            FixRule::LangAlias => "lang-alias",
+        }
+    }
+}
+
+impl Display for FixRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Language codes of wikipedias that redirect to another, and the code they redirect to.
+pub const LANG_ALIASES: &[(&str, &str)] = &[
+    ("be-x-old", "be-tarask"),
+    ("cz", "cs"),
+    ("dk", "da"),
+    ("jp", "ja"),
+    ("lzh", "zh-classical"),
+    ("nan", "zh-min-nan"),
+    ("nb", "no"),
+    ("yue", "zh-yue"),
+];
+
+/// A suggested change of a single tag of an OSM object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// Line of the tag file the object is on.
+    pub line: u64,
+    pub osm_type: osm::Kind,
+    pub osm_id: osm::Id,
+    pub key: &'static str,
+    pub old_value: String,
+    /// The corrected value, or empty if the tag should be removed.
+    pub suggested_value: String,
+    pub rule: FixRule,
+}
+
+impl Fix {
+    /// The object in the `type/id` format of OSM urls, e.g. `node/1234`.
+    pub fn object(&self) -> String {
+        format!("{}/{}", self.osm_type.oname(), self.osm_id)
+    }
+}
+
+/// Suggest fixes of the values in the TSV tag file `r`, using the `rules` that are enabled.
+///
+/// ```
+/// use om_wikiparser::fixes::{suggest_fixes, FixRule};
+///
+/// let tags = "@id\t@otype\twikidata\twikipedia\n1\t0\tq42\ten:Douglas Adams\n";
+/// let fixes = suggest_fixes(tags.as_bytes(), FixRule::ALL).unwrap();
+/// assert_eq!(1, fixes.len());
+/// assert_eq!("node/1", fixes[0].object());
+/// assert_eq!(("q42", "Q42"), (fixes[0].old_value.as_str(), fixes[0].suggested_value.as_str()));
+/// ```
+pub fn suggest_fixes(r: impl Read, rules: &[FixRule]) -> anyhow::Result<Vec<Fix>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .flexible(true)
+        .from_reader(r);
+
+    let headers = rdr.headers()?.clone();
+    let column = |name| headers.iter().position(|h| h == name);
+    let found = || headers.iter().collect::<Vec<_>>();
+    let qid_col = column("wikidata")
+        .ok_or_else(|| anyhow!("Cannot find 'wikidata' column in {:?}", found()))?;
+    let title_col = column("wikipedia")
+        .ok_or_else(|| anyhow!("Cannot find 'wikipedia' column in {:?}", found()))?;
+    let (id_col, otype_col, oname_col) = (column("@id"), column("@otype"), column("@oname"));
+
+    let enabled = |rule| rules.contains(&rule);
+
+    let mut fixes = Vec::new();
+    let mut row = csv::StringRecord::new();
+    loop {
+        match rdr.read_record(&mut row) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            // Malformed lines are reported by `check-tags`.
+            Err(_) => continue,
+        }
+        let line = row.position().map_or(rdr.position().line(), |p| p.line());
+
+        let osm_id = id_col.and_then(|i| row.get(i)?.trim().parse::<osm::Id>().ok());
+        let osm_type = otype_col
+            .and_then(|i| row.get(i)?.trim().parse().ok())
+            .and_then(osm::Kind::from_otype)
+            .or_else(|| oname_col.and_then(|i| osm::Kind::from_oname(row.get(i)?)));
+        let (Some(osm_id), Some(osm_type)) = (osm_id, osm_type) else {
+            continue;
+        };
+        let qid = row.get(qid_col).unwrap_or_default().trim();
+        let title = row.get(title_col).unwrap_or_default().trim();
+
+        let mut fix = |key, old_value: &str, suggested_value: String, rule| {
+            fixes.push(Fix {
+                line,
+                osm_type,
+                osm_id,
+                key,
+                old_value: old_value.to_owned(),
+                suggested_value,
+                rule,
+            })
+        };
+
+        if enabled(FixRule::LowercaseQid) {
+            if let Some(fixed) = fix_lowercase_qid(qid) {
+                fix("wikidata", qid, fixed, FixRule::LowercaseQid);
+            }
+        }
+
+        if enabled(FixRule::WikidataUrl) {
+            if let Some(url_qid) = wikidata_url_qid(title) {
+                let url_qid = url_qid.to_string();
+                if qid.is_empty() {
+                    fix("wikidata", qid, url_qid, FixRule::WikidataUrl);
+                    fix("wikipedia", title, String::new(), FixRule::WikidataUrl);
+                } else if Qid::from_str(qid).is_ok_and(|q| q.to_string() == url_qid) {
+                    fix("wikipedia", title, String::new(), FixRule::WikidataUrl);
+                }
+                // Otherwise the tags conflict, and it's unclear which is correct.
+                continue;
+            }
+        }
+
+        if enabled(FixRule::EncodedTitle) {
+            if let Some(fixed) = fix_encoded_title(title) {
+                fix("wikipedia", title, fixed, FixRule::EncodedTitle);
+                continue;
+            }
+        }
+
+        if enabled(FixRule::LangAlias) {
+            if let Some(fixed) = fix_lang_alias(title) {
+                fix("wikipedia", title, fixed, FixRule::LangAlias);
+            }
+        }
+    }
+
+    Ok(fixes)
+}
+
+/// `q42` -> `Q42`
+fn fix_lowercase_qid(qid: &str) -> Option<String> {
+    let digits = qid.strip_prefix('q')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Qid::from_str(qid).ok().map(|q| q.to_string())
+}
+
+/// The QID of a `https://www.wikidata.org/wiki/Q42` url, optionally after a language prefix.
+fn wikidata_url_qid(title: &str) -> Option<Qid> {
+    let start = title.find("http://").or_else(|| title.find("https://"))?;
+    let url = Url::parse(&title[start..]).ok()?;
+    let host = url.host_str()?;
+    if host != "wikidata.org" && !host.ends_with(".wikidata.org") {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    if !matches!(segments.next(), Some("wiki" | "entity")) {
+        return None;
+    }
+    let id = segments.next()?;
+    if segments.next().is_some() || !id.starts_with('Q') {
+        return None;
+    }
+    Qid::from_str(id).ok()
+}
+
+/// `de:K%C3%B6ln` -> `de:Köln`
+fn fix_encoded_title(title: &str) -> Option<String> {
+    let (lang, name) = title.split_once(':')?;
+    if name.contains("://") || !has_percent_escape(name) {
+        return None;
+    }
+    let decoded = urlencoding::decode(name).ok()?;
+    // Decoding again would change the title, so it was encoded more than once or is not encoded at all.
+    if has_percent_escape(&decoded) {
+        return None;
+    }
+    let fixed = format!("{lang}:{decoded}");
+    Title::from_osm_tag(&fixed).ok()?;
+    Some(fixed)
+}
+
+/// `nb:Oslo` -> `no:Oslo`
+fn fix_lang_alias(title: &str) -> Option<String> {
+    let (lang, name) = title.split_once(':')?;
+    let (_, canonical) = LANG_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(lang.trim()))?;
+    let fixed = format!("{canonical}:{name}");
+    Title::from_osm_tag(&fixed).ok()?;
+    Some(fixed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TAGS: &str = "\
+@id\t@otype\twikidata\twikipedia
+1\t0\tq42\ten:Douglas Adams
+2\t1\t\thttps://www.wikidata.org/wiki/Q64
+3\t2\tQ64\ten:https://www.wikidata.org/wiki/Q64
+4\t0\tQ365\tde:K%C3%B6ln
+5\t1\tQ20\tnb:Norge
+6\t0\tQ1\ten:https://www.wikidata.org/wiki/Q2
+7\t0\t\tde:K%25C3%25B6ln
+8\t0\t\ten:100% Pure
+9\t0\tQ1\tzz:Oslo
+10\t0\tq
+11\t\tq42\t
+";
+
+    #[test]
+    fn one_of_each_class() {
+        let fixes = suggest_fixes(TAGS.as_bytes(), FixRule::ALL).unwrap();
+        let fixes: Vec<_> = fixes
+            .iter()
+            .map(|f| {
+                (
+                    f.object(),
+                    f.key,
+                    f.old_value.as_str(),
+                    f.suggested_value.as_str(),
+                    f.rule,
+                )
+            })
+            .collect();
+        use FixRule::*;
+        assert_eq!(
+            vec![
+                ("node/1".into(), "wikidata", "q42", "Q42", LowercaseQid),
+                ("way/2".into(), "wikidata", "", "Q64", WikidataUrl),
+                (
+                    "way/2".into(),
+                    "wikipedia",
+                    "https://www.wikidata.org/wiki/Q64",
+                    "",
+                    WikidataUrl
+                ),
+                (
+                    "relation/3".into(),
+                    "wikipedia",
+                    "en:https://www.wikidata.org/wiki/Q64",
+                    "",
+                    WikidataUrl
+                ),
+                (
+                    "node/4".into(),
+                    "wikipedia",
+                    "de:K%C3%B6ln",
+                    "de:Köln",
+                    EncodedTitle
+                ),
+                (
+                    "way/5".into(),
+                    "wikipedia",
+                    "nb:Norge",
+                    "no:Norge",
+                    LangAlias
+                ),
+            ],
+            fixes
+        );
+    }
+
+    #[test]
+    fn disabled_rules() {
+        let fixes = suggest_fixes(TAGS.as_bytes(), &[FixRule::LangAlias]).unwrap();
+        assert_eq!(1, fixes.len());
+        assert_eq!(FixRule::LangAlias, fixes[0].rule);
+
+        assert!(suggest_fixes(TAGS.as_bytes(), &[]).unwrap().is_empty());
+    }
+}
@@ -1,38 +1,36 @@
 use std::{
     borrow::Cow,
     collections::HashSet,
-    fs::{self, File},
-    io::{stdin, stdout, BufRead, BufReader, Write},
-    os::unix,
+    fs::File,
+    io::{self, stderr, stdin, stdout, BufReader, BufWriter, Write},
     path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
 };
 
-use anyhow::{anyhow, bail, Context};
+use anyhow::Context;
+use serde::Serialize;
 
 use om_wikiparser::{
     extend,
-    html::{self, HtmlError},
-    parse_osm_tag_file, parse_wikidata_file, parse_wikipedia_file,
-    wm::{Page, Title},
+    extract::{
+        self, capture_raw_titles, ArticleFilter, AuditEntry, AuditLog, BudgetOrder, DumpStats,
+        DuplicatePolicy, HtmlCompression, LinkDirection, MatchPolicy, Matcher, PathPrinter,
+        Publish, RawTitles,
+    },
+    parse_osm_tag_file_raw, parse_osm_tag_file_with, parse_wikidata_file_with,
+    parse_wikipedia_file_raw_with_options, parse_wikipedia_file_with_options,
+    wm::{LanguageMap, Layout, ParseOptions},
+    ErrorSummary, ListFileOptions, LogThrottle, ParseLineError, ReadLimits, TagFileOptions,
+    TagKinds,
 };
 
-#[derive(clap::ValueEnum, Copy, Clone)]
-pub enum ArticleFilter {
-    /// All articles that match on title/QID
-    Match,
-    /// Articles that cannot be simplified
-    Error,
-    /// Articles that cause panics when simplified
-    Panic, // FIXME: move panic dumping to this
-}
-
 /// Extract, filter, and simplify article HTML from Wikipedia Enterprise HTML dumps.
 ///
 /// Expects an uncompressed dump (newline-delimited JSON) connected to stdin.
 #[derive(clap::Args)]
 pub struct Args {
     /// Directory to write the extracted articles to.
-    #[arg(required_unless_present = "passthrough")]
+    #[arg(required_unless_present_any = ["passthrough", "validate_only"])]
     pub output_dir: Option<PathBuf>,
 
     /// Copy input article JSON to stdout if it matches certain criteria.
@@ -42,9 +40,23 @@ pub struct Args {
     /// Path to a TSV file that contains one or more of `wikidata`, `wikipedia` columns.
     ///
     /// This can be generated with the `get-tags` command or `osmconvert --csv-headline --csv 'wikidata wikipedia'`.
+    /// Titles in an optional `wikivoyage` column are extracted from Wikivoyage dumps.
     #[arg(long, help_heading = "FILTERS", value_name = "FILE.tsv")]
     pub osm_tags: Option<PathBuf>,
 
+    /// Only read these tags from the `--osm-tags` file, skipping the others without parsing them.
+    ///
+    /// This is faster when only one kind is used, e.g. with `--match-policy title-only`.
+    /// Errors in the skipped tags are not reported, and the counts of the other policies in the `--write-stats` file don't include them.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help_heading = "FILTERS",
+        requires = "osm_tags"
+    )]
+    pub osm_tag_kinds: TagKinds,
+
     /// Path to file that contains a Wikidata QID to extract on each line
     /// (e.g. `Q12345`).
     #[arg(long, help_heading = "FILTERS", value_name = "FILE")]
@@ -55,6 +67,17 @@ pub struct Args {
     #[arg(long, help_heading = "FILTERS", value_name = "FILE")]
     pub wikipedia_urls: Option<PathBuf>,
 
+    /// Language of a single-language dump, for the titles in `--wikipedia-urls` without one, e.g. `Berlin`.
+    ///
+    /// Titles with a language, like `de:Köln`, keep theirs.
+    #[arg(
+        long,
+        help_heading = "FILTERS",
+        value_name = "LANG",
+        requires = "wikipedia_urls"
+    )]
+    pub dump_lang: Option<String>,
+
     /// Append to the provided file path the QIDs of articles matched by title but not QID.
     ///
     /// Use this to save the QIDs of articles you know the url of, but not the QID.
@@ -63,24 +86,459 @@ pub struct Args {
     #[arg(long, value_name = "FILE")]
     pub write_new_qids: Option<PathBuf>,
 
+    /// Write the requested QIDs and titles that did not match any article to the provided directory.
+    ///
+    /// Creates `missing_qids.txt` and `missing_titles.txt`, which can be passed to the `--wikidata-qids` and `--wikipedia-urls` options.
+    /// Only articles read from stdin are considered, so to find the articles missing from multiple dumps, concatenate them into a single run.
+    #[arg(long, value_name = "DIR")]
+    pub write_missing: Option<PathBuf>,
+
+    /// Keep the text that each title was read from, and group the missing titles by article in `missing_titles_raw.jsonl` of the `--write-missing` directory.
+    ///
+    /// Each line lists the distinct spellings of a missing title, e.g. mobile and desktop urls, with the number of times each was read.
+    #[arg(long, requires = "write_missing")]
+    pub capture_raw_titles: bool,
+
+    /// Exit with an error if more than this fraction of the requested QIDs and titles did not match any article, e.g. `0.2`.
+    ///
+    /// A high fraction usually means the requested articles are stale, or the dump is of the wrong language or project.
+    /// The stats are still printed and written first.
+    #[arg(long, value_name = "FRACTION")]
+    pub max_missing: Option<f64>,
+
+    /// Write errors from parsing the `--osm-tags`, `--wikidata-qids`, and `--wikipedia-urls` files to the provided file instead of logging them.
+    ///
+    /// Each line contains the input file path and the error.
+    /// Errors in the `--osm-tags` file are only counted unless this is set; see the `check-tags` command for more details on them.
+    #[arg(long, value_name = "FILE")]
+    pub parse_errors: Option<PathBuf>,
+
+    /// Log only the first N errors from parsing the input files, then the number of suppressed errors every 10,000 errors.
+    ///
+    /// The limit is for all input files together, and doesn't apply to errors written to `--parse-errors`.
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    pub max_parse_warnings: usize,
+
+    /// Write the input parse errors and the matched articles that were not written to the provided file in newline-delimited JSON.
+    ///
+    /// Each line has an `event` field of `parse_error` or `skip`, and a `reason`.
+    #[arg(long, value_name = "FILE.jsonl")]
+    pub write_audit: Option<PathBuf>,
+
+    /// Append the written articles to the manifest file in the output directory.
+    ///
+    /// Writes are atomicly appended to the file, so the same output directory may be used by multiple concurrent instances.
+    #[arg(long, requires = "output_dir")]
+    pub write_manifest: bool,
+
+    /// Record articles without a `dir` attribute as right-to-left in the manifest if their language is written right-to-left.
+    ///
+    /// Otherwise they are recorded as left-to-right.
+    #[arg(long, requires = "write_manifest")]
+    pub infer_text_direction: bool,
+
+    /// After processing the dump, write the languages of the articles written for each QID to `qid_langs.json` in the output directory.
+    ///
+    /// The file is a JSON object of QIDs and their sorted languages, from every run recorded in the manifest.
+    #[arg(long, requires = "write_manifest")]
+    pub write_qid_langs: bool,
+
+    /// After processing the dump, add the title and redirects of each written article to `link_index.json` in the output directory.
+    ///
+    /// The file is a JSON object of normalized titles like `en:Berlin,_Germany` and the paths of their html files, to resolve links between articles offline.
+    /// Titles from earlier runs are kept.
+    #[arg(long, requires = "output_dir")]
+    pub write_link_index: bool,
+
+    /// Write the provenance and metadata of each article to a JSON file next to it, e.g. `en.meta.json` for `en.html`.
+    ///
+    /// Records how the article was matched, by which requested QIDs or titles, its line and revision in the dump, and when it was written.
+    /// Also records the text direction and word count, and the coordinates, short description, and categories from the original html.
+    /// The file has a `schema_version`, which is increased when the fields change.
+    /// Use `check-sidecars` to compare them with the manifest.
+    #[arg(long, requires = "output_dir")]
+    pub write_sidecars: bool,
+
+    /// Append the other names of each article to `altnames.tsv` in the output directory, for search.
+    ///
+    /// Each row has the path of the article's html file, the name, and whether it is a `redirect` or a `langlink`.
+    /// Names in other languages are only known for articles found by the QID of an article matched by title, see `--no-expand-titles`.
+    #[arg(long, requires = "output_dir")]
+    pub write_altnames: bool,
+
+    /// Write at most this many rows of each article with `--write-altnames`.
+    ///
+    /// Defaults to 32.
+    #[arg(long, value_name = "N", requires = "write_altnames")]
+    pub max_altnames: Option<usize>,
+
+    /// Record the Wikidata descriptions of each article's QID in the manifest and sidecars, read from the provided Wikidata JSON dump.
+    ///
+    /// The dump may be gzipped. It is read into memory before the articles, so limit the languages with `--description-lang` or filter it to the requested items first.
+    #[arg(long, value_name = "FILE.json[.gz]", requires = "output_dir")]
+    pub wikidata_descriptions: Option<PathBuf>,
+
+    /// Only read descriptions in this language from `--wikidata-descriptions`.
+    ///
+    /// Can be repeated. Defaults to all languages.
+    #[arg(long, value_name = "LANG", requires = "wikidata_descriptions")]
+    pub description_lang: Vec<String>,
+
+    /// Write statistics about the processed dump to the provided file in JSON format.
+    #[arg(long, value_name = "FILE.json")]
+    pub write_stats: Option<PathBuf>,
+
+    /// Time the processing of each matched article, and print the N slowest at the end.
+    ///
+    /// Each article is listed with its time from parsing to writing, and the size of its JSON in the dump.
+    /// They are also included in the `--write-stats` file.
+    #[arg(long, value_name = "N")]
+    pub profile: Option<usize>,
+
+    /// Directory structure to write the extracted articles in.
+    #[arg(long, value_enum, default_value_t)]
+    pub layout: Layout,
+
+    /// Write articles in the output directory with other language codes, e.g. `zh-CN` for `zh-hans`.
+    ///
+    /// Each line of the file is a language code and its replacement, separated by a tab.
+    /// Only the paths are changed, pages are still matched by their original language.
+    #[arg(long, value_name = "FILE.tsv", requires = "output_dir")]
+    pub language_map: Option<PathBuf>,
+
+    /// Which way the links between the QID and title directories of an article point.
+    #[arg(long, value_enum, default_value_t)]
+    pub link_direction: LinkDirection,
+
+    /// Create symlinks with targets relative to the link, e.g. `../../wikidata/Q42`, so the output directory can be moved.
+    ///
+    /// If the filesystem of the output directory doesn't support symlinks, they are written to `links.jsonl` in it instead.
+    #[arg(long)]
+    pub relative_links: bool,
+
+    /// Write the articles to a hidden staging directory next to the output directory, and only rename it into place if the run succeeds.
+    ///
+    /// The output directory is never partially written, and a failed run leaves the previous output as it was.
+    /// The new output only has the articles of this run, with relative links.
+    /// If the output directory already exists, `error` fails before reading the dump, and `swap` replaces it.
+    #[arg(
+        long,
+        value_enum,
+        requires = "output_dir",
+        conflicts_with = "print_paths"
+    )]
+    pub publish: Option<Publish>,
+
+    /// Which of the QID and title rules must match for a page to be extracted.
+    ///
+    /// The number of pages each policy would extract is written to the `--write-stats` file either way, to compare them.
+    #[arg(long, value_enum, default_value_t, help_heading = "FILTERS")]
+    pub match_policy: MatchPolicy,
+
+    /// Don't extract articles with the QIDs of articles that were matched by title earlier in the input.
+    ///
+    /// By default, when an article is matched by title, its QID is used to match the same article in the other languages of any dumps that follow it on stdin.
+    /// This means the order of the dumps is significant: an article is only found in the languages of dumps after the one it was matched by title in.
+    /// To find it in all languages regardless of order, use `--write-new-qids` and run again with `--wikidata-qids`.
+    #[arg(long)]
+    pub no_expand_titles: bool,
+
+    /// Don't write articles that are marked as stubs.
+    ///
+    /// Stubs are detected with the `stub_selectors` for the article's language in `article_processing_config.json`.
+    #[arg(long)]
+    pub skip_stubs: bool,
+
+    /// Don't write articles with fewer characters of text than this after simplifying them.
+    ///
+    /// Whitespace, markup, and text in infoboxes are not counted.
+    /// Skipped articles are listed in the `--write-audit` file.
+    #[arg(long, value_name = "CHARS")]
+    pub min_text_chars: Option<usize>,
+
+    /// Don't write articles with fewer paragraphs of text than this after simplifying them.
+    #[arg(long, value_name = "PARAGRAPHS")]
+    pub min_paragraphs: Option<usize>,
+
     /// Don't process extracted HTML; write the original text to disk.
     #[arg(long)]
     pub no_simplify: bool,
+
+    /// Keep elements matching this CSS selector when simplifying articles, even if they match a removal rule.
+    ///
+    /// Elements inside and containing them are kept as well, e.g. `--protect-selector 'p.hours'` keeps opening hours inside a removed `div`.
+    /// Can be repeated.
+    #[arg(long, value_name = "SELECTOR", conflicts_with = "no_simplify")]
+    pub protect_selector: Vec<String>,
+
+    /// Compress each article, writing e.g. `en.html.gz` instead of `en.html`.
+    ///
+    /// Static hosts can serve the compressed files directly with a `Content-Encoding` header.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub compress_html: Option<HtmlCompression>,
+
+    /// How to handle an article that appears more than once in the input.
+    ///
+    /// Dumps occasionally contain the same page twice if it was edited while the dump was created.
+    #[arg(long, value_enum, default_value_t)]
+    pub duplicates: DuplicatePolicy,
+
+    /// Write the `lang:Title` of each duplicate article in the input to the provided file.
+    #[arg(long, value_name = "FILE")]
+    pub write_duplicates: Option<PathBuf>,
+
+    /// Match requested titles that are not in the dump to articles whose titles only differ in apostrophes, dashes, or whitespace.
+    ///
+    /// For example `en:King’s Cross` matches the article `King's Cross`.
+    /// Exact matches are always preferred, and the recovered matches are listed in the `--write-stats` file.
+    #[arg(long)]
+    pub near_miss_titles: bool,
+
+    /// Match requested titles to articles and redirects whose titles only differ in case or whitespace.
+    ///
+    /// For example `en:berlin,  germany` matches the article `Berlin` through its redirect `Berlin, Germany`.
+    #[arg(long)]
+    pub loose_titles: bool,
+
+    /// Skip lines of the title and QID files that are longer than this many bytes, as parse errors.
+    ///
+    /// Defaults to 4 MiB.
+    #[arg(long, value_name = "BYTES")]
+    pub max_line_bytes: Option<usize>,
+
+    /// Skip `wikidata` and `wikipedia` values in the osm tag file that are longer than this many bytes, as parse errors.
+    ///
+    /// Defaults to 64 KiB.
+    #[arg(long, value_name = "BYTES")]
+    pub max_field_bytes: Option<usize>,
+
+    /// Fail on the first line of the `--osm-tags`, `--wikidata-qids`, and `--wikipedia-urls` files that cannot be parsed, instead of skipping it.
+    #[arg(long)]
+    pub strict_inputs: bool,
+
+    /// Skip pages of the dump that are longer than this many bytes.
+    ///
+    /// Defaults to 64 MiB.
+    #[arg(long, value_name = "BYTES")]
+    pub max_page_bytes: Option<usize>,
+
+    /// Stop writing articles once their html would add up to more than this many bytes, e.g. to fit on a device with fixed storage.
+    ///
+    /// Compression and the files written alongside the articles are not counted, so leave some room for them.
+    /// The number of articles that didn't fit is logged and written to the `--write-stats` file.
+    #[arg(long, value_name = "BYTES")]
+    pub max_total_bytes: Option<usize>,
+
+    /// Which articles to keep when `--max-total-bytes` is reached.
+    ///
+    /// With `qid`, the articles are written after the whole dump is read, so holding them back uses memory or temporary disk space.
+    #[arg(long, value_enum, default_value_t, requires = "max_total_bytes")]
+    pub budget_order: BudgetOrder,
+
+    /// Skip corrupt regions of the dump instead of failing, resuming at the next page that can be parsed.
+    ///
+    /// Skipped byte ranges are logged, and written to the `--write-stats` and `--write-audit` files.
+    /// Processing still fails after many consecutive corrupt lines.
+    #[arg(long)]
+    pub recover_corrupt: bool,
+
+    /// Reduce memory use instead of running out when the estimated memory approaches this many bytes.
+    ///
+    /// For example, articles deferred by `--near-miss-titles` are moved to a temporary file.
+    /// The estimate only covers the largest consumers, so leave some headroom below the memory actually available.
+    /// The reductions that were made are listed in the `--write-stats` file.
+    #[arg(long, value_name = "BYTES")]
+    pub memory_ceiling: Option<usize>,
+
+    /// Only parse the QID, title, and tag files, and write the number of valid and invalid values in each to stdout.
+    ///
+    /// Doesn't read the dump from stdin. Exits with an error if any value is invalid, unless `--allow-invalid` is set.
+    /// Titles in non-article namespaces are counted separately, and are not treated as invalid.
+    #[arg(long)]
+    pub validate_only: bool,
+
+    /// Print the path of the html file of each matched article in the output directory to stdout, without writing anything.
+    ///
+    /// The paths depend on `--layout`, `--link-direction`, and `--compress-html` like they do when writing, but the links to them are not listed.
+    /// The output directory doesn't need to exist.
+    #[arg(long, requires = "output_dir", conflicts_with = "passthrough")]
+    pub print_paths: bool,
+
+    /// Don't exit with an error for invalid values with `--validate-only`.
+    #[arg(long, requires = "validate_only")]
+    pub allow_invalid: bool,
+
+    /// Format of the reports printed when finished: the table of languages, the slowest articles with `--profile`, and the input files with `--validate-only`.
+    ///
+    /// `json` prints each report as an object on one line, with its name in `report` and its rows in `rows`.
+    /// `csv` prints each report as a table with a header, and a blank line between them.
+    #[arg(long, value_enum, default_value_t)]
+    pub report_format: ReportFormat,
+}
+
+/// How the reports of [Args::report_format] are printed.
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Aligned columns for reading.
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    /// Write the report `name` with `rows` to `w`, or with `text` if the format is [ReportFormat::Text].
+    fn write<W: Write, T: Serialize>(
+        self,
+        mut w: W,
+        name: &str,
+        rows: &[T],
+        text: impl FnOnce(&mut W) -> io::Result<()>,
+    ) -> anyhow::Result<()> {
+        match self {
+            ReportFormat::Text => text(&mut w)?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut w, &JsonReport { report: name, rows })?;
+                writeln!(w)?;
+            }
+            ReportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(&mut w);
+                for row in rows {
+                    writer.serialize(row)?;
+                }
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Separate a report from the one before it.
+    fn separate(self, mut w: impl Write) -> io::Result<()> {
+        match self {
+            ReportFormat::Json => Ok(()),
+            ReportFormat::Text | ReportFormat::Csv => writeln!(w),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a, T> {
+    report: &'a str,
+    rows: &'a [T],
+}
+
+/// A row of the `files` report of [Args::validate_only].
+#[derive(Serialize)]
+struct FileRow<'a> {
+    path: &'a Path,
+    valid: usize,
+    invalid: usize,
+    namespaces: usize,
+}
+
+/// A row of the `langs` report, from [DumpStats::lang_rows].
+#[derive(Serialize)]
+struct LangRow<'a> {
+    lang: Cow<'a, str>,
+    titles: usize,
+    matches: usize,
+    written: usize,
+    bytes: usize,
+    errors: usize,
+}
+
+fn lang_rows(stats: &DumpStats) -> Vec<LangRow<'_>> {
+    stats
+        .lang_rows()
+        .into_iter()
+        .map(|(lang, s)| LangRow {
+            lang,
+            titles: s.titles,
+            matches: s.matches,
+            written: s.written,
+            bytes: s.bytes,
+            errors: s.errors,
+        })
+        .collect()
 }
 
 pub fn run(args: Args) -> anyhow::Result<()> {
+    if let Some(max) = args.max_missing {
+        if !(0.0..=1.0).contains(&max) {
+            anyhow::bail!("--max-missing must be between 0 and 1, not {max}");
+        }
+    }
+
+    let audit = args
+        .write_audit
+        .as_ref()
+        .map(AuditLog::create)
+        .transpose()?;
+    let throttle = LogThrottle::new(args.max_parse_warnings, 10_000);
+    let mut parse_errors = ParseErrorLog::new(args.parse_errors.as_ref(), audit, &throttle)?;
+
+    let defaults = ReadLimits::default();
+    let limits = ReadLimits {
+        max_line_bytes: args.max_line_bytes.unwrap_or(defaults.max_line_bytes),
+        max_field_bytes: args.max_field_bytes.unwrap_or(defaults.max_field_bytes),
+    };
+    let list_options = ListFileOptions {
+        limits,
+        strict: args.strict_inputs,
+    };
+    let tag_options = TagFileOptions {
+        limits,
+        strict: args.strict_inputs,
+        tag_kinds: args.osm_tag_kinds,
+    };
+
     let mut wikipedia_titles = HashSet::new();
-    if let Some(path) = args.wikipedia_urls {
+    let mut raw_titles = args.capture_raw_titles.then(RawTitles::default);
+    if let Some(path) = &args.wikipedia_urls {
         info!("Loading article urls from {path:?}");
         let file = BufReader::new(File::open(path)?);
-        parse_wikipedia_file(file, &mut wikipedia_titles)?
+        let mut errors = Vec::new();
+        let title_options = ParseOptions {
+            default_lang: args.dump_lang.clone(),
+            ..Default::default()
+        };
+        let valid = match &mut raw_titles {
+            Some(raw) => {
+                let mut titles = capture_raw_titles(&mut wikipedia_titles, raw);
+                let mut titles = extend::counted(&mut titles);
+                parse_wikipedia_file_raw_with_options(
+                    file,
+                    &mut titles,
+                    &mut errors,
+                    &list_options,
+                    &title_options,
+                )?;
+                titles.count()
+            }
+            None => {
+                let mut titles = extend::counted(&mut wikipedia_titles);
+                parse_wikipedia_file_with_options(
+                    file,
+                    &mut titles,
+                    &mut errors,
+                    &list_options,
+                    &title_options,
+                )?;
+                titles.count()
+            }
+        };
+        parse_errors.record(path, valid, errors, true)?;
     }
 
     let mut wikidata_qids = HashSet::new();
-    if let Some(path) = args.wikidata_qids {
+    if let Some(path) = &args.wikidata_qids {
         info!("Loading wikidata QIDs from {path:?}");
         let file = BufReader::new(File::open(path)?);
-        parse_wikidata_file(file, &mut wikidata_qids)?
+        let mut errors = Vec::new();
+        let mut qids = extend::counted(&mut wikidata_qids);
+        parse_wikidata_file_with(file, &mut qids, &mut errors, &list_options)?;
+        let valid = qids.count();
+        parse_errors.record(path, valid, errors, true)?;
     };
 
     if let Some(ref path) = args.osm_tags {
@@ -88,262 +546,318 @@ pub fn run(args: Args) -> anyhow::Result<()> {
         let file = File::open(path)?;
 
         let original_items = wikidata_qids.len() + wikipedia_titles.len();
-        let mut error_count = 0;
-        parse_osm_tag_file(
-            file,
-            &mut wikidata_qids,
-            &mut wikipedia_titles,
-            &mut extend::from_fn(|_| error_count += 1),
-        )?;
+        let mut errors = Vec::new();
+        let mut qids = extend::counted(&mut wikidata_qids);
+        let valid = match &mut raw_titles {
+            Some(raw) => {
+                let mut titles = capture_raw_titles(&mut wikipedia_titles, raw);
+                let mut titles = extend::counted(&mut titles);
+                parse_osm_tag_file_raw(file, &mut qids, &mut titles, &mut errors, &tag_options)?;
+                titles.count()
+            }
+            None => {
+                let mut titles = extend::counted(&mut wikipedia_titles);
+                parse_osm_tag_file_with(file, &mut qids, &mut titles, &mut errors, &tag_options)?;
+                titles.count()
+            }
+        } + qids.count();
 
-        if error_count != 0 {
+        let summary = ErrorSummary::new(&errors);
+        if summary.errors != 0 {
             let new_items = wikidata_qids.len() + wikipedia_titles.len() - original_items;
-            let percentage = 100.0 * error_count as f64 / new_items as f64;
-            warn!("{error_count} errors ({percentage:.4}%) parsing osm tags from {path:?}",);
+            let percentage = 100.0 * summary.errors as f64 / new_items as f64;
+            warn!(
+                "{} errors ({percentage:.4}%) parsing osm tags from {path:?}",
+                summary.errors
+            );
         }
-    }
-
-    debug!("Parsed {} unique article titles", wikipedia_titles.len());
-    debug!("Parsed {} unique wikidata QIDs", wikidata_qids.len());
-
-    // NOTE: For atomic writes to the same file across threads/processes:
-    // - The file needs to be opened in APPEND mode (`.append(true)`).
-    // - Each write needs to be a single syscall (for Rust, use `format!` for formatting before calling `write!`, or `write!` to a `String` first).
-    // - Each write needs to be under `PIPE_BUF` size (see `man write(3)`), usually 4kb on Linux.
-    //
-    // For more information, see:
-    // - `man write(3posix)`: https://www.man7.org/linux/man-pages/man3/write.3p.html
-    // - `std::fs::OpenOptions::append`: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html#method.append
-    // - https://stackoverflow.com/questions/1154446/is-file-append-atomic-in-unix
-    let mut write_new_qids = args
-        .write_new_qids
-        .as_ref()
-        .map(|p| File::options().create(true).append(true).open(p))
-        .transpose()?;
-
-    if let Some(output_dir) = &args.output_dir {
-        if !output_dir.is_dir() {
-            bail!("output dir {:?} does not exist", output_dir);
+        if summary.namespaces != 0 {
+            info!(
+                "Skipped {} titles in non-article namespaces from {path:?}",
+                summary.namespaces
+            );
         }
+        parse_errors.record(path, valid, errors, false)?;
     }
 
-    let mut stdout = stdout();
-
-    info!("Processing dump");
-    let mut dump = stdin().lock();
-
-    let mut buffer = String::new();
-    let mut line = 0;
-    let mut byte = 1;
-    loop {
-        line += 1;
-        byte += buffer.len();
-        buffer.clear();
-
-        if 0 == dump.read_line(&mut buffer).context("reading dump")? {
-            // Reached end of file.
-            break;
+    if args.validate_only {
+        let summary = parse_errors.summary;
+        let rows: Vec<FileRow> = parse_errors
+            .files
+            .iter()
+            .map(|(path, valid, summary)| FileRow {
+                path,
+                valid: *valid,
+                invalid: summary.errors,
+                namespaces: summary.namespaces,
+            })
+            .collect();
+        args.report_format
+            .write(stdout().lock(), "files", &rows, |w| {
+                for row in &rows {
+                    writeln!(
+                        w,
+                        "{}: {} valid, {} invalid, {} namespace titles",
+                        row.path.display(),
+                        row.valid,
+                        row.invalid,
+                        row.namespaces
+                    )?;
+                }
+                Ok(())
+            })?;
+        parse_errors.finish()?;
+        if summary.errors != 0 && !args.allow_invalid {
+            anyhow::bail!("{} invalid lines in input files", summary.errors);
         }
+        return Ok(());
+    }
+    parse_errors.finish()?;
 
-        // TODO: Compare different deserialization methods.
-        // The docs warn against using a reader directly, and it's slower than tar can decompress the dump.
-        // let stream = serde_json::Deserializer::from_reader(dump).into_iter::<Page>();
-        let page: Page = serde_json::from_str(&buffer).context("deserializing json")?;
-
-        let span = info_span!(
-            "page",
-            lang = page.in_language.identifier,
-            title = page.name,
-            url = page.url,
-            qid = page.main_entity.as_ref().map(|w| &w.identifier),
-            line,
-            byte,
-        );
-        let _handle = span.enter();
-
-        let qid = page.wikidata();
-        let is_wikidata_match = qid
-            .as_ref()
-            .map(|qid| wikidata_qids.contains(qid))
-            .unwrap_or_default();
-
-        let matching_titles = if wikipedia_titles.is_empty() {
-            Default::default()
-        } else {
-            page.all_titles()
-                .filter_map(|r| {
-                    r.map(Some).unwrap_or_else(|e| {
-                        warn!("Could not parse title: {:#}", e);
-                        None
-                    })
-                })
-                .filter(|t| wikipedia_titles.contains(t))
-                .collect::<Vec<_>>()
-        };
+    let matcher = Matcher {
+        qids: wikidata_qids,
+        titles: wikipedia_titles,
+        raw_titles,
+    };
+    let requested = matcher.stats();
+    info!(
+        qids = requested.qids,
+        titles = requested.titles,
+        langs = requested.titles_by_lang.len(),
+        "Loaded requested articles"
+    );
+    for (lang, titles) in &requested.titles_by_lang {
+        debug!(lang, titles, "Requested titles in language");
+    }
 
-        if !is_wikidata_match && matching_titles.is_empty() {
-            continue;
+    let options = extract::Options {
+        output_dir: args.output_dir,
+        passthrough: args.passthrough,
+        write_new_qids: args.write_new_qids,
+        write_missing: args.write_missing,
+        write_manifest: args.write_manifest,
+        layout: args.layout,
+        language_map: args
+            .language_map
+            .map(LanguageMap::read)
+            .transpose()?
+            .unwrap_or_default(),
+        link_direction: args.link_direction,
+        relative_links: args.relative_links,
+        publish: args.publish,
+        no_expand_titles: args.no_expand_titles,
+        skip_stubs: args.skip_stubs,
+        min_text_chars: args.min_text_chars,
+        min_paragraphs: args.min_paragraphs,
+        no_simplify: args.no_simplify,
+        protect_selectors: args.protect_selector,
+        compress_html: args.compress_html,
+        duplicates: args.duplicates,
+        write_duplicates: args.write_duplicates,
+        write_audit: args.write_audit,
+        near_miss_titles: args.near_miss_titles,
+        loose_titles: args.loose_titles,
+        max_page_bytes: args.max_page_bytes,
+        max_total_bytes: args.max_total_bytes,
+        budget_order: args.budget_order,
+        recover_corrupt: args.recover_corrupt,
+        write_sidecars: args.write_sidecars,
+        write_altnames: args.write_altnames,
+        max_altnames: args.max_altnames,
+        wikidata_descriptions: args.wikidata_descriptions,
+        description_langs: args.description_lang,
+        infer_text_direction: args.infer_text_direction,
+        write_qid_langs: args.write_qid_langs,
+        write_link_index: args.write_link_index,
+        match_policy: args.match_policy,
+        memory_ceiling: args.memory_ceiling,
+        profile: args.profile,
+        post_processor: None,
+        page_filter: None,
+    };
+    let stats = match &options.output_dir {
+        Some(dir) if args.print_paths => {
+            let printer = PathPrinter::new(dir, &options, BufWriter::new(stdout().lock()));
+            extract::run_with(
+                stdin().lock(),
+                io::sink(),
+                &matcher,
+                &options,
+                printer,
+                &AtomicBool::new(false),
+            )?
         }
+        _ => extract::run(
+            stdin().lock(),
+            stdout(),
+            &matcher,
+            &options,
+            &AtomicBool::new(false),
+        )?,
+    };
 
-        // Write matched new QIDs back to file.
-        if let (Some(f), Some(qid)) = (&mut write_new_qids, &qid) {
-            if !is_wikidata_match && !matching_titles.is_empty() {
-                debug!("Writing new id {}", qid);
-                // NOTE: Write to string buffer first to have a single atomic write syscall.
-                // See `write_new_qids` for more info.
-                let line = format!("{}\n", qid);
-                write!(f, "{}", line).with_context(|| {
-                    format!(
-                        "writing new QID to file {:?}",
-                        args.write_new_qids.as_ref().unwrap()
-                    )
-                })?;
+    info!(
+        pages = stats.pages,
+        qid_matches = stats.qid_matches,
+        title_matches = stats.title_matches,
+        expanded_matches = stats.expanded_matches,
+        near_miss_matches = stats.near_miss_matches,
+        duplicates = stats.duplicates,
+        replaced = stats.replaced,
+        stubs = stats.stubs,
+        insubstantial = stats.insubstantial,
+        written = stats.written,
+        untitled = stats.untitled,
+        errors = stats.errors,
+        oversized_pages = stats.oversized_pages,
+        over_budget = stats.over_budget,
+        skipped_regions = stats.skipped_regions.len(),
+        path_collisions = stats.path_collisions.len(),
+        missing_qids = stats.missing_qids,
+        missing_titles = stats.missing_titles,
+        "Finished processing dump"
+    );
+    let timings = &stats.timings;
+    info!(
+        read = timings.read,
+        parse = timings.parse,
+        matching = timings.matching,
+        simplify = timings.simplify,
+        write = timings.write,
+        total = timings.total,
+        pages_per_second = timings.pages_per_second,
+        "Seconds spent in each stage"
+    );
+    let cpu = &timings.cpu;
+    info!(
+        read = cpu.read,
+        parse = cpu.parse,
+        matching = cpu.matching,
+        simplify = cpu.simplify,
+        write = cpu.write,
+        total = cpu.total,
+        "CPU seconds spent in each stage"
+    );
+    let report_format = args.report_format;
+    let mut stderr = stderr().lock();
+    report_format.write(&mut stderr, "langs", &lang_rows(&stats), |w| {
+        write!(w, "{stats}")
+    })?;
+    if args.profile.is_some() {
+        report_format.separate(&mut stderr)?;
+        report_format.write(&mut stderr, "slowest", &stats.slowest, |w| {
+            writeln!(w, "{:>10} {:>14} {:>10}  title", "seconds", "bytes", "line")?;
+            for slow in &stats.slowest {
+                writeln!(
+                    w,
+                    "{:>10.3} {:>14} {:>10}  {}",
+                    slow.seconds, slow.bytes, slow.line, slow.title
+                )?;
             }
-        }
-
-        // Always write regardless of later errors.
-        if let Some(ArticleFilter::Match) = args.passthrough {
-            stdout.write_all(buffer.as_bytes())?;
-        }
+            Ok(())
+        })?;
+    }
+    drop(stderr);
 
-        let article_output = if args.no_simplify {
-            Ok(Cow::Borrowed(&page.article_body.html))
-        } else {
-            html::process_str(&page.article_body.html, &page.in_language.identifier).map(Cow::Owned)
-        };
+    if let Some(path) = &args.write_stats {
+        info!("Writing stats to {path:?}");
+        let file = File::create(path).with_context(|| format!("creating stats file {path:?}"))?;
+        serde_json::to_writer_pretty(file, &stats)
+            .with_context(|| format!("writing stats file {path:?}"))?;
+    }
 
-        match article_output {
-            Err(e) => {
-                error!("Error processing article: {:#}", e);
-                if let Some(filter) = args.passthrough {
-                    match (e, filter) {
-                        (_, ArticleFilter::Error) | (HtmlError::Panic(_), ArticleFilter::Panic) => {
-                            stdout.write_all(buffer.as_bytes())?
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            Ok(html) => {
-                if let Some(output_dir) = args.output_dir.as_ref() {
-                    if let Err(e) = write(output_dir, &page, matching_titles, &html) {
-                        error!("Error writing article: {:#}", e);
-                    }
-                }
-            }
+    let requested = requested.qids + requested.titles;
+    let missing = stats.missing_qids + stats.missing_titles;
+    if let Some(max) = args.max_missing {
+        let fraction = missing as f64 / requested.max(1) as f64;
+        if fraction > max {
+            anyhow::bail!(
+                "{missing} of {requested} requested articles ({:.1}%) did not match any article, more than the --max-missing of {:.1}%",
+                fraction * 100.0,
+                max * 100.0
+            );
         }
     }
 
     Ok(())
 }
 
-/// Determine the directory to write the article contents to, create it, and create any necessary symlinks to it.
-fn create_article_dir(
-    base: impl AsRef<Path>,
-    page: &Page,
-    redirects: impl IntoIterator<Item = Title>,
-) -> anyhow::Result<PathBuf> {
-    let base = base.as_ref();
-    let mut redirects = redirects.into_iter();
-
-    let main_dir = match page.wikidata() {
-        None => {
-            // Write to wikipedia title directory.
-            // Prefer first redirect, fall back to page title if none exist
-            info!("Page without wikidata qid");
-            redirects
-                .next()
-                .or_else(|| match page.title() {
-                    Ok(title) => Some(title),
-                    Err(e) => {
-                        warn!("Unable to parse title: {:#}", e);
-                        None
-                    }
-                })
-                // hard fail when no titles can be parsed
-                .ok_or_else(|| anyhow!("No available titles for page {:?}", page.name))?
-                .get_dir(base.to_owned())
-        }
-        Some(qid) => {
-            // Otherwise use wikidata as main directory and symlink from wikipedia titles.
-            qid.get_dir(base.to_owned())
-        }
-    };
+/// Destination of errors from parsing the input files.
+struct ParseErrorLog<'a> {
+    file: Option<(PathBuf, BufWriter<File>)>,
+    audit: Option<AuditLog>,
+    throttle: &'a LogThrottle,
+    summary: ErrorSummary,
+    /// Each input file, with its number of valid values and errors.
+    files: Vec<(PathBuf, usize, ErrorSummary)>,
+}
 
-    if main_dir.is_symlink() {
-        fs::remove_file(&main_dir)
-            .with_context(|| format!("removing old link for main directory {:?}", &main_dir))?;
+impl<'a> ParseErrorLog<'a> {
+    fn new(
+        path: Option<&PathBuf>,
+        audit: Option<AuditLog>,
+        throttle: &'a LogThrottle,
+    ) -> anyhow::Result<Self> {
+        let file = path
+            .map(|p| {
+                File::create(p)
+                    .map(|f| (p.to_owned(), BufWriter::new(f)))
+                    .with_context(|| format!("creating parse error file {:?}", p))
+            })
+            .transpose()?;
+        Ok(Self {
+            file,
+            audit,
+            throttle,
+            summary: ErrorSummary::default(),
+            files: Vec::new(),
+        })
     }
-    fs::create_dir_all(&main_dir)
-        .with_context(|| format!("creating main directory {:?}", &main_dir))?;
-
-    // Write symlinks to main directory.
-    for title in redirects {
-        let wikipedia_dir = title.get_dir(base.to_owned());
-
-        // Build required directory.
-        //
-        // Possible states from previous run:
-        // - Does not exist (and is not a symlink)
-        // - Exists, is a directory
-        // - Exists, is a valid symlink to correct location
-        // - Exists, is a valid symlink to incorrect location
-        if wikipedia_dir.exists() {
-            if wikipedia_dir.is_symlink() {
-                // Only replace if not valid
-                if fs::read_link(&wikipedia_dir)? == main_dir {
-                    continue;
+
+    /// Write `errors` from parsing `input` to the file if set, otherwise log them if `log` is true.
+    fn record(
+        &mut self,
+        input: &Path,
+        valid: usize,
+        errors: Vec<ParseLineError>,
+        log: bool,
+    ) -> anyhow::Result<()> {
+        let summary = ErrorSummary::new(&errors);
+        self.summary.errors += summary.errors;
+        self.summary.namespaces += summary.namespaces;
+        self.files.push((input.to_owned(), valid, summary));
+        if let Some(audit) = &mut self.audit {
+            for e in &errors {
+                audit.record(&AuditEntry::parse_error(input, e))?;
+            }
+        }
+        match &mut self.file {
+            Some((path, file)) => {
+                for e in errors {
+                    writeln!(file, "{}: {}", input.display(), e)
+                        .with_context(|| format!("writing parse error file {:?}", path))?;
                 }
-                fs::remove_file(&wikipedia_dir)?;
-            } else {
-                fs::remove_dir_all(&wikipedia_dir)?;
             }
-        } else {
-            // titles can contain `/`, so ensure necessary subdirs exist
-            let parent_dir = wikipedia_dir.parent().unwrap();
-            fs::create_dir_all(parent_dir)
-                .with_context(|| format!("creating wikipedia directory {:?}", parent_dir))?;
+            None if log && !errors.is_empty() => {
+                warn!("{} parse errors in {:?}", errors.len(), input);
+                self.throttle.extend(errors);
+            }
+            None => {}
         }
-
-        unix::fs::symlink(&main_dir, &wikipedia_dir).with_context(|| {
-            format!(
-                "creating symlink from {:?} to {:?}",
-                wikipedia_dir, main_dir
-            )
-        })?;
+        Ok(())
     }
 
-    Ok(main_dir)
-}
-
-/// Write selected article to disk.
-///
-/// - Write page contents to wikidata page (`wikidata.org/wiki/QXXX/lang.html`).
-/// - If the page has no wikidata qid, write contents to wikipedia location (`lang.wikipedia.org/wiki/article_title/lang.html`).
-/// - Create links from all wikipedia urls and redirects (`lang.wikipedia.org/wiki/a_redirect -> wikidata.org/wiki/QXXX`).
-fn write(
-    base: impl AsRef<Path>,
-    page: &Page,
-    redirects: impl IntoIterator<Item = Title>,
-    html: &str,
-) -> anyhow::Result<()> {
-    let article_dir = create_article_dir(&base, page, redirects)?;
-
-    // Write html to determined file.
-    let mut filename = article_dir;
-    filename.push(&page.in_language.identifier);
-    filename.set_extension("html");
-
-    debug!(
-        file = filename.to_string_lossy().as_ref(),
-        exists = filename.exists(),
-        "Writing article"
-    );
-
-    let mut file =
-        File::create(&filename).with_context(|| format!("creating html file {:?}", filename))?;
-    file.write_all(html.as_bytes())
-        .with_context(|| format!("writing html file {:?}", filename))?;
-
-    Ok(())
+    fn finish(self) -> anyhow::Result<()> {
+        if let Some(audit) = self.audit {
+            audit.finish()?;
+        }
+        if let Some((path, mut file)) = self.file {
+            file.flush()
+                .with_context(|| format!("writing parse error file {:?}", path))?;
+            info!("Wrote {} to {:?}", self.summary, path);
+        } else if self.summary.total() != 0 {
+            info!("Found {} parsing input files", self.summary);
+        }
+        Ok(())
+    }
 }
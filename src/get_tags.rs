@@ -14,6 +14,7 @@ struct Record {
     version: Option<Version>,
     wikidata: String,
     wikipedia: String,
+    wikivoyage: String,
 }
 
 /// Extract matching tags from an osm pbf file and write to stdout in TSV.
@@ -48,7 +49,14 @@ fn write(recv: mpsc::Receiver<Record>) -> anyhow::Result<usize> {
     let mut output = csv::WriterBuilder::new()
         .delimiter(b'\t')
         .from_writer(stdout().lock());
-    output.write_record(["@id", "@otype", "@version", "wikidata", "wikipedia"])?;
+    output.write_record([
+        "@id",
+        "@otype",
+        "@version",
+        "wikidata",
+        "wikipedia",
+        "wikivoyage",
+    ])?;
 
     let mut count = 0;
 
@@ -58,6 +66,7 @@ fn write(recv: mpsc::Receiver<Record>) -> anyhow::Result<usize> {
         version,
         wikidata,
         wikipedia,
+        wikivoyage,
     } in recv
     {
         output.write_record([
@@ -66,6 +75,7 @@ fn write(recv: mpsc::Receiver<Record>) -> anyhow::Result<usize> {
             version.map(|v| v.to_string()).unwrap_or_default(),
             wikidata,
             wikipedia,
+            wikivoyage,
         ])?;
         count += 1;
     }
@@ -91,16 +101,18 @@ fn make_record<'i>(
 ) -> Option<Record> {
     let mut wikipedia = String::new();
     let mut wikidata = String::new();
+    let mut wikivoyage = String::new();
 
     for (key, value) in tags {
         match key {
             "wikipedia" => wikipedia = value.trim().to_owned(),
             "wikidata" => wikidata = value.trim().to_owned(),
+            "wikivoyage" => wikivoyage = value.trim().to_owned(),
             _ => {}
         }
     }
 
-    if wikidata.is_empty() && wikipedia.is_empty() {
+    if wikidata.is_empty() && wikipedia.is_empty() && wikivoyage.is_empty() {
         return None;
     }
 
@@ -110,5 +122,6 @@ fn make_record<'i>(
         version,
         wikipedia,
         wikidata,
+        wikivoyage,
     })
 }
@@ -11,7 +11,7 @@
 use std::{
     any::Any,
     borrow::Cow,
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::Display,
     ops::Deref,
     panic,
@@ -21,7 +21,7 @@ use ego_tree::NodeId;
 use markup5ever::{LocalName, Namespace, QualName};
 use once_cell::sync::Lazy;
 use scraper::{ElementRef, Html, Node, Selector};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 mod pretty;
 pub use pretty::pretty_print;
@@ -31,6 +31,12 @@ use url::Url;
 struct Config<'a> {
     #[serde(borrow)]
     sections_to_remove: BTreeMap<&'a str, BTreeSet<&'a str>>,
+    /// Selectors of elements that mark an article as a stub.
+    #[serde(borrow, default)]
+    stub_selectors: BTreeMap<&'a str, Vec<&'a str>>,
+    /// Text of the element appended by [truncate_html].
+    #[serde(borrow, default)]
+    truncation_markers: BTreeMap<&'a str, &'a str>,
 }
 
 /// Path to the processing config file.
@@ -52,9 +58,23 @@ static CONFIG: Lazy<Config<'static>> = Lazy::new(|| {
     ))
 });
 
+static STUB_SELECTORS: Lazy<BTreeMap<&'static str, Selector>> = Lazy::new(|| {
+    CONFIG
+        .stub_selectors
+        .iter()
+        .map(|(lang, selectors)| {
+            let selector = Selector::parse(&selectors.join(", "))
+                .unwrap_or_else(|e| panic!("invalid stub selector for {lang:?}: {e:?}"));
+            (*lang, selector)
+        })
+        .collect()
+});
+
 static HEADERS: Lazy<Selector> =
     Lazy::new(|| Selector::parse("h1, h2, h3, h4, h5, h6, h7").unwrap());
 
+static INFOBOXES: Lazy<Selector> = Lazy::new(|| Selector::parse(".infobox").unwrap());
+
 /// Elements that should always be kept, regardless of other metrics.
 static ELEMENT_ALLOW_LIST: Lazy<Selector> = Lazy::new(|| {
     Selector::parse(
@@ -107,20 +127,91 @@ static ELEMENT_DENY_LIST: Lazy<Selector> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Options of [simplify_with] in addition to the static config.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimplifyConfig {
+    /// CSS selectors of elements to keep, e.g. opening hours in a travel guide.
+    ///
+    /// Matching elements, their contents, and the elements containing them are never removed, even if they match a removal rule.
+    /// Their attributes are still removed, and wrapper elements are still replaced by their contents.
+    pub protect_selectors: Vec<String>,
+    /// CSS selectors of elements to remove in addition to the static config, e.g. page banners.
+    ///
+    /// Protected elements are still kept.
+    pub remove_selectors: Vec<String>,
+}
+
+impl SimplifyConfig {
+    /// Defaults for Wikivoyage travel guides: listings of places to see, eat, and sleep are kept, and page banners are removed.
+    pub fn wikivoyage() -> Self {
+        Self {
+            protect_selectors: vec![".vcard".into(), ".listing".into()],
+            remove_selectors: vec![".pagebanner".into(), ".wpb-banner".into()],
+        }
+    }
+
+    /// Check that all selectors parse.
+    ///
+    /// ```
+    /// use om_wikiparser::html::{HtmlError, SimplifyConfig};
+    ///
+    /// let config = SimplifyConfig { protect_selectors: vec!["div.hours".into(), "div[".into()], ..Default::default() };
+    /// assert!(matches!(config.validate(), Err(HtmlError::InvalidSelector(s)) if s == "div["));
+    /// ```
+    pub fn validate(&self) -> Result<(), HtmlError> {
+        parse_selectors(&self.protect_selectors)?;
+        parse_selectors(&self.remove_selectors)?;
+        Ok(())
+    }
+}
+
+/// Parse `selectors` into a single selector matching any of them, or `None` if there are none.
+fn parse_selectors(selectors: &[String]) -> Result<Option<Selector>, HtmlError> {
+    for selector in selectors {
+        if Selector::parse(selector).is_err() {
+            return Err(HtmlError::InvalidSelector(selector.to_owned()));
+        }
+    }
+    if selectors.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Selector::parse(&selectors.join(", ")).unwrap()))
+}
+
 /// Convenience wrapper around [[process]].
 pub fn process_str(html: &str, lang: &str) -> Result<String, HtmlError> {
+    process_str_with(html, lang, &SimplifyConfig::default())
+}
+
+/// Convenience wrapper around [[process_with]].
+pub fn process_str_with(
+    html: &str,
+    lang: &str,
+    config: &SimplifyConfig,
+) -> Result<String, HtmlError> {
     let document = Html::parse_document(html);
-    let document = process(document, lang)?;
+    let document = process_with(document, lang, config)?;
     Ok(document.html())
 }
 
 /// Simplify an article, checking for bad pages and failures.
-pub fn process(mut document: Html, lang: &str) -> Result<Html, HtmlError> {
+pub fn process(document: Html, lang: &str) -> Result<Html, HtmlError> {
+    process_with(document, lang, &SimplifyConfig::default())
+}
+
+/// Like [process], with the additional options of `config`.
+pub fn process_with(
+    mut document: Html,
+    lang: &str,
+    config: &SimplifyConfig,
+) -> Result<Html, HtmlError> {
+    config.validate()?;
     panic::catch_unwind(|| {
         if let Some(redirect) = detect_redirect(&document) {
             return Err(HtmlError::Redirect(redirect.to_owned()));
         }
-        simplify(&mut document, lang);
+        simplify_with(&mut document, lang, config);
         if !has_text(&document) {
             return Err(HtmlError::NoText);
         }
@@ -172,6 +263,301 @@ pub fn detect_lang(document: &Html) -> Option<String> {
         })
 }
 
+/// Direction of an article's text, see [detect_dir].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Left-to-right.
+    #[default]
+    Ltr,
+    /// Right-to-left, e.g. Arabic, Hebrew, and Persian.
+    Rtl,
+}
+
+/// Languages of wikipedias written right-to-left.
+const RTL_LANGS: &[&str] = &[
+    "ar", "arc", "ary", "arz", "azb", "bqi", "ckb", "dv", "fa", "glk", "he", "khw", "ks", "lrc",
+    "mzn", "nqo", "pnb", "ps", "sd", "skr", "ug", "ur", "yi",
+];
+
+impl Direction {
+    pub fn is_ltr(&self) -> bool {
+        *self == Direction::Ltr
+    }
+
+    /// Parse the value of a `dir` attribute.
+    ///
+    /// `auto` and invalid values have no direction.
+    pub fn from_attr(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("ltr") {
+            Some(Direction::Ltr)
+        } else if value.eq_ignore_ascii_case("rtl") {
+            Some(Direction::Rtl)
+        } else {
+            None
+        }
+    }
+
+    /// The direction the wikipedia of `lang` is written in.
+    ///
+    /// ```
+    /// use om_wikiparser::html::Direction;
+    ///
+    /// assert_eq!(Direction::Rtl, Direction::from_lang("he"));
+    /// assert_eq!(Direction::Ltr, Direction::from_lang("de"));
+    /// ```
+    pub fn from_lang(lang: &str) -> Self {
+        if RTL_LANGS.contains(&lang) {
+            Direction::Rtl
+        } else {
+            Direction::Ltr
+        }
+    }
+}
+
+/// Find the direction set by the `dir` attribute of the `html` or `body` element of the article.
+///
+/// Only the html up to the `body` start tag is parsed, so this is cheap for large articles.
+/// The attribute of `body` takes precedence, as it applies to the text.
+///
+/// ```
+/// use om_wikiparser::html::{detect_dir, Direction};
+///
+/// assert_eq!(Some(Direction::Rtl), detect_dir(r#"<html dir="rtl"><body><p>שלום</p></body></html>"#));
+/// assert_eq!(Some(Direction::Ltr), detect_dir(r#"<html dir="rtl"><body dir="ltr"><p>Hello</p></body></html>"#));
+/// assert_eq!(None, detect_dir("<html><body><p>Hello</p></body></html>"));
+/// ```
+pub fn detect_dir(html: &str) -> Option<Direction> {
+    static DIR: Lazy<Selector> = Lazy::new(|| Selector::parse("html[dir], body[dir]").unwrap());
+
+    let end = html
+        .find("<body")
+        .and_then(|start| Some(start + html[start..].find('>')? + 1))
+        .unwrap_or(html.len());
+    let document = Html::parse_document(&html[..end]);
+    document
+        .select(&DIR)
+        .filter_map(|el| Direction::from_attr(el.value().attr("dir")?))
+        .next_back()
+}
+
+/// Coordinates of the subject of an article, in decimal degrees, see [detect_coordinates].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Attempt to find the coordinates of the article's subject in its original html.
+///
+/// They are read from the first `geo` microformat of a coordinate template, e.g. `<span class="geo">53.103; -8.775</span>`.
+///
+/// ```
+/// use om_wikiparser::html::{detect_coordinates, Coordinates};
+/// use scraper::Html;
+///
+/// let document = Html::parse_document(r#"<p><span class="geo">53.103; -8.775</span></p>"#);
+/// assert_eq!(Some(Coordinates { lat: 53.103, lon: -8.775 }), detect_coordinates(&document));
+/// let document = Html::parse_document(r#"<p><span class="geo">153.1; 8.7</span></p>"#);
+/// assert_eq!(None, detect_coordinates(&document));
+/// ```
+pub fn detect_coordinates(document: &Html) -> Option<Coordinates> {
+    static GEO: Lazy<Selector> = Lazy::new(|| Selector::parse(".geo").unwrap());
+
+    document.select(&GEO).find_map(|el| {
+        let text: String = el.text().collect();
+        let (lat, lon) = text.split_once(';')?;
+        let lat: f64 = lat.trim().parse().ok()?;
+        let lon: f64 = lon.trim().parse().ok()?;
+        ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon))
+            .then_some(Coordinates { lat, lon })
+    })
+}
+
+/// Attempt to find the short description of the article in its original html, from a `{{Short description}}` template.
+///
+/// Only some wikis, like the English Wikipedia, use the template.
+pub fn detect_short_description(document: &Html) -> Option<String> {
+    static SHORT_DESCRIPTION: Lazy<Selector> =
+        Lazy::new(|| Selector::parse(".shortdescription").unwrap());
+
+    document.select(&SHORT_DESCRIPTION).find_map(|el| {
+        let text: String = el.text().collect();
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_owned())
+    })
+}
+
+/// Names of the categories of the article in its original html, in order and without the namespace.
+///
+/// They are read from the `mw:PageProp/Category` links of the html, which include hidden maintenance categories.
+///
+/// ```
+/// use om_wikiparser::html::detect_categories;
+/// use scraper::Html;
+///
+/// let document = Html::parse_document(concat!(
+///     r#"<link rel="mw:PageProp/Category" href="./Category:Towers_in_County_Galway#Thoor">"#,
+///     r#"<link rel="mw:PageProp/Category" href="./Kategorie:K%C3%B6ln">"#,
+/// ));
+/// assert_eq!(vec!["Towers in County Galway", "Köln"], detect_categories(&document));
+/// ```
+pub fn detect_categories(document: &Html) -> Vec<String> {
+    static CATEGORY: Lazy<Selector> =
+        Lazy::new(|| Selector::parse(r#"link[rel="mw:PageProp/Category"][href]"#).unwrap());
+
+    let mut categories: Vec<String> = Vec::new();
+    for el in document.select(&CATEGORY) {
+        let href = el.value().attr("href").unwrap_or_default().trim();
+        let href = href.strip_prefix("./").unwrap_or(href);
+        let href = href.split_once('#').map_or(href, |(href, _sort_key)| href);
+        let Some((_namespace, name)) = href.split_once(':') else {
+            continue;
+        };
+        let name = urlencoding::decode(name).unwrap_or_else(|_| name.into());
+        let name = name.replace('_', " ");
+        if !name.is_empty() && !categories.contains(&name) {
+            categories.push(name);
+        }
+    }
+    categories
+}
+
+/// Number of words in the text of the document, separated by whitespace.
+///
+/// Languages that are written without spaces between words, like Chinese, have far fewer.
+pub fn word_count(document: &Html) -> usize {
+    document
+        .root_element()
+        .text()
+        .map(|text| text.split_whitespace().count())
+        .sum()
+}
+
+/// Check if the article is marked as a stub by any of the language's `stub_selectors` in the config.
+///
+/// Always `false` for languages without configured selectors.
+pub fn is_stub(document: &Html, lang: &str) -> bool {
+    STUB_SELECTORS
+        .get(lang)
+        .map(|selector| document.select(selector).next().is_some())
+        .unwrap_or_default()
+}
+
+/// Shorten simplified html to at most `max_bytes`, keeping it well-formed.
+///
+/// Whole top-level elements are kept until the budget is reached, followed by a paragraph with the language's `truncation_markers` text from the config.
+/// If the first element alone is too large, its text is cut at the last sentence that fits, without any formatting.
+/// Headers without any following content are dropped.
+/// If not even the marker fits, the result is empty.
+///
+/// Html that already fits is returned unchanged.
+pub fn truncate_html(html: &str, max_bytes: usize, lang: &str) -> String {
+    if html.len() <= max_bytes {
+        return html.to_owned();
+    }
+
+    let text = CONFIG
+        .truncation_markers
+        .get(lang)
+        .or_else(|| CONFIG.truncation_markers.get("en"))
+        .copied()
+        .unwrap_or("…");
+    let marker = format!("<p><i>{}</i></p>", escape_text(text));
+    let Some(budget) = max_bytes.checked_sub(marker.len()) else {
+        return String::new();
+    };
+
+    let fragment = Html::parse_fragment(html);
+    let mut blocks: Vec<(String, bool)> = Vec::new();
+    let mut size = 0;
+    for node in fragment.root_element().children() {
+        let (block, is_header) = match node.value() {
+            Node::Text(text) => (escape_text(text), false),
+            Node::Element(_) => {
+                let el = ElementRef::wrap(node).unwrap();
+                (el.html(), HEADERS.matches(&el))
+            }
+            _ => continue,
+        };
+
+        if size + block.len() > budget {
+            if blocks.iter().all(|(_, is_header)| *is_header) {
+                let text = ElementRef::wrap(node)
+                    .map(|el| el.text().collect::<String>())
+                    .unwrap_or_else(|| node.value().as_text().unwrap().to_string());
+                if let Some(block) = truncate_sentences(&text, budget - size) {
+                    blocks.push((block, false));
+                }
+            }
+            break;
+        }
+        size += block.len();
+        blocks.push((block, is_header));
+    }
+
+    while blocks
+        .last()
+        .map(|(_, is_header)| *is_header)
+        .unwrap_or_default()
+    {
+        blocks.pop();
+    }
+
+    let mut output: String = blocks.into_iter().map(|(block, _)| block).collect();
+    output.push_str(&marker);
+    output
+}
+
+/// Wrap the longest prefix of whole sentences of `text` in a paragraph that is at most `max_bytes`.
+fn truncate_sentences(text: &str, max_bytes: usize) -> Option<String> {
+    const OVERHEAD: usize = "<p></p>".len();
+    let max_bytes = max_bytes.checked_sub(OVERHEAD)?;
+
+    let text = text.trim_start();
+    // Length of the escaped text up to the current char, and the end of the last sentence that fits.
+    let mut escaped = 0;
+    let mut fits = None;
+    for (i, c) in text.char_indices() {
+        escaped += escape_char(c).map_or(c.len_utf8(), str::len);
+        if escaped > max_bytes {
+            break;
+        }
+        if !matches!(c, '.' | '!' | '?' | '。' | '！' | '？') {
+            continue;
+        }
+        let end = i + c.len_utf8();
+        if text[end..].chars().next().is_none_or(char::is_whitespace) {
+            fits = Some(end);
+        }
+    }
+    fits.map(|end| format!("<p>{}</p>", escape_text(&text[..end])))
+}
+
+/// Escape text for use in html text nodes.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match escape_char(c) {
+            Some(reference) => escaped.push_str(reference),
+            None => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The character reference of `c` in html text nodes, if it needs one.
+fn escape_char(c: char) -> Option<&'static str> {
+    match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '\u{a0}' => Some("&nbsp;"),
+        _ => None,
+    }
+}
+
 /// Check if the html contains any non-whitespace text nodes.
 pub fn has_text(document: &Html) -> bool {
     if let Some(root) = ElementRef::wrap(document.tree.root()) {
@@ -186,6 +572,66 @@ pub fn has_text(document: &Html) -> bool {
     }
 }
 
+/// Measures of how much of an article is left after simplifying it, see [substance].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Substance {
+    /// Characters of text outside of infoboxes, excluding whitespace.
+    pub text_chars: usize,
+    /// Paragraphs with any text outside of infoboxes.
+    pub paragraphs: usize,
+    /// The article has an infobox, e.g. because it is kept with [SimplifyConfig::protect_selectors].
+    pub infobox: bool,
+}
+
+impl Substance {
+    /// Check if an infobox is all there is to the article.
+    pub fn only_infobox(&self) -> bool {
+        self.infobox && self.text_chars == 0
+    }
+}
+
+/// Measure the text and paragraphs of the document, ignoring markup, the `head`, scripts and styles, and infoboxes.
+///
+/// ```
+/// use om_wikiparser::html::substance;
+/// use scraper::Html;
+///
+/// let document = Html::parse_document(
+///     r#"<table class="infobox"><tr><td><p>Population 12</p></td></tr></table><p>Foo is a <b>village</b>.</p><p> </p>"#,
+/// );
+/// let substance = substance(&document);
+/// assert_eq!((14, 1), (substance.text_chars, substance.paragraphs));
+/// assert!(substance.infobox && !substance.only_infobox());
+/// ```
+pub fn substance(document: &Html) -> Substance {
+    let mut substance = Substance::default();
+    let mut stack = vec![document.tree.root()];
+    while let Some(node) = stack.pop() {
+        match node.value() {
+            Node::Text(text) => {
+                substance.text_chars += text.chars().filter(|c| !c.is_whitespace()).count();
+                continue;
+            }
+            Node::Element(element) => {
+                if matches!(element.name(), "head" | "script" | "style") {
+                    continue;
+                }
+                let el = ElementRef::wrap(node).unwrap();
+                if INFOBOXES.matches(&el) {
+                    substance.infobox = true;
+                    continue;
+                }
+                if element.name() == "p" && !is_empty_or_whitespace(&el) {
+                    substance.paragraphs += 1;
+                }
+            }
+            _ => {}
+        }
+        stack.extend(node.children());
+    }
+    substance
+}
+
 /// Simplify an article to only basic text.
 ///
 /// # Panics
@@ -196,15 +642,41 @@ pub fn has_text(document: &Html) -> bool {
 /// If this is undesirable, see [[process]] for a higher-level wrapper that
 /// handles panics and other errors.
 pub fn simplify(document: &mut Html, lang: &str) {
+    simplify_with(document, lang, &SimplifyConfig::default())
+}
+
+/// Like [simplify], keeping the elements protected by `config` and removing its additional elements.
+///
+/// # Panics
+///
+/// In addition to the panics of [simplify], if any of the selectors of `config` are invalid.
+pub fn simplify_with(document: &mut Html, lang: &str, config: &SimplifyConfig) {
+    let (protect, remove) = match (
+        parse_selectors(&config.protect_selectors),
+        parse_selectors(&config.remove_selectors),
+    ) {
+        (Ok(protect), Ok(remove)) => (protect, remove),
+        (Err(e), _) | (_, Err(e)) => panic!("{e}"),
+    };
+    let protected = match protect {
+        Some(selector) => protected_ids(document, &selector),
+        None => HashSet::new(),
+    };
+
     if let Some(titles) = CONFIG.sections_to_remove.get(lang) {
-        remove_named_header_siblings(document, titles);
+        remove_named_header_siblings(document, titles, &protected);
     }
 
-    remove_denylist_elements(document);
+    remove_denylist_elements(document, &protected);
 
-    remove_empty_sections(document);
+    if let Some(selector) = remove {
+        let to_remove: Vec<_> = document.select(&selector).map(|el| el.id()).collect();
+        remove_unprotected(document, to_remove, &protected);
+    }
+
+    remove_empty_sections(document, &protected);
 
-    remove_empty(document);
+    remove_empty(document, &protected);
 
     remove_non_element_nodes(document);
 
@@ -223,11 +695,42 @@ fn remove_ids(document: &mut Html, ids: impl IntoIterator<Item = NodeId>) {
     }
 }
 
+/// The elements matching `selector`, every node they contain, and the elements containing them.
+///
+/// Removing any of these would remove a protected element.
+fn protected_ids(document: &Html, selector: &Selector) -> HashSet<NodeId> {
+    let mut protected = HashSet::new();
+    for el in document.select(selector) {
+        if !protected.insert(el.id()) {
+            continue;
+        }
+        protected.extend(el.descendants().map(|node| node.id()));
+        protected.extend(el.ancestors().map(|node| node.id()));
+    }
+    protected
+}
+
+/// [remove_ids] for a removal rule, keeping any `protected` nodes.
+fn remove_unprotected(
+    document: &mut Html,
+    ids: impl IntoIterator<Item = NodeId>,
+    protected: &HashSet<NodeId>,
+) {
+    remove_ids(
+        document,
+        ids.into_iter().filter(|id| !protected.contains(id)),
+    );
+}
+
 /// Remove headers with the specified `titles` and all following siblings until the next header greater or equal level.
 ///
 /// `titles` are matched by case-sensitive simple byte comparison.
 /// `titles` should be normalized to Unicode NFC to match Wikipedia's internal normalization: <https://mediawiki.org/wiki/Unicode_normalization_considerations>.
-fn remove_named_header_siblings(document: &mut Html, titles: &BTreeSet<&str>) {
+fn remove_named_header_siblings(
+    document: &mut Html,
+    titles: &BTreeSet<&str>,
+    protected: &HashSet<NodeId>,
+) {
     let mut to_remove = Vec::new();
 
     for header in document.select(&HEADERS) {
@@ -253,10 +756,10 @@ fn remove_named_header_siblings(document: &mut Html, titles: &BTreeSet<&str>) {
         }
     }
 
-    remove_ids(document, to_remove.drain(..));
+    remove_unprotected(document, to_remove.drain(..), protected);
 }
 
-fn remove_denylist_elements(document: &mut Html) {
+fn remove_denylist_elements(document: &mut Html, protected: &HashSet<NodeId>) {
     let mut to_remove = Vec::new();
     for el in document
         .root_element()
@@ -267,7 +770,7 @@ fn remove_denylist_elements(document: &mut Html) {
             to_remove.push(el.id());
         }
     }
-    remove_ids(document, to_remove.drain(..));
+    remove_unprotected(document, to_remove.drain(..), protected);
 }
 
 fn remove_non_element_nodes(document: &mut Html) {
@@ -307,7 +810,7 @@ fn remove_toplevel_whitespace(document: &mut Html) {
     remove_ids(document, to_remove.drain(..));
 }
 
-fn remove_empty(document: &mut Html) {
+fn remove_empty(document: &mut Html, protected: &HashSet<NodeId>) {
     let mut to_remove = Vec::new();
 
     for el in document
@@ -320,10 +823,10 @@ fn remove_empty(document: &mut Html) {
         }
     }
 
-    remove_ids(document, to_remove.drain(..));
+    remove_unprotected(document, to_remove.drain(..), protected);
 }
 
-fn remove_empty_sections(document: &mut Html) {
+fn remove_empty_sections(document: &mut Html, protected: &HashSet<NodeId>) {
     let mut to_remove = Vec::new();
     for el in document.select(&HEADERS) {
         let Some(parent) = el.parent() else {
@@ -355,7 +858,7 @@ fn remove_empty_sections(document: &mut Html) {
         }
     }
 
-    remove_ids(document, to_remove);
+    remove_unprotected(document, to_remove, protected);
 }
 
 fn remove_attrs(document: &mut Html) {
@@ -461,6 +964,8 @@ pub enum HtmlError {
     Redirect(String),
     #[error("page has no text after processing")]
     NoText,
+    #[error("invalid css selector {0:?}")]
+    InvalidSelector(String),
 }
 
 /// Error wrapper around panic payloads that handles static and formatted messages.
@@ -502,6 +1007,44 @@ mod test {
     #[test]
     fn static_config_parses() {
         assert!(!CONFIG.sections_to_remove.is_empty());
+        assert!(!STUB_SELECTORS.is_empty());
+        assert!(CONFIG.truncation_markers.contains_key("en"));
+    }
+
+    #[test]
+    fn truncate_sentences_fits() {
+        let text = "One. Two! Three? Four";
+        assert_eq!(None, truncate_sentences(text, 10));
+        assert_eq!(Some("<p>One.</p>".into()), truncate_sentences(text, 11));
+        assert_eq!(
+            Some("<p>One. Two!</p>".into()),
+            truncate_sentences(text, 20)
+        );
+        assert_eq!(
+            Some("<p>One. Two! Three?</p>".into()),
+            truncate_sentences(text, 100)
+        );
+        // Not a sentence end.
+        assert_eq!(None, truncate_sentences("3.5 km", 100));
+        // The escaped text has to fit.
+        assert_eq!(
+            Some("<p>A &amp; B.</p>".into()),
+            truncate_sentences("  A & B. C & D.", 20)
+        );
+        assert_eq!(None, truncate_sentences("A & B.", 16));
+    }
+
+    #[test]
+    fn detect_stub() {
+        let stub = Html::parse_document(
+            r#"<p>Foo is a village.</p><div class="metadata plainlinks asbox stub" role="presentation"><p>This article is a stub.</p></div>"#,
+        );
+        assert!(is_stub(&stub, "en"));
+        // No selectors for lang.
+        assert!(!is_stub(&stub, "de"));
+
+        let not_stub = Html::parse_document(r#"<p>Foo is a village.</p>"#);
+        assert!(!is_stub(&not_stub, "en"));
     }
 
     /// Ensure config sections match Wikipedia's Unicode normalization (NFC) so
@@ -535,6 +1078,78 @@ mod test {
         );
     }
 
+    #[test]
+    fn protected_elements_survive_removal() {
+        let html = r#"
+            <p>Visit the museum.</p>
+            <div class="listing">
+                <p class="hours" id="mwAB" data-mw="{}">Open 9-17</p>
+                <p>Free on Sundays.</p>
+            </div>
+            <table class="hours noprint"><tr><td>Closed on Mondays</td></tr></table>
+            <figure><img src="museum.jpg"></figure>
+            <h2>References</h2>
+            <p>Ref <i class="hours"></i></p>
+        "#;
+        let config = SimplifyConfig {
+            protect_selectors: vec![".hours".into()],
+            ..Default::default()
+        };
+
+        let mut document = Html::parse_fragment(html);
+        simplify(&mut document, "en");
+        let unprotected = document.html();
+        assert!(!unprotected.contains("Open 9-17"), "{unprotected}");
+        assert!(!unprotected.contains("Closed on Mondays"), "{unprotected}");
+
+        let mut document = Html::parse_fragment(html);
+        simplify_with(&mut document, "en", &config);
+        let output = document.html();
+        eprintln!("{output}");
+        // Inside a removed `div`.
+        assert!(output.contains("Open 9-17"));
+        // Matching the `table` and `.noprint` removal rules.
+        assert!(output.contains("Closed on Mondays"));
+        // Empty, in a removed section.
+        assert!(output.contains("<p>Ref <i class=\"hours\"></i></p>"));
+
+        // Removal rules still apply to unprotected elements.
+        assert!(!output.contains("<img"));
+        assert!(!output.contains("<figure"));
+        // Attributes of protected elements are still removed.
+        assert!(!output.contains("mwAB"));
+        assert!(!output.contains("data-mw"));
+    }
+
+    #[test]
+    fn invalid_protect_selector() {
+        let config = SimplifyConfig {
+            protect_selectors: vec!["p[".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            Err(HtmlError::InvalidSelector("p[".into())),
+            process_str_with("<p>Text</p>", "en", &config)
+        );
+    }
+
+    #[test]
+    fn wikivoyage_defaults() {
+        let html = r#"
+            <p class="pagebanner"><span>Berlin banner</span></p>
+            <h2>See</h2>
+            <ul>
+                <li><span class="vcard"><span class="fn">Pergamon Museum</span><span class="listing-content">Ancient art.</span></span></li>
+            </ul>
+        "#;
+        let mut document = Html::parse_fragment(html);
+        simplify_with(&mut document, "en", &SimplifyConfig::wikivoyage());
+        let output = document.html();
+        assert!(!output.contains("Berlin banner"), "{output}");
+        assert!(output.contains("Pergamon Museum"), "{output}");
+        assert!(output.contains("Ancient art."), "{output}");
+    }
+
     fn expand_links(document: &mut Html) {
         let links: Vec<_> = document
             .select(&Selector::parse("a").unwrap())
@@ -621,7 +1236,11 @@ mod test {
             "paragraphs in original html are not expected"
         );
 
-        remove_named_header_siblings(&mut document, &BTreeSet::from_iter(Some("Section 1")));
+        remove_named_header_siblings(
+            &mut document,
+            &BTreeSet::from_iter(Some("Section 1")),
+            &HashSet::new(),
+        );
 
         eprintln!("{}", document.html());
 
@@ -1,52 +1,264 @@
-use std::{
-    io::{self, BufRead},
-    str::FromStr,
-};
+use std::io::{self, BufRead};
 
 #[macro_use]
 extern crate log;
 
 pub mod html;
+mod limits;
+mod spill;
+pub use limits::{ReadLimits, TooLongError};
 pub mod osm;
+mod report;
+pub use report::{LogReporter, LogThrottle};
 mod tag_file;
 pub use tag_file::*;
 pub mod extend;
+pub mod extract;
+pub mod fixes;
 pub mod wm;
 
+use limits::{read_text_line_capped, CappedLine};
 use wm::{Qid, Title};
 
-/// Read from a file of urls on each line.
-pub fn parse_wikidata_file(r: impl BufRead, collection: &mut impl Extend<Qid>) -> io::Result<()> {
-    for (i, line) in r.lines().enumerate() {
-        let line = line?;
-        match Qid::from_str(&line) {
-            Ok(qid) => collection.extend(Some(qid)),
-            Err(e) => {
-                let line_num = i + 1;
-                warn!("Could not parse QID: on line {line_num}: {line:?}: {:#}", e);
-            }
+/// How to read a QID or title list file in [parse_wikidata_file_with] and the similar functions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ListFileOptions {
+    /// Lines longer than [ReadLimits::max_line_bytes] are skipped as errors.
+    pub limits: ReadLimits,
+    /// Fail on the first line that cannot be parsed, with its [ParseLineError], instead of collecting it and continuing.
+    pub strict: bool,
+}
+
+impl ListFileOptions {
+    /// The default limits, failing on the first bad line.
+    ///
+    /// ```
+    /// use om_wikiparser::{parse_wikidata_file_with, ListFileOptions};
+    ///
+    /// let mut qids = Vec::new();
+    /// let e = parse_wikidata_file_with("Q1\nbad\nQ2\n".as_bytes(), &mut qids, &mut Vec::new(), &ListFileOptions::strict())
+    ///     .unwrap_err();
+    /// assert!(e.to_string().contains("line 2"), "{e}");
+    /// assert_eq!(1, qids.len());
+    /// ```
+    pub fn strict() -> Self {
+        Self {
+            strict: true,
+            ..Self::default()
         }
     }
-    Ok(())
+}
+
+/// Read from a file of QIDs on each line.
+///
+/// Urls of Wikidata items, like `https://www.wikidata.org/wiki/Q42`, are parsed with [Qid::from_url].
+/// Lines can end with `\n`, `\r\n`, or a lone `\r`, and none of them are part of the parsed values.
+/// Lines that cannot be parsed are logged with [log_line_errors].
+/// To collect them instead, use [parse_wikidata_file_with].
+///
+/// ```
+/// use om_wikiparser::parse_wikidata_file;
+///
+/// let mut qids = Vec::new();
+/// parse_wikidata_file("Q1\nnot a qid\nhttps://www.wikidata.org/wiki/Q2\n".as_bytes(), &mut qids).unwrap();
+/// assert_eq!(2, qids.len());
+/// ```
+pub fn parse_wikidata_file(r: impl BufRead, collection: &mut impl Extend<Qid>) -> io::Result<()> {
+    parse_wikidata_file_with(
+        r,
+        collection,
+        &mut log_line_errors(),
+        &ListFileOptions::default(),
+    )
+}
+
+/// Like [parse_wikidata_file], adding the lines that cannot be parsed to `line_errors`, and skipping lines longer than the [ListFileOptions::limits] as errors.
+///
+/// Only the first bytes of a long line are read into memory.
+///
+/// ```
+/// use om_wikiparser::{parse_wikidata_file_with, ListFileOptions, ParseErrorKind, ReadLimits, TooLongError};
+///
+/// let options = ListFileOptions {
+///     limits: ReadLimits { max_line_bytes: 8, ..Default::default() },
+///     ..Default::default()
+/// };
+/// let input = format!("Q1\n{}\nQ2\n", "9".repeat(1 << 20));
+/// let mut qids = Vec::new();
+/// let mut errors = Vec::new();
+/// parse_wikidata_file_with(input.as_bytes(), &mut qids, &mut errors, &options).unwrap();
+/// assert_eq!(2, qids.len());
+/// assert_eq!(2, errors[0].line);
+/// assert!(matches!(
+///     errors[0].kind,
+///     ParseErrorKind::TooLong(TooLongError { len: 1048577, max: 8 })
+/// ));
+/// ```
+pub fn parse_wikidata_file_with(
+    r: impl BufRead,
+    collection: &mut impl Extend<Qid>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &ListFileOptions,
+) -> io::Result<()> {
+    parse_list_file(r, Qid::parse_value, collection, line_errors, options)
 }
 
 /// Read article titles from a file of urls on each line.
+///
+/// Line endings are handled like [parse_wikidata_file].
+/// Lines that cannot be parsed are logged with [log_line_errors].
+/// To collect them instead, use [parse_wikipedia_file_with].
 pub fn parse_wikipedia_file(
     r: impl BufRead,
     collection: &mut impl Extend<Title>,
 ) -> io::Result<()> {
-    for (i, line) in r.lines().enumerate() {
-        let line = line?;
-        match Title::from_osm_tag(&line) {
-            Ok(title) => collection.extend(Some(title)),
-            Err(e) => {
-                let line_num = i + 1;
-                warn!(
-                    "Could not parse wikipedia title: on line {line_num}: {line:?}: {:#}",
-                    e
-                );
+    parse_wikipedia_file_with(
+        r,
+        collection,
+        &mut log_line_errors(),
+        &ListFileOptions::default(),
+    )
+}
+
+/// Like [parse_wikipedia_file], adding the lines that cannot be parsed to `line_errors`, and skipping lines longer than the [ListFileOptions::limits] as errors.
+pub fn parse_wikipedia_file_with(
+    r: impl BufRead,
+    collection: &mut impl Extend<Title>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &ListFileOptions,
+) -> io::Result<()> {
+    parse_list_file(r, Title::from_osm_tag, collection, line_errors, options)
+}
+
+/// Like [parse_wikipedia_file_with], parsing each line with [Title::from_osm_tag_with] and `title_options`.
+///
+/// For example, a [wm::ParseOptions::default_lang] reads a list of bare article names for a single-language dump.
+///
+/// ```
+/// use om_wikiparser::{parse_wikipedia_file_with_options, wm::{ParseOptions, Title}, ListFileOptions};
+///
+/// let options = ParseOptions { default_lang: Some("en".into()), ..Default::default() };
+/// let mut titles = Vec::new();
+/// let mut errors = Vec::new();
+/// parse_wikipedia_file_with_options(
+///     "Berlin\nde:Köln\n".as_bytes(),
+///     &mut titles,
+///     &mut errors,
+///     &ListFileOptions::default(),
+///     &options,
+/// )
+/// .unwrap();
+/// assert!(errors.is_empty());
+/// assert_eq!(
+///     vec![Title::from_title("Berlin", "en").unwrap(), Title::from_title("Köln", "de").unwrap()],
+///     titles
+/// );
+/// ```
+pub fn parse_wikipedia_file_with_options(
+    r: impl BufRead,
+    collection: &mut impl Extend<Title>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &ListFileOptions,
+    title_options: &wm::ParseOptions,
+) -> io::Result<()> {
+    parse_list_file(
+        r,
+        |line| Title::from_osm_tag_with(line, title_options),
+        collection,
+        line_errors,
+        options,
+    )
+}
+
+/// Like [parse_wikipedia_file_with], also returning the line that each title was parsed from, cleaned with [wm::clean_value].
+pub fn parse_wikipedia_file_raw(
+    r: impl BufRead,
+    collection: &mut impl Extend<(Title, String)>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &ListFileOptions,
+) -> io::Result<()> {
+    parse_wikipedia_file_raw_with_options(
+        r,
+        collection,
+        line_errors,
+        options,
+        &wm::ParseOptions::default(),
+    )
+}
+
+/// Like [parse_wikipedia_file_raw], parsing each line with `title_options` like [parse_wikipedia_file_with_options].
+pub fn parse_wikipedia_file_raw_with_options(
+    r: impl BufRead,
+    collection: &mut impl Extend<(Title, String)>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &ListFileOptions,
+    title_options: &wm::ParseOptions,
+) -> io::Result<()> {
+    parse_list_file(
+        r,
+        |line| {
+            Title::from_osm_tag_with(line, title_options)
+                .map(|title| (title, wm::clean_value(line).into_owned()))
+        },
+        collection,
+        line_errors,
+        options,
+    )
+}
+
+fn parse_list_file<T, E: Into<ParseErrorKind>>(
+    mut r: impl BufRead,
+    parse: impl Fn(&str) -> Result<T, E>,
+    collection: &mut impl Extend<T>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &ListFileOptions,
+) -> io::Result<()> {
+    let limits = &options.limits;
+    let mut buf = Vec::new();
+    for i in 0.. {
+        buf.clear();
+        let (kind, line) = match read_text_line_capped(&mut r, &mut buf, limits.max_line_bytes)? {
+            CappedLine::Eof => break,
+            CappedLine::Line(_) => {
+                let line = std::str::from_utf8(&buf)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let line = line.strip_suffix('\n').unwrap_or(line);
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                match parse(line) {
+                    Ok(item) => {
+                        collection.extend(Some(item));
+                        continue;
+                    }
+                    Err(e) => (e.into(), line.to_owned()),
+                }
+            }
+            CappedLine::TooLong(len) => {
+                let text = String::from_utf8_lossy(&buf);
+                let error = TooLongError {
+                    len,
+                    max: limits.max_line_bytes,
+                };
+                (error.into(), limits::prefix(&text, 64).to_owned())
             }
+        };
+        let error = ParseLineError {
+            kind,
+            text: line,
+            line: i + 1,
+            osm_id: None,
+            osm_type: None,
+            osm_version: None,
+            tag: None,
+        };
+        if options.strict {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
         }
+        line_errors.extend(Some(error));
     }
     Ok(())
 }
+
+/// Log each [ParseLineError] at the warn level, suppressing repeated errors with the default [LogReporter].
+pub fn log_line_errors() -> LogReporter {
+    LogReporter::default()
+}
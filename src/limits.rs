@@ -0,0 +1,224 @@
+use std::io::{self, BufRead};
+
+/// Limits on the size of input lines and values, so corrupted input is skipped without being read into memory.
+///
+/// They are part of the [crate::ListFileOptions] and [crate::TagFileOptions] of the input files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadLimits {
+    /// Maximum bytes of a line in a QID or title list file, excluding the line ending.
+    pub max_line_bytes: usize,
+    /// Maximum bytes of a `wikidata` or `wikipedia` value in an OSM tag file.
+    ///
+    /// The TSV record containing the value is still read into memory.
+    pub max_field_bytes: usize,
+}
+
+impl Default for ReadLimits {
+    /// 4 MiB lines and 64 KiB tag values.
+    fn default() -> Self {
+        Self {
+            max_line_bytes: 4 << 20,
+            max_field_bytes: 64 << 10,
+        }
+    }
+}
+
+/// A line or value exceeded a [ReadLimits] limit.
+///
+/// The length of a line includes its line ending.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{len} bytes is longer than the limit of {max} bytes")]
+pub struct TooLongError {
+    pub len: usize,
+    pub max: usize,
+}
+
+/// A line read with [read_line_capped].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CappedLine {
+    /// No more lines could be read.
+    Eof,
+    /// The line of this many bytes, including the line ending, was appended to the buffer.
+    Line(usize),
+    /// The line of this many bytes was longer than the limit.
+    ///
+    /// Only its first bytes up to the limit were appended to the buffer, the rest was discarded.
+    TooLong(usize),
+}
+
+/// Like [BufRead::read_until] with a newline, without storing more than `max` bytes of the line excluding the newline.
+pub(crate) fn read_line_capped(
+    r: &mut impl BufRead,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> io::Result<CappedLine> {
+    read_capped(r, buf, max, false)
+}
+
+/// Like [read_line_capped], also ending lines at a lone `\r`, as in files from classic Mac OS.
+///
+/// A `\r\n` is a single line ending, which is appended to the buffer whole.
+pub(crate) fn read_text_line_capped(
+    r: &mut impl BufRead,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> io::Result<CappedLine> {
+    read_capped(r, buf, max, true)
+}
+
+fn read_capped(
+    r: &mut impl BufRead,
+    buf: &mut Vec<u8>,
+    max: usize,
+    lone_cr: bool,
+) -> io::Result<CappedLine> {
+    let start = buf.len();
+    let mut total = 0;
+    let mut too_long = false;
+    let mut ended_by_cr = false;
+    loop {
+        let available = match r.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            break;
+        }
+        let end = available
+            .iter()
+            .position(|&b| b == b'\n' || (lone_cr && b == b'\r'));
+        let (chunk, done) = match end {
+            Some(i) => (&available[..=i], true),
+            None => (available, false),
+        };
+        ended_by_cr = done && chunk.last() == Some(&b'\r');
+
+        let content = chunk.len() - done as usize;
+        if !too_long {
+            let stored = buf.len() - start;
+            if stored + content > max {
+                buf.extend_from_slice(&chunk[..max - stored]);
+                too_long = true;
+            } else {
+                buf.extend_from_slice(chunk);
+            }
+        }
+
+        let len = chunk.len();
+        r.consume(len);
+        total += len;
+        if done {
+            break;
+        }
+    }
+
+    // The `\n` of a `\r\n` can be in the next chunk.
+    if ended_by_cr {
+        let next = loop {
+            match r.fill_buf() {
+                Ok(available) => break available.first().copied(),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if next == Some(b'\n') {
+            r.consume(1);
+            total += 1;
+            if !too_long {
+                buf.push(b'\n');
+            }
+        }
+    }
+
+    Ok(match (total, too_long) {
+        (0, _) => CappedLine::Eof,
+        (total, false) => CappedLine::Line(total),
+        (total, true) => CappedLine::TooLong(total),
+    })
+}
+
+/// The first bytes of `s` for context in errors, up to a char boundary.
+pub(crate) fn prefix(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capped_lines() {
+        let mut r = "short\r\nthis line is too long\nlast".as_bytes();
+        let mut buf = Vec::new();
+
+        assert_eq!(
+            CappedLine::Line(7),
+            read_line_capped(&mut r, &mut buf, 10).unwrap()
+        );
+        assert_eq!(b"short\r\n", buf.as_slice());
+
+        buf.clear();
+        assert_eq!(
+            CappedLine::TooLong(22),
+            read_line_capped(&mut r, &mut buf, 10).unwrap()
+        );
+        assert_eq!(b"this line ", buf.as_slice());
+
+        buf.clear();
+        assert_eq!(
+            CappedLine::Line(4),
+            read_line_capped(&mut r, &mut buf, 10).unwrap()
+        );
+        assert_eq!(b"last", buf.as_slice());
+
+        buf.clear();
+        assert_eq!(
+            CappedLine::Eof,
+            read_line_capped(&mut r, &mut buf, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn lone_carriage_returns() {
+        // A small buffer splits the `\r\n` across reads.
+        let mut r = io::BufReader::with_capacity(4, "one\rtwo\r\nthree\rfour".as_bytes());
+        let mut lines = Vec::new();
+        let mut buf = Vec::new();
+        while let CappedLine::Line(len) = read_text_line_capped(&mut r, &mut buf, 10).unwrap() {
+            assert_eq!(buf.len(), len);
+            lines.push(String::from_utf8(std::mem::take(&mut buf)).unwrap());
+        }
+        assert_eq!(vec!["one\r", "two\r\n", "three\r", "four"], lines);
+
+        // Only newlines end the lines of the dumps.
+        let mut buf = Vec::new();
+        assert_eq!(
+            CappedLine::Line(7),
+            read_line_capped(&mut "one\rtwo".as_bytes(), &mut buf, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn newline_not_counted() {
+        let mut buf = Vec::new();
+        assert_eq!(
+            CappedLine::Line(4),
+            read_line_capped(&mut "abc\n".as_bytes(), &mut buf, 3).unwrap()
+        );
+    }
+
+    #[test]
+    fn prefix_char_boundary() {
+        assert_eq!("Kö", prefix("Köln", 3));
+        assert_eq!("K", prefix("Köln", 2));
+        assert_eq!("Köln", prefix("Köln", 10));
+    }
+}
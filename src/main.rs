@@ -2,11 +2,12 @@ use std::{
     collections::HashSet,
     env,
     fs::File,
-    io::{stderr, stdin, stdout, BufReader, IsTerminal, Read, Write},
+    io::{stderr, stdin, stdout, BufReader, BufWriter, IsTerminal, Read, Write},
     num::NonZeroUsize,
     path::PathBuf,
     process,
     str::FromStr,
+    sync::atomic::AtomicBool,
     thread::available_parallelism,
     time::Instant,
 };
@@ -17,7 +18,11 @@ use clap::{CommandFactory, Parser, Subcommand};
 extern crate tracing;
 use tracing_subscriber::{filter::EnvFilter, Layer};
 
-use om_wikiparser::osm;
+use om_wikiparser::{
+    fixes::{suggest_fixes, FixRule},
+    osm,
+    wm::plan,
+};
 
 mod get_articles;
 mod get_tags;
@@ -30,9 +35,11 @@ struct Args {
     cmd: Cmd,
 }
 
+// Only created once, so the size of the `get-articles` options doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Cmd {
-    /// Extract wikidata/wikipedia tags from an OpenStreetMap PBF dump.
+    /// Extract wikidata/wikipedia/wikivoyage tags from an OpenStreetMap PBF dump.
     ///
     /// Writes to stdout the extracted tags in a TSV format similar to `osmconvert --csv`.
     /// Unlike `osmconvert`, this **does not** truncate long tag values and create invalid UTF-8.
@@ -58,6 +65,18 @@ enum Cmd {
         /// If `@id`, `@version`, and `@otype` or `@oname` columns are present, they will be added to the output for additional context.
         #[arg(value_name = "FILE.tsv")]
         osm_tags: PathBuf,
+
+        /// Write suggested fixes of the tags to the provided file.
+        ///
+        /// Each line of the TSV file contains the OSM object (e.g. `node/1234`), the tag key, the current value, the suggested value, and the rule that found it.
+        /// An empty suggested value means the tag should be removed.
+        /// Fixes are only suggested when they are unambiguous, and for objects that have `@id` and `@otype` or `@oname` columns.
+        #[arg(long, value_name = "FILE.tsv")]
+        write_fixes: Option<PathBuf>,
+
+        /// The kinds of fixes to suggest with `--write-fixes`.
+        #[arg(long, value_enum, value_delimiter = ',', default_values_t = FixRule::ALL.to_vec())]
+        fix_rules: Vec<FixRule>,
     },
 
     /// Extract, filter, and simplify article HTML from Wikipedia Enterprise HTML dumps.
@@ -65,6 +84,70 @@ enum Cmd {
     /// Expects an uncompressed dump (newline-delimited JSON) connected to stdin.
     GetArticles(get_articles::Args),
 
+    /// Write html index pages for browsing a directory of extracted articles.
+    ///
+    /// Uses the manifest written by `get-articles --write-manifest`.
+    /// Creates `index.html` in the directory, linking to pages of articles for each language in the `index/` subdirectory.
+    WriteIndexes {
+        /// Directory the articles were extracted to.
+        output_dir: PathBuf,
+
+        /// The number of articles to list on each page.
+        #[arg(long, default_value = "1000")]
+        page_size: NonZeroUsize,
+    },
+
+    /// Check that the sidecars written by `get-articles --write-sidecars` agree with the manifest.
+    ///
+    /// Writes each article that is missing a sidecar or disagrees with it to stdout, and fails if there are any.
+    CheckSidecars {
+        /// Directory the articles were extracted to.
+        output_dir: PathBuf,
+    },
+
+    /// Plan an extraction run without reading the dumps past their first pages, and write the plan to stdout as JSON.
+    ///
+    /// The plan lists the dumps, the QIDs and titles to extract, and the articles of a previous run in the output directory that are no longer requested.
+    /// Fails if a dump is not in its language from the config's `expected_langs` or its file name (e.g. `dewiki-NS0-...json`), or has pages in more than one language without `allow_mixed_langs`.
+    /// Run it with `execute-plan` after reviewing it.
+    Plan {
+        /// JSON file of the dumps, input files, and `get-articles` options to plan a run of.
+        config: PathBuf,
+    },
+
+    /// Run the extraction described by a JSON plan from the `plan` command.
+    ///
+    /// Fails if a dump changed size since the plan was built.
+    ExecutePlan {
+        /// JSON file written by `plan`.
+        plan: PathBuf,
+    },
+
+    /// Write an index of the byte offset of each page in a dump, for reading single pages with `get-page`.
+    ///
+    /// Only uncompressed dumps (newline-delimited JSON) can be indexed.
+    IndexDump {
+        /// The dump to index.
+        dump: PathBuf,
+        /// TSV file to write the index to.
+        index: PathBuf,
+        /// Skip pages of the dump that are longer than this many bytes.
+        ///
+        /// Defaults to 64 MiB.
+        #[arg(long, value_name = "BYTES")]
+        max_page_bytes: Option<usize>,
+    },
+
+    /// Write the JSON of a single page of a dump to stdout, using the index from `index-dump`.
+    ///
+    /// Fails if no page matches.
+    GetPage {
+        dump: PathBuf,
+        index: PathBuf,
+        /// A QID like `Q64`, or a title like `en:Berlin` or its url.
+        key: String,
+    },
+
     /// Apply html simplification to a single article.
     ///
     /// Reads from stdin and writes the simplified version to stdout.
@@ -73,6 +156,16 @@ enum Cmd {
         /// The language to use when processing the article (tries to detect it by default, falling back to `en`).
         #[arg(long)]
         lang: Option<String>,
+
+        /// Keep elements matching this CSS selector, even if they match a removal rule.
+        ///
+        /// Can be repeated.
+        #[arg(long, value_name = "SELECTOR")]
+        protect_selector: Vec<String>,
+
+        /// Use the defaults for Wikivoyage travel guides, keeping listings and removing page banners.
+        #[arg(long)]
+        wikivoyage: bool,
     },
 }
 
@@ -97,7 +190,9 @@ fn main() -> anyhow::Result<()> {
                 .exit()
             }
 
-            warn_if_stdin_is_tty();
+            if !args.validate_only {
+                warn_if_stdin_is_tty();
+            }
 
             let pid = process::id();
             let span = info_span!("", pid);
@@ -117,14 +212,43 @@ fn main() -> anyhow::Result<()> {
             let pbf_file = File::open(pbf_file).map(BufReader::new)?;
             get_tags::run(pbf_file)
         }
-        Cmd::CheckTags { osm_tags } => {
+        Cmd::CheckTags {
+            osm_tags,
+            write_fixes,
+            fix_rules,
+        } => {
             let mut qids = HashSet::new();
             let mut titles = HashSet::new();
             let mut errors = Vec::new();
             info!("Reading osm tag file");
-            let file = File::open(osm_tags)?;
+            let file = File::open(&osm_tags)?;
             om_wikiparser::parse_osm_tag_file(file, &mut qids, &mut titles, &mut errors)?;
-            info!("Found {} errors in tag file", errors.len());
+
+            if let Some(path) = write_fixes {
+                let fixes = suggest_fixes(File::open(&osm_tags)?, &fix_rules)?;
+                info!("Writing {} suggested fixes to {path:?}", fixes.len());
+                let mut writer = csv::WriterBuilder::new()
+                    .delimiter(b'\t')
+                    .from_path(&path)
+                    .with_context(|| format!("creating fixes file {path:?}"))?;
+                writer.write_record(["object", "key", "old_value", "suggested_value", "rule"])?;
+                for fix in fixes {
+                    writer.write_record([
+                        &fix.object(),
+                        fix.key,
+                        &fix.old_value,
+                        &fix.suggested_value,
+                        fix.rule.name(),
+                    ])?;
+                }
+                writer
+                    .flush()
+                    .with_context(|| format!("writing fixes file {path:?}"))?;
+            }
+            info!(
+                "Found {} in tag file",
+                om_wikiparser::ErrorSummary::new(&errors)
+            );
 
             let mut writer = csv::WriterBuilder::new()
                 .delimiter(b'\t')
@@ -137,7 +261,7 @@ fn main() -> anyhow::Result<()> {
                 let key = match error.kind {
                     Title(_) => "wikipedia",
                     Qid(_) => "wikidata",
-                    Tsv(_) => "",
+                    Tsv(_) | TooLong(_) => "",
                 };
 
                 // Url or id.
@@ -158,6 +282,7 @@ fn main() -> anyhow::Result<()> {
                     Title(e) => e.into(),
                     Qid(e) => e.into(),
                     Tsv(e) => e.into(),
+                    TooLong(e) => e.into(),
                 };
                 let msg = format!("{:#}", e);
 
@@ -173,7 +298,103 @@ fn main() -> anyhow::Result<()> {
 
             Ok(())
         }
-        Cmd::Simplify { lang } => {
+        Cmd::WriteIndexes {
+            output_dir,
+            page_size,
+        } => {
+            let manifest = om_wikiparser::wm::output::Manifest::read(&output_dir)?;
+            info!("Writing index of {} articles", manifest.entries.len());
+            om_wikiparser::wm::output::write_indexes(&output_dir, &manifest, page_size)?;
+            Ok(())
+        }
+        Cmd::CheckSidecars { output_dir } => {
+            let manifest = om_wikiparser::wm::output::Manifest::read(&output_dir)?;
+            let mismatches = om_wikiparser::wm::output::check_sidecars(&output_dir, &manifest);
+            let mut stdout = stdout().lock();
+            for mismatch in &mismatches {
+                writeln!(stdout, "{mismatch}")?;
+            }
+            if !mismatches.is_empty() {
+                anyhow::bail!(
+                    "{} of {} articles have missing or mismatched sidecars",
+                    mismatches
+                        .iter()
+                        .map(|m| &m.path)
+                        .collect::<HashSet<_>>()
+                        .len(),
+                    manifest.entries.len()
+                );
+            }
+            info!("Checked sidecars of {} articles", manifest.entries.len());
+            Ok(())
+        }
+        Cmd::Plan { config } => {
+            let config: plan::RunConfig = serde_json::from_reader(BufReader::new(
+                File::open(&config).with_context(|| format!("opening config {:?}", config))?,
+            ))
+            .with_context(|| format!("reading config {:?}", config))?;
+            let plan = plan::build(&config)?;
+            info!(
+                dumps = plan.dumps.len(),
+                qids = plan.qids.len(),
+                titles = plan.titles.len(),
+                pruned = plan.pruned.len(),
+                "Built plan"
+            );
+            let mut stdout = stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &plan)?;
+            writeln!(stdout)?;
+            Ok(())
+        }
+        Cmd::ExecutePlan { plan: path } => {
+            let plan: plan::RunPlan = serde_json::from_reader(BufReader::new(
+                File::open(&path).with_context(|| format!("opening plan {:?}", path))?,
+            ))
+            .with_context(|| format!("reading plan {:?}", path))?;
+            let stats = plan::execute(&plan, stdout(), &AtomicBool::new(false))?;
+            eprint!("{stats}");
+            Ok(())
+        }
+        Cmd::IndexDump {
+            dump,
+            index,
+            max_page_bytes,
+        } => {
+            let input = File::open(&dump)
+                .map(BufReader::new)
+                .with_context(|| format!("opening dump {:?}", dump))?;
+            let output = File::create(&index)
+                .map(BufWriter::new)
+                .with_context(|| format!("creating index {:?}", index))?;
+            let max_page_bytes =
+                max_page_bytes.unwrap_or(om_wikiparser::extract::DEFAULT_MAX_PAGE_BYTES);
+            let (pages, skipped) =
+                om_wikiparser::wm::dump::write_index(input, output, max_page_bytes)
+                    .with_context(|| format!("indexing dump {:?}", dump))?;
+            info!("Indexed {pages} pages, skipped {skipped} lines that are not pages");
+            Ok(())
+        }
+        Cmd::GetPage { dump, index, key } => {
+            use om_wikiparser::wm::dump::{find_entry, read_entry, IndexKey};
+
+            let key: IndexKey = key.parse()?;
+            let file = File::open(&index)
+                .map(BufReader::new)
+                .with_context(|| format!("opening index {:?}", index))?;
+            let Some(entry) = find_entry(file, &key)? else {
+                anyhow::bail!("no page matching {key} in index {:?}", index);
+            };
+            let file = File::open(&dump).with_context(|| format!("opening dump {:?}", dump))?;
+            let mut stdout = stdout().lock();
+            stdout.write_all(&read_entry(file, &entry)?)?;
+            writeln!(stdout)?;
+            Ok(())
+        }
+        Cmd::Simplify {
+            lang,
+            protect_selector,
+            wikivoyage,
+        } => {
             use om_wikiparser::html;
 
             warn_if_stdin_is_tty();
@@ -195,7 +416,13 @@ fn main() -> anyhow::Result<()> {
             });
 
             let start = Instant::now();
-            let output = html::process(document, &lang)?.html();
+            let mut config = if wikivoyage {
+                html::SimplifyConfig::wikivoyage()
+            } else {
+                html::SimplifyConfig::default()
+            };
+            config.protect_selectors.extend(protect_selector);
+            let output = html::process_with(document, &lang, &config)?.html();
             let stop = Instant::now();
             let time = stop.duration_since(start);
 
@@ -229,6 +456,8 @@ fn init_logger() {
 
     dispatcher::set_global_default(Dispatch::new(subscriber))
         .expect("Global logger has already been set!");
+    // Forward the library's `log` records.
+    tracing_log::LogTracer::init().expect("Global logger has already been set!");
 }
 
 /// Determine the number of threads to use.
@@ -275,6 +504,7 @@ fn warn_if_stdin_is_tty() {
 /// - `v2.0` if a git tag
 /// - the commit hash `034ac04` if not a tag
 /// - `034ac04-dirty` if uncommited changes are present,
+///
 /// or the crate version if not available (debug build or installed from crates.io).
 ///
 /// See `build.rs` file for more info.
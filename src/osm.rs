@@ -15,7 +15,7 @@ pub type Version = i32;
 /// OSM Object Type
 ///
 /// See <https://wiki.openstreetmap.org/wiki/Elements>
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Kind {
     Node,
     Way,
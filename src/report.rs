@@ -0,0 +1,199 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{ParseErrorKind, ParseLineError};
+
+/// Logs [ParseLineError]s at the warn level, suppressing repeated errors of the same kind.
+///
+/// For each kind of error, the first `first` occurrences are logged individually.
+/// After that, a message with the number of suppressed errors is logged every `every` occurrences.
+/// The totals of each kind are logged when the reporter is dropped, and are available with [LogReporter::counts].
+///
+/// ```
+/// use om_wikiparser::{parse_wikidata_file_with, LogReporter, ListFileOptions};
+///
+/// let mut reporter = LogReporter::new(1, 100);
+/// let mut qids = Vec::new();
+/// let input = "Q1\nfoo\nbar\n".as_bytes();
+/// parse_wikidata_file_with(input, &mut qids, &mut reporter, &ListFileOptions::default()).unwrap();
+/// assert_eq!(2, reporter.total());
+/// ```
+#[derive(Debug)]
+pub struct LogReporter {
+    first: usize,
+    every: usize,
+    counts: BTreeMap<String, usize>,
+}
+
+impl LogReporter {
+    /// Log the first `first` errors of each kind, then a summary every `every` errors of that kind.
+    ///
+    /// An `every` of zero never logs the periodic summary.
+    pub fn new(first: usize, every: usize) -> Self {
+        Self {
+            first,
+            every,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Number of errors reported of each kind, e.g. `title: no ':' separating lang and title`.
+    pub fn counts(&self) -> &BTreeMap<String, usize> {
+        &self.counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    fn report(&mut self, e: ParseLineError) {
+        let count = self.counts.entry(error_kind(&e.kind)).or_default();
+        *count += 1;
+        let count = *count;
+
+        if count <= self.first {
+            warn!("Parse error {}", e);
+            return;
+        }
+        let suppressed = count - self.first;
+        if self.every != 0 && suppressed.is_multiple_of(self.every) {
+            warn!(
+                "Suppressed {} similar parse errors: {}",
+                suppressed,
+                error_kind(&e.kind)
+            );
+        }
+    }
+}
+
+/// Logs the first 100 errors of each kind, then a summary every 10,000 errors.
+impl Default for LogReporter {
+    fn default() -> Self {
+        Self::new(100, 10_000)
+    }
+}
+
+impl Extend<ParseLineError> for LogReporter {
+    fn extend<T: IntoIterator<Item = ParseLineError>>(&mut self, iter: T) {
+        for e in iter {
+            self.report(e);
+        }
+    }
+}
+
+impl Drop for LogReporter {
+    fn drop(&mut self) {
+        for (kind, &count) in &self.counts {
+            if count > self.first {
+                warn!(
+                    "{} parse errors in total ({} suppressed): {}",
+                    count,
+                    count - self.first,
+                    kind
+                );
+            }
+        }
+    }
+}
+
+/// Logs [ParseLineError]s at the warn level up to a limit for the whole run, and can be shared between threads.
+///
+/// The first `first` errors are logged individually.
+/// After that, a message with the number of suppressed errors is logged every `every` errors.
+/// Unlike [LogReporter], errors of every kind count towards the same limit, so a badly formatted file can't flood the log.
+/// The total is logged when the throttle is dropped.
+///
+/// Errors are reported through a shared reference, e.g. from several threads parsing different files:
+///
+/// ```
+/// use om_wikiparser::{parse_wikidata_file_with, LogThrottle, ListFileOptions};
+///
+/// let throttle = LogThrottle::new(10, 1000);
+/// std::thread::scope(|s| {
+///     for input in ["Q1\nfoo\n", "bar\nQ2\n"] {
+///         let mut throttle = &throttle;
+///         let options = ListFileOptions::default();
+///         s.spawn(move || parse_wikidata_file_with(input.as_bytes(), &mut Vec::new(), &mut throttle, &options));
+///     }
+/// });
+/// assert_eq!(2, throttle.total());
+/// ```
+#[derive(Debug)]
+pub struct LogThrottle {
+    first: usize,
+    every: usize,
+    count: AtomicUsize,
+}
+
+impl LogThrottle {
+    /// Log the first `first` errors, then a summary every `every` errors.
+    ///
+    /// An `every` of zero never logs the periodic summary.
+    pub fn new(first: usize, every: usize) -> Self {
+        Self {
+            first,
+            every,
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of errors reported.
+    pub fn total(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn report(&self, e: &ParseLineError) {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count <= self.first {
+            warn!("Parse error {}", e);
+            return;
+        }
+        let suppressed = count - self.first;
+        if self.every != 0 && suppressed.is_multiple_of(self.every) {
+            warn!("Suppressed {} further parse errors", suppressed);
+        }
+    }
+}
+
+/// Logs the first 100 errors, then a summary every 10,000 errors, like [LogReporter::default].
+impl Default for LogThrottle {
+    fn default() -> Self {
+        Self::new(100, 10_000)
+    }
+}
+
+impl Extend<ParseLineError> for &LogThrottle {
+    fn extend<T: IntoIterator<Item = ParseLineError>>(&mut self, iter: T) {
+        for e in iter {
+            self.report(&e);
+        }
+    }
+}
+
+impl Drop for LogThrottle {
+    fn drop(&mut self) {
+        let total = self.total();
+        if total > self.first {
+            warn!(
+                "{} parse errors in total ({} suppressed)",
+                total,
+                total - self.first
+            );
+        }
+    }
+}
+
+/// Description of the error without the value that caused it.
+fn error_kind(kind: &ParseErrorKind) -> String {
+    match kind {
+        // csv errors contain positions.
+        ParseErrorKind::Tsv(_) => kind.to_string(),
+        _ => match kind.source() {
+            Some(source) => format!("{}: {}", kind, source),
+            None => kind.to_string(),
+        },
+    }
+}
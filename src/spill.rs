@@ -0,0 +1,155 @@
+//! Buffering of pages to process after the rest of the dump, either in memory or spilled to a temporary file.
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    vec,
+};
+
+/// Pages with their line and byte offset in the dump, in the order they were pushed.
+pub(crate) enum PageBuffer {
+    Memory {
+        pages: Vec<(usize, usize, Vec<u8>)>,
+        bytes: usize,
+    },
+    /// The line, byte offset, and length of each page, and the file of their contents.
+    Disk {
+        index: Vec<(usize, usize, usize)>,
+        file: BufWriter<File>,
+    },
+}
+
+impl Default for PageBuffer {
+    fn default() -> Self {
+        Self::Memory {
+            pages: Vec::new(),
+            bytes: 0,
+        }
+    }
+}
+
+impl PageBuffer {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Memory { pages, .. } => pages.len(),
+            Self::Disk { index, .. } => index.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        matches!(self, Self::Disk { .. })
+    }
+
+    /// Bytes of page contents held in memory.
+    pub fn memory_bytes(&self) -> usize {
+        match self {
+            Self::Memory { bytes, .. } => *bytes,
+            Self::Disk { .. } => 0,
+        }
+    }
+
+    pub fn push(&mut self, line: usize, byte: usize, page: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Memory { pages, bytes } => {
+                *bytes += page.len();
+                pages.push((line, byte, page.to_owned()));
+            }
+            Self::Disk { index, file } => {
+                file.write_all(page)?;
+                index.push((line, byte, page.len()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the buffered pages to a temporary file, and write any further pages to it.
+    ///
+    /// The file is deleted when the buffer is dropped.
+    pub fn spill(&mut self) -> io::Result<()> {
+        let Self::Memory { pages, .. } = self else {
+            return Ok(());
+        };
+        let mut file = BufWriter::new(tempfile::tempfile()?);
+        let mut index = Vec::with_capacity(pages.len());
+        for (line, byte, page) in pages.drain(..) {
+            file.write_all(&page)?;
+            index.push((line, byte, page.len()));
+        }
+        *self = Self::Disk { index, file };
+        Ok(())
+    }
+
+    pub fn into_pages(self) -> io::Result<Pages> {
+        Ok(match self {
+            Self::Memory { pages, .. } => Pages::Memory(pages.into_iter()),
+            Self::Disk { index, file } => {
+                let mut file = file.into_inner().map_err(|e| e.into_error())?;
+                file.rewind()?;
+                Pages::Disk {
+                    index: index.into_iter(),
+                    file: BufReader::new(file),
+                }
+            }
+        })
+    }
+}
+
+/// Iterator over the pages of a [PageBuffer].
+pub(crate) enum Pages {
+    Memory(vec::IntoIter<(usize, usize, Vec<u8>)>),
+    Disk {
+        index: vec::IntoIter<(usize, usize, usize)>,
+        file: BufReader<File>,
+    },
+}
+
+impl Iterator for Pages {
+    type Item = io::Result<(usize, usize, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Memory(pages) => pages.next().map(Ok),
+            Self::Disk { index, file } => {
+                let (line, byte, len) = index.next()?;
+                let mut page = vec![0; len];
+                Some(file.read_exact(&mut page).map(|_| (line, byte, page)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spilled_pages_in_order() {
+        let mut buffer = PageBuffer::default();
+        buffer.push(1, 1, b"first").unwrap();
+        buffer.push(3, 20, b"").unwrap();
+        assert_eq!(5, buffer.memory_bytes());
+
+        buffer.spill().unwrap();
+        assert!(buffer.is_spilled());
+        assert_eq!(0, buffer.memory_bytes());
+        buffer.push(4, 30, b"last").unwrap();
+        assert_eq!(3, buffer.len());
+
+        let pages: Vec<_> = buffer
+            .into_pages()
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (1, 1, b"first".to_vec()),
+                (3, 20, Vec::new()),
+                (4, 30, b"last".to_vec())
+            ],
+            pages
+        );
+    }
+}
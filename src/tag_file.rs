@@ -1,45 +1,188 @@
-use std::{error::Error, fmt::Display, io::Read, str::FromStr};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::Display,
+    io::{BufRead, BufReader, Read},
+};
 
-use anyhow::{anyhow, bail};
+use anyhow::bail;
 
 use crate::{
-    osm,
-    wm::{ParseQidError, ParseTitleError, Qid, Title},
+    limits, osm,
+    wm::{clean_value, is_valid_lang, ParseQidError, ParseTitleError, Project, Qid, Title},
+    ReadLimits, TooLongError,
 };
 
 /// Read a TSV file of OSM tags, using wikipedia/wikidata tags.
+///
+/// Titles in an optional `wikivoyage` column are read as Wikivoyage articles, see [Project].
+/// Columns of language-specific tags like `wikipedia:de` are read as titles in that language, usually without a `lang:` prefix.
+/// They can be used in addition to or instead of the `wikipedia` column, and errors in them have the tag in [ParseLineError::tag].
+/// Values of the `wikidata` column can also be urls of the items, see [Qid::from_url].
+/// Multiple values separated by `;`, like `Q1;Q2`, are each parsed, and errors are reported for each value that can't be.
+///
+/// Returns a [TagFileFormatError] if the file is obviously not a TSV file.
 pub fn parse_osm_tag_file(
     r: impl Read,
     qids: &mut impl Extend<Qid>,
     titles: &mut impl Extend<Title>,
     line_errors: &mut impl Extend<ParseLineError>,
 ) -> anyhow::Result<()> {
-    let mut rdr = csv::ReaderBuilder::new().delimiter(b'\t').from_reader(r);
+    parse_osm_tag_file_with(r, qids, titles, line_errors, &TagFileOptions::default())
+}
+
+/// Like [parse_osm_tag_file], reading the file with `options`.
+///
+/// With [TagFileOptions::strict()], the first line that cannot be parsed fails the whole file.
+///
+/// ```
+/// use om_wikiparser::{parse_osm_tag_file_with, ParseLineError, TagFileOptions};
+///
+/// let tags = "wikidata\twikipedia\nQ1\ten:Berlin\nbad\t\nQ2\t\n";
+/// let (mut qids, mut titles, mut errors) = (Vec::new(), Vec::new(), Vec::new());
+/// let strict = TagFileOptions { strict: true, ..Default::default() };
+/// let e = parse_osm_tag_file_with(tags.as_bytes(), &mut qids, &mut titles, &mut errors, &strict).unwrap_err();
+/// assert_eq!(3, e.downcast_ref::<ParseLineError>().unwrap().line);
+/// assert_eq!(1, qids.len());
+/// assert!(errors.is_empty());
+/// ```
+pub fn parse_osm_tag_file_with(
+    r: impl Read,
+    qids: &mut impl Extend<Qid>,
+    titles: &mut impl Extend<Title>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &TagFileOptions,
+) -> anyhow::Result<()> {
+    parse_tag_file(
+        r,
+        qids,
+        |title, _| titles.extend(Some(title)),
+        line_errors,
+        options,
+    )
+}
+
+/// Like [parse_osm_tag_file_with], also returning the `wikipedia` tag that each title was parsed from.
+pub fn parse_osm_tag_file_raw(
+    r: impl Read,
+    qids: &mut impl Extend<Qid>,
+    titles: &mut impl Extend<(Title, String)>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &TagFileOptions,
+) -> anyhow::Result<()> {
+    parse_tag_file(
+        r,
+        qids,
+        |title, raw| titles.extend(Some((title, raw.to_owned()))),
+        line_errors,
+        options,
+    )
+}
+
+/// Like [parse_osm_tag_file_with], reading the columns of a Parquet file at `path` instead of a TSV file.
+///
+/// Columns of other types than strings, like integer `@id`s, are read as their text, and null values as empty ones.
+/// Only the columns of the tags that are read and of the OSM metadata are decoded.
+/// The [ParseLineError::line] of an error is the number of the row, starting at 1.
+#[cfg(feature = "parquet")]
+pub fn parse_osm_tag_parquet(
+    path: impl AsRef<std::path::Path>,
+    qids: &mut impl Extend<Qid>,
+    titles: &mut impl Extend<Title>,
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &TagFileOptions,
+) -> anyhow::Result<()> {
+    use std::fs::File;
+
+    use anyhow::Context;
+    use arrow_array::{cast::AsArray, Array};
+    use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ProjectionMask};
+
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("opening tag file {:?}", path))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+        .with_context(|| format!("reading Parquet metadata of {:?}", path))?;
+
+    let headers: csv::StringRecord = builder.schema().fields().iter().map(|f| f.name()).collect();
+    let columns = Columns::find(&headers, options.tag_kinds)?;
+    let read = columns.read();
+    let mask = ProjectionMask::roots(builder.parquet_schema(), read.iter().copied());
+    let reader = builder.with_projection(mask).build()?;
 
-    let mut push_error = |e: ParseLineError| {
+    let mut push_error = |e: ParseLineError| -> anyhow::Result<()> {
+        if options.strict {
+            return Err(e.into());
+        }
         line_errors.extend(Some(e));
+        Ok(())
     };
 
-    let mut qid_col = None;
-    let mut title_col = None;
-    let mut osm_id_col = None;
-    let mut osm_otype_col = None;
-    let mut osm_oname_col = None;
-    let mut osm_version_col = None;
-    for (column, title) in rdr.headers()?.iter().enumerate() {
-        match title {
-            "wikidata" => qid_col = Some(column),
-            "wikipedia" => title_col = Some(column),
-            "@id" => osm_id_col = Some(column),
-            "@otype" => osm_otype_col = Some(column),
-            "@oname" => osm_oname_col = Some(column),
-            "@version" => osm_version_col = Some(column),
-            _ => (),
+    let mut line = 0;
+    let mut row = csv::StringRecord::new();
+    for batch in reader {
+        let batch = batch.with_context(|| format!("reading Parquet file {:?}", path))?;
+        // The batch only has the projected columns, in the order of the file.
+        let values = read
+            .iter()
+            .zip(batch.columns())
+            .map(|(&column, array)| {
+                arrow_cast::cast(array, &arrow_schema::DataType::Utf8)
+                    .with_context(|| format!("reading column {:?} as text", &headers[column]))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let values: Vec<_> = read
+            .iter()
+            .zip(values.iter().map(|array| array.as_string::<i32>()))
+            .collect();
+        for i in 0..batch.num_rows() {
+            line += 1;
+            row.clear();
+            let mut values = values.iter().peekable();
+            for column in 0..headers.len() {
+                match values.next_if(|(read, _)| **read == column) {
+                    Some((_, array)) if !array.is_null(i) => row.push_field(array.value(i)),
+                    _ => row.push_field(""),
+                }
+            }
+            parse_row(
+                &row,
+                line,
+                &columns,
+                &options.limits,
+                qids,
+                &mut |title, _| titles.extend(Some(title)),
+                &mut push_error,
+            )?;
         }
     }
 
-    let qid_col = qid_col.ok_or_else(|| anyhow!("Cannot find 'wikidata' column"))?;
-    let title_col = title_col.ok_or_else(|| anyhow!("Cannot find 'wikipedia' column"))?;
+    Ok(())
+}
+
+fn parse_tag_file(
+    r: impl Read,
+    qids: &mut impl Extend<Qid>,
+    mut push_title: impl FnMut(Title, &str),
+    line_errors: &mut impl Extend<ParseLineError>,
+    options: &TagFileOptions,
+) -> anyhow::Result<()> {
+    let mut r = BufReader::new(r);
+    if let Some(e) = sniff_format(r.fill_buf()?) {
+        bail!(e);
+    }
+
+    let mut rdr = csv::ReaderBuilder::new().delimiter(b'\t').from_reader(r);
+
+    let mut push_error = |e: ParseLineError| -> anyhow::Result<()> {
+        if options.strict {
+            return Err(e.into());
+        }
+        line_errors.extend(Some(e));
+        Ok(())
+    };
+
+    let headers = rdr.headers()?.clone();
+    let columns = Columns::find(&headers, options.tag_kinds)?;
 
     let mut row = csv::StringRecord::new();
     loop {
@@ -52,74 +195,397 @@ pub fn parse_osm_tag_file(
                 if e.is_io_error() {
                     bail!(e)
                 }
+                let line = e.position().map_or(rdr.position().line(), |p| p.line());
                 push_error(ParseLineError {
                     kind: e.into(),
                     text: String::new(),
-                    line: rdr.position().line(),
+                    line,
                     osm_id: None,
                     osm_type: None,
                     osm_version: None,
-                });
+                    tag: None,
+                })?;
                 continue;
             }
         }
 
-        let parse_metadata = || {
-            (
-                osm_id_col.and_then(|i| row[i].trim().parse::<osm::Id>().ok()),
-                // Prefer otype, use oname if not available
-                osm_otype_col
-                    .and_then(|i| row[i].trim().parse().ok())
-                    .and_then(osm::Kind::from_otype)
-                    .or_else(|| osm_oname_col.and_then(|i| osm::Kind::from_oname(&row[i]))),
-                osm_version_col.and_then(|i| row[i].trim().parse::<osm::Version>().ok()),
-            )
+        // Quoted fields can contain line endings, so report the line the record starts on rather than the reader's position after it.
+        let line = row
+            .position()
+            .map_or(rdr.position().line(), |position| position.line());
+
+        parse_row(
+            &row,
+            line,
+            &columns,
+            &options.limits,
+            qids,
+            &mut push_title,
+            &mut push_error,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The columns of a tag file that are read, found by their names in its header.
+struct Columns<'h> {
+    qid: Option<usize>,
+    title: Option<usize>,
+    voyage: Option<usize>,
+    /// The column, tag, and lang of each `wikipedia:lang` column.
+    langs: Vec<(usize, &'h str, &'h str)>,
+    osm_id: Option<usize>,
+    osm_otype: Option<usize>,
+    osm_oname: Option<usize>,
+    osm_version: Option<usize>,
+}
+
+impl<'h> Columns<'h> {
+    /// Find the columns in `headers`, without those of the tags that `tag_kinds` doesn't read.
+    ///
+    /// Fails if the columns of the tags that are read are missing.
+    fn find(headers: &'h csv::StringRecord, tag_kinds: TagKinds) -> anyhow::Result<Self> {
+        let mut columns = Self {
+            qid: None,
+            title: None,
+            voyage: None,
+            langs: Vec::new(),
+            osm_id: None,
+            osm_otype: None,
+            osm_oname: None,
+            osm_version: None,
         };
+        for (column, title) in headers.iter().enumerate() {
+            match title {
+                "wikidata" => columns.qid = Some(column),
+                "wikipedia" => columns.title = Some(column),
+                "wikivoyage" => columns.voyage = Some(column),
+                "@id" => columns.osm_id = Some(column),
+                "@otype" => columns.osm_otype = Some(column),
+                "@oname" => columns.osm_oname = Some(column),
+                "@version" => columns.osm_version = Some(column),
+                // Other suffixes, like those of `wikipedia:de:note`, are not languages.
+                tag => match tag.strip_prefix("wikipedia:") {
+                    Some(lang) if is_valid_lang(lang) => columns.langs.push((column, tag, lang)),
+                    _ => (),
+                },
+            }
+        }
+
+        let found = || headers.iter().collect::<Vec<_>>();
+        // Columns of the tags that aren't read don't have to exist.
+        if !tag_kinds.reads_qids() {
+            columns.qid = None;
+        } else if columns.qid.is_none() {
+            bail!("Cannot find 'wikidata' column in {:?}", found());
+        }
+        if !tag_kinds.reads_titles() {
+            columns.title = None;
+            columns.voyage = None;
+            columns.langs.clear();
+        } else if columns.title.is_none() && columns.langs.is_empty() {
+            bail!("Cannot find 'wikipedia' column in {:?}", found());
+        }
+        Ok(columns)
+    }
+
+    /// The columns that are read, in order.
+    #[cfg(feature = "parquet")]
+    fn read(&self) -> Vec<usize> {
+        let mut read: Vec<usize> = [
+            self.qid,
+            self.title,
+            self.voyage,
+            self.osm_id,
+            self.osm_otype,
+            self.osm_oname,
+            self.osm_version,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.langs.iter().map(|&(column, _, _)| column))
+        .collect();
+        read.sort_unstable();
+        read.dedup();
+        read
+    }
+}
+
+/// Parse the tags in the `columns` of `row`, which starts on `line` of the file.
+fn parse_row(
+    row: &csv::StringRecord,
+    line: u64,
+    columns: &Columns,
+    limits: &ReadLimits,
+    qids: &mut impl Extend<Qid>,
+    push_title: &mut impl FnMut(Title, &str),
+    push_error: &mut impl FnMut(ParseLineError) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let parse_metadata = || {
+        (
+            columns
+                .osm_id
+                .and_then(|i| row[i].trim().parse::<osm::Id>().ok()),
+            // Prefer otype, use oname if not available
+            columns
+                .osm_otype
+                .and_then(|i| row[i].trim().parse().ok())
+                .and_then(osm::Kind::from_otype)
+                .or_else(|| {
+                    columns
+                        .osm_oname
+                        .and_then(|i| osm::Kind::from_oname(&row[i]))
+                }),
+            columns
+                .osm_version
+                .and_then(|i| row[i].trim().parse::<osm::Version>().ok()),
+        )
+    };
+    let error_at = |kind: ParseErrorKind, text: &str, tag: Option<&str>| {
+        let (osm_id, osm_type, osm_version) = parse_metadata();
+        ParseLineError {
+            kind,
+            text: text.to_owned(),
+            line,
+            osm_id,
+            osm_type,
+            osm_version,
+            tag: tag.map(str::to_owned),
+        }
+    };
 
-        let qid = &row[qid_col].trim();
-        if !qid.is_empty() {
-            match Qid::from_str(qid) {
+    if let Some(qid_col) = columns.qid {
+        let qid = clean_value(&row[qid_col]);
+        if qid.len() > limits.max_field_bytes {
+            let (osm_id, osm_type, osm_version) = parse_metadata();
+            push_error(too_long(&qid, limits, line, osm_id, osm_type, osm_version))?;
+        } else if !qid.is_empty() {
+            match Qid::parse_value(&qid) {
                 Ok(qid) => {
                     qids.extend(Some(qid));
                 }
-                Err(e) => {
-                    let (osm_id, osm_type, osm_version) = parse_metadata();
-                    push_error(ParseLineError {
-                        kind: e.into(),
-                        text: qid.to_string(),
-                        line: rdr.position().line(),
-                        osm_id,
-                        osm_type,
-                        osm_version,
-                    })
+                Err(e) if !qid.contains(';') => push_error(error_at(e.into(), &qid, None))?,
+                Err(_) => {
+                    for value in split_values(&qid) {
+                        match Qid::parse_value(value) {
+                            Ok(qid) => qids.extend(Some(qid)),
+                            Err(e) => push_error(error_at(e.into(), value, None))?,
+                        }
+                    }
                 }
             }
         }
+    }
 
-        let title = &row[title_col].trim();
-        if !title.is_empty() {
-            match Title::from_osm_tag(title) {
-                Ok(title) => {
-                    titles.extend(Some(title));
+    let title_cols = columns
+        .title
+        .map(|col| (col, Project::Wikipedia, None))
+        .into_iter()
+        .chain(columns.voyage.map(|col| (col, Project::Wikivoyage, None)))
+        .chain(
+            columns
+                .langs
+                .iter()
+                .map(|&(col, tag, lang)| (col, Project::Wikipedia, Some((tag, lang)))),
+        );
+    for (col, project, lang_tag) in title_cols {
+        let title = clean_value(&row[col]);
+        let (tag, lang) = lang_tag.unzip();
+        if title.len() > limits.max_field_bytes {
+            let (osm_id, osm_type, osm_version) = parse_metadata();
+            let mut e = too_long(&title, limits, line, osm_id, osm_type, osm_version);
+            e.tag = tag.map(str::to_owned);
+            push_error(e)?;
+        } else if !title.is_empty() {
+            let whole = Title::from_osm_tag(&with_lang(&title, lang));
+            // Titles can contain `;`, so the whole value is kept if it parses, unless every later value is a tag of its own.
+            let split = title.contains(';')
+                && (whole.is_err() || split_values(&title).skip(1).all(is_tag_like));
+            if split {
+                for value in split_values(&title) {
+                    let value = with_lang(value, lang);
+                    match Title::from_osm_tag(&value) {
+                        Ok(parsed) => push_title(parsed.in_project(project), &value),
+                        Err(e) => push_error(error_at(e.into(), &value, tag))?,
+                    }
                 }
-                Err(e) => {
-                    let (osm_id, osm_type, osm_version) = parse_metadata();
-                    push_error(ParseLineError {
-                        kind: e.into(),
-                        text: title.to_string(),
-                        line: rdr.position().line(),
-                        osm_id,
-                        osm_type,
-                        osm_version,
-                    })
+            } else {
+                match whole {
+                    Ok(parsed) => push_title(parsed.in_project(project), &with_lang(&title, lang)),
+                    Err(e) => push_error(error_at(e.into(), &title, tag))?,
                 }
             }
         }
     }
-
     Ok(())
 }
 
+/// The values of a multi-value tag like `Q1;Q2` or `en:Foo;de:Bar`, trimmed and without empty ones.
+fn split_values(value: &str) -> impl Iterator<Item = &str> {
+    value.split(';').map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// The value of a `wikipedia:lang` tag as a `lang:Title` tag, unless it already has the lang or is a url.
+fn with_lang<'a>(value: &'a str, lang: Option<&str>) -> Cow<'a, str> {
+    let Some(lang) = lang else {
+        return Cow::Borrowed(value);
+    };
+    let is_url = value.starts_with("http://") || value.starts_with("https://");
+    let has_lang = value
+        .split_once(':')
+        .is_some_and(|(prefix, _)| prefix.trim().eq_ignore_ascii_case(lang));
+    if is_url || has_lang {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("{lang}:{value}"))
+    }
+}
+
+/// Check if `value` starts with a lang and `:` like a `wikipedia` tag, or is a url.
+fn is_tag_like(value: &str) -> bool {
+    value.split_once(':').is_some_and(|(lang, _)| {
+        lang.len() >= 2 && lang.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+    })
+}
+
+/// Error for a `value` longer than [ReadLimits::max_field_bytes], with only its start as the text.
+fn too_long(
+    value: &str,
+    limits: &ReadLimits,
+    line: u64,
+    osm_id: Option<osm::Id>,
+    osm_type: Option<osm::Kind>,
+    osm_version: Option<osm::Version>,
+) -> ParseLineError {
+    ParseLineError {
+        kind: TooLongError {
+            len: value.len(),
+            max: limits.max_field_bytes,
+        }
+        .into(),
+        text: limits::prefix(value, 64).to_owned(),
+        line,
+        osm_id,
+        osm_type,
+        osm_version,
+        tag: None,
+    }
+}
+
+/// How to read an OSM tag file in [parse_osm_tag_file_with] and [parse_osm_tag_file_raw].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TagFileOptions {
+    /// Values longer than [ReadLimits::max_field_bytes] are skipped as errors.
+    pub limits: ReadLimits,
+    /// Fail on the first TSV record that cannot be parsed, with its [ParseLineError], instead of collecting it and continuing.
+    pub strict: bool,
+    /// Which tags to read.
+    ///
+    /// The other tags are skipped without parsing them, so their errors are not reported, and their columns don't have to exist.
+    pub tag_kinds: TagKinds,
+}
+
+/// Bytes of the longest tag value that OSM allows, 255 characters of up to 4 bytes each.
+pub const MAX_OSM_VALUE_BYTES: usize = 255 * 4;
+
+impl TagFileOptions {
+    /// The default limits, failing on the first bad record.
+    pub fn strict() -> Self {
+        Self {
+            strict: true,
+            ..Self::default()
+        }
+    }
+
+    /// Skip bad records, and values longer than [MAX_OSM_VALUE_BYTES] that can only come from a corrupted file, for the tags of an extraction.
+    ///
+    /// Sets [ReadLimits::max_field_bytes] to [MAX_OSM_VALUE_BYTES].
+    /// The default limits allow longer values, for tag files that weren't exported from OSM.
+    pub fn production_extract() -> Self {
+        Self {
+            limits: ReadLimits {
+                max_field_bytes: MAX_OSM_VALUE_BYTES,
+                ..ReadLimits::default()
+            },
+            ..Self::default()
+        }
+    }
+}
+
+/// Which tags of an OSM tag file to read, see [TagFileOptions::tag_kinds].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TagKinds {
+    /// Both the `wikidata` and the `wikipedia` and `wikivoyage` tags.
+    #[default]
+    All,
+    /// Only the `wikidata` tags.
+    Qids,
+    /// Only the `wikipedia` and `wikivoyage` tags.
+    Titles,
+}
+
+impl TagKinds {
+    pub fn reads_qids(&self) -> bool {
+        matches!(self, TagKinds::All | TagKinds::Qids)
+    }
+
+    pub fn reads_titles(&self) -> bool {
+        matches!(self, TagKinds::All | TagKinds::Titles)
+    }
+}
+
+/// The tag file is in another format than TSV.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum TagFileFormatError {
+    #[error("tag file is {0} compressed, decompress it first")]
+    Compressed(&'static str),
+    #[error("tag file looks like an OSM PBF file, use the `get-tags` command to extract the tags from it")]
+    LooksLikePbf,
+    #[error("tag file looks like JSON, expected TSV with `wikidata` and `wikipedia` columns")]
+    LooksLikeJson,
+    #[error("tag file contains binary data, expected TSV with `wikidata` and `wikipedia` columns")]
+    Binary,
+}
+
+/// Check the first bytes of a file for formats that are commonly confused with a tag file.
+fn sniff_format(start: &[u8]) -> Option<TagFileFormatError> {
+    use TagFileFormatError::*;
+
+    const MAGIC: &[(&[u8], &str)] = &[
+        (&[0x1f, 0x8b], "gzip"),
+        (&[0x28, 0xb5, 0x2f, 0xfd], "zstd"),
+        (b"BZh", "bzip2"),
+        (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], "xz"),
+    ];
+    for (magic, format) in MAGIC {
+        if start.starts_with(magic) {
+            return Some(Compressed(format));
+        }
+    }
+
+    // PBF files start with the length of the first BlobHeader, which contains its type.
+    if start.len() > 4 && start[4..].starts_with(b"\x0a\x09OSMHeader") {
+        return Some(LooksLikePbf);
+    }
+
+    match start.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') | Some(b'[') => return Some(LooksLikeJson),
+        _ => {}
+    }
+
+    // Allow a multibyte character to be cut off at the end of the buffer.
+    let is_utf8 = match std::str::from_utf8(start) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if !is_utf8 || start.contains(&0) {
+        return Some(Binary);
+    }
+
+    None
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ParseErrorKind {
     #[error("title")]
@@ -128,6 +594,66 @@ pub enum ParseErrorKind {
     Qid(#[from] ParseQidError),
     #[error("TSV line")]
     Tsv(#[from] csv::Error),
+    #[error("value")]
+    TooLong(#[from] TooLongError),
+}
+
+impl ParseErrorKind {
+    /// The value is a title in a non-article namespace, see [ParseTitleError::Namespace].
+    ///
+    /// These are usually expected, e.g. from `wikipedia` tags that link to a category, rather than errors in the data.
+    pub fn is_namespace(&self) -> bool {
+        matches!(self, ParseErrorKind::Title(ParseTitleError::Namespace(_)))
+    }
+}
+
+/// Number of [ParseLineError]s, with the expected namespace rejections counted separately.
+///
+/// ```
+/// use om_wikiparser::{parse_wikipedia_file_with, ErrorSummary, ListFileOptions};
+///
+/// let input = "en:Berlin\nen:Category:Cities\nen:File:Berlin.jpg\nnot a title\n";
+/// let mut errors = Vec::new();
+/// parse_wikipedia_file_with(input.as_bytes(), &mut Vec::new(), &mut errors, &ListFileOptions::default()).unwrap();
+///
+/// let summary = ErrorSummary::new(&errors);
+/// assert_eq!(ErrorSummary { errors: 1, namespaces: 2 }, summary);
+/// assert_eq!("1 errors and 2 namespace titles", summary.to_string());
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorSummary {
+    /// Errors that are not namespace rejections.
+    pub errors: usize,
+    /// Rejected titles in non-article namespaces.
+    pub namespaces: usize,
+}
+
+impl ErrorSummary {
+    pub fn new<'a>(errors: impl IntoIterator<Item = &'a ParseLineError>) -> Self {
+        let mut summary = Self::default();
+        for e in errors {
+            if e.kind.is_namespace() {
+                summary.namespaces += 1;
+            } else {
+                summary.errors += 1;
+            }
+        }
+        summary
+    }
+
+    pub fn total(&self) -> usize {
+        self.errors + self.namespaces
+    }
+}
+
+impl Display for ErrorSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} errors and {} namespace titles",
+            self.errors, self.namespaces
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -138,6 +664,10 @@ pub struct ParseLineError {
     pub osm_id: Option<osm::Id>,
     pub osm_type: Option<osm::Kind>,
     pub osm_version: Option<osm::Version>,
+    /// The language-specific tag of the value, like `wikipedia:de`, see [parse_osm_tag_file].
+    ///
+    /// It is not set for the values of the `wikidata` and `wikipedia` tags, which the kind of error already tells apart.
+    pub tag: Option<String>,
 }
 
 impl Display for ParseLineError {
@@ -146,6 +676,9 @@ impl Display for ParseLineError {
         if let Some(osm_id) = self.osm_id {
             write!(f, " ({osm_id})")?;
         }
+        if let Some(tag) = &self.tag {
+            write!(f, " in {tag}")?;
+        }
         write!(f, ": {} {:?}", self.kind, self.text)?;
 
         // Write source error chain to ensure they are logged.
@@ -164,3 +697,444 @@ impl Error for ParseLineError {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn parse(contents: &[u8]) -> anyhow::Result<()> {
+        parse_osm_tag_file(
+            contents,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut Vec::<ParseLineError>::new(),
+        )
+    }
+
+    fn format_error(contents: &[u8]) -> Option<TagFileFormatError> {
+        parse(contents)
+            .unwrap_err()
+            .downcast::<TagFileFormatError>()
+            .ok()
+    }
+
+    #[test]
+    fn wrong_formats() {
+        use TagFileFormatError::*;
+
+        assert_eq!(
+            Some(Compressed("gzip")),
+            format_error(&[0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            Some(Compressed("zstd")),
+            format_error(&[0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x00])
+        );
+        assert_eq!(
+            Some(LooksLikePbf),
+            format_error(b"\x00\x00\x00\x0d\x0a\x09OSMHeader\x18\x8b\x01")
+        );
+        assert_eq!(
+            Some(LooksLikeJson),
+            format_error(br#"{"name": "Berlin", "in_language": {"identifier": "de"}}"#)
+        );
+        assert_eq!(Some(LooksLikeJson), format_error(b"  [1, 2, 3]"));
+        assert_eq!(
+            Some(Binary),
+            format_error(b"wikidata\twikipedia\n\x00\x01\x02")
+        );
+        assert_eq!(Some(Binary), format_error(b"wikidata\twikipedia\n\xff\xfe"));
+    }
+
+    #[test]
+    fn missing_columns_lists_found() {
+        let e = parse(b"@id\twikidata\tname\n1\tQ1\tFoo\n").unwrap_err();
+        assert!(e.downcast_ref::<TagFileFormatError>().is_none());
+        assert_eq!(
+            r#"Cannot find 'wikipedia' column in ["@id", "wikidata", "name"]"#,
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn invisible_characters() {
+        let mut qids = Vec::new();
+        let mut titles = Vec::new();
+        let mut errors = Vec::new();
+        parse_osm_tag_file(
+            "@id\twikidata\twikipedia\n1\t\u{A0}Q42\u{A0}\ten:Douglas\u{200B} Adams\n2\t\u{200B}\t\u{200E}\n3\tQ42\u{200B}\t\n"
+                .as_bytes(),
+            &mut qids,
+            &mut titles,
+            &mut errors,
+        )
+        .unwrap();
+        // Values of only invisible characters are skipped like empty ones.
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(vec![Qid::from_str("Q42").unwrap(); 2], qids);
+        assert_eq!(
+            vec![Title::from_osm_tag("en:Douglas Adams").unwrap()],
+            titles
+        );
+    }
+
+    #[test]
+    fn wikidata_urls() {
+        let mut qids = Vec::new();
+        let mut titles = Vec::new();
+        let mut errors = Vec::new();
+        parse_osm_tag_file(
+            "@id\twikidata\twikipedia\n1\thttps://www.wikidata.org/wiki/Q42\t\n2\thttp://m.wikidata.org/entity/Q42/\t\n3\thttps://www.wikidata.org/wiki/Property:P31\t\n4\thttps://en.wikipedia.org/wiki/Q42\t\n"
+                .as_bytes(),
+            &mut qids,
+            &mut titles,
+            &mut errors,
+        )
+        .unwrap();
+        assert_eq!(vec![Qid::from_str("Q42").unwrap(); 2], qids);
+        assert_eq!(
+            vec![4, 5],
+            errors.iter().map(|e| e.line).collect::<Vec<_>>()
+        );
+        assert!(
+            matches!(&errors[0].kind, ParseErrorKind::Qid(ParseQidError::NotItem(id)) if id == "Property:P31"),
+            "{errors:?}"
+        );
+        assert!(
+            matches!(
+                errors[1].kind,
+                ParseErrorKind::Qid(ParseQidError::NotWikidata)
+            ),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn multiple_values() {
+        let mut qids = Vec::new();
+        let mut titles = Vec::new();
+        let mut errors = Vec::new();
+        parse_osm_tag_file(
+            "@id\twikidata\twikipedia\n1\tQ1; Q2;\ten:Foo;de:Bar\n2\tQ3;foo\ten:Love; Actually\n3\t\tfoo;;fr:Baz\n"
+                .as_bytes(),
+            &mut qids,
+            &mut titles,
+            &mut errors,
+        )
+        .unwrap();
+        assert_eq!(
+            ["Q1", "Q2", "Q3"]
+                .map(|q| Qid::from_str(q).unwrap())
+                .to_vec(),
+            qids
+        );
+        assert_eq!(
+            ["en:Foo", "de:Bar", "en:Love; Actually", "fr:Baz"]
+                .map(|t| Title::from_osm_tag(t).unwrap())
+                .to_vec(),
+            titles
+        );
+        // Only the values that can't be parsed are errors.
+        assert_eq!(
+            vec![(3, "foo"), (4, "foo")],
+            errors
+                .iter()
+                .map(|e| (e.line, e.text.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn lang_columns() {
+        let mut qids = Vec::new();
+        let mut titles = Vec::new();
+        let mut errors = Vec::new();
+        parse_osm_tag_file_raw(
+            "@id\twikidata\twikipedia:de\twikipedia:ceb\twikipedia:de:note\twikipedia:x_y\n\
+             1\tQ1\tKöln\tCologne\tnot a title\tFoo\n\
+             2\t\tde:Berlin\thttps://ceb.wikipedia.org/wiki/Berlin\t\t\n\
+             3\t\tKategorie:Köln\t\t\t\n"
+                .as_bytes(),
+            &mut qids,
+            &mut titles,
+            &mut errors,
+            &TagFileOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(vec![Qid::from_str("Q1").unwrap()], qids);
+        assert_eq!(
+            vec![
+                ("de:Köln", "de:Köln"),
+                ("ceb:Cologne", "ceb:Cologne"),
+                ("de:Berlin", "de:Berlin"),
+                ("ceb:Berlin", "https://ceb.wikipedia.org/wiki/Berlin"),
+            ]
+            .into_iter()
+            .map(|(title, raw)| (Title::from_osm_tag(title).unwrap(), raw.to_owned()))
+            .collect::<Vec<_>>(),
+            titles
+        );
+        assert_eq!(1, errors.len(), "{errors:?}");
+        assert_eq!(
+            (4, Some("wikipedia:de"), "Kategorie:Köln"),
+            (
+                errors[0].line,
+                errors[0].tag.as_deref(),
+                errors[0].text.as_str()
+            )
+        );
+        assert!(
+            errors[0].to_string().contains(" in wikipedia:de: "),
+            "{}",
+            errors[0]
+        );
+
+        // Without a `wikipedia` column or any language columns, there are no titles to read.
+        assert!(parse(b"@id\twikidata\twikipedia:x_y\n1\tQ1\tFoo\n").is_err());
+    }
+
+    #[test]
+    fn tsv_with_multibyte_characters() {
+        let mut titles = Vec::new();
+        parse_osm_tag_file(
+            "wikidata\twikipedia\nQ1\tde:Köln\n".as_bytes(),
+            &mut Vec::new(),
+            &mut titles,
+            &mut Vec::<ParseLineError>::new(),
+        )
+        .unwrap();
+        assert_eq!(vec![Title::from_title("Köln", "de").unwrap()], titles);
+    }
+
+    #[test]
+    fn oversized_values_skipped() {
+        let options = TagFileOptions {
+            limits: ReadLimits {
+                max_field_bytes: 16,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let long_title = format!("en:{}", "x".repeat(100));
+        let tags = format!("@id\twikidata\twikipedia\n1\tQ1\t{long_title}\n2\tQ2\ten:Berlin\n");
+
+        let mut qids = Vec::new();
+        let mut titles = Vec::new();
+        let mut errors = Vec::new();
+        parse_osm_tag_file_with(
+            tags.as_bytes(),
+            &mut qids,
+            &mut titles,
+            &mut errors,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(2, qids.len());
+        assert_eq!(vec![Title::from_osm_tag("en:Berlin").unwrap()], titles);
+        assert_eq!(1, errors.len());
+        assert!(matches!(
+            &errors[0].kind,
+            ParseErrorKind::TooLong(TooLongError { len: 103, max: 16 })
+        ));
+        assert_eq!(Some(1), errors[0].osm_id);
+        assert_eq!(&long_title[..64], errors[0].text);
+    }
+
+    #[test]
+    fn multi_line_record_line_numbers() {
+        let tags =
+            "@id\twikidata\twikipedia\n1\tbad\t\"en:Two\nLines\"\n2\tQ2\ten:Berlin\n3\tQ\t\n";
+        let mut qids = Vec::new();
+        let mut errors = Vec::new();
+        parse_osm_tag_file(tags.as_bytes(), &mut qids, &mut Vec::new(), &mut errors).unwrap();
+
+        assert_eq!(1, qids.len());
+        let lines: Vec<_> = errors
+            .iter()
+            .filter(|e| matches!(e.kind, ParseErrorKind::Qid(_)))
+            .map(|e| (e.osm_id, e.line))
+            .collect();
+        // The first record spans lines 2 and 3.
+        assert_eq!(vec![(Some(1), 2), (Some(3), 5)], lines);
+    }
+
+    #[test]
+    fn strict_aborts_on_first_bad_row() {
+        let tags = "@id\twikidata\twikipedia\n1\tQ1\ten:Berlin\n2\tQ2\tBerlin\n3\tbad\t\n4\tQ4\t\n";
+        let parse = |strict| {
+            let mut qids = Vec::new();
+            let mut errors = Vec::new();
+            let options = TagFileOptions {
+                strict,
+                ..Default::default()
+            };
+            let result = parse_osm_tag_file_with(
+                tags.as_bytes(),
+                &mut qids,
+                &mut Vec::new(),
+                &mut errors,
+                &options,
+            );
+            (result, qids.len(), errors)
+        };
+
+        let (result, qids, errors) = parse(false);
+        assert!(result.is_ok());
+        assert_eq!((3, 2), (qids, errors.len()));
+
+        let (result, qids, errors) = parse(true);
+        let e = result.unwrap_err();
+        let e = e.downcast_ref::<ParseLineError>().unwrap();
+        assert_eq!((Some(2), "Berlin"), (e.osm_id, e.text.as_str()));
+        assert!(matches!(e.kind, ParseErrorKind::Title(_)));
+        assert_eq!(2, qids);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn option_profiles() {
+        assert_eq!(
+            "TagFileOptions { limits: ReadLimits { max_line_bytes: 4194304, max_field_bytes: 65536 }, strict: true, tag_kinds: All }",
+            format!("{:?}", TagFileOptions::strict())
+        );
+        assert_eq!(
+            "TagFileOptions { limits: ReadLimits { max_line_bytes: 4194304, max_field_bytes: 1020 }, strict: false, tag_kinds: All }",
+            format!("{:?}", TagFileOptions::production_extract())
+        );
+
+        // Longer values than OSM allows are skipped before parsing them.
+        let tags = format!(
+            "@id\twikidata\twikipedia\n1\t\ten:{}\n2\t\ten:{}\n",
+            "ö".repeat(100),
+            "ö".repeat(600)
+        );
+        let parse = |options| {
+            let mut titles = Vec::new();
+            let mut errors = Vec::new();
+            parse_osm_tag_file_with(
+                tags.as_bytes(),
+                &mut Vec::new(),
+                &mut titles,
+                &mut errors,
+                &options,
+            )
+            .unwrap();
+            (titles.len(), errors)
+        };
+        let (titles, errors) = parse(TagFileOptions::default());
+        assert_eq!((1, 1), (titles, errors.len()));
+        assert!(matches!(errors[0].kind, ParseErrorKind::Title(_)));
+        let (titles, errors) = parse(TagFileOptions::production_extract());
+        assert_eq!((1, 1), (titles, errors.len()));
+        assert_eq!(Some(2), errors[0].osm_id);
+        assert!(matches!(
+            errors[0].kind,
+            ParseErrorKind::TooLong(TooLongError {
+                len: 1203,
+                max: 1020
+            })
+        ));
+    }
+
+    #[test]
+    fn unread_kinds_skipped() {
+        let tags = "@id\twikidata\twikipedia\n1\tbad\ten:Berlin\n2\tQ2\tBerlin\n";
+        let parse = |tags: &str, tag_kinds| {
+            let mut qids = Vec::new();
+            let mut titles = Vec::new();
+            let mut errors = Vec::new();
+            let options = TagFileOptions {
+                tag_kinds,
+                ..Default::default()
+            };
+            parse_osm_tag_file_with(
+                tags.as_bytes(),
+                &mut qids,
+                &mut titles,
+                &mut errors,
+                &options,
+            )
+            .unwrap();
+            (qids.len(), titles.len(), errors)
+        };
+
+        let (qids, titles, errors) = parse(tags, TagKinds::Titles);
+        assert_eq!((0, 1), (qids, titles));
+        assert_eq!(1, errors.len());
+        assert!(matches!(errors[0].kind, ParseErrorKind::Title(_)));
+
+        let (qids, titles, errors) = parse(tags, TagKinds::Qids);
+        assert_eq!((1, 0), (qids, titles));
+        assert_eq!(1, errors.len());
+        assert!(matches!(errors[0].kind, ParseErrorKind::Qid(_)));
+
+        // The columns of unread tags don't have to exist.
+        let (qids, titles, errors) = parse("@id\twikipedia\n1\ten:Berlin\n", TagKinds::Titles);
+        assert_eq!((0, 1, 0), (qids, titles, errors.len()));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_like_tsv() {
+        let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        let (mut tsv_qids, mut tsv_titles, mut tsv_errors) = (Vec::new(), Vec::new(), Vec::new());
+        parse_osm_tag_file(
+            std::fs::File::open(data.join("tags.tsv")).unwrap(),
+            &mut tsv_qids,
+            &mut tsv_titles,
+            &mut tsv_errors,
+        )
+        .unwrap();
+
+        // The same rows, with integer `@id` and `@otype` columns, null empty values, and two row groups.
+        let (mut qids, mut titles, mut errors) = (Vec::new(), Vec::new(), Vec::new());
+        let parquet = data.join("tags.parquet");
+        let options = TagFileOptions::default();
+        parse_osm_tag_parquet(&parquet, &mut qids, &mut titles, &mut errors, &options).unwrap();
+        assert_eq!(tsv_qids, qids);
+        assert_eq!(tsv_titles, titles);
+
+        let summary = |errors: &[ParseLineError]| -> Vec<_> {
+            errors
+                .iter()
+                .map(|e| (e.line, e.osm_id, e.osm_type, e.text.clone()))
+                .collect()
+        };
+        // Rows are counted without the header line of the TSV file.
+        let tsv_rows: Vec<_> = summary(&tsv_errors)
+            .into_iter()
+            .map(|(line, id, kind, text)| (line - 1, id, kind, text))
+            .collect();
+        assert_eq!(2, errors.len());
+        assert_eq!(tsv_rows, summary(&errors));
+
+        let strict = TagFileOptions::strict();
+        let e = parse_osm_tag_parquet(
+            &parquet,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut errors,
+            &strict,
+        )
+        .unwrap_err();
+        assert_eq!(8, e.downcast_ref::<ParseLineError>().unwrap().line);
+
+        let (mut qids, mut titles) = (Vec::new(), Vec::new());
+        let qids_only = TagFileOptions {
+            tag_kinds: TagKinds::Qids,
+            ..Default::default()
+        };
+        parse_osm_tag_parquet(
+            &parquet,
+            &mut qids,
+            &mut titles,
+            &mut Vec::new(),
+            &qids_only,
+        )
+        .unwrap();
+        assert_eq!((tsv_qids.len(), 0), (qids.len(), titles.len()));
+    }
+}
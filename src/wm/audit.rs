@@ -0,0 +1,451 @@
+//! Checks that the OSM tags requesting articles lead to the articles written by a run.
+//!
+//! Each [TagRecord] is followed from its parsed QID or title, through the [Manifest] entries that list it as a requester or match it, to the html file in the output directory.
+//! Redirects and other languages of an article matched by title are covered by the requesters of the manifest entries.
+//! Records that don't reach a non-empty file are reported with a [BrokenReason], taken from the [DumpStats] and the audit log of the run.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs,
+    io::{BufRead, Read, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    output::{Manifest, ManifestEntry},
+    Qid, Title,
+};
+use crate::{extract::DumpStats, osm};
+
+/// The `wikidata` and `wikipedia` tags of an OSM object, as they are written in a tag file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagRecord {
+    /// Line of the tag file the object is on.
+    pub line: u64,
+    /// The object in the `type/id` format of OSM urls, e.g. `node/1234`, if the file has the columns to identify it.
+    pub object: Option<String>,
+    pub wikidata: String,
+    pub wikipedia: String,
+}
+
+/// Read the records of the TSV tag file `r` that have a `wikidata` or `wikipedia` tag.
+///
+/// ```
+/// use om_wikiparser::wm::audit::read_tag_records;
+///
+/// let tags = "@id\t@otype\twikidata\twikipedia\n1\t0\tQ64\t\n2\t1\t\t\n3\t2\t\tde:Köln\n";
+/// let records = read_tag_records(tags.as_bytes()).unwrap();
+/// assert_eq!(2, records.len());
+/// assert_eq!((4, Some("relation/3")), (records[1].line, records[1].object.as_deref()));
+/// ```
+pub fn read_tag_records(r: impl Read) -> anyhow::Result<Vec<TagRecord>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .flexible(true)
+        .from_reader(r);
+
+    let headers = rdr.headers()?.clone();
+    let column = |name| headers.iter().position(|h| h == name);
+    let found = || headers.iter().collect::<Vec<_>>();
+    let qid_col = column("wikidata")
+        .ok_or_else(|| anyhow!("Cannot find 'wikidata' column in {:?}", found()))?;
+    let title_col = column("wikipedia")
+        .ok_or_else(|| anyhow!("Cannot find 'wikipedia' column in {:?}", found()))?;
+    let (id_col, otype_col, oname_col) = (column("@id"), column("@otype"), column("@oname"));
+
+    let mut records = Vec::new();
+    let mut row = csv::StringRecord::new();
+    loop {
+        match rdr.read_record(&mut row) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            // Malformed lines are reported by `check-tags`.
+            Err(_) => continue,
+        }
+        let line = row.position().map_or(rdr.position().line(), |p| p.line());
+        let wikidata = row.get(qid_col).unwrap_or_default().trim();
+        let wikipedia = row.get(title_col).unwrap_or_default().trim();
+        if wikidata.is_empty() && wikipedia.is_empty() {
+            continue;
+        }
+
+        let osm_id = id_col.and_then(|i| row.get(i)?.trim().parse::<osm::Id>().ok());
+        let osm_type = otype_col
+            .and_then(|i| row.get(i)?.trim().parse().ok())
+            .and_then(osm::Kind::from_otype)
+            .or_else(|| oname_col.and_then(|i| osm::Kind::from_oname(row.get(i)?)));
+        records.push(TagRecord {
+            line,
+            object: osm_type
+                .zip(osm_id)
+                .map(|(kind, id)| format!("{}/{id}", kind.oname())),
+            wikidata: wikidata.to_owned(),
+            wikipedia: wikipedia.to_owned(),
+        });
+    }
+    Ok(records)
+}
+
+/// A matched page that was not written, read from the audit log of a run with [read_skips].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedPage {
+    pub lang: String,
+    /// Title of the page, as it appears in the dump.
+    pub title: String,
+    #[serde(default)]
+    pub qid: Option<Qid>,
+    /// Why it wasn't written, e.g. `stub` or `writing: ...`.
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum AuditEvent {
+    Skip(SkippedPage),
+    #[serde(other)]
+    Other,
+}
+
+/// Read the skipped pages from the newline-delimited JSON written with [crate::extract::Options::write_audit].
+pub fn read_skips(r: impl BufRead) -> anyhow::Result<Vec<SkippedPage>> {
+    let mut skips = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: AuditEvent = serde_json::from_str(&line)
+            .with_context(|| format!("parsing audit entry on line {}", i + 1))?;
+        if let AuditEvent::Skip(skip) = event {
+            skips.push(skip);
+        }
+    }
+    Ok(skips)
+}
+
+/// Configuration of [coverage].
+#[derive(Debug, Default, Clone)]
+pub struct CoverageOptions {
+    /// Output directory of the run, where the paths of the manifest are checked.
+    pub output_dir: PathBuf,
+    /// Check an evenly spaced sample of at most this many records, instead of all of them.
+    pub sample: Option<usize>,
+    /// Pages that the run skipped, to explain records without an article, see [read_skips].
+    ///
+    /// Without them, records of skipped pages are reported as [BrokenReason::Unmatched].
+    pub skips: Vec<SkippedPage>,
+}
+
+/// Why a [TagRecord] does not lead to an article.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BrokenReason {
+    /// Neither tag could be parsed.
+    Unparsed,
+    /// No page of the dump matched the tags.
+    Unmatched,
+    /// A matching page was left out by a filter, e.g. as a stub or a duplicate.
+    Pruned,
+    /// A matching page failed to be simplified or written.
+    WriteFailed,
+    /// The manifest lists an article for the tags, but its file is missing or empty.
+    MissingFile,
+}
+
+impl BrokenReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BrokenReason::Unparsed => "unparsed",
+            BrokenReason::Unmatched => "unmatched",
+            BrokenReason::Pruned => "pruned",
+            BrokenReason::WriteFailed => "write-failed",
+            BrokenReason::MissingFile => "missing-file",
+        }
+    }
+
+    /// Classify the reason of a [SkippedPage].
+    fn of_skip(reason: &str) -> Self {
+        if ["processing:", "post-processing:", "writing:"]
+            .iter()
+            .any(|prefix| reason.starts_with(prefix))
+        {
+            BrokenReason::WriteFailed
+        } else {
+            BrokenReason::Pruned
+        }
+    }
+}
+
+impl Display for BrokenReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A [TagRecord] that does not lead to an article.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrokenChain {
+    #[serde(flatten)]
+    pub record: TagRecord,
+    pub reason: BrokenReason,
+    /// Details of the failure, e.g. the parse error or the path of the missing file.
+    pub detail: String,
+}
+
+/// Records checked and covered in a single language.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LangCoverage {
+    pub records: usize,
+    pub covered: usize,
+}
+
+impl LangCoverage {
+    /// Percentage of the records that lead to an article, or 100 if there are none.
+    pub fn percent(&self) -> f64 {
+        if self.records == 0 {
+            100.0
+        } else {
+            self.covered as f64 * 100.0 / self.records as f64
+        }
+    }
+}
+
+/// Result of [coverage].
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    /// All records that were checked.
+    pub total: LangCoverage,
+    /// Records by the language of their `wikipedia` tag.
+    ///
+    /// Records without a `wikipedia` tag that can be parsed are counted under an empty language.
+    pub by_lang: BTreeMap<String, LangCoverage>,
+    pub broken: Vec<BrokenChain>,
+}
+
+impl CoverageReport {
+    /// Percentage of the checked records that lead to an article.
+    pub fn percent(&self) -> f64 {
+        self.total.percent()
+    }
+
+    /// Write the report as pretty-printed JSON.
+    pub fn write_json(&self, w: impl Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(w, self)?;
+        Ok(())
+    }
+
+    /// Write the broken chains as a TSV file with a header.
+    pub fn write_tsv(&self, w: impl Write) -> anyhow::Result<()> {
+        let mut writer = csv::WriterBuilder::new().delimiter(b'\t').from_writer(w);
+        writer.write_record([
+            "line",
+            "object",
+            "wikidata",
+            "wikipedia",
+            "reason",
+            "detail",
+        ])?;
+        for chain in &self.broken {
+            let record = &chain.record;
+            writer.write_record([
+                record.line.to_string().as_str(),
+                record.object.as_deref().unwrap_or_default(),
+                &record.wikidata,
+                &record.wikipedia,
+                chain.reason.name(),
+                &chain.detail,
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Lookups of the articles and failures of a run by QID and title.
+struct Index<'a> {
+    entries: &'a [ManifestEntry],
+    by_qid: HashMap<Qid, Vec<usize>>,
+    by_title: HashMap<Title, Vec<usize>>,
+    /// Reasons that pages were not written, by QID and title.
+    failed_qids: HashMap<Qid, (BrokenReason, String)>,
+    failed_titles: HashMap<Title, (BrokenReason, String)>,
+}
+
+impl<'a> Index<'a> {
+    fn new(manifest: &'a Manifest, stats: &DumpStats, skips: &[SkippedPage]) -> Self {
+        let mut by_qid: HashMap<Qid, Vec<usize>> = HashMap::new();
+        let mut by_title: HashMap<Title, Vec<usize>> = HashMap::new();
+        for (i, entry) in manifest.entries.iter().enumerate() {
+            if let Some(qid) = entry.qid {
+                by_qid.entry(qid).or_default().push(i);
+            }
+            if let Ok(title) = Title::from_title(&entry.title, &entry.lang) {
+                by_title
+                    .entry(title.in_project(entry.project))
+                    .or_default()
+                    .push(i);
+            }
+            for requester in &entry.requesters {
+                if let Ok(qid) = Qid::from_str(requester) {
+                    by_qid.entry(qid).or_default().push(i);
+                } else if let Ok(title) = Title::from_osm_tag(requester) {
+                    by_title.entry(title).or_default().push(i);
+                }
+            }
+        }
+
+        let mut failed_qids = HashMap::new();
+        let mut failed_titles = HashMap::new();
+        for skip in skips {
+            let failure = (BrokenReason::of_skip(&skip.reason), skip.reason.clone());
+            if let Some(qid) = skip.qid {
+                failed_qids.insert(qid, failure.clone());
+            }
+            if let Ok(title) = Title::from_title(&skip.title, &skip.lang) {
+                failed_titles.insert(title, failure);
+            }
+        }
+        // The stats of the run have the errors in full, so they are preferred to the audit log.
+        for (tag, error) in &stats.post_process_errors {
+            if let Ok(title) = Title::from_osm_tag(tag) {
+                failed_titles.insert(
+                    title,
+                    (
+                        BrokenReason::WriteFailed,
+                        format!("post-processing: {error}"),
+                    ),
+                );
+            }
+        }
+        for collision in &stats.path_collisions {
+            if let Ok(title) = Title::from_osm_tag(&collision.skipped) {
+                failed_titles.insert(
+                    title,
+                    (
+                        BrokenReason::WriteFailed,
+                        format!("path collision with {}", collision.kept),
+                    ),
+                );
+            }
+        }
+
+        Self {
+            entries: &manifest.entries,
+            by_qid,
+            by_title,
+            failed_qids,
+            failed_titles,
+        }
+    }
+}
+
+/// Check which of `records` lead to a non-empty article file in the output directory of a run.
+///
+/// `manifest` and `stats` are those of the run that extracted the articles requested by the records.
+/// A record is covered if either of its tags leads to a file.
+pub fn coverage(
+    records: &[TagRecord],
+    manifest: &Manifest,
+    stats: &DumpStats,
+    opts: &CoverageOptions,
+) -> CoverageReport {
+    let index = Index::new(manifest, stats, &opts.skips);
+    let mut report = CoverageReport::default();
+
+    let sampled: Box<dyn Iterator<Item = &TagRecord>> = match opts.sample {
+        Some(n) if n < records.len() => {
+            Box::new((0..n).map(move |i| &records[i * records.len() / n]))
+        }
+        _ => Box::new(records.iter()),
+    };
+    for record in sampled {
+        let broken = check(record, &index, stats, opts);
+
+        let title = Title::from_osm_tag(&record.wikipedia).ok();
+        let lang = title.as_ref().map(Title::lang).unwrap_or_default();
+        let lang = report.by_lang.entry(lang.to_owned()).or_default();
+        lang.records += 1;
+        report.total.records += 1;
+        match broken {
+            None => {
+                lang.covered += 1;
+                report.total.covered += 1;
+            }
+            Some((reason, detail)) => report.broken.push(BrokenChain {
+                record: record.clone(),
+                reason,
+                detail,
+            }),
+        }
+    }
+    report
+}
+
+/// Follow a single record to its articles, returning why it doesn't reach one.
+fn check(
+    record: &TagRecord,
+    index: &Index,
+    stats: &DumpStats,
+    opts: &CoverageOptions,
+) -> Option<(BrokenReason, String)> {
+    let mut parse_errors = Vec::new();
+    let qid = (!record.wikidata.is_empty())
+        .then(|| Qid::from_str(&record.wikidata))
+        .and_then(|r| {
+            r.map_err(|e| parse_errors.push(format!("wikidata: {e}")))
+                .ok()
+        });
+    let title = (!record.wikipedia.is_empty())
+        .then(|| Title::from_osm_tag(&record.wikipedia))
+        .and_then(|r| {
+            r.map_err(|e| parse_errors.push(format!("wikipedia: {e}")))
+                .ok()
+        });
+    if qid.is_none() && title.is_none() {
+        return Some((BrokenReason::Unparsed, parse_errors.join("; ")));
+    }
+
+    let mut entries: Vec<usize> = title
+        .as_ref()
+        .and_then(|title| index.by_title.get(title))
+        .into_iter()
+        .chain(qid.and_then(|qid| index.by_qid.get(&qid)))
+        .flatten()
+        .copied()
+        .collect();
+    entries.sort_unstable();
+    entries.dedup();
+
+    if !entries.is_empty() {
+        let mut missing = Vec::new();
+        for &i in &entries {
+            let path = &index.entries[i].path;
+            match fs::metadata(opts.output_dir.join(path)) {
+                Ok(metadata) if metadata.len() > 0 => return None,
+                Ok(_) => missing.push(format!("{} is empty", path.display())),
+                Err(e) => missing.push(format!("{}: {e}", path.display())),
+            }
+        }
+        return Some((BrokenReason::MissingFile, missing.join("; ")));
+    }
+
+    let failure = title
+        .as_ref()
+        .and_then(|title| index.failed_titles.get(title))
+        .or_else(|| qid.and_then(|qid| index.failed_qids.get(&qid)));
+    if let Some(failure) = failure {
+        return Some(failure.clone());
+    }
+
+    let detail = if stats.cancelled {
+        "not found before the run was cancelled".to_owned()
+    } else {
+        "no page of the dumps matched".to_owned()
+    };
+    Some((BrokenReason::Unmatched, detail))
+}
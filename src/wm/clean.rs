@@ -0,0 +1,42 @@
+use std::borrow::Cow;
+
+/// Whether `c` is invisible and removed anywhere in a value by [clean_value].
+///
+/// The zero-width joiner and non-joiner are kept inside values, as they are part of the spelling of words in some scripts, e.g. Persian.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        // Zero-width space, word joiner, and byte order mark.
+        '\u{200B}' | '\u{2060}' | '\u{FEFF}'
+        // Bidi marks, embeddings, overrides, and isolates.
+        | '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+fn is_trimmed(c: char) -> bool {
+    c.is_whitespace() || is_invisible(c) || matches!(c, '\u{200C}' | '\u{200D}')
+}
+
+/// Trim whitespace, including non-breaking spaces, from the ends of a value copied from a web page, and remove invisible characters anywhere in it.
+///
+/// Invisible characters are zero-width spaces and bidi controls, which make values that look the same compare unequal.
+/// A value of only whitespace and invisible characters is cleaned to an empty string.
+/// The value is only copied if it contains invisible characters after trimming.
+///
+/// ```
+/// use om_wikiparser::wm::clean_value;
+///
+/// assert_eq!("Q42", clean_value("\u{A0}Q42\u{200B}"));
+/// assert_eq!("en:Köln", clean_value("\u{200E}en:Kö\u{200B}ln\u{200F}"));
+/// assert_eq!("", clean_value("\u{200B}\u{A0}"));
+/// // Joiners are kept inside words.
+/// assert_eq!("fa:می\u{200C}خواهم", clean_value("fa:می\u{200C}خواهم"));
+/// ```
+pub fn clean_value(value: &str) -> Cow<'_, str> {
+    let value = value.trim_matches(is_trimmed);
+    if value.contains(is_invisible) {
+        Cow::Owned(value.chars().filter(|&c| !is_invisible(c)).collect())
+    } else {
+        Cow::Borrowed(value)
+    }
+}
@@ -0,0 +1,115 @@
+//! Short descriptions of Wikidata items, to store with the articles matched to them.
+//!
+//! Descriptions are read from a Wikidata JSON dump (`wikidata-*-all.json.gz`), or any file with one entity on each line in the same format.
+//! A full dump has descriptions of every item, so restrict them to the languages that are needed, or filter the dump to the requested items first.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use super::Qid;
+
+/// Descriptions of Wikidata items by language, e.g. `capital and largest city of Germany` for `Q64` in `en`.
+#[derive(Debug, Default, Clone)]
+pub struct Descriptions {
+    by_qid: HashMap<Qid, BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct Entity {
+    id: String,
+    #[serde(default)]
+    descriptions: BTreeMap<String, Description>,
+}
+
+#[derive(Deserialize)]
+struct Description {
+    value: String,
+}
+
+impl Descriptions {
+    /// Read the descriptions in `langs`, or all languages if it is empty, from the file at `path`.
+    ///
+    /// Files ending in `.gz` are decompressed.
+    pub fn read(path: impl AsRef<Path>, langs: &[String]) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("opening descriptions {:?}", path))?;
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            Self::from_reader(
+                BufReader::new(flate2::read::MultiGzDecoder::new(file)),
+                langs,
+            )
+        } else {
+            Self::from_reader(BufReader::new(file), langs)
+        }
+        .with_context(|| format!("reading descriptions {:?}", path))
+    }
+
+    /// Read descriptions from entities in the format of the Wikidata JSON dumps.
+    ///
+    /// The dumps are a JSON array with one entity on each line, so the brackets and trailing commas are ignored.
+    /// Entities that are not items, like properties, are skipped.
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    /// use om_wikiparser::wm::{descriptions::Descriptions, Qid};
+    ///
+    /// let dump = r#"[
+    /// {"id":"Q64","type":"item","descriptions":{"en":{"language":"en","value":"capital of Germany"},"de":{"language":"de","value":"Hauptstadt Deutschlands"}}},
+    /// {"id":"P31","type":"property","descriptions":{"en":{"language":"en","value":"that class of which this subject is a particular example"}}}
+    /// ]
+    /// "#;
+    /// let descriptions = Descriptions::from_reader(dump.as_bytes(), &["en".into()]).unwrap();
+    /// assert_eq!(1, descriptions.len());
+    /// let q64 = descriptions.get(Qid::from_str("Q64").unwrap()).unwrap();
+    /// assert_eq!(Some("capital of Germany"), q64.get("en").map(String::as_str));
+    /// assert_eq!(None, q64.get("de"));
+    /// ```
+    pub fn from_reader(r: impl BufRead, langs: &[String]) -> anyhow::Result<Self> {
+        let mut by_qid = HashMap::new();
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let line = line.trim().trim_end_matches(',');
+            if line.is_empty() || line == "[" || line == "]" {
+                continue;
+            }
+            let entity: Entity = serde_json::from_str(line)
+                .with_context(|| format!("parsing entity on line {}", i + 1))?;
+            if !entity.id.starts_with('Q') {
+                continue;
+            }
+            let Ok(qid) = entity.id.parse::<Qid>() else {
+                continue;
+            };
+            let descriptions: BTreeMap<String, String> = entity
+                .descriptions
+                .into_iter()
+                .filter(|(lang, _)| langs.is_empty() || langs.contains(lang))
+                .map(|(lang, d)| (lang, d.value))
+                .collect();
+            if !descriptions.is_empty() {
+                by_qid.insert(qid, descriptions);
+            }
+        }
+        Ok(Self { by_qid })
+    }
+
+    /// The descriptions of `qid` by language, if it has any.
+    pub fn get(&self, qid: Qid) -> Option<&BTreeMap<String, String>> {
+        self.by_qid.get(&qid)
+    }
+
+    /// Number of items with descriptions.
+    pub fn len(&self) -> usize {
+        self.by_qid.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_qid.is_empty()
+    }
+}
@@ -0,0 +1,338 @@
+//! Random access to the pages of a dump by title or QID, with an index of their byte offsets.
+//!
+//! Build the index once with [write_index], then read single pages with [get_page] without scanning the dump.
+//! The index is a TSV file with a header line, and a row of each page's [IndexEntry] for its title and another for its QID.
+//! The rows are sorted by their first `key` column, the title or QID, so [find_entry] can binary search them.
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use super::{Page, Qid, Title};
+use crate::limits::{read_line_capped, CappedLine};
+
+/// The location of a single page in a dump.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Page id, see [Page::identifier].
+    pub id: Option<u64>,
+    pub qid: Option<Qid>,
+    /// Title of the page as an osm tag, e.g. `en:Berlin`, or empty if it can't be parsed.
+    pub title: String,
+    /// Member of an archive that contains the page, or empty for a plain NDJSON dump.
+    ///
+    /// Reserved for dumps in archives, which can't be indexed yet.
+    pub member: String,
+    /// Byte offset of the start of the page's line in the dump or member, starting at 0.
+    pub offset: u64,
+    /// Length of the page's line in bytes, excluding the newline.
+    pub length: u64,
+}
+
+/// What to look up a page by in an index.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IndexKey {
+    Title(Title),
+    Qid(Qid),
+}
+
+impl FromStr for IndexKey {
+    type Err = anyhow::Error;
+
+    /// A QID like `Q64`, or a title in the formats of [Title::from_osm_tag].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with('Q') {
+            if let Ok(qid) = Qid::from_str(s) {
+                return Ok(IndexKey::Qid(qid));
+            }
+        }
+        Ok(IndexKey::Title(Title::from_osm_tag(s)?))
+    }
+}
+
+impl Display for IndexKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexKey::Title(title) => title.fmt(f),
+            IndexKey::Qid(qid) => qid.fmt(f),
+        }
+    }
+}
+
+impl IndexEntry {
+    pub fn matches(&self, key: &IndexKey) -> bool {
+        match key {
+            IndexKey::Title(title) => self.title == title.to_string(),
+            IndexKey::Qid(qid) => self.qid == Some(*qid),
+        }
+    }
+}
+
+/// A row of the index file, with the `key` it is sorted by before the fields of its [IndexEntry].
+#[derive(Serialize)]
+struct IndexRow<'a> {
+    key: String,
+    id: Option<u64>,
+    qid: Option<Qid>,
+    title: &'a str,
+    member: &'a str,
+    offset: u64,
+    length: u64,
+}
+
+impl<'a> IndexRow<'a> {
+    fn new(key: String, entry: &'a IndexEntry) -> Self {
+        Self {
+            key,
+            id: entry.id,
+            qid: entry.qid,
+            title: &entry.title,
+            member: &entry.member,
+            offset: entry.offset,
+            length: entry.length,
+        }
+    }
+}
+
+/// Read the location of each page in the newline-delimited JSON `dump`, sorted by title.
+///
+/// Lines that can't be parsed as pages, or are longer than `max_page_bytes`, are skipped and counted in the returned number.
+pub fn build_index(
+    mut dump: impl BufRead,
+    max_page_bytes: usize,
+) -> anyhow::Result<(Vec<IndexEntry>, usize)> {
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    let mut buffer = Vec::new();
+    let mut offset = 0;
+    loop {
+        buffer.clear();
+        let read = match read_line_capped(&mut dump, &mut buffer, max_page_bytes)
+            .context("reading dump")?
+        {
+            CappedLine::Eof => break,
+            CappedLine::Line(len) => len,
+            CappedLine::TooLong(len) => {
+                warn!("Skipping page at byte {offset} longer than {max_page_bytes} bytes");
+                skipped += 1;
+                offset += len as u64;
+                continue;
+            }
+        };
+        let line = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+        if !line.iter().all(u8::is_ascii_whitespace) {
+            match serde_json::from_slice::<Page>(line) {
+                Ok(page) => entries.push(IndexEntry {
+                    id: page.identifier,
+                    qid: page.wikidata(),
+                    title: page
+                        .title()
+                        .map(|title| title.to_string())
+                        .unwrap_or_default(),
+                    member: String::new(),
+                    offset,
+                    length: line.len() as u64,
+                }),
+                Err(e) => {
+                    warn!("Skipping page at byte {offset} that can't be parsed: {e}");
+                    skipped += 1;
+                }
+            }
+        }
+        offset += read as u64;
+    }
+    entries.sort_by(|a, b| (&a.title, a.id, a.offset).cmp(&(&b.title, b.id, b.offset)));
+    Ok((entries, skipped))
+}
+
+/// Write an index of `dump` to `index`, returning the pages indexed and the lines skipped.
+///
+/// Pages longer than `max_page_bytes` are skipped like in [build_index].
+///
+/// ```
+/// use std::io::Cursor;
+/// use om_wikiparser::wm::dump::{find_entry, read_entry, write_index, IndexKey};
+///
+/// let dump = concat!(
+///     r#"{"name": "Berlin", "identifier": 3354, "date_modified": "", "in_language": {"identifier": "en"}, "main_entity": {"identifier": "Q64"}, "article_body": {"html": ""}}"#,
+///     "\n",
+///     r#"{"name": "Köln", "identifier": 2789, "date_modified": "", "in_language": {"identifier": "de"}, "main_entity": {"identifier": "Q365"}, "article_body": {"html": ""}}"#,
+///     "\n",
+/// );
+/// let mut index = Vec::new();
+/// assert_eq!((2, 0), write_index(dump.as_bytes(), &mut index, 1 << 20).unwrap());
+///
+/// let entry = find_entry(Cursor::new(&index), &"Q64".parse().unwrap()).unwrap().unwrap();
+/// assert_eq!((Some(3354), "en:Berlin", 0), (entry.id, entry.title.as_str(), entry.offset));
+/// let json = read_entry(Cursor::new(dump), &entry).unwrap();
+/// assert!(json.ends_with(b"}}"));
+///
+/// let key: IndexKey = "en:Köln".parse().unwrap();
+/// assert_eq!(None, find_entry(Cursor::new(&index), &key).unwrap());
+/// ```
+pub fn write_index(
+    dump: impl BufRead,
+    index: impl Write,
+    max_page_bytes: usize,
+) -> anyhow::Result<(usize, usize)> {
+    let (entries, skipped) = build_index(dump, max_page_bytes)?;
+    let mut rows = Vec::with_capacity(entries.len() * 2);
+    for entry in &entries {
+        if !entry.title.is_empty() {
+            rows.push(IndexRow::new(entry.title.clone(), entry));
+        }
+        if let Some(qid) = entry.qid {
+            rows.push(IndexRow::new(qid.to_string(), entry));
+        }
+    }
+    // Titles have a `lang:` prefix, so they can't be the same as a QID.
+    rows.sort_by(|a, b| (&a.key, a.id, a.offset).cmp(&(&b.key, b.id, b.offset)));
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(index);
+    for row in &rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok((entries.len(), skipped))
+}
+
+/// The first entry of the index read from `index` that matches `key`.
+///
+/// The sorted rows are binary searched, so only a few of them are read.
+pub fn find_entry(
+    mut index: impl BufRead + Seek,
+    key: &IndexKey,
+) -> anyhow::Result<Option<IndexEntry>> {
+    let key = key.to_string();
+    let mut line = Vec::new();
+    index.rewind()?;
+    if index.read_until(b'\n', &mut line)? == 0 {
+        bail!("index is empty");
+    }
+    let headers = parse_row(&line, None)?.0;
+    if headers.get(0) != Some("key") {
+        bail!("index has no key column, rebuild it");
+    }
+    let data_start = line.len() as u64;
+    let end = index.seek(SeekFrom::End(0))?;
+
+    // Each line starting before `low` has a smaller key, and each line starting at or after `high` doesn't.
+    let (mut low, mut high) = (data_start, end);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        // Skip to the start of the next line, `mid` itself if the previous byte ends a line.
+        index.seek(SeekFrom::Start(mid - 1))?;
+        line.clear();
+        let start = mid - 1 + index.read_until(b'\n', &mut line)? as u64;
+        if start >= high {
+            high = mid;
+            continue;
+        }
+        line.clear();
+        let len = index.read_until(b'\n', &mut line)? as u64;
+        let (row, _) = parse_row(&line, Some(&headers))?;
+        match row.get(0).unwrap_or_default().cmp(key.as_str()) {
+            Ordering::Less => low = start + len,
+            _ => high = start,
+        }
+    }
+
+    index.seek(SeekFrom::Start(low))?;
+    line.clear();
+    if index.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    let (row, entry) = parse_row(&line, Some(&headers))?;
+    Ok(entry.filter(|_| row.get(0) == Some(key.as_str())))
+}
+
+/// Parse a `line` of the index, along with its [IndexEntry] if the `headers` are given.
+fn parse_row(
+    line: &[u8],
+    headers: Option<&csv::StringRecord>,
+) -> anyhow::Result<(csv::StringRecord, Option<IndexEntry>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .from_reader(line);
+    let mut row = csv::StringRecord::new();
+    if !reader.read_record(&mut row).context("reading index")? {
+        bail!("index has an empty line");
+    }
+    let entry = headers
+        .map(|headers| row.deserialize(Some(headers)))
+        .transpose()
+        .context("reading index")?;
+    Ok((row, entry))
+}
+
+/// Read the JSON of the page at `entry` in `dump`.
+pub fn read_entry(mut dump: impl Read + Seek, entry: &IndexEntry) -> anyhow::Result<Vec<u8>> {
+    if !entry.member.is_empty() {
+        bail!(
+            "reading page {:?} from archive member {:?} is not supported",
+            entry.title,
+            entry.member
+        );
+    }
+    dump.seek(SeekFrom::Start(entry.offset))?;
+    let mut json = Vec::with_capacity(entry.length as usize);
+    dump.take(entry.length).read_to_end(&mut json)?;
+    if json.len() as u64 != entry.length {
+        bail!(
+            "dump ends before the end of page {:?} at byte {}",
+            entry.title,
+            entry.offset
+        );
+    }
+    Ok(json)
+}
+
+/// Read the page matching `key` from the dump at `dump_path` with the index at `index_path`, if there is one.
+///
+/// Fails if the page at the indexed location doesn't match, e.g. because the dump changed since the index was built.
+/// To read from something other than files, use [find_entry] and [read_entry].
+pub fn get_page(
+    dump_path: impl AsRef<Path>,
+    index_path: impl AsRef<Path>,
+    key: &IndexKey,
+) -> anyhow::Result<Option<Page>> {
+    let (dump_path, index_path) = (dump_path.as_ref(), index_path.as_ref());
+    let index = File::open(index_path)
+        .map(BufReader::new)
+        .with_context(|| format!("opening index {:?}", index_path))?;
+    let Some(entry) =
+        find_entry(index, key).with_context(|| format!("reading index {:?}", index_path))?
+    else {
+        return Ok(None);
+    };
+
+    let dump = File::open(dump_path).with_context(|| format!("opening dump {:?}", dump_path))?;
+    let json = read_entry(dump, &entry).with_context(|| format!("reading dump {:?}", dump_path))?;
+    let page: Page = serde_json::from_slice(&json).with_context(|| {
+        format!(
+            "parsing page {:?} at byte {} of {:?}",
+            entry.title, entry.offset, dump_path
+        )
+    })?;
+    if page.identifier != entry.id || page.wikidata() != entry.qid {
+        bail!(
+            "page at byte {} of {:?} is not {:?} as indexed in {:?}, rebuild the index",
+            entry.offset,
+            dump_path,
+            entry.title,
+            index_path
+        );
+    }
+    Ok(Some(page))
+}
@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use super::{Qid, Title};
+
+/// Directory structure of extracted articles.
+///
+/// ```
+/// use std::{path::PathBuf, str::FromStr};
+/// use om_wikiparser::wm::{Layout, Qid, Title};
+///
+/// let title = Title::from_title("Article Title", "en").unwrap();
+/// assert_eq!(
+///     Layout::Flat.title_dir(PathBuf::from("base"), &title),
+///     PathBuf::from("base/en.wikipedia.org/wiki/Article_Title")
+/// );
+/// assert_eq!(
+///     Layout::Sharded.title_dir(PathBuf::from("base"), &title),
+///     PathBuf::from("base/7a/bc/en.wikipedia.org/wiki/Article_Title")
+/// );
+///
+/// let qid = Qid::from_str("Q12345").unwrap();
+/// assert_eq!(
+///     Layout::Sharded.qid_dir(PathBuf::from("base"), &qid),
+///     PathBuf::from("base/c8/ed/wikidata/Q12345")
+/// );
+/// ```
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// `wikidata/QXXX` and `lang.wikipedia.org/wiki/Article_Title`.
+    #[default]
+    Flat,
+    /// The flat layout within two levels of directories derived from a hash of the QID or title,
+    /// e.g. `ab/cd/lang.wikipedia.org/wiki/Article_Title`.
+    ///
+    /// This keeps the number of entries in each directory low, which some filesystems handle poorly.
+    Sharded,
+}
+
+impl Layout {
+    pub fn title_dir(&self, base: PathBuf, title: &Title) -> PathBuf {
+        match self {
+            Layout::Flat => title.get_dir(base),
+            Layout::Sharded => title.get_dir(shard(base, &title.to_string())),
+        }
+    }
+
+    pub fn qid_dir(&self, base: PathBuf, qid: &Qid) -> PathBuf {
+        match self {
+            Layout::Flat => qid.get_dir(base),
+            Layout::Sharded => qid.get_dir(shard(base, &qid.to_string())),
+        }
+    }
+}
+
+/// Language codes to use in the output directory in place of those of the wikis, e.g. `zh-CN` for `zh-hans`.
+///
+/// Only the paths of the written articles are changed: pages are still matched, and listed in the manifest, by their original language.
+///
+/// ```
+/// use std::path::PathBuf;
+/// use om_wikiparser::wm::{LanguageMap, Layout, Title};
+///
+/// let map = LanguageMap::from_reader("zh-hans\tzh-CN\n".as_bytes()).unwrap();
+/// assert_eq!("zh-CN", map.lang("zh-hans"));
+/// assert_eq!("en", map.lang("en"));
+///
+/// let title = Title::from_osm_tag("zh-hans:北京").unwrap();
+/// assert_eq!(
+///     PathBuf::from("base/zh-CN.wikipedia.org/wiki/北京"),
+///     map.title_dir(Layout::Flat, PathBuf::from("base"), &title)
+/// );
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct LanguageMap(BTreeMap<String, String>);
+
+impl LanguageMap {
+    /// Read the map from the file at `path`, see [LanguageMap::from_reader].
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("opening language map {:?}", path))?;
+        Self::from_reader(BufReader::new(file))
+            .with_context(|| format!("reading language map {:?}", path))
+    }
+
+    /// Read lines of a language code and its replacement separated by a tab, like `zh-hans\tzh-CN`.
+    ///
+    /// Blank lines are ignored.
+    pub fn from_reader(r: impl BufRead) -> anyhow::Result<Self> {
+        let mut map = BTreeMap::new();
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some((from, to)) = line.split_once('\t') else {
+                bail!("no tab between the languages on line {}: {:?}", i + 1, line);
+            };
+            let (from, to) = (from.trim(), to.trim());
+            if from.is_empty() || to.is_empty() || to.contains(['/', '\t']) {
+                bail!("invalid languages on line {}: {:?}", i + 1, line);
+            }
+            if map.insert(from.to_owned(), to.to_owned()).is_some() {
+                bail!("{:?} is mapped more than once on line {}", from, i + 1);
+            }
+        }
+        Ok(Self(map))
+    }
+
+    /// The language code to write articles in `lang` as.
+    pub fn lang<'a>(&'a self, lang: &'a str) -> &'a str {
+        self.0.get(lang).map_or(lang, String::as_str)
+    }
+
+    /// The [Layout::title_dir] of `title` with its language replaced.
+    pub fn title_dir(&self, layout: Layout, base: PathBuf, title: &Title) -> PathBuf {
+        match self.0.get(title.lang()) {
+            None => layout.title_dir(base, title),
+            Some(lang) => layout.title_dir(base, &title.clone().in_lang(lang)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Append the shard directories for `key` to `base`.
+fn shard(mut base: PathBuf, key: &str) -> PathBuf {
+    let [a, b, ..] = fnv1a(key.as_bytes()).to_be_bytes();
+    base.push(format!("{a:02x}"));
+    base.push(format!("{b:02x}"));
+    base
+}
+
+/// 64-bit FNV-1a hash.
+///
+/// Unlike [std::hash::DefaultHasher], the output is guaranteed to be stable across releases and platforms.
+/// See <http://www.isthe.com/chongo/tech/comp/fnv/index.html>.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
@@ -1,7 +1,19 @@
 //! Wikimedia types
 mod page;
-pub use page::Page;
+pub use page::{ArticleBody, ContentEncoding, Page};
 mod title;
 pub use title::*;
 mod qid;
 pub use qid::*;
+mod layout;
+pub use layout::{LanguageMap, Layout};
+mod clean;
+pub use clean::clean_value;
+pub mod audit;
+pub mod descriptions;
+pub mod dump;
+pub mod output;
+pub mod plan;
+pub mod sets;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
@@ -0,0 +1,996 @@
+//! Reading and post-processing of a directory of extracted articles.
+use std::{
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap},
+    fmt::{Display, Write as _},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    num::NonZeroUsize,
+    os::unix,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::{path_to_tag, Layout, Project, Qid, Title};
+use crate::html::{Coordinates, Direction};
+
+/// Name of the manifest file within an output directory.
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// Name of the file of [LinkEntry]s within an output directory, for links that could not be created as symlinks.
+pub const LINKS_FILE: &str = "links.jsonl";
+
+/// Name of the file of the languages written for each QID within an output directory, see [write_qid_langs].
+pub const QID_LANGS_FILE: &str = "qid_langs.json";
+
+/// Name of the TSV file of [AltName]s within an output directory.
+pub const ALTNAMES_FILE: &str = "altnames.tsv";
+
+/// Name of the file of the html file of each title within an output directory, see [write_link_index].
+pub const LINK_INDEX_FILE: &str = "link_index.json";
+
+/// Where an [AltName] of an article comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AltNameSource {
+    /// A redirect to the article in its wiki.
+    Redirect,
+    /// The title of the same article in another language.
+    Langlink,
+}
+
+impl std::fmt::Display for AltNameSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AltNameSource::Redirect => "redirect",
+            AltNameSource::Langlink => "langlink",
+        })
+    }
+}
+
+/// Another name of a written article, e.g. `Kyiv` for `wikidata/Q1899/uk.html`, as a row of [ALTNAMES_FILE].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AltName {
+    /// Path of the article's html file, relative to the output directory.
+    pub path: PathBuf,
+    pub name: String,
+    pub source: AltNameSource,
+}
+
+impl AltName {
+    /// Format as a single tab-separated line of the altnames file, including the line ending.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::output::{AltName, AltNameSource};
+    ///
+    /// let alt = AltName { path: "wikidata/Q1899/uk.html".into(), name: "Kyiv".into(), source: AltNameSource::Langlink };
+    /// assert_eq!("wikidata/Q1899/uk.html\tKyiv\tlanglink\n", alt.to_line());
+    /// ```
+    pub fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\n", self.path.display(), self.name, self.source)
+    }
+}
+
+/// A link between article directories, recorded in [LINKS_FILE] where the filesystem doesn't support symlinks.
+///
+/// Paths are relative to the output directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkEntry {
+    pub link: PathBuf,
+    pub target: PathBuf,
+}
+
+impl LinkEntry {
+    /// Format as a single line of the links file, including the line ending.
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("link entry is serializable");
+        line.push('\n');
+        line
+    }
+}
+
+/// Read the [LINKS_FILE] in `base`, if there is one.
+pub fn read_links(base: impl AsRef<Path>) -> anyhow::Result<Vec<LinkEntry>> {
+    let path = base.as_ref().join(LINKS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = BufReader::new(
+        File::open(&path).with_context(|| format!("opening links file {:?}", path))?,
+    );
+    let mut links = Vec::new();
+    for (i, line) in file.lines().enumerate() {
+        let line = line.with_context(|| format!("reading links file {:?}", path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        links.push(
+            serde_json::from_str(&line)
+                .with_context(|| format!("parsing line {} of links file {:?}", i + 1, path))?,
+        );
+    }
+    Ok(links)
+}
+
+/// Record of the articles written to an output directory.
+///
+/// The manifest is stored as newline-delimited JSON [ManifestEntry]s in [MANIFEST_FILE] at the root of the output directory.
+/// Entries are appended by each run, so the file may contain multiple entries for the same article.
+/// They are collapsed into one entry per path when it is read, see [Manifest::dedup_by_path].
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A single article written to an output directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path of the html file, relative to the output directory.
+    pub path: PathBuf,
+    /// Language of the article.
+    pub lang: String,
+    /// Title of the article, as it appears in the dump.
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qid: Option<Qid>,
+    /// Id of the page the article was written from, which is the page that was kept if several had the same path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<u64>,
+    /// Requested QIDs and `lang:Title`s that matched the article, e.g. `Q64` and `en:Berlin`.
+    ///
+    /// For an article matched by the QID of an article matched by title in another language, these are the titles.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requesters: Vec<String>,
+    /// Project of the article, omitted for Wikipedia.
+    #[serde(default, skip_serializing_if = "Project::is_wikipedia")]
+    pub project: Project,
+    /// Direction of the article's text, omitted for left-to-right.
+    ///
+    /// Simplified html has no `html` or `body` element to set it on, so it is kept here.
+    #[serde(default, skip_serializing_if = "Direction::is_ltr")]
+    pub dir: Direction,
+    /// Wikidata descriptions of the article's QID by language, if [crate::extract::Options::wikidata_descriptions] was set.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub descriptions: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Read the manifest in the output directory `base`.
+    pub fn read(base: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = base.as_ref().join(MANIFEST_FILE);
+        let file = File::open(&path).with_context(|| format!("opening manifest {:?}", path))?;
+        Self::from_reader(BufReader::new(file))
+            .with_context(|| format!("reading manifest {:?}", path))
+    }
+
+    pub fn from_reader(r: impl BufRead) -> anyhow::Result<Self> {
+        let mut entries = Vec::new();
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .with_context(|| format!("parsing entry on line {}", i + 1))?;
+            entries.push(entry);
+        }
+        let mut manifest = Self { entries };
+        manifest.dedup_by_path();
+        Ok(manifest)
+    }
+
+    /// Collapse entries with the same path into the first of them, combining their [ManifestEntry::requesters].
+    ///
+    /// The other fields are taken from the last entry, which describes the file that was written last.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::output::{Manifest, ManifestEntry};
+    ///
+    /// let entry = |title: &str, requester: &str| ManifestEntry {
+    ///     path: "en.wikipedia.org/wiki/Berlin/en.html".into(),
+    ///     lang: "en".into(),
+    ///     title: title.into(),
+    ///     qid: None,
+    ///     page_id: None,
+    ///     requesters: vec![requester.into()],
+    ///     project: Default::default(),
+    ///     dir: Default::default(),
+    ///     descriptions: Default::default(),
+    /// };
+    /// let mut manifest = Manifest {
+    ///     entries: vec![entry("Berlin", "en:Berlin"), entry("Berlin", "en:Berlin,_Germany")],
+    /// };
+    /// manifest.dedup_by_path();
+    /// assert_eq!(1, manifest.entries.len());
+    /// assert_eq!(vec!["en:Berlin", "en:Berlin,_Germany"], manifest.entries[0].requesters);
+    /// ```
+    pub fn dedup_by_path(&mut self) {
+        let mut indexes: HashMap<PathBuf, usize> = HashMap::new();
+        let mut entries: Vec<ManifestEntry> = Vec::with_capacity(self.entries.len());
+        for mut entry in self.entries.drain(..) {
+            match indexes.get(&entry.path) {
+                None => {
+                    indexes.insert(entry.path.clone(), entries.len());
+                    entries.push(entry);
+                }
+                Some(&i) => {
+                    let mut requesters = std::mem::take(&mut entries[i].requesters);
+                    for requester in entry.requesters.drain(..) {
+                        if !requesters.contains(&requester) {
+                            requesters.push(requester);
+                        }
+                    }
+                    entry.requesters = requesters;
+                    entries[i] = entry;
+                }
+            }
+        }
+        self.entries = entries;
+    }
+}
+
+impl Manifest {
+    /// The languages of the Wikipedia articles written for each QID.
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    /// use om_wikiparser::wm::{output::{Manifest, ManifestEntry}, Qid};
+    ///
+    /// let entry = |lang: &str| ManifestEntry {
+    ///     path: format!("wikidata/Q64/{lang}.html").into(),
+    ///     lang: lang.into(),
+    ///     title: "Berlin".into(),
+    ///     qid: Some(Qid::from_str("Q64").unwrap()),
+    ///     page_id: None,
+    ///     requesters: Vec::new(),
+    ///     project: Default::default(),
+    ///     dir: Default::default(),
+    ///     descriptions: Default::default(),
+    /// };
+    /// let manifest = Manifest { entries: vec![entry("en"), entry("de")] };
+    /// let langs = manifest.langs_by_qid();
+    /// assert_eq!(vec!["de", "en"], langs[&Qid::from_str("Q64").unwrap()].iter().collect::<Vec<_>>());
+    /// ```
+    pub fn langs_by_qid(&self) -> BTreeMap<Qid, BTreeSet<String>> {
+        let mut langs: BTreeMap<Qid, BTreeSet<String>> = BTreeMap::new();
+        for entry in &self.entries {
+            if let (Some(qid), true) = (entry.qid, entry.project.is_wikipedia()) {
+                langs.entry(qid).or_default().insert(entry.lang.clone());
+            }
+        }
+        langs
+    }
+}
+
+/// Write the [Manifest::langs_by_qid] of the manifest in the output directory `base` to [QID_LANGS_FILE] in it, as a JSON object.
+///
+/// The languages are those of the articles that were actually written, after any filters, by every run recorded in the manifest.
+/// The file is replaced atomically, so it can be read while another run rewrites it.
+pub fn write_qid_langs(base: impl AsRef<Path>) -> anyhow::Result<()> {
+    let base = base.as_ref();
+    let langs = Manifest::read(base)?.langs_by_qid();
+    let path = base.join(QID_LANGS_FILE);
+    let mut file = tempfile::NamedTempFile::new_in(base)
+        .with_context(|| format!("creating temporary file for {:?}", path))?;
+    serde_json::to_writer_pretty(BufWriter::new(&mut file), &langs)
+        .with_context(|| format!("writing {:?}", path))?;
+    file.persist(&path)
+        .with_context(|| format!("replacing {:?}", path))?;
+    Ok(())
+}
+
+/// Read the [LINK_INDEX_FILE] in `base`, or an empty index if there is none.
+///
+/// The index is a JSON object of titles like `en:Berlin,_Germany`, normalized like [Title::to_string], and the paths of their html files relative to `base`.
+pub fn read_link_index(base: impl AsRef<Path>) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    let path = base.as_ref().join(LINK_INDEX_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let file = File::open(&path).with_context(|| format!("opening link index {:?}", path))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("reading link index {:?}", path))
+}
+
+/// Add `entries` of titles and their html files to the [LINK_INDEX_FILE] in `base`, see [read_link_index].
+///
+/// The entries of earlier runs are kept, unless this run wrote the same title to another file.
+/// Like [write_qid_langs], the file is replaced atomically.
+pub fn write_link_index(
+    base: impl AsRef<Path>,
+    entries: BTreeMap<String, PathBuf>,
+) -> anyhow::Result<()> {
+    let base = base.as_ref();
+    let mut index = read_link_index(base)?;
+    index.extend(entries);
+    let path = base.join(LINK_INDEX_FILE);
+    let mut file = tempfile::NamedTempFile::new_in(base)
+        .with_context(|| format!("creating temporary file for {:?}", path))?;
+    serde_json::to_writer_pretty(BufWriter::new(&mut file), &index)
+        .with_context(|| format!("writing {:?}", path))?;
+    file.persist(&path)
+        .with_context(|| format!("replacing {:?}", path))?;
+    Ok(())
+}
+
+impl ManifestEntry {
+    /// Format as a single line of the manifest file, including the line ending.
+    ///
+    /// To append to a manifest file shared by multiple processes, write the line in a single call.
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("manifest entry is serializable");
+        line.push('\n');
+        line
+    }
+}
+
+/// Version of the [Sidecar] format, increased when its fields change.
+///
+/// - 1: Provenance of the article. Sidecars of this version have no `schema_version`.
+/// - 2: Adds `schema_version`, `dir`, `word_count`, `coordinates`, `short_description`, and `categories`.
+pub const SIDECAR_SCHEMA_VERSION: u32 = 2;
+
+/// Provenance and metadata of an article, written next to its html file by `get-articles --write-sidecars`.
+///
+/// For `en.html` or `en.html.gz`, the sidecar is `en.meta.json`, see [sidecar_path].
+/// It is a JSON object with the fields below, where optional fields that are empty are omitted.
+/// The metadata that simplification removes, like the coordinates and categories, is read from the original html of the page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// Version of the format, see [SIDECAR_SCHEMA_VERSION].
+    #[serde(default = "Sidecar::first_schema_version")]
+    pub schema_version: u32,
+    /// Language of the article.
+    pub lang: String,
+    /// Title of the article, as it appears in the dump.
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qid: Option<Qid>,
+    pub matched_by: MatchedBy,
+    /// Requested QIDs and `lang:Title`s that matched the article, as in [ManifestEntry::requesters].
+    #[serde(default)]
+    pub requesters: Vec<String>,
+    /// Line of the page in the dump.
+    pub line: usize,
+    /// Byte offset of the start of the page in the dump.
+    pub byte: usize,
+    /// Revision id of the page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u64>,
+    /// Unix time in seconds the article was written at.
+    pub written_at: u64,
+    /// Wikidata descriptions of the article's QID, as in [ManifestEntry::descriptions].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub descriptions: BTreeMap<String, String>,
+    /// Direction of the article's text, as in [ManifestEntry::dir].
+    #[serde(default, skip_serializing_if = "Direction::is_ltr")]
+    pub dir: Direction,
+    /// Words in the written html, see [crate::html::word_count].
+    #[serde(default)]
+    pub word_count: usize,
+    /// Coordinates of the article's subject, see [crate::html::detect_coordinates].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<Coordinates>,
+    /// Short description of the article from its wiki, see [crate::html::detect_short_description].
+    ///
+    /// Unlike the [Sidecar::descriptions] from Wikidata, this is only in the article's own language.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_description: Option<String>,
+    /// Categories of the article, see [crate::html::detect_categories].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+}
+
+impl Sidecar {
+    fn first_schema_version() -> u32 {
+        1
+    }
+}
+
+/// How a written article was matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchedBy {
+    /// A requested QID.
+    Qid,
+    /// A requested title of the page.
+    Title,
+    /// A requested title that redirects to the page.
+    Redirect,
+    /// The QID of an article matched by title earlier in the input.
+    Expanded,
+    /// A requested title with the same skeleton as the page, see [super::Title::skeleton].
+    NearMiss,
+}
+
+/// Path of the [Sidecar] of the html file at `html`.
+///
+/// ```
+/// use std::path::Path;
+/// use om_wikiparser::wm::output::sidecar_path;
+///
+/// assert_eq!(Path::new("wikidata/Q64/en.meta.json"), sidecar_path(Path::new("wikidata/Q64/en.html")));
+/// assert_eq!(Path::new("wikidata/Q64/de.meta.json"), sidecar_path(Path::new("wikidata/Q64/de.html.gz")));
+/// ```
+pub fn sidecar_path(html: &Path) -> PathBuf {
+    let name = html.file_name().unwrap_or_default().to_string_lossy();
+    let lang = name.split_once('.').map_or(&*name, |(lang, _)| lang);
+    html.with_file_name(format!("{lang}.meta.json"))
+}
+
+/// An article of the manifest whose [Sidecar] is missing or disagrees with it, from [check_sidecars].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SidecarMismatch {
+    /// Path of the html file, relative to the output directory.
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl Display for SidecarMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.path, self.reason)
+    }
+}
+
+/// Check that each article in `manifest` has a sidecar in `base` with the same language, title, QID, and requesters.
+pub fn check_sidecars(base: impl AsRef<Path>, manifest: &Manifest) -> Vec<SidecarMismatch> {
+    let base = base.as_ref();
+    let mut mismatches = Vec::new();
+    for entry in &manifest.entries {
+        let mismatch = |reason: String| SidecarMismatch {
+            path: entry.path.clone(),
+            reason,
+        };
+        let path = sidecar_path(&base.join(&entry.path));
+        let sidecar: Sidecar = match fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(serde_json::from_slice(&contents)?))
+        {
+            Ok(sidecar) => sidecar,
+            Err(e) => {
+                mismatches.push(mismatch(format!("reading sidecar {:?}: {:#}", path, e)));
+                continue;
+            }
+        };
+
+        let mut differs = |field: &str, manifest: String, sidecar: String| {
+            if manifest != sidecar {
+                mismatches.push(mismatch(format!(
+                    "{field} is {manifest:?} in the manifest but {sidecar:?} in the sidecar"
+                )));
+            }
+        };
+        differs("lang", entry.lang.clone(), sidecar.lang);
+        differs("title", entry.title.clone(), sidecar.title);
+        let qid = |qid: Option<Qid>| qid.map(|q| q.to_string()).unwrap_or_default();
+        differs("qid", qid(entry.qid), qid(sidecar.qid));
+        // The manifest combines the requesters of every run that wrote the article.
+        let missing: Vec<_> = sidecar
+            .requesters
+            .iter()
+            .filter(|r| !entry.requesters.contains(r))
+            .collect();
+        if !missing.is_empty() {
+            mismatches.push(mismatch(format!(
+                "requesters {missing:?} of the sidecar are not in the manifest"
+            )));
+        }
+    }
+    mismatches
+}
+
+/// An article in an output directory, from [iter_articles].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredArticle {
+    /// Path of the html file, within the output directory.
+    pub path: PathBuf,
+    /// Language of the article, from the name of the html file.
+    pub lang: String,
+    /// Title of the article.
+    ///
+    /// Without a manifest, this is only known for articles in a title directory or linked from one in the same language.
+    pub title: Option<Title>,
+    pub qid: Option<Qid>,
+    /// Size of the html file, which may be compressed.
+    pub bytes: u64,
+}
+
+impl StoredArticle {
+    /// Read the html of the article, decompressing it if it was written with [crate::extract::HtmlCompression].
+    pub fn read_html(&self) -> anyhow::Result<String> {
+        let file =
+            File::open(&self.path).with_context(|| format!("opening article {:?}", self.path))?;
+        let mut html = String::new();
+        if self.path.extension().is_some_and(|ext| ext == "gz") {
+            flate2::read::GzDecoder::new(file).read_to_string(&mut html)
+        } else {
+            BufReader::new(file).read_to_string(&mut html)
+        }
+        .with_context(|| format!("reading article {:?}", self.path))?;
+        Ok(html)
+    }
+}
+
+/// Iterate over the articles in the output directory `base`, written with either [super::Layout].
+///
+/// If `base` has a manifest, the articles are those in it, in order.
+/// Otherwise, they are found from the directory structure, sorted by path.
+pub fn iter_articles(
+    base: impl AsRef<Path>,
+) -> impl Iterator<Item = anyhow::Result<StoredArticle>> {
+    let base = base.as_ref().to_owned();
+    let articles = if base.join(MANIFEST_FILE).is_file() {
+        Manifest::read(&base).map(|manifest| {
+            manifest
+                .entries
+                .into_iter()
+                .map(|entry| {
+                    let title = Title::from_title(&entry.title, &entry.lang).ok();
+                    (base.join(entry.path), entry.lang, title, entry.qid)
+                })
+                .collect()
+        })
+    } else {
+        infer_articles(&base)
+    };
+
+    let (articles, error) = match articles {
+        Ok(articles) => (articles, None),
+        Err(e) => (Vec::new(), Some(e)),
+    };
+    error
+        .into_iter()
+        .map(Err)
+        .chain(articles.into_iter().map(|(path, lang, title, qid)| {
+            let bytes = fs::metadata(&path)
+                .with_context(|| format!("reading metadata of article {:?}", path))?
+                .len();
+            Ok(StoredArticle {
+                path,
+                lang,
+                title,
+                qid,
+                bytes,
+            })
+        }))
+}
+
+type ArticleInfo = (PathBuf, String, Option<Title>, Option<Qid>);
+
+/// Find the html files in `base`, and determine their titles from the directories they're in or linked from.
+fn infer_articles(base: &Path) -> anyhow::Result<Vec<ArticleInfo>> {
+    let mut files = Vec::new();
+    let mut links = Vec::new();
+    find_files(base, &mut files, &mut links)?;
+
+    let tag_of = |path: &Path| {
+        path_to_tag(path, base, Layout::Flat).or_else(|| path_to_tag(path, base, Layout::Sharded))
+    };
+
+    // Titles that link to each article directory.
+    let mut linked: BTreeMap<PathBuf, BTreeSet<Title>> = BTreeMap::new();
+    for link in links {
+        let (Some(tag), Ok(target)) = (tag_of(&link), fs::canonicalize(&link)) else {
+            continue;
+        };
+        if let Ok(title) = Title::from_osm_tag(&tag) {
+            linked.entry(target).or_default().insert(title);
+        }
+    }
+
+    let mut articles = Vec::new();
+    for path in files {
+        let Some(dir) = path.parent() else { continue };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let lang = name
+            .split_once('.')
+            .map_or(&*name, |(lang, _)| lang)
+            .to_owned();
+
+        let qid = dir
+            .file_name()
+            .and_then(|name| name.to_str()?.parse::<Qid>().ok())
+            .filter(|qid| {
+                [Layout::Flat, Layout::Sharded]
+                    .iter()
+                    .any(|layout| layout.qid_dir(base.to_owned(), qid) == dir)
+            });
+        let title = match qid {
+            Some(_) => fs::canonicalize(dir).ok().and_then(|dir| {
+                linked
+                    .get(&dir)?
+                    .iter()
+                    .find(|title| title.lang() == lang)
+                    .cloned()
+            }),
+            None => tag_of(dir).and_then(|tag| Title::from_osm_tag(&tag).ok()),
+        };
+        if qid.is_none() && title.is_none() {
+            continue;
+        }
+        articles.push((path, lang, title, qid));
+    }
+    Ok(articles)
+}
+
+/// Append the paths of all html files in `dir` to `files`, and of all symlinks to `links`, without following them.
+fn find_files(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+    links: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("reading directory {:?}", dir))? {
+            let entry = entry.with_context(|| format!("reading directory {:?}", dir))?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_symlink() {
+                links.push(path);
+            } else if file_type.is_dir() {
+                dirs.push(path);
+            } else if path.to_string_lossy().ends_with(".html")
+                || path.to_string_lossy().ends_with(".html.gz")
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    links.sort();
+    Ok(())
+}
+
+/// Name of the root index file written by [write_indexes].
+pub const INDEX_FILE: &str = "index.html";
+/// Directory of per-language index pages written by [write_indexes].
+pub const INDEX_DIR: &str = "index";
+
+/// Write html index pages of the articles in `manifest` for browsing the output directory `base`.
+///
+/// - `index.html` lists the number of articles in each language, linking to the first page of the language.
+/// - `index/{lang}-{page}.html` lists the articles of a language alphabetically, `page_size` articles per page.
+///
+/// Article layouts never create these names, so they can be safely ignored when walking the output directory.
+pub fn write_indexes(
+    base: impl AsRef<Path>,
+    manifest: &Manifest,
+    page_size: NonZeroUsize,
+) -> io::Result<()> {
+    let base = base.as_ref();
+
+    let mut langs: BTreeMap<&str, Vec<&ManifestEntry>> = BTreeMap::new();
+    for entry in &manifest.entries {
+        langs.entry(&entry.lang).or_default().push(entry);
+    }
+    for entries in langs.values_mut() {
+        entries.sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.path.cmp(&b.path)));
+        entries.dedup_by(|a, b| a.path == b.path);
+    }
+
+    let mut root = String::new();
+    for (lang, entries) in &langs {
+        writeln!(
+            root,
+            r#"<li><a href="{INDEX_DIR}/{}">{}</a> ({})</li>"#,
+            escape(&index_page_name(lang, 1)),
+            escape(lang),
+            entries.len()
+        )
+        .unwrap();
+    }
+    fs::write(
+        base.join(INDEX_FILE),
+        index_page("Articles", &format!("<ul>\n{root}</ul>\n")),
+    )?;
+
+    let index_dir = base.join(INDEX_DIR);
+    fs::create_dir_all(&index_dir)?;
+    for (lang, entries) in &langs {
+        let pages: Vec<_> = entries.chunks(page_size.get()).collect();
+        for (i, articles) in pages.iter().enumerate() {
+            let page = i + 1;
+
+            let mut body = String::from("<ul>\n");
+            for entry in articles.iter() {
+                writeln!(
+                    body,
+                    r#"<li><a href="../{}">{}</a></li>"#,
+                    escape(&encode_path(&entry.path)),
+                    escape(&entry.title),
+                )
+                .unwrap();
+            }
+            body.push_str("</ul>\n<p>");
+            if page > 1 {
+                write!(
+                    body,
+                    r#"<a href="{}">previous</a> "#,
+                    escape(&index_page_name(lang, page - 1))
+                )
+                .unwrap();
+            }
+            write!(body, "page {page} of {}", pages.len()).unwrap();
+            if page < pages.len() {
+                write!(
+                    body,
+                    r#" <a href="{}">next</a>"#,
+                    escape(&index_page_name(lang, page + 1))
+                )
+                .unwrap();
+            }
+            body.push_str("</p>\n");
+
+            fs::write(
+                index_dir.join(index_page_name(lang, page)),
+                index_page(&format!("{lang} articles"), &body),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn index_page_name(lang: &str, page: usize) -> String {
+    format!("{lang}-{page}.html")
+}
+
+fn index_page(title: &str, body: &str) -> String {
+    let title = escape(title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n"
+    )
+}
+
+/// Percent-encode each component of a relative path for use in a url.
+fn encode_path(path: &Path) -> String {
+    path.iter()
+        .map(|c| urlencoding::encode(&c.to_string_lossy()).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Escape text for use in html text and attribute values.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Counts of the files in a language pack created by [split_by_lang].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PackStats {
+    /// Articles in the pack's manifest.
+    pub articles: usize,
+    /// Articles hard linked from the output directory.
+    pub linked: usize,
+    /// Articles copied because they could not be hard linked, e.g. across filesystems.
+    pub copied: usize,
+    /// Redirect links to the pack's articles.
+    pub redirects: usize,
+}
+
+/// Split the articles of `manifest` in the output directory `base` into one directory per language in `dest_dir`.
+///
+/// Each pack `dest_dir/{lang}` has the same structure as `base`, containing:
+/// - The articles of the language, hard linked from `base` if possible or copied otherwise.
+/// - Links of the language's redirects in `base` (`lang.wikipedia.org/wiki/Redirect` or `lang.wikivoyage.org/wiki/Redirect`) to the articles in the pack.
+///   They are relative like the links of [crate::extract::Options::relative_links], so the pack can be moved or archived.
+///   Redirects recorded in the [LINKS_FILE] of `base` are included too,
+///   and where the pack's filesystem doesn't support symlinks, its redirects are recorded in its own [LINKS_FILE].
+/// - A manifest of the articles in the pack.
+///
+/// Redirects to articles that are not in `manifest` are not included in any pack.
+pub fn split_by_lang(
+    base: impl AsRef<Path>,
+    manifest: &Manifest,
+    dest_dir: impl AsRef<Path>,
+) -> anyhow::Result<BTreeMap<String, PackStats>> {
+    let base = base.as_ref();
+    let dest_dir = dest_dir.as_ref();
+
+    // Keep the latest entry of articles that were written more than once.
+    let mut langs: BTreeMap<&str, BTreeMap<&Path, &ManifestEntry>> = BTreeMap::new();
+    for entry in &manifest.entries {
+        langs
+            .entry(&entry.lang)
+            .or_default()
+            .insert(&entry.path, entry);
+    }
+
+    let mut stats = BTreeMap::new();
+    for (&lang, entries) in &langs {
+        let pack = dest_dir.join(lang);
+        let lang_stats: &mut PackStats = stats.entry(lang.to_owned()).or_default();
+
+        let manifest_path = pack.join(MANIFEST_FILE);
+        fs::create_dir_all(&pack).with_context(|| format!("creating pack {:?}", pack))?;
+        let links_path = pack.join(LINKS_FILE);
+        if links_path.exists() {
+            fs::remove_file(&links_path)
+                .with_context(|| format!("removing old links file {:?}", links_path))?;
+        }
+        let mut pack_manifest = BufWriter::new(
+            File::create(&manifest_path)
+                .with_context(|| format!("creating manifest {:?}", manifest_path))?,
+        );
+
+        for (path, entry) in entries {
+            let src = base.join(path);
+            let dst = pack.join(path);
+            fs::create_dir_all(dst.parent().unwrap())
+                .with_context(|| format!("creating article directory for {:?}", dst))?;
+            if dst.exists() {
+                fs::remove_file(&dst).with_context(|| format!("removing old article {:?}", dst))?;
+            }
+            if fs::hard_link(&src, &dst).is_ok() {
+                lang_stats.linked += 1;
+            } else {
+                fs::copy(&src, &dst)
+                    .with_context(|| format!("copying article {:?} to {:?}", src, dst))?;
+                lang_stats.copied += 1;
+            }
+            lang_stats.articles += 1;
+
+            pack_manifest
+                .write_all(entry.to_line().as_bytes())
+                .with_context(|| format!("writing manifest {:?}", manifest_path))?;
+        }
+        pack_manifest
+            .flush()
+            .with_context(|| format!("writing manifest {:?}", manifest_path))?;
+    }
+
+    // Resolve links within `base` to compare them with the manifest paths.
+    let canonical_base =
+        fs::canonicalize(base).with_context(|| format!("resolving output dir {:?}", base))?;
+    let skip = fs::canonicalize(dest_dir).ok();
+    let mut links = Vec::new();
+    find_links(base, skip.as_deref(), &mut links)?;
+
+    // Redirects by their path relative to `base`, with the later entries of the links file replacing earlier ones.
+    let mut redirects = BTreeMap::new();
+    for link in links {
+        // Broken links have no article to point to.
+        let Ok(target) = fs::canonicalize(&link) else {
+            continue;
+        };
+        let Ok(target) = target.strip_prefix(&canonical_base) else {
+            continue;
+        };
+        let rel = link.strip_prefix(base).expect("link is within base");
+        redirects.insert(rel.to_owned(), target.to_owned());
+    }
+    for entry in read_links(base)? {
+        redirects.insert(entry.link, entry.target);
+    }
+
+    // Articles may be compressed, so compare their directories with the link targets.
+    let article_dirs: BTreeMap<&str, BTreeSet<&Path>> = langs
+        .iter()
+        .map(|(&lang, entries)| (lang, entries.keys().filter_map(|p| p.parent()).collect()))
+        .collect();
+
+    let mut fallbacks = BTreeMap::new();
+    for (rel, target) in &redirects {
+        let Some(lang) = link_lang(rel) else {
+            continue;
+        };
+        let Some(dirs) = article_dirs.get(lang) else {
+            continue;
+        };
+        if !dirs.contains(target.as_path()) {
+            continue;
+        }
+
+        let pack = dest_dir.join(lang);
+        let dst = pack.join(rel);
+        fs::create_dir_all(dst.parent().unwrap())
+            .with_context(|| format!("creating redirect directory for {:?}", dst))?;
+        if dst.is_symlink() {
+            fs::remove_file(&dst).with_context(|| format!("removing old link {:?}", dst))?;
+        }
+        let stored = relative_target(&pack, &dst, &pack.join(target));
+        match unix::fs::symlink(&stored, &dst) {
+            Ok(()) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+                ) =>
+            {
+                let links_path = pack.join(LINKS_FILE);
+                let file = match fallbacks.entry(lang) {
+                    Entry::Occupied(file) => file.into_mut(),
+                    Entry::Vacant(entry) => {
+                        warn!(
+                            "Unable to create symlinks in pack {:?} ({}), writing them to {:?}",
+                            pack, e, links_path
+                        );
+                        let file = File::create(&links_path)
+                            .with_context(|| format!("creating links file {:?}", links_path))?;
+                        entry.insert(BufWriter::new(file))
+                    }
+                };
+                let line = LinkEntry {
+                    link: rel.clone(),
+                    target: target.clone(),
+                }
+                .to_line();
+                file.write_all(line.as_bytes())
+                    .with_context(|| format!("writing to links file {:?}", links_path))?;
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("creating symlink from {:?} to {:?}", dst, stored))
+            }
+        }
+        stats.get_mut(lang).unwrap().redirects += 1;
+    }
+    for (lang, mut file) in fallbacks {
+        file.flush().with_context(|| {
+            format!(
+                "writing to links file {:?}",
+                dest_dir.join(lang).join(LINKS_FILE)
+            )
+        })?;
+    }
+
+    Ok(stats)
+}
+
+/// The lang of the `lang.wikipedia.org` or `lang.wikivoyage.org` directory that the redirect at `rel` is in.
+fn link_lang(rel: &Path) -> Option<&str> {
+    rel.iter().find_map(|c| {
+        let c = c.to_str()?;
+        Project::ALL
+            .iter()
+            .find_map(|project| c.strip_suffix(project.domain())?.strip_suffix('.'))
+    })
+}
+
+/// The target of a link at `link` to `target` relative to the link's directory, e.g. `../../wikidata/Q42`.
+///
+/// Both paths must be within `base`, otherwise `target` is returned as is.
+pub(crate) fn relative_target(base: &Path, link: &Path, target: &Path) -> PathBuf {
+    let (Some(link_dir), Ok(target)) = (
+        link.parent().and_then(|dir| dir.strip_prefix(base).ok()),
+        target.strip_prefix(base),
+    ) else {
+        return target.to_owned();
+    };
+    let mut relative: PathBuf = link_dir.components().map(|_| "..").collect();
+    relative.push(target);
+    relative
+}
+
+/// Append the paths of all symlinks in `dir` to `links`, without following them or descending into `skip`.
+fn find_links(dir: &Path, skip: Option<&Path>, links: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        if skip.is_some() && fs::canonicalize(&dir).ok().as_deref() == skip {
+            continue;
+        }
+        for entry in fs::read_dir(&dir).with_context(|| format!("reading directory {:?}", dir))? {
+            let entry = entry.with_context(|| format!("reading directory {:?}", dir))?;
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                links.push(entry.path());
+            } else if file_type.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    links.sort();
+    Ok(())
+}
@@ -3,7 +3,8 @@ use std::{iter, str::FromStr};
 use anyhow::Context;
 use serde::Deserialize;
 
-use super::{Qid, Title};
+use super::{Project, Qid, Title};
+use crate::html::{self, Direction};
 
 // TODO: consolidate into single struct
 /// Deserialized Wikimedia Enterprise API Article
@@ -13,12 +14,22 @@ use super::{Qid, Title};
 #[derive(Deserialize)]
 pub struct Page {
     // TODO: Check if CoW has a performance impact.
+    /// Title of the page, which is blank or missing in some records.
+    #[serde(default)]
     pub name: String,
+    /// Page id, unique within a wiki.
+    #[serde(default)]
+    pub identifier: Option<u64>,
+    #[serde(default)]
+    pub version: Option<Version>,
     pub date_modified: String,
     pub in_language: Language,
     #[serde(default)]
     pub url: String,
     pub main_entity: Option<Wikidata>,
+    /// The wiki the page is in, e.g. `enwikivoyage`.
+    #[serde(default)]
+    pub is_part_of: Option<Wiki>,
     // TODO: See what impact parsing/unescaping/allocating this has.
     pub article_body: ArticleBody,
     #[serde(default)]
@@ -33,9 +44,31 @@ impl Page {
             .map(|e| Qid::from_str(&e.identifier).unwrap())
     }
 
+    /// Revision id of the page, which increases with each edit.
+    pub fn revision(&self) -> Option<u64> {
+        self.version.as_ref().map(|v| v.identifier)
+    }
+
+    /// The project of the page's wiki, from its `is_part_of` identifier or its url.
+    pub fn project(&self) -> Project {
+        let wikivoyage = match &self.is_part_of {
+            Some(wiki) => wiki.identifier.ends_with("wikivoyage"),
+            None => url::Url::parse(&self.url)
+                .ok()
+                .and_then(|url| Some(url.host_str()?.ends_with(".wikivoyage.org")))
+                .unwrap_or_default(),
+        };
+        if wikivoyage {
+            Project::Wikivoyage
+        } else {
+            Project::Wikipedia
+        }
+    }
+
     /// Title of the article
     pub fn title(&self) -> anyhow::Result<Title> {
         Title::from_title(&self.name, &self.in_language.identifier)
+            .map(|title| title.in_project(self.project()))
             .with_context(|| format!("bad title {:?}", self.name))
     }
 
@@ -44,9 +77,31 @@ impl Page {
         iter::once(self.title()).chain(self.redirects())
     }
 
+    /// Heuristically detect if the article is a stub, using the markers of the page's language.
+    ///
+    /// This parses the article's html, see [html::is_stub] for use with an already-parsed document.
+    pub fn is_stub(&self) -> bool {
+        let document = scraper::Html::parse_document(&self.article_body.html);
+        html::is_stub(&document, &self.in_language.identifier)
+    }
+
+    /// Direction of the article's text from the `dir` attribute of its html, see [html::detect_dir].
+    ///
+    /// Left-to-right if the html has no direction.
+    pub fn text_direction(&self) -> Direction {
+        html::detect_dir(&self.article_body.html).unwrap_or_default()
+    }
+
+    /// Like [Page::text_direction], inferring the direction from the page's language if the html has none.
+    pub fn text_direction_or_lang(&self) -> Direction {
+        html::detect_dir(&self.article_body.html)
+            .unwrap_or_else(|| Direction::from_lang(&self.in_language.identifier))
+    }
+
     pub fn redirects(&self) -> impl Iterator<Item = anyhow::Result<Title>> + '_ {
         self.redirects.iter().map(|r| {
             Title::from_title(&r.name, &self.in_language.identifier)
+                .map(|title| title.in_project(self.project()))
                 .with_context(|| format!("bad redirect {:?}", &r.name))
         })
     }
@@ -58,12 +113,155 @@ pub struct Wikidata {
 }
 
 #[derive(Deserialize)]
+pub struct Wiki {
+    pub identifier: String,
+}
+
+#[derive(Deserialize)]
+pub struct Version {
+    pub identifier: u64,
+}
+
+/// Html of the article, decoded if it is in a [ContentEncoding].
+///
+/// ```
+/// use om_wikiparser::wm::ArticleBody;
+///
+/// let parse = |json| serde_json::from_str::<ArticleBody>(json).unwrap().html;
+/// assert_eq!("<p>Berlin</p>", parse(r#"{"html": "<p>Berlin</p>"}"#));
+/// assert_eq!("<p>Berlin</p>", parse(r#"{"html": "PHA+QmVybGluPC9wPg==", "encoding": "base64"}"#));
+/// // Without an encoding field, it is detected.
+/// assert_eq!("<p>Berlin</p>", parse(r#"{"html": "PHA+QmVybGluPC9wPg=="}"#));
+/// assert_eq!("<p>Berlin</p>", parse(r#"{"html": "\"\\u003cp\\u003eBerlin\\u003c/p\\u003e\""}"#));
+/// // Text that isn't html isn't decoded.
+/// assert_eq!("Berlin", parse(r#"{"html": "Berlin"}"#));
+/// ```
+#[derive(Deserialize)]
+#[serde(try_from = "RawArticleBody")]
 pub struct ArticleBody {
     // TODO: Look into RawValue to lazily parse/allocate this:
     // https://docs.rs/serde_json/latest/serde_json/value/struct.RawValue.html
     pub html: String,
 }
 
+/// How the html of an [ArticleBody] is stored in some dump variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentEncoding {
+    /// Standard or url-safe base64 of the UTF-8 html, with or without padding.
+    Base64,
+    /// A JSON string of the html, e.g. `"<p>..."`, as if it had been serialized twice.
+    ///
+    /// The surrounding quotes are optional.
+    Escaped,
+}
+
+impl ContentEncoding {
+    /// Guess the encoding of `html`, or `None` if it is plain html.
+    ///
+    /// Plain html is recognized by its `<`, which neither encoding has.
+    pub fn detect(html: &str) -> Option<Self> {
+        if html.contains('<') {
+            return None;
+        }
+        let trimmed = html.trim();
+        if trimmed.starts_with('"') || trimmed.to_ascii_lowercase().contains("\\u003c") {
+            return Some(ContentEncoding::Escaped);
+        }
+        let base64 = |c: u8| c.is_ascii_alphanumeric() || b"+/-_=".contains(&c);
+        if !trimmed.is_empty()
+            && trimmed
+                .bytes()
+                .all(|c| base64(c) || c.is_ascii_whitespace())
+        {
+            return Some(ContentEncoding::Base64);
+        }
+        None
+    }
+
+    /// Decode `html`, or `None` if it isn't valid in the encoding.
+    pub fn decode(self, html: &str) -> Option<String> {
+        match self {
+            ContentEncoding::Base64 => String::from_utf8(decode_base64(html)?).ok(),
+            ContentEncoding::Escaped => {
+                let trimmed = html.trim();
+                if trimmed.starts_with('"') {
+                    serde_json::from_str(trimmed).ok()
+                } else {
+                    serde_json::from_str(&format!("\"{trimmed}\"")).ok()
+                }
+            }
+        }
+    }
+}
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(s.len() / 4 * 3);
+    let (mut buffer, mut bits, mut chars) = (0u32, 0, 0);
+    let mut padded = false;
+    for c in s.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'=' => {
+                padded = true;
+                continue;
+            }
+            // Nothing but padding may follow the padding.
+            _ if padded => return None,
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        chars += 1;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    // A single character of a group doesn't have enough bits for a byte.
+    if chars % 4 == 1 {
+        return None;
+    }
+    Some(decoded)
+}
+
+/// [ArticleBody] as it is in the dump.
+#[derive(Deserialize)]
+struct RawArticleBody {
+    html: String,
+    /// Encoding of `html`, detected with [ContentEncoding::detect] if it is missing.
+    #[serde(default)]
+    encoding: Option<ContentEncoding>,
+}
+
+impl TryFrom<RawArticleBody> for ArticleBody {
+    type Error = String;
+
+    fn try_from(raw: RawArticleBody) -> Result<Self, Self::Error> {
+        let html = match raw.encoding {
+            Some(encoding) => encoding
+                .decode(&raw.html)
+                .ok_or_else(|| format!("article html is not valid {encoding:?}"))?,
+            // Only use a detected encoding if it decodes to something that looks like html.
+            None => match ContentEncoding::detect(&raw.html)
+                .and_then(|encoding| Some((encoding, encoding.decode(&raw.html)?)))
+            {
+                Some((encoding, html)) if html.contains('<') => {
+                    debug!("Decoded article html detected as {encoding:?}");
+                    html
+                }
+                _ => raw.html,
+            },
+        };
+        Ok(Self { html })
+    }
+}
+
 #[allow(dead_code)] // TODO: Reevaluate fields.
 #[derive(Deserialize)]
 pub struct Redirect {
@@ -0,0 +1,341 @@
+//! Plan an extraction run before reading any dumps, so it can be reviewed before it starts.
+//!
+//! [build] does the cheap work of a run: it parses the QID, title, and tag files, reads the previous manifest, and checks the size and language of each dump.
+//! The resulting [RunPlan] can be written as JSON, and [execute] then runs exactly what it describes.
+//!
+//! The language of each dump is detected from its first pages and file name, so a dump paired with the wrong language fails the plan instead of silently matching nothing.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    output::{Manifest, ManifestEntry, MANIFEST_FILE},
+    page::Language,
+    Qid, Title,
+};
+use crate::{
+    extract::{self, DumpStats, Matcher, DEFAULT_MAX_PAGE_BYTES},
+    limits::{read_line_capped, CappedLine},
+    LogReporter,
+};
+
+/// Inputs and options of an extraction run to [build] a plan from.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunConfig {
+    /// Uncompressed dumps (newline-delimited JSON) to read, in order.
+    pub dumps: Vec<PathBuf>,
+    /// Files of QIDs to extract, one on each line.
+    pub wikidata_qids: Vec<PathBuf>,
+    /// Files of article urls or `lang:Title` tags to extract, one on each line.
+    pub wikipedia_urls: Vec<PathBuf>,
+    /// TSV files of OSM tags with `wikidata` and `wikipedia` columns.
+    pub osm_tags: Vec<PathBuf>,
+    /// The language each dump is expected to be in, by its path in `dumps`.
+    ///
+    /// The plan fails if a dump is detected to be in another language.
+    pub expected_langs: BTreeMap<PathBuf, String>,
+    /// Allow dumps with pages in more than one language, instead of failing the plan.
+    ///
+    /// Each page is matched against the requested titles of its own language.
+    pub allow_mixed_langs: bool,
+    pub options: extract::Options,
+}
+
+/// Everything an extraction run will do, from [build].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunPlan {
+    pub dumps: Vec<DumpPlan>,
+    /// QIDs to extract in every language.
+    pub qids: BTreeSet<Qid>,
+    /// Titles to extract.
+    pub titles: BTreeSet<Title>,
+    /// Number of requested titles in each language.
+    pub titles_by_lang: BTreeMap<String, usize>,
+    /// Lines of the input files that could not be parsed and were skipped.
+    pub parse_errors: usize,
+    /// Articles in the manifest of the output directory that are still requested, as an estimate of the articles to write.
+    ///
+    /// `None` if the output directory has no manifest.
+    pub estimated_articles: Option<usize>,
+    /// Articles in the manifest of the output directory that are no longer requested.
+    ///
+    /// They are not written again by this run, and can be removed after it.
+    pub pruned: Vec<ManifestEntry>,
+    pub options: extract::Options,
+}
+
+/// A dump to read, and its size when the plan was built.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpPlan {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// Languages of the dump from [detect_langs], or of its file name if it has no pages.
+    ///
+    /// Empty if neither has a language.
+    #[serde(default)]
+    pub langs: BTreeSet<String>,
+    /// Number of requested titles in `langs`.
+    #[serde(default)]
+    pub titles: usize,
+}
+
+/// Number of pages at the start of a dump that [detect_langs] reads.
+pub const DETECT_PAGES: usize = 10;
+
+/// The languages of the first [DETECT_PAGES] pages of the dump at `path`.
+///
+/// Pages longer than `max_page_bytes` are skipped.
+pub fn detect_langs(path: &Path, max_page_bytes: usize) -> anyhow::Result<BTreeSet<String>> {
+    #[derive(Deserialize)]
+    struct LangOnly {
+        in_language: Language,
+    }
+
+    let mut file =
+        BufReader::new(File::open(path).with_context(|| format!("opening dump {:?}", path))?);
+    let mut langs = BTreeSet::new();
+    let mut buffer = Vec::new();
+    for _ in 0..DETECT_PAGES {
+        buffer.clear();
+        match read_line_capped(&mut file, &mut buffer, max_page_bytes)
+            .with_context(|| format!("reading dump {:?}", path))?
+        {
+            CappedLine::Eof => break,
+            CappedLine::TooLong(_) => continue,
+            CappedLine::Line(_) => {}
+        }
+        if buffer.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let page: LangOnly = serde_json::from_slice(&buffer)
+            .with_context(|| format!("reading language of page in dump {:?}", path))?;
+        langs.insert(page.in_language.identifier);
+    }
+    Ok(langs)
+}
+
+/// The language of dump file names like `dewiki-NS0-20230701-ENTERPRISE-HTML.json`.
+///
+/// ```
+/// use om_wikiparser::wm::plan::file_name_lang;
+///
+/// assert_eq!(Some("de".into()), file_name_lang("dumps/dewiki-NS0-20230701-ENTERPRISE-HTML.json".as_ref()));
+/// assert_eq!(Some("zh-yue".into()), file_name_lang("zh_yuewiki-NS0-20230701-ENTERPRISE-HTML.json".as_ref()));
+/// assert_eq!(None, file_name_lang("enwikivoyage-NS0-20230701-ENTERPRISE-HTML.json".as_ref()));
+/// assert_eq!(None, file_name_lang("de.ndjson".as_ref()));
+/// ```
+pub fn file_name_lang(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let (db, _) = name.split_once("wiki-")?;
+    if db.is_empty() || !db.bytes().all(|b| b.is_ascii_lowercase() || b == b'_') {
+        return None;
+    }
+    Some(db.replace('_', "-"))
+}
+
+/// Detect the languages of the dump at `path` and check them against its file name and the `expected` language.
+fn dump_langs(
+    path: &Path,
+    expected: Option<&String>,
+    allow_mixed: bool,
+    max_page_bytes: usize,
+) -> anyhow::Result<BTreeSet<String>> {
+    let mut langs = detect_langs(path, max_page_bytes)?;
+    let name_lang = file_name_lang(path);
+    if let Some(name_lang) = &name_lang {
+        if !langs.is_empty() && !langs.contains(name_lang) {
+            bail!(
+                "dump {:?} is named like a {:?} dump, but its first pages are in {:?}",
+                path,
+                name_lang,
+                langs
+            );
+        }
+    }
+    if langs.is_empty() {
+        langs.extend(name_lang);
+    }
+
+    if langs.len() > 1 && !allow_mixed {
+        bail!(
+            "dump {:?} has pages in more than one language ({:?}), allow mixed languages to extract from it",
+            path,
+            langs
+        );
+    }
+
+    if let Some(expected) = expected {
+        if !langs.is_empty() && (langs.len() != 1 || !langs.contains(expected)) {
+            bail!(
+                "dump {:?} was expected to be in {:?}, but is in {:?}",
+                path,
+                expected,
+                langs
+            );
+        }
+    }
+    Ok(langs)
+}
+
+/// Build the plan of the run described by `config`, only reading the first pages of each dump to detect its language.
+pub fn build(config: &RunConfig) -> anyhow::Result<RunPlan> {
+    for path in config.expected_langs.keys() {
+        if !config.dumps.contains(path) {
+            bail!("expected language of {:?}, which is not a dump", path);
+        }
+    }
+
+    let max_page_bytes = config
+        .options
+        .max_page_bytes
+        .unwrap_or(DEFAULT_MAX_PAGE_BYTES);
+    let mut dumps = config
+        .dumps
+        .iter()
+        .map(|path| {
+            let metadata = fs::metadata(path)
+                .with_context(|| format!("reading metadata of dump {:?}", path))?;
+            if !metadata.is_file() {
+                bail!("dump {:?} is not a file", path);
+            }
+            let langs = dump_langs(
+                path,
+                config.expected_langs.get(path),
+                config.allow_mixed_langs,
+                max_page_bytes,
+            )?;
+            Ok(DumpPlan {
+                path: path.to_owned(),
+                bytes: metadata.len(),
+                langs,
+                titles: 0,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let (matcher, errors) = Matcher::from_files(
+        &config.wikidata_qids,
+        &config.wikipedia_urls,
+        &config.osm_tags,
+    )?;
+    let parse_errors = errors.len();
+    LogReporter::default().extend(errors.into_iter().map(|e| e.error));
+
+    let qids: BTreeSet<Qid> = matcher.qids.into_iter().collect();
+    let titles: BTreeSet<Title> = matcher.titles.into_iter().collect();
+
+    let mut titles_by_lang = BTreeMap::new();
+    for title in &titles {
+        *titles_by_lang.entry(title.lang().to_owned()).or_default() += 1;
+    }
+    for dump in &mut dumps {
+        dump.titles = dump
+            .langs
+            .iter()
+            .filter_map(|lang| titles_by_lang.get(lang))
+            .sum();
+        if dump.titles == 0 && qids.is_empty() && !dump.langs.is_empty() {
+            warn!(
+                "No titles requested in the languages {:?} of dump {:?}",
+                dump.langs, dump.path
+            );
+        }
+    }
+
+    let mut estimated_articles = None;
+    let mut pruned = Vec::new();
+    if let Some(dir) = &config.options.output_dir {
+        if dir.join(MANIFEST_FILE).is_file() {
+            let manifest = Manifest::read(dir)?;
+            let (kept, removed): (Vec<_>, Vec<_>) = manifest
+                .entries
+                .into_iter()
+                .partition(|entry| is_requested(entry, &qids, &titles));
+            estimated_articles = Some(kept.len());
+            pruned = removed;
+        }
+    }
+
+    Ok(RunPlan {
+        dumps,
+        qids,
+        titles,
+        titles_by_lang,
+        parse_errors,
+        estimated_articles,
+        pruned,
+        options: config.options.clone(),
+    })
+}
+
+fn is_requested(entry: &ManifestEntry, qids: &BTreeSet<Qid>, titles: &BTreeSet<Title>) -> bool {
+    if entry.qid.is_some_and(|qid| qids.contains(&qid)) {
+        return true;
+    }
+    Title::from_title(&entry.title, &entry.lang)
+        .is_ok_and(|title| titles.contains(&title.in_project(entry.project)))
+}
+
+/// Run the extraction described by `plan`, with [extract::run] over all of its dumps.
+///
+/// A dump without a trailing newline is ended with one, so its last page isn't joined to the first page of the next dump.
+/// Fails before reading anything if a dump changed size since the plan was built.
+pub fn execute(
+    plan: &RunPlan,
+    passthrough: impl Write,
+    cancel: &AtomicBool,
+) -> anyhow::Result<DumpStats> {
+    let mut input: Box<dyn Read> = Box::new(std::io::empty());
+    for dump in &plan.dumps {
+        let mut file =
+            File::open(&dump.path).with_context(|| format!("opening dump {:?}", dump.path))?;
+        let bytes = file.metadata()?.len();
+        if bytes != dump.bytes {
+            bail!(
+                "dump {:?} is {} bytes, but was {} bytes when the plan was built",
+                dump.path,
+                bytes,
+                dump.bytes
+            );
+        }
+        let newline = ends_with_newline(&mut file)
+            .with_context(|| format!("reading dump {:?}", dump.path))?;
+        input = Box::new(input.chain(file));
+        if !newline {
+            input = Box::new(input.chain(&b"\n"[..]));
+        }
+    }
+
+    let matcher = Matcher {
+        qids: plan.qids.iter().copied().collect(),
+        titles: plan.titles.iter().cloned().collect(),
+        raw_titles: None,
+    };
+    extract::run(
+        BufReader::new(input),
+        passthrough,
+        &matcher,
+        &plan.options,
+        cancel,
+    )
+}
+
+/// Check if `file` is empty or ends with a newline, leaving it at its start.
+fn ends_with_newline(file: &mut File) -> io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+    file.seek(SeekFrom::End(-1))?;
+    let mut last = [0];
+    file.read_exact(&mut last)?;
+    file.rewind()?;
+    Ok(last == *b"\n")
+}
@@ -1,9 +1,17 @@
-use std::{error::Error, fmt::Display, num::ParseIntError, path::PathBuf, str::FromStr};
+use std::{borrow::Cow, fmt::Display, num::ParseIntError, path::PathBuf, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use url::Url;
+
+use super::clean_value;
 
 /// Wikidata QID/Q Number
 ///
 /// See https://www.wikidata.org/wiki/Wikidata:Glossary#QID
 ///
+/// Values are cleaned with [clean_value] before they are parsed.
+/// To parse a url of the item's page, use [Qid::from_url].
+///
 /// ```
 /// use std::str::FromStr;
 /// use om_wikiparser::wm::Qid;
@@ -17,17 +25,18 @@ use std::{error::Error, fmt::Display, num::ParseIntError, path::PathBuf, str::Fr
 /// assert!(Qid::from_str("Article_Title").is_err());
 /// assert!(Qid::from_str("Q").is_err());
 /// assert!(Qid::from_str("").is_err());
+/// assert_eq!(with_q, Qid::from_str("\u{A0}Q12345\u{200B}").unwrap());
 /// ```
-#[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct Qid(u32);
 
 impl FromStr for Qid {
     type Err = ParseQidError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.trim();
-        let s = s.strip_prefix(['Q', 'q']).unwrap_or(s);
-        u32::from_str(s).map(Qid).map_err(ParseQidError)
+        let s = clean_value(s);
+        let s = s.strip_prefix(['Q', 'q']).unwrap_or(&s);
+        Ok(Qid(u32::from_str(s)?))
     }
 }
 
@@ -37,7 +46,84 @@ impl Display for Qid {
     }
 }
 
+/// Serialized as a string, e.g. `"Q12345"`.
+impl Serialize for Qid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Qid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        Qid::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
 impl Qid {
+    /// Parse the QID from the url of a Wikidata item, like `https://www.wikidata.org/wiki/Q42`.
+    ///
+    /// Both the `/wiki/` page and the `/entity/` concept urls are accepted, on the `www.` or `m.` subdomains or none.
+    /// Fragments, queries, and a trailing slash are ignored.
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    /// use om_wikiparser::wm::{ParseQidError, Qid};
+    ///
+    /// let q42 = Qid::from_str("Q42").unwrap();
+    /// assert_eq!(q42, Qid::from_url("https://www.wikidata.org/wiki/Q42").unwrap());
+    /// assert_eq!(q42, Qid::from_url("http://www.wikidata.org/entity/Q42").unwrap());
+    /// assert_eq!(q42, Qid::from_url("https://m.wikidata.org/wiki/Q42#sitelinks-wikipedia").unwrap());
+    /// assert_eq!(q42, Qid::from_url("https://wikidata.org/wiki/Q42/").unwrap());
+    ///
+    /// assert_eq!(
+    ///     Err(ParseQidError::NotWikidata),
+    ///     Qid::from_url("https://en.wikipedia.org/wiki/Q42")
+    /// );
+    /// assert_eq!(
+    ///     Err(ParseQidError::NotItem("Property:P31".into())),
+    ///     Qid::from_url("https://www.wikidata.org/wiki/Property:P31")
+    /// );
+    /// assert_eq!(Err(ParseQidError::BadPath), Qid::from_url("https://www.wikidata.org/"));
+    /// ```
+    pub fn from_url(url: &str) -> Result<Self, ParseQidError> {
+        let url = Url::parse(&clean_value(url))?;
+        if !matches!(
+            url.host_str(),
+            Some("wikidata.org" | "www.wikidata.org" | "m.wikidata.org")
+        ) {
+            return Err(ParseQidError::NotWikidata);
+        }
+        let path = url.path().trim_end_matches('/');
+        let (base, id) = path
+            .strip_prefix('/')
+            .and_then(|path| path.split_once('/'))
+            .ok_or(ParseQidError::BadPath)?;
+        if !matches!(base, "wiki" | "entity") || id.contains('/') {
+            return Err(ParseQidError::BadPath);
+        }
+        if !id.starts_with(['Q', 'q']) || id.contains(':') {
+            return Err(ParseQidError::NotItem(id.to_owned()));
+        }
+        Self::from_str(id)
+    }
+
+    /// Parse `s` with [Qid::from_url] if it looks like a url, otherwise with [Qid::from_str].
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Qid;
+    ///
+    /// assert_eq!(Qid::parse_value("Q42"), Qid::parse_value(" https://www.wikidata.org/wiki/Q42"));
+    /// assert!(Qid::parse_value("https://www.wikidata.org/wiki/Property:P31").is_err());
+    /// ```
+    pub fn parse_value(s: &str) -> Result<Self, ParseQidError> {
+        if clean_value(s).starts_with("http") {
+            Self::from_url(s)
+        } else {
+            Self::from_str(s)
+        }
+    }
+
     pub fn get_dir(&self, base: PathBuf) -> PathBuf {
         let mut path = base;
         path.push("wikidata");
@@ -48,17 +134,19 @@ impl Qid {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct ParseQidError(ParseIntError);
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ParseQidError {
+    #[error(transparent)]
+    Number(#[from] ParseIntError),
 
-impl Display for ParseQidError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
-    }
-}
-
-impl Error for ParseQidError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        self.0.source()
-    }
+    // url-specific
+    #[error("cannot parse url")]
+    Url(#[from] url::ParseError),
+    #[error("url host is not wikidata.org")]
+    NotWikidata,
+    #[error("url path is not /wiki/ or /entity/ followed by an id")]
+    BadPath,
+    /// The url is for an entity that is not an item, like the property `Property:P31`.
+    #[error("url is for {0:?}, not a wikidata item")]
+    NotItem(String),
 }
@@ -0,0 +1,250 @@
+//! Set operations on QID and title list files.
+//!
+//! ```
+//! use om_wikiparser::wm::{sets::diff_readers, Title};
+//!
+//! let curated = "https://en.wikipedia.org/wiki/Berlin\nhttps://de.wikipedia.org/wiki/Köln\n";
+//! let osm = "en:Berlin\nen:Spatial database\n";
+//! let diff = diff_readers::<Title>(curated.as_bytes(), osm.as_bytes()).unwrap();
+//! assert_eq!(diff.stats().to_string(), "only a: 1\nonly b: 1\nboth: 1\n");
+//! ```
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::File,
+    hash::Hash,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use super::{Qid, Title};
+use crate::{
+    extend, log_line_errors, parse_wikidata_file_with, parse_wikipedia_file_with, ListFileOptions,
+    ParseLineError,
+};
+
+/// Items of a list file that can be read with the crate's parsers.
+pub trait ListItem: Ord + Hash + Clone + Display + Sized {
+    fn parse_list(
+        r: impl BufRead,
+        collection: &mut impl Extend<Self>,
+        line_errors: &mut impl Extend<ParseLineError>,
+    ) -> io::Result<()>;
+}
+
+/// Read with [parse_wikidata_file_with].
+impl ListItem for Qid {
+    fn parse_list(
+        r: impl BufRead,
+        collection: &mut impl Extend<Self>,
+        line_errors: &mut impl Extend<ParseLineError>,
+    ) -> io::Result<()> {
+        parse_wikidata_file_with(r, collection, line_errors, &ListFileOptions::default())
+    }
+}
+
+/// Read with [parse_wikipedia_file_with], so urls and `lang:Title` tags are compared by their normalized title.
+impl ListItem for Title {
+    fn parse_list(
+        r: impl BufRead,
+        collection: &mut impl Extend<Self>,
+        line_errors: &mut impl Extend<ParseLineError>,
+    ) -> io::Result<()> {
+        parse_wikipedia_file_with(r, collection, line_errors, &ListFileOptions::default())
+    }
+}
+
+/// Items in one or both of two lists, from [diff_files].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition<T> {
+    pub only_a: BTreeSet<T>,
+    pub only_b: BTreeSet<T>,
+    pub both: BTreeSet<T>,
+}
+
+impl<T: Ord> Partition<T> {
+    /// Partition the items of `a` and `b`, reading `b` without collecting it first.
+    pub fn new(a: impl IntoIterator<Item = T>, b: impl IntoIterator<Item = T>) -> Self {
+        let mut partition = Self {
+            only_a: a.into_iter().collect(),
+            only_b: BTreeSet::new(),
+            both: BTreeSet::new(),
+        };
+        for item in b {
+            partition.insert_b(item);
+        }
+        partition
+    }
+
+    fn insert_b(&mut self, item: T) {
+        match self.only_a.take(&item) {
+            Some(item) => {
+                self.both.insert(item);
+            }
+            None if self.both.contains(&item) => {}
+            None => {
+                self.only_b.insert(item);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> PartitionStats {
+        PartitionStats {
+            only_a: self.only_a.len(),
+            only_b: self.only_b.len(),
+            both: self.both.len(),
+        }
+    }
+}
+
+impl<T: Display> Partition<T> {
+    /// Write `only_a.txt`, `only_b.txt`, and `both.txt` to `dir` with [write_list].
+    pub fn write_to_dir(&self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        write_list(dir.join("only_a.txt"), &self.only_a)?;
+        write_list(dir.join("only_b.txt"), &self.only_b)?;
+        write_list(dir.join("both.txt"), &self.both)?;
+        Ok(())
+    }
+}
+
+/// Sizes of a [Partition].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct PartitionStats {
+    pub only_a: usize,
+    pub only_b: usize,
+    pub both: usize,
+}
+
+impl Display for PartitionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "only a: {}", self.only_a)?;
+        writeln!(f, "only b: {}", self.only_b)?;
+        writeln!(f, "both: {}", self.both)
+    }
+}
+
+/// Partition the QIDs or titles of the list files at `a` and `b`.
+///
+/// Lines that cannot be parsed are logged and ignored.
+pub fn diff_files<T: ListItem>(
+    a: impl AsRef<Path>,
+    b: impl AsRef<Path>,
+) -> anyhow::Result<Partition<T>> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("opening list file {:?}", path))
+    };
+    let (a, b) = (a.as_ref(), b.as_ref());
+    diff_readers(open(a)?, open(b)?).with_context(|| format!("comparing {:?} and {:?}", a, b))
+}
+
+/// Like [diff_files], reading the lists from `a` and `b`.
+pub fn diff_readers<T: ListItem>(a: impl BufRead, b: impl BufRead) -> anyhow::Result<Partition<T>> {
+    let mut a_items = Vec::new();
+    T::parse_list(a, &mut a_items, &mut log_line_errors())?;
+
+    let mut partition = Partition::new(a_items, []);
+    T::parse_list(
+        b,
+        &mut extend::from_fn(|item| partition.insert_b(item)),
+        &mut log_line_errors(),
+    )?;
+
+    Ok(partition)
+}
+
+/// Write `items` to `path` in the format read by [ListItem::parse_list], one on each line.
+pub fn write_list<T: Display>(
+    path: impl AsRef<Path>,
+    items: impl IntoIterator<Item = T>,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let mut file = BufWriter::new(
+        File::create(path).with_context(|| format!("creating list file {:?}", path))?,
+    );
+    for item in items {
+        writeln!(file, "{}", item).with_context(|| format!("writing list file {:?}", path))?;
+    }
+    file.flush()
+        .with_context(|| format!("writing list file {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn qids(qids: &[&str]) -> BTreeSet<Qid> {
+        qids.iter().map(|q| Qid::from_str(q).unwrap()).collect()
+    }
+
+    fn titles(titles: &[&str]) -> BTreeSet<Title> {
+        titles
+            .iter()
+            .map(|t| Title::from_osm_tag(t).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn qid_partition() {
+        let a = "Q1\nQ2\nQ3\nQ3\nnot a qid\n";
+        let b = "Q3\nQ4\nQ2\nQ4\n";
+        let diff = diff_readers::<Qid>(a.as_bytes(), b.as_bytes()).unwrap();
+        assert_eq!(
+            Partition {
+                only_a: qids(&["Q1"]),
+                only_b: qids(&["Q4"]),
+                both: qids(&["Q2", "Q3"]),
+            },
+            diff
+        );
+        assert_eq!(
+            PartitionStats {
+                only_a: 1,
+                only_b: 1,
+                both: 2
+            },
+            diff.stats()
+        );
+    }
+
+    #[test]
+    fn title_partition_normalizes() {
+        let a = "https://en.wikipedia.org/wiki/Spatial_database\nen:Berlin\nde:Berlin\n";
+        let b = "en:Spatial database\nhttps://en.m.wikipedia.org/wiki/Berlin#History\nfr:Berlin\n";
+        let diff = diff_readers::<Title>(a.as_bytes(), b.as_bytes()).unwrap();
+        assert_eq!(
+            Partition {
+                only_a: titles(&["de:Berlin"]),
+                only_b: titles(&["fr:Berlin"]),
+                both: titles(&["en:Berlin", "en:Spatial database"]),
+            },
+            diff
+        );
+    }
+
+    #[test]
+    fn written_lists_read_back() {
+        let dir = std::env::temp_dir().join("om-wikiparser-sets-written_lists_read_back");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let a = "en:Spatial database\nde:Köln\n";
+        let b = "https://de.wikipedia.org/wiki/K%C3%B6ln\n";
+        let diff = diff_readers::<Title>(a.as_bytes(), b.as_bytes()).unwrap();
+        diff.write_to_dir(&dir).unwrap();
+
+        assert_eq!(
+            "en:Spatial_database\n",
+            std::fs::read_to_string(dir.join("only_a.txt")).unwrap()
+        );
+        assert_eq!("", std::fs::read_to_string(dir.join("only_b.txt")).unwrap());
+        let both = diff_files::<Title>(dir.join("both.txt"), dir.join("both.txt")).unwrap();
+        assert_eq!(diff.both, both.both);
+    }
+}
@@ -0,0 +1,404 @@
+//! Snapshot testing of html simplification over a corpus of articles.
+//!
+//! To check an upgrade of the crate against your own articles:
+//! 1. Run [simplify_corpus] over a directory of original article html with the current version and keep the output.
+//! 2. Upgrade, run it again into a new directory, and use [compare_dirs] to list the differences.
+//!
+//! A small corpus of articles in several languages is included in [BUILTIN_CORPUS].
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use scraper::Html;
+
+use crate::html;
+
+/// Fixture articles included with the crate, as `(file name, original html)` pairs.
+pub const BUILTIN_CORPUS: &[(&str, &str)] = &[
+    (
+        "de-Kölner_Dom.html",
+        include_str!("../../tests/data/corpus/input/de-Kölner_Dom.html"),
+    ),
+    (
+        "en-Thames_Barrier.html",
+        include_str!("../../tests/data/corpus/input/en-Thames_Barrier.html"),
+    ),
+    (
+        "fr-Pont_du_Gard.html",
+        include_str!("../../tests/data/corpus/input/fr-Pont_du_Gard.html"),
+    ),
+];
+
+/// Write the [BUILTIN_CORPUS] to `dir` for use with [simplify_corpus].
+pub fn write_builtin_corpus(dir: impl AsRef<Path>) -> anyhow::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).with_context(|| format!("creating corpus dir {:?}", dir))?;
+    for (name, html) in BUILTIN_CORPUS {
+        let path = dir.join(name);
+        fs::write(&path, html).with_context(|| format!("writing corpus file {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Options for [simplify_corpus].
+#[derive(Debug, Clone)]
+pub struct SimplifyConfig {
+    /// Language to process every article with.
+    ///
+    /// By default, it is detected from each article, falling back to `en`.
+    pub lang: Option<String>,
+    /// Write the output with [html::pretty_print] so differences are easier to read.
+    ///
+    /// Defaults to `true`.
+    pub pretty: bool,
+}
+
+impl Default for SimplifyConfig {
+    fn default() -> Self {
+        Self {
+            lang: None,
+            pretty: true,
+        }
+    }
+}
+
+/// Simplify each `.html` file in `dir_in`, writing the output to a file of the same name in `dir_out`.
+///
+/// Returns the names of the files written, in sorted order.
+/// Fails on the first article that cannot be simplified.
+pub fn simplify_corpus(
+    dir_in: impl AsRef<Path>,
+    dir_out: impl AsRef<Path>,
+    config: &SimplifyConfig,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let (dir_in, dir_out) = (dir_in.as_ref(), dir_out.as_ref());
+    fs::create_dir_all(dir_out).with_context(|| format!("creating output dir {:?}", dir_out))?;
+
+    let mut names = html_files(dir_in)?;
+    names.sort();
+
+    for name in &names {
+        let path = dir_in.join(name);
+        let input =
+            fs::read_to_string(&path).with_context(|| format!("reading article {:?}", path))?;
+        let document = Html::parse_document(&input);
+        let lang = config
+            .lang
+            .clone()
+            .or_else(|| html::detect_lang(&document))
+            .unwrap_or_else(|| "en".to_string());
+
+        let document = html::process(document, &lang)
+            .with_context(|| format!("simplifying article {:?}", path))?;
+        let output = if config.pretty {
+            html::pretty_print(&document)
+        } else {
+            document.html()
+        };
+
+        let path = dir_out.join(name);
+        fs::write(&path, output).with_context(|| format!("writing article {:?}", path))?;
+    }
+
+    Ok(names)
+}
+
+/// Names of the `.html` files in `dir`.
+fn html_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("reading dir {:?}", dir))? {
+        let entry = entry?;
+        let path = PathBuf::from(entry.file_name());
+        if path.extension().is_some_and(|e| e == "html") && entry.file_type()?.is_file() {
+            names.push(path);
+        }
+    }
+    Ok(names)
+}
+
+/// Differences between two directories of simplified articles, from [compare_dirs].
+///
+/// The [Display] implementation prints a summary followed by a unified diff of each changed file.
+#[derive(Debug, Default)]
+pub struct CorpusDiff {
+    /// Files with identical contents.
+    pub unchanged: Vec<PathBuf>,
+    /// Files with different contents, and a unified diff from the expected to the actual contents.
+    pub changed: Vec<(PathBuf, String)>,
+    /// Files only in the expected directory.
+    pub removed: Vec<PathBuf>,
+    /// Files only in the actual directory.
+    pub added: Vec<PathBuf>,
+}
+
+impl CorpusDiff {
+    /// Both directories contain the same files with the same contents.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+impl Display for CorpusDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} unchanged, {} changed, {} removed, {} added",
+            self.unchanged.len(),
+            self.changed.len(),
+            self.removed.len(),
+            self.added.len()
+        )?;
+        for path in &self.removed {
+            writeln!(f, "removed: {}", path.display())?;
+        }
+        for path in &self.added {
+            writeln!(f, "added: {}", path.display())?;
+        }
+        for (path, diff) in &self.changed {
+            writeln!(f, "--- expected/{}", path.display())?;
+            writeln!(f, "+++ actual/{}", path.display())?;
+            write!(f, "{diff}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the `.html` files in the `expected` and `actual` directories, e.g. outputs of [simplify_corpus].
+pub fn compare_dirs(
+    expected: impl AsRef<Path>,
+    actual: impl AsRef<Path>,
+) -> anyhow::Result<CorpusDiff> {
+    let (expected, actual) = (expected.as_ref(), actual.as_ref());
+    let mut expected_names = html_files(expected)?;
+    expected_names.sort();
+    let mut actual_names = html_files(actual)?;
+    actual_names.sort();
+
+    let mut diff = CorpusDiff::default();
+    for name in expected_names {
+        if !actual_names.contains(&name) {
+            diff.removed.push(name);
+            continue;
+        }
+        let read = |dir: &Path| {
+            let path = dir.join(&name);
+            fs::read_to_string(&path).with_context(|| format!("reading article {:?}", path))
+        };
+        let (old, new) = (read(expected)?, read(actual)?);
+        if old == new {
+            diff.unchanged.push(name);
+        } else {
+            diff.changed.push((name, unified_diff(&old, &new, 3)));
+        }
+    }
+    diff.added = actual_names
+        .into_iter()
+        .filter(|name| !diff.unchanged.contains(name))
+        .filter(|name| !diff.changed.iter().any(|(changed, _)| changed == name))
+        .collect();
+
+    Ok(diff)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Same,
+    Delete,
+    Insert,
+}
+
+/// Line-based diff of `old` and `new` in the unified format, without the file headers.
+///
+/// Uses the longest common subsequence of the lines after removing the common prefix and suffix,
+/// which is quadratic in the size of the changed region.
+fn unified_diff(old: &str, new: &str, context: usize) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = vec![Op::Same; prefix];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push(Op::Same);
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(Op::Delete);
+            i += 1;
+        } else {
+            ops.push(Op::Insert);
+            j += 1;
+        }
+    }
+    ops.extend(std::iter::repeat_n(Op::Same, suffix));
+
+    // Group changes closer than twice the context into hunks.
+    let changes: Vec<_> = (0..ops.len()).filter(|&k| ops[k] != Op::Same).collect();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for k in changes {
+        let start = k.saturating_sub(context);
+        let end = (k + context + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    // Positions in old and new at the start of the op at `pos`.
+    let (mut pos, mut old_line, mut new_line) = (0, 0, 0);
+    for (start, end) in hunks {
+        for op in &ops[pos..start] {
+            match op {
+                Op::Same => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                Op::Delete => old_line += 1,
+                Op::Insert => new_line += 1,
+            }
+        }
+
+        let hunk = &ops[start..end];
+        let old_len = hunk.iter().filter(|&&op| op != Op::Insert).count();
+        let new_len = hunk.iter().filter(|&&op| op != Op::Delete).count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_line + (old_len != 0) as usize,
+            old_len,
+            new_line + (new_len != 0) as usize,
+            new_len
+        ));
+        for op in hunk {
+            match op {
+                Op::Same => {
+                    out.push_str(&format!(" {}\n", old[old_line]));
+                    old_line += 1;
+                    new_line += 1;
+                }
+                Op::Delete => {
+                    out.push_str(&format!("-{}\n", old[old_line]));
+                    old_line += 1;
+                }
+                Op::Insert => {
+                    out.push_str(&format!("+{}\n", new[new_line]));
+                    new_line += 1;
+                }
+            }
+        }
+        pos = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join("om-wikiparser-testing")
+            .join(name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        dir
+    }
+
+    const SNAPSHOTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/corpus/output");
+
+    /// To update the snapshots after an intentional change, run this with `UPDATE_EXPECT=1`.
+    #[test]
+    fn builtin_corpus_matches_snapshots() {
+        let dir = test_dir("builtin_corpus_matches_snapshots");
+        let input = dir.join("input");
+        write_builtin_corpus(&input).unwrap();
+
+        if std::env::var_os("UPDATE_EXPECT").is_some() {
+            simplify_corpus(&input, SNAPSHOTS, &SimplifyConfig::default()).unwrap();
+        }
+
+        let output = dir.join("output");
+        let names = simplify_corpus(&input, &output, &SimplifyConfig::default()).unwrap();
+        assert_eq!(BUILTIN_CORPUS.len(), names.len());
+
+        let diff = compare_dirs(SNAPSHOTS, &output).unwrap();
+        assert!(diff.is_empty(), "{diff}");
+        assert_eq!(BUILTIN_CORPUS.len(), diff.unchanged.len());
+    }
+
+    #[test]
+    fn compare_dirs_reports_changes() {
+        let dir = test_dir("compare_dirs_reports_changes");
+        let (expected, actual) = (dir.join("expected"), dir.join("actual"));
+        fs::create_dir_all(&expected).unwrap();
+        fs::create_dir_all(&actual).unwrap();
+
+        fs::write(expected.join("same.html"), "<p>a</p>\n").unwrap();
+        fs::write(actual.join("same.html"), "<p>a</p>\n").unwrap();
+        fs::write(expected.join("changed.html"), "1\n2\n3\n4\n5\n6\n7\n8\n9\n").unwrap();
+        fs::write(
+            actual.join("changed.html"),
+            "1\n2\n3\n4\nfive\n6\n7\n8\n9\n",
+        )
+        .unwrap();
+        fs::write(expected.join("removed.html"), "").unwrap();
+        fs::write(actual.join("added.html"), "").unwrap();
+        fs::write(actual.join("ignored.txt"), "").unwrap();
+
+        let diff = compare_dirs(&expected, &actual).unwrap();
+        assert_eq!(vec![PathBuf::from("same.html")], diff.unchanged);
+        assert_eq!(vec![PathBuf::from("removed.html")], diff.removed);
+        assert_eq!(vec![PathBuf::from("added.html")], diff.added);
+        assert_eq!(
+            vec![(
+                PathBuf::from("changed.html"),
+                "@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n".to_string()
+            )],
+            diff.changed
+        );
+    }
+
+    #[test]
+    fn unified_diff_hunks() {
+        assert_eq!("", unified_diff("a\nb\n", "a\nb\n", 3));
+        assert_eq!(
+            "@@ -1,2 +1,3 @@\n a\n+x\n b\n",
+            unified_diff("a\nb\n", "a\nx\nb\n", 3)
+        );
+        assert_eq!("@@ -1,1 +0,0 @@\n-a\n", unified_diff("a\n", "", 3));
+        assert_eq!(
+            "@@ -1,2 +1,2 @@\n-a\n+x\n b\n@@ -8,2 +8,2 @@\n h\n-i\n+y\n",
+            unified_diff(
+                "a\nb\nc\nd\ne\nf\ng\nh\ni\n",
+                "x\nb\nc\nd\ne\nf\ng\nh\ny\n",
+                1
+            )
+        );
+    }
+}
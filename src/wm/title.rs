@@ -1,7 +1,17 @@
-use std::{fmt::Display, path::PathBuf, string::FromUtf8Error};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    path::{Component, Path, PathBuf},
+    string::FromUtf8Error,
+};
 
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use url::Url;
 
+use super::{clean_value, Layout};
+
 /// Normalized wikipedia article title that can compare:
 /// - titles `Spatial Database`
 /// - urls `https://en.wikipedia.org/wiki/Spatial_database#Geodatabase`
@@ -28,42 +38,201 @@ use url::Url;
 ///     Title::from_url("https://de.wikipedia.org/wiki/Breil").unwrap()
 /// );
 /// ```
-#[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+///
+/// Titles are compared by their project, normalized language, and name only, see [Title::original_name] and [Title::oldid].
+#[derive(Debug, Clone)]
 pub struct Title {
+    project: Project,
     lang: String,
     name: String,
+    /// Name before normalization.
+    original: String,
+    /// Revision of a permalink url, see [Title::oldid].
+    oldid: Option<u64>,
+}
+
+impl PartialEq for Title {
+    fn eq(&self, other: &Self) -> bool {
+        self.project == other.project && self.lang == other.lang && self.name == other.name
+    }
+}
+
+impl Eq for Title {}
+
+impl Hash for Title {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.project.hash(state);
+        self.lang.hash(state);
+        self.name.hash(state);
+    }
 }
 
+impl PartialOrd for Title {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Title {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.project, &self.lang, &self.name).cmp(&(other.project, &other.lang, &other.name))
+    }
+}
+
+/// Formatted as an osm tag, with the interwiki prefix of projects other than Wikipedia, e.g. `voy:en:Berlin`.
 impl Display for Title {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(prefix) = self.project.prefix() {
+            write!(f, "{prefix}:")?;
+        }
         write!(f, "{}:{}", self.lang, self.name)
     }
 }
 
+/// The Wikimedia project an article is in.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Project {
+    #[default]
+    Wikipedia,
+    /// Travel guides at `lang.wikivoyage.org`.
+    Wikivoyage,
+}
+
+impl Project {
+    pub const ALL: &'static [Project] = &[Project::Wikipedia, Project::Wikivoyage];
+
+    /// Domain of the project's wikis, without the language subdomain.
+    pub fn domain(&self) -> &'static str {
+        match self {
+            Project::Wikipedia => "wikipedia.org",
+            Project::Wikivoyage => "wikivoyage.org",
+        }
+    }
+
+    /// The [interwiki prefix](https://meta.wikimedia.org/wiki/Help:Interwiki_linking) of the project's titles in tags, if it isn't Wikipedia.
+    pub fn prefix(&self) -> Option<&'static str> {
+        match self {
+            Project::Wikipedia => None,
+            Project::Wikivoyage => Some("voy"),
+        }
+    }
+
+    pub fn is_wikipedia(&self) -> bool {
+        *self == Project::Wikipedia
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        let prefix = prefix.trim();
+        if prefix.eq_ignore_ascii_case("voy") || prefix.eq_ignore_ascii_case("wikivoyage") {
+            Some(Project::Wikivoyage)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for Project {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Project::Wikipedia => "wikipedia",
+            Project::Wikivoyage => "wikivoyage",
+        })
+    }
+}
+
+/// Serialized as an osm tag string, e.g. `"en:Article_Title"`.
+impl Serialize for Title {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Title {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        Title::from_osm_tag(&s).map_err(de::Error::custom)
+    }
+}
+
 impl Title {
+    /// Trim whitespace and stray leading or trailing `/` subpage separators, replace spaces with `_`, and uppercase the first letter.
+    ///
+    /// Trailing separators would otherwise result in an empty component of the [Title::get_dir] path.
+    /// MediaWiki stores titles with the first letter uppercased, so `spatial database` is the same page as `Spatial database`.
     fn normalize_title(title: &str) -> String {
         // TODO: Compare with map generator url creation, ensure covers all cases.
-        title.trim().replace(' ', "_")
+        let title = title.trim().trim_matches('/').trim().replace(' ', "_");
+        // Wikis with `$wgCapitalLinks` disabled, like Wiktionary, keep the first letter as it is, but every Wikipedia and Wikivoyage uppercases it.
+        // The map generator does the same for all languages, so it isn't limited to an allowlist of wikis.
+        let mut chars = title.chars();
+        match chars.next() {
+            Some(first) if first.is_lowercase() => first.to_uppercase().chain(chars).collect(),
+            _ => title,
+        }
     }
 
-    // https://en.wikipedia.org/wiki/Article_Title/More_Title
+    /// Parse an article url of the form `https://lang.wikipedia.org/wiki/Article_Title`.
+    ///
+    /// The mobile host `lang.m.wikipedia.org` is also supported, as are Wikivoyage urls like `https://lang.wikivoyage.org/wiki/Article_Title`.
+    /// A mobile host without a language, like `m.wikipedia.org`, is rejected with [ParseTitleError::MobileNoLang].
+    /// Spaces in the title may be written as `_` or percent-encoded as `%20`, both are normalized to `_`.
+    ///
+    /// The `title` parameter of `https://lang.wikipedia.org/w/index.php?title=Article_Title` urls is parsed in place of the path,
+    /// and the revision of permalinks like `?title=Article_Title&oldid=123456` is kept in [Title::oldid].
+    /// Urls of index.php without a title, like those with only an `oldid`, are rejected with [ParseTitleError::NoTitleParam].
+    ///
+    /// MediaWiki doesn't allow percent escapes like `%C3` in titles, so a title that still has them after decoding comes from a url that was encoded twice, and is decoded again.
+    /// If it still has escapes after that, or they aren't UTF-8, it is rejected with [ParseTitleError::AmbiguousEncoding].
+    /// A `%` that isn't followed by two hex digits is part of the title, whether it was encoded or not, e.g. `100%25_Pure` and `100%_Pure`.
+    /// Urls of the Wikimedia Enterprise API and other Wikimedia projects do not contain the article's language and title,
+    /// so they are rejected with [ParseTitleError::EnterpriseApi] and [ParseTitleError::WikimediaProject].
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseTitleError, Title};
+    ///
+    /// assert_eq!(
+    ///     Err(ParseTitleError::EnterpriseApi),
+    ///     Title::from_url("https://api.enterprise.wikimedia.com/v2/articles/Berlin")
+    /// );
+    /// assert_eq!(
+    ///     Err(ParseTitleError::WikimediaProject),
+    ///     Title::from_url("https://meta.wikimedia.org/wiki/Main_Page")
+    /// );
+    /// ```
     pub fn from_url(url: &str) -> Result<Self, ParseTitleError> {
-        let url = url.trim();
+        let url = clean_value(url);
         if url.is_empty() {
             return Err(ParseTitleError::Empty);
         }
 
-        let url = Url::parse(url)?;
+        let url = Url::parse(&url)?;
 
-        let (subdomain, host) = url
-            .host_str()
-            .ok_or(ParseTitleError::NoHost)?
-            .split_once('.')
-            .ok_or(ParseTitleError::NoSubdomain)?;
-        let host = host.strip_prefix("m.").unwrap_or(host);
-        if host != "wikipedia.org" {
-            return Err(ParseTitleError::BadDomain);
+        let host = url.host_str().ok_or(ParseTitleError::NoHost)?;
+        let is_domain = |domain: &str| {
+            host.strip_suffix(domain)
+                .is_some_and(|sub| sub.is_empty() || sub.ends_with('.'))
+        };
+        if is_domain("enterprise.wikimedia.com") {
+            return Err(ParseTitleError::EnterpriseApi);
+        }
+        if is_domain("wikimedia.org") {
+            return Err(ParseTitleError::WikimediaProject);
+        }
+
+        let (subdomain, host) = host.split_once('.').ok_or(ParseTitleError::NoSubdomain)?;
+        // `m.wikipedia.org` has the mobile subdomain in place of the language.
+        if subdomain == "m" {
+            return Err(ParseTitleError::MobileNoLang);
         }
+        let host = host.strip_prefix("m.").unwrap_or(host);
+        let project = Project::ALL
+            .iter()
+            .copied()
+            .find(|p| p.domain() == host)
+            .ok_or(ParseTitleError::BadDomain)?;
         let lang = subdomain;
 
         let path = url.path();
@@ -74,21 +243,105 @@ impl Title {
             .split_once('/')
             .ok_or(ParseTitleError::ShortPath)?;
 
-        if root != "wiki" {
-            return Err(ParseTitleError::BadPath);
+        let mut oldid = None;
+        let mut title = match (root, title) {
+            ("wiki", title) => urlencoding::decode(title)?,
+            ("w", "index.php") => {
+                let mut param = None;
+                for (key, value) in url.query_pairs() {
+                    match &*key {
+                        "title" => param = Some(value.into_owned()),
+                        "oldid" => oldid = value.parse().ok(),
+                        _ => {}
+                    }
+                }
+                Cow::Owned(param.ok_or(ParseTitleError::NoTitleParam)?)
+            }
+            _ => return Err(ParseTitleError::BadPath),
+        };
+        if has_percent_escape(&title) {
+            title = match urlencoding::decode(&title) {
+                Ok(twice) if !has_percent_escape(&twice) => Cow::Owned(twice.into_owned()),
+                _ => return Err(ParseTitleError::AmbiguousEncoding),
+            };
         }
-        let title = urlencoding::decode(title)?;
+        // Titles can't contain `#`, so an encoded one is the start of a fragment, e.g. `Berlin%23History`.
+        let title = title
+            .split_once('#')
+            .map_or(&*title, |(title, _fragment)| title);
 
-        Self::from_title(&title, lang)
+        let mut title = Self::from_title(title, lang)?.in_project(project);
+        title.oldid = oldid;
+        Ok(title)
     }
 
-    // en:Article Title
+    /// Parse a `lang:Title` tag, e.g. `en:Article Title`.
+    ///
+    /// Articles of other projects can be prefixed with an interwiki prefix, e.g. `voy:en:Berlin` for Wikivoyage.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{Project, Title};
+    ///
+    /// let title = Title::from_osm_tag("voy:en:Berlin").unwrap();
+    /// assert_eq!(Project::Wikivoyage, title.project());
+    /// assert_eq!(title, Title::from_osm_tag("https://en.wikivoyage.org/wiki/Berlin").unwrap());
+    /// assert_ne!(title, Title::from_osm_tag("en:Berlin").unwrap());
+    /// assert_eq!("voy:en:Berlin", title.to_string());
+    /// ```
+    ///
+    /// Angle brackets or quotes around the whole tag, e.g. `<en:Berlin>` or `"en:Berlin"`, are removed.
+    /// Quotes are only removed from outside of the language, as they can be part of a title, e.g. `en:"Heroes"`.
     pub fn from_osm_tag(tag: &str) -> Result<Self, ParseTitleError> {
-        let tag = tag.trim();
+        Self::from_osm_tag_with(tag, &ParseOptions::default())
+    }
+
+    /// Like [Title::from_osm_tag], with additional formats enabled by `options`.
+    pub fn from_osm_tag_with(tag: &str, options: &ParseOptions) -> Result<Self, ParseTitleError> {
+        let tag = clean_value(tag);
+        let mut tag: &str = strip_wrapping(&tag);
         if tag.is_empty() {
             return Err(ParseTitleError::Empty);
         }
-        let (lang, title) = tag.split_once(':').ok_or(ParseTitleError::MissingColon)?;
+
+        if let Some((prefix, rest)) = tag.split_once(':') {
+            if let Some(project) = Project::from_prefix(prefix).filter(|_| rest.contains(':')) {
+                return Self::from_osm_tag_with(rest, options).map(|t| t.in_project(project));
+            }
+        }
+
+        if options.interwiki_prefix {
+            tag = strip_interwiki_prefix(tag);
+        }
+
+        let title = Self::parse_osm_tag(tag, options)?;
+        Ok(if options.ignore_case {
+            title.into_lowercase()
+        } else {
+            title
+        })
+    }
+
+    fn parse_osm_tag(tag: &str, options: &ParseOptions) -> Result<Self, ParseTitleError> {
+        let from_title = |title, lang| Self::from_title_with(title, lang, options);
+        // A `lang:` prefix always wins, so `en:Ur (ur)` keeps its disambiguator.
+        let has_lang_prefix = tag
+            .split_once(':')
+            .is_some_and(|(lang, _)| is_valid_lang(lang.trim()));
+        if options.trailing_lang && !has_lang_prefix {
+            if let Some((title, lang)) = split_trailing_lang(tag) {
+                return from_title(title, lang);
+            }
+        }
+
+        let Some((lang, title)) = tag
+            .split_once(':')
+            .filter(|(lang, _)| options.default_lang.is_none() || is_lang_like(lang))
+        else {
+            return match &options.default_lang {
+                Some(lang) => from_title(tag, lang),
+                None => Err(ParseTitleError::MissingColon),
+            };
+        };
 
         let lang = lang.trim_start();
         let title = title.trim_start();
@@ -101,43 +354,412 @@ impl Title {
             return Self::from_url(title);
         }
 
-        Self::from_title(title, lang)
+        // A namespace without a lang, like `Template:Infobox`, would otherwise be read as the lang `template`.
+        if let Some(namespace) = namespace(tag, "") {
+            return Err(ParseTitleError::Namespace(namespace.to_owned()));
+        }
+
+        from_title(title, lang)
+    }
+
+    /// Parse a url, a `lang:Title` tag, or a bare title in `default_lang`, whichever `value` is, for inputs that mix them.
+    ///
+    /// They are tried in that order with [Title::from_url], [Title::from_osm_tag], and [Title::from_title], and the first that succeeds is returned.
+    /// Bare titles are only tried with a `default_lang`.
+    /// If none succeed, the error has the reason of each.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseTitleError, Title};
+    ///
+    /// let berlin = Title::from_title("Berlin", "de").unwrap();
+    /// assert_eq!(berlin, Title::parse_any("https://de.wikipedia.org/wiki/Berlin", None).unwrap());
+    /// assert_eq!(berlin, Title::parse_any("Berlin", Some("de")).unwrap());
+    ///
+    /// let e = Title::parse_any("Berlin", None).unwrap_err();
+    /// assert_eq!(ParseTitleError::MissingColon, e.tag);
+    /// assert_eq!(None, e.title);
+    /// ```
+    pub fn parse_any(value: &str, default_lang: Option<&str>) -> Result<Self, ParseAnyError> {
+        let url = match Self::from_url(value) {
+            Ok(title) => return Ok(title),
+            Err(e) => e,
+        };
+        let tag = match Self::from_osm_tag(value) {
+            Ok(title) => return Ok(title),
+            Err(e) => e,
+        };
+        let title = match default_lang.map(|lang| Self::from_title(value, lang)) {
+            Some(Ok(title)) => return Ok(title),
+            Some(Err(e)) => Some(e),
+            None => None,
+        };
+        Err(ParseAnyError { url, tag, title })
     }
 
+    /// Parse the title `title` of an article in the `lang` wiki.
+    ///
+    /// Both are cleaned with [clean_value] first, as are the tags and urls of the other constructors.
+    /// All checks that can reject the title run before anything is allocated, as most invalid tags fail them.
+    /// The exception is a title with percent escapes, which is decoded into a new string before its length and namespace are checked.
+    /// The `lang` must be letters separated by single `-`s in the shape of a BCP-47 tag, like `en` or `zh-hans`.
+    ///
+    /// Like MediaWiki, the first letter of the name is uppercased, and the rest are kept as they are.
+    /// Percent escapes are decoded like those of [Title::from_url], so tags copied from urls are the same titles.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// assert_eq!(
+    ///     Title::from_title("Spatial database", "en").unwrap(),
+    ///     Title::from_title("spatial database", "en").unwrap()
+    /// );
+    /// assert_ne!(
+    ///     Title::from_title("Spatial database", "en").unwrap(),
+    ///     Title::from_title("Spatial Database", "en").unwrap()
+    /// );
+    /// assert_eq!("ru:Ёлка", Title::from_title("ёлка", "ru").unwrap().to_string());
+    ///
+    /// assert_eq!(
+    ///     Title::from_title("Café", "en").unwrap(),
+    ///     Title::from_osm_tag("en:Caf%C3%A9").unwrap()
+    /// );
+    /// assert_eq!("en:100%_Pure", Title::from_title("100% Pure", "en").unwrap().to_string());
+    /// ```
     pub fn from_title(title: &str, lang: &str) -> Result<Self, ParseTitleError> {
-        let title = title.trim();
+        Self::from_title_with(title, lang, &ParseOptions::default())
+    }
+
+    /// Like [Title::from_title], with the [ParseOptions] that apply to titles, [ParseOptions::verbatim] and [ParseOptions::html_entities].
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, Title};
+    ///
+    /// let options = ParseOptions { html_entities: true, ..Default::default() };
+    /// assert_eq!(
+    ///     Title::from_title("Ben & Jerry's", "en").unwrap(),
+    ///     Title::from_title_with("Ben &amp; Jerry&#39;s", "en", &options).unwrap()
+    /// );
+    /// ```
+    pub fn from_title_with(
+        title: &str,
+        lang: &str,
+        options: &ParseOptions,
+    ) -> Result<Self, ParseTitleError> {
+        if options.html_entities {
+            Self::parse_title(&decode_entities(title), lang, options.verbatim, false)
+        } else {
+            Self::parse_title(title, lang, options.verbatim, false)
+        }
+    }
+
+    /// Like [Title::from_title], storing the name as it is written instead of normalizing it.
+    ///
+    /// Use this for sources that already have canonical titles, like the sitelinks of a Wikidata dump, where normalizing could change them,
+    /// e.g. the leading `/` of `/dev/random`.
+    /// Only surrounding whitespace and invisible characters are removed, and the title is validated like [Title::from_title].
+    ///
+    /// Verbatim titles with spaces are not equal to normalized titles, which have `_` instead.
+    /// Compare them with other verbatim titles, not with the titles of [crate::wm::Page]s or urls.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// let verbatim = Title::from_title_verbatim("/dev/random", "en").unwrap();
+    /// assert_eq!("en:/dev/random", verbatim.to_string());
+    /// assert_eq!("en:Dev/random", Title::from_title("/dev/random", "en").unwrap().to_string());
+    /// ```
+    pub fn from_title_verbatim(title: &str, lang: &str) -> Result<Self, ParseTitleError> {
+        Self::parse_title(title, lang, true, false)
+    }
+
+    /// Like [Title::from_title], allowing pages in namespaces that are not articles, like `Category:Lighthouses`.
+    ///
+    /// Other constructors reject them with [ParseTitleError::Namespace], as they should not be linked from OSM.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseTitleError, Title};
+    ///
+    /// assert_eq!(
+    ///     Err(ParseTitleError::Namespace("Category".into())),
+    ///     Title::from_title("Kategorie:Leuchtturm in Europa", "de")
+    /// );
+    /// let category = Title::from_title_in_namespace("Kategorie:Leuchtturm in Europa", "de").unwrap();
+    /// assert_eq!("de:Kategorie:Leuchtturm_in_Europa", category.to_string());
+    /// ```
+    pub fn from_title_in_namespace(title: &str, lang: &str) -> Result<Self, ParseTitleError> {
+        Self::parse_title(title, lang, false, true)
+    }
+
+    fn parse_title(
+        title: &str,
+        lang: &str,
+        verbatim: bool,
+        in_namespace: bool,
+    ) -> Result<Self, ParseTitleError> {
+        let mut title = clean_value(title);
         if title.is_empty() {
             return Err(ParseTitleError::NoTitle);
         }
+
+        let lang = clean_value(lang);
+        let lang: &str = &lang;
+        if lang.is_empty() {
+            return Err(ParseTitleError::NoLang);
+        }
+        if lang.contains(|c: char| !(c.is_ascii_alphabetic() || c == '-')) {
+            return Err(ParseTitleError::LangBadChar);
+        }
+        if !is_lang_shaped(lang) {
+            return Err(ParseTitleError::LangMalformed);
+        }
+
+        // Tags are sometimes copied from urls with their escapes, e.g. `Caf%C3%A9`.
+        // Escapes that aren't UTF-8 are kept as they are, as are `%`s that aren't escapes, e.g. `100%_Pure`.
+        // They are decoded after the checks of the lang, but before those of the length and namespace, which apply to the decoded title.
+        if !verbatim && has_percent_escape(&title) {
+            if let Ok(decoded) = urlencoding::decode(&title) {
+                title = Cow::Owned(decoded.into_owned());
+            }
+        }
+        let title: &str = &title;
         // Wikipedia titles must be less than 256 bytes of UTF-8.
         // See: https://en.wikipedia.org/wiki/Wikipedia:Naming_conventions_(technical_restrictions)#Title_length
-        if !title.len() < 256 {
+        // The limit is on bytes, not characters, so `len` is used rather than counting chars.
+        if title.len() >= 256 {
             return Err(ParseTitleError::TitleLong);
         }
 
         // TODO: titles have a number of restrictions, including containing percent-encoded characters
         // See <https://en.wikipedia.org/wiki/Wikipedia:Page_name#Technical_restrictions_and_limitations>
 
-        // TODO: special titles in "namespaces" start with a word and colon. They should not be linked from OSM.
-        // See <https://en.wikipedia.org/wiki/Wikipedia:Namespace>
-
-        let lang = lang.trim();
-        if lang.is_empty() {
-            return Err(ParseTitleError::NoLang);
+        // Special titles in "namespaces" start with a word and colon. They should not be linked from OSM.
+        if !in_namespace {
+            if let Some(namespace) = namespace(title, lang) {
+                return Err(ParseTitleError::Namespace(namespace.to_owned()));
+            }
         }
-        if lang.contains(|c: char| !(c.is_ascii_alphabetic() || c == '-')) {
-            return Err(ParseTitleError::LangBadChar);
+
+        // Only stray `/` separators are left to remove.
+        if !verbatim && title.trim_matches('/').trim().is_empty() {
+            return Err(ParseTitleError::NoTitle);
         }
+
         let lang = lang.to_ascii_lowercase();
+        let name = if verbatim {
+            title.to_owned()
+        } else {
+            Self::normalize_title(title)
+        };
+        Ok(Self {
+            project: Project::Wikipedia,
+            name,
+            lang,
+            original: title.to_owned(),
+            oldid: None,
+        })
+    }
+
+    /// The same title in the wiki of `project`.
+    pub fn in_project(mut self, project: Project) -> Self {
+        self.project = project;
+        self
+    }
+
+    /// The same title with the language code `lang`, see [super::LanguageMap].
+    pub(crate) fn in_lang(mut self, lang: &str) -> Self {
+        self.lang = lang.to_owned();
+        self
+    }
+
+    pub fn project(&self) -> Project {
+        self.project
+    }
+
+    fn into_lowercase(mut self) -> Self {
+        self.name = self.name.to_lowercase();
+        self
+    }
 
-        let name = Self::normalize_title(title);
-        Ok(Self { name, lang })
+    /// Lowercase language code of the wiki the article is in.
+    pub fn lang(&self) -> &str {
+        &self.lang
+    }
+
+    /// The normalized article name, with `_` in place of spaces, see [Title::from_title].
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// let title = Title::from_url("https://de.m.wikipedia.org/wiki/K%C3%B6lner_Dom#Geschichte").unwrap();
+    /// assert_eq!(("de", "Kölner_Dom"), (title.lang(), title.name()));
+    /// assert_eq!(title, Title::from_title(title.name(), title.lang()).unwrap());
+    /// assert_eq!(format!("{}:{}", title.lang(), title.name()), title.to_string());
+    /// ```
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The url of the article, e.g. `https://de.wikipedia.org/wiki/K%C3%B6lner_Dom`.
+    ///
+    /// The name is percent-encoded like MediaWiki does, which keeps `/`, `:`, and `(),;@$!*~` as they are.
+    /// Parsing the url with [Title::from_url] returns the same title.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// for tag in ["de:Breil/Brigels", "de:Kölner Dom", "en:Mercury (planet)", "en:AT&T", "voy:en:Berlin"] {
+    ///     let title = Title::from_osm_tag(tag).unwrap();
+    ///     assert_eq!(title, Title::from_url(&title.to_url()).unwrap(), "{tag}");
+    /// }
+    /// assert_eq!(
+    ///     "https://de.wikipedia.org/wiki/K%C3%B6lner_Dom",
+    ///     Title::from_osm_tag("de:Kölner Dom").unwrap().to_url()
+    /// );
+    /// assert_eq!(
+    ///     "https://en.wikipedia.org/wiki/Mercury_(planet)",
+    ///     Title::from_osm_tag("en:Mercury (planet)").unwrap().to_url()
+    /// );
+    /// assert_eq!(
+    ///     "https://en.wikivoyage.org/wiki/Berlin",
+    ///     Title::from_osm_tag("voy:en:Berlin").unwrap().to_url()
+    /// );
+    /// ```
+    pub fn to_url(&self) -> String {
+        let mut url = format!("https://{}.{}/wiki/", self.lang, self.project.domain());
+        for b in self.name.bytes() {
+            if b.is_ascii_alphanumeric() || b"-_.~/:(),;@$!*".contains(&b) {
+                url.push(b as char);
+            } else {
+                url.push_str(&format!("%{b:02X}"));
+            }
+        }
+        url
+    }
+
+    /// The title as an OSM `wikipedia` tag, with spaces instead of `_`, e.g. `de:Kölner Dom`.
+    ///
+    /// Titles of other projects have their interwiki prefix, like [Title]'s [Display], e.g. `voy:en:Berlin`.
+    /// Parsing the tag with [Title::from_osm_tag] returns the same title.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// for url in ["https://de.wikipedia.org/wiki/Breil/Brigels", "https://de.wikipedia.org/wiki/K%C3%B6lner_Dom"] {
+    ///     let title = Title::from_url(url).unwrap();
+    ///     assert_eq!(title, Title::from_osm_tag(&title.to_osm_tag()).unwrap(), "{url}");
+    /// }
+    /// assert_eq!(
+    ///     "de:Kölner Dom",
+    ///     Title::from_url("https://de.wikipedia.org/wiki/K%C3%B6lner_Dom").unwrap().to_osm_tag()
+    /// );
+    /// ```
+    pub fn to_osm_tag(&self) -> String {
+        let name = self.name.replace('_', " ");
+        match self.project.prefix() {
+            Some(prefix) => format!("{prefix}:{}:{name}", self.lang),
+            None => format!("{}:{name}", self.lang),
+        }
+    }
+
+    /// The article name as it was written before normalization, e.g. for display.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, Title};
+    ///
+    /// let options = ParseOptions { ignore_case: true, ..Default::default() };
+    /// let title = Title::from_osm_tag_with("en:iPhone 4", &options).unwrap();
+    /// assert_eq!("iPhone 4", title.original_name());
+    /// assert_eq!("en:iphone_4", title.to_string());
+    /// ```
+    pub fn original_name(&self) -> &str {
+        &self.original
+    }
+
+    /// The revision id of a permalink url that the title was parsed from, see [Title::from_url].
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// let permalink = Title::from_url("https://en.wikipedia.org/w/index.php?title=Berlin&oldid=123456").unwrap();
+    /// assert_eq!(Some(123456), permalink.oldid());
+    /// assert_eq!(Title::from_osm_tag("en:Berlin").unwrap(), permalink);
+    /// assert_eq!(None, Title::from_osm_tag("en:Berlin").unwrap().oldid());
+    /// ```
+    pub fn oldid(&self) -> Option<u64> {
+        self.oldid
+    }
+
+    /// A key of the title with apostrophes, dashes, and runs of whitespace folded into a single form.
+    ///
+    /// Titles with the same skeleton only differ in those characters, e.g. from being typed on different keyboards.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// let curly = Title::from_osm_tag("en:King’s Cross – St Pancras").unwrap();
+    /// let straight = Title::from_osm_tag("en:King's Cross - St  Pancras").unwrap();
+    /// assert_ne!(curly, straight);
+    /// assert_eq!(curly.skeleton(), straight.skeleton());
+    /// ```
+    pub fn skeleton(&self) -> String {
+        let mut skeleton = String::with_capacity(self.lang.len() + 1 + self.name.len());
+        if let Some(prefix) = self.project.prefix() {
+            skeleton.push_str(prefix);
+            skeleton.push(':');
+        }
+        skeleton.push_str(&self.lang);
+        skeleton.push(':');
+        let mut last_space = false;
+        for c in self.name.chars() {
+            let c = match c {
+                '\'' | '’' | '‘' | 'ʼ' | '′' | '`' | '´' => '\'',
+                '-' | '‐' | '‑' | '‒' | '–' | '—' | '―' | '−' => '-',
+                c if c == '_' || c.is_whitespace() => '_',
+                c => c,
+            };
+            if c == '_' && last_space {
+                continue;
+            }
+            last_space = c == '_';
+            skeleton.push(c);
+        }
+        skeleton
+    }
+
+    /// A key of the title that ignores case and runs of whitespace, with the same case folding as [ParseOptions::ignore_case].
+    ///
+    /// ```
+    /// use om_wikiparser::wm::Title;
+    ///
+    /// let loose = Title::from_osm_tag("en:berlin,  GERMANY").unwrap();
+    /// let exact = Title::from_osm_tag("en:Berlin, Germany").unwrap();
+    /// assert_ne!(loose, exact);
+    /// assert_eq!(loose.loose_key(), exact.loose_key());
+    /// ```
+    pub fn loose_key(&self) -> String {
+        let mut key = String::with_capacity(self.lang.len() + 1 + self.name.len());
+        if let Some(prefix) = self.project.prefix() {
+            key.push_str(prefix);
+            key.push(':');
+        }
+        key.push_str(&self.lang);
+        key.push(':');
+        let mut last_space = false;
+        for c in self.name.to_lowercase().chars() {
+            let space = c == '_' || c.is_whitespace();
+            if space && last_space {
+                continue;
+            }
+            last_space = space;
+            key.push(if space { '_' } else { c });
+        }
+        key
     }
 
     pub fn get_dir(&self, base: PathBuf) -> PathBuf {
         let mut path = base;
         // TODO: can use as_mut_os_string with 1.70.0
-        path.push(format!("{}.wikipedia.org", self.lang));
+        path.push(format!("{}.{}", self.lang, self.project.domain()));
         path.push("wiki");
         path.push(&self.name);
 
@@ -145,6 +767,589 @@ impl Title {
     }
 }
 
+/// Determine the directory an OSM tag's article is written to in `base`.
+///
+/// Equivalent to parsing the tag with [Title::from_osm_tag] and calling [Layout::title_dir].
+///
+/// ```
+/// use std::path::Path;
+/// use om_wikiparser::wm::{normalize_tag_to_path, path_to_tag, Layout};
+///
+/// let path = normalize_tag_to_path("de:Breil/Brigels", Path::new("base"), Layout::Flat).unwrap();
+/// assert_eq!(Path::new("base/de.wikipedia.org/wiki/Breil/Brigels"), path);
+/// assert_eq!(
+///     Some("de:Breil/Brigels".to_string()),
+///     path_to_tag(&path, Path::new("base"), Layout::Flat)
+/// );
+/// ```
+pub fn normalize_tag_to_path(
+    tag: &str,
+    base: &Path,
+    layout: Layout,
+) -> Result<PathBuf, ParseTitleError> {
+    let title = Title::from_osm_tag(tag)?;
+    Ok(layout.title_dir(base.to_path_buf(), &title))
+}
+
+/// Determine the `lang:title` tag of an article directory created with [normalize_tag_to_path].
+///
+/// Returns `None` if `path` is not an article title directory of `layout` in `base`.
+pub fn path_to_tag(path: &Path, base: &Path, layout: Layout) -> Option<String> {
+    let mut components = path.strip_prefix(base).ok()?.components();
+
+    if layout == Layout::Sharded {
+        for _ in 0..2 {
+            let Component::Normal(shard) = components.next()? else {
+                return None;
+            };
+            let shard = shard.to_str()?;
+            if shard.len() != 2 || !shard.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+        }
+    }
+
+    let Component::Normal(domain) = components.next()? else {
+        return None;
+    };
+    let domain = domain.to_str()?;
+    let (lang, project) = Project::ALL.iter().find_map(|project| {
+        let lang = domain.strip_suffix(project.domain())?.strip_suffix('.')?;
+        Some((lang, project))
+    })?;
+    if components.next()? != Component::Normal("wiki".as_ref()) {
+        return None;
+    }
+
+    let mut tag = match project.prefix() {
+        Some(prefix) => format!("{prefix}:{lang}:"),
+        None => format!("{lang}:"),
+    };
+    let prefix_len = tag.len();
+    for (i, c) in components.enumerate() {
+        let Component::Normal(c) = c else {
+            return None;
+        };
+        if i != 0 {
+            tag.push('/');
+        }
+        tag.push_str(c.to_str()?);
+    }
+    if tag.len() == prefix_len {
+        return None;
+    }
+
+    Some(tag)
+}
+
+/// Opt-in support for loosely-formatted titles.
+///
+/// The default options accept the same values as [Title::from_osm_tag].
+/// The constructors [ParseOptions::strict], [ParseOptions::osm_cleanup], and [ParseOptions::production_extract] are combinations for common uses.
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// Read the language from a trailing parenthetical instead of a prefix, e.g. `Berlin (de)`.
+    ///
+    /// Tags with a `lang:` prefix are read as usual, keeping any parenthetical.
+    /// Only 2-3 letter codes with optional subtags (`zh-hans`) are treated as languages, but a tag without a prefix
+    /// still conflicts with disambiguators like `Ur (ur)`, so only use it for sources that are known to use this format.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, Title};
+    ///
+    /// let options = ParseOptions { trailing_lang: true, ..Default::default() };
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("Berlin (de)", &options).unwrap(),
+    ///     Title::from_osm_tag("de:Berlin").unwrap()
+    /// );
+    /// // Disambiguators are kept.
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("en:Mercury (planet)", &options).unwrap(),
+    ///     Title::from_title("Mercury (planet)", "en").unwrap()
+    /// );
+    ///
+    /// assert!(Title::from_osm_tag("Berlin (de)").is_err());
+    /// ```
+    pub trailing_lang: bool,
+
+    /// Ignore a leading MediaWiki interwiki prefix for wikipedia, e.g. `w:en:Berlin` or `wikipedia:en:Berlin`.
+    ///
+    /// See <https://meta.wikimedia.org/wiki/Help:Interwiki_linking>.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, ParseTitleError, Title};
+    ///
+    /// let options = ParseOptions { interwiki_prefix: true, ..Default::default() };
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("w:en:Berlin", &options).unwrap(),
+    ///     Title::from_osm_tag("en:Berlin").unwrap()
+    /// );
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("wikipedia:de:Berlin", &options).unwrap(),
+    ///     Title::from_osm_tag("de:Berlin").unwrap()
+    /// );
+    ///
+    /// // Otherwise the prefix is read as the language, which `w` is too short to be.
+    /// assert_eq!(
+    ///     Err(ParseTitleError::LangMalformed),
+    ///     Title::from_osm_tag("w:en:Berlin")
+    /// );
+    /// ```
+    pub interwiki_prefix: bool,
+
+    /// Language for titles without one, e.g. `Berlin` in a single-language source.
+    ///
+    /// A prefix is still used as the language if it has the characters of one,
+    /// otherwise the whole tag is the title, e.g. `Star Wars: Episode IV`.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, Title};
+    ///
+    /// let options = ParseOptions { default_lang: Some("de".into()), ..Default::default() };
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("Berlin", &options).unwrap(),
+    ///     Title::from_title("Berlin", "de").unwrap()
+    /// );
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("en:Berlin", &options).unwrap(),
+    ///     Title::from_title("Berlin", "en").unwrap()
+    /// );
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("Star Wars: Episode IV", &options).unwrap(),
+    ///     Title::from_title("Star Wars: Episode IV", "de").unwrap()
+    /// );
+    ///
+    /// assert!(Title::from_osm_tag("Berlin").is_err());
+    /// ```
+    pub default_lang: Option<String>,
+
+    /// Lowercase the name, so titles that only differ in case are equal.
+    ///
+    /// Wikipedia titles are case-sensitive except for the first letter, so only use this for sources with unreliable casing.
+    /// The directories of these titles are lowercase too, so don't use them to write articles.
+    /// The original casing is kept in [Title::original_name].
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, Title};
+    ///
+    /// let options = ParseOptions { ignore_case: true, ..Default::default() };
+    /// let a = Title::from_osm_tag_with("en:iPhone", &options).unwrap();
+    /// let b = Title::from_osm_tag_with("en:IPHONE", &options).unwrap();
+    /// assert_eq!(a, b);
+    /// assert_eq!("iPhone", a.original_name());
+    /// assert_eq!("IPHONE", b.original_name());
+    ///
+    /// assert_ne!(Title::from_osm_tag("en:iPhone").unwrap(), Title::from_osm_tag("en:IPHONE").unwrap());
+    /// ```
+    pub ignore_case: bool,
+
+    /// Keep the titles of `lang:Title` tags as they are written, see [Title::from_title_verbatim].
+    ///
+    /// Titles in urls are still normalized.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, Title};
+    ///
+    /// let options = ParseOptions { verbatim: true, ..Default::default() };
+    /// assert_eq!(
+    ///     Title::from_osm_tag_with("en:Article Title", &options).unwrap(),
+    ///     Title::from_title_verbatim("Article Title", "en").unwrap()
+    /// );
+    /// ```
+    pub verbatim: bool,
+
+    /// Decode the HTML character references in titles, e.g. `&amp;` and `&#39;` in titles scraped from web pages.
+    ///
+    /// References are only decoded once, so `&amp;amp;` is `&amp;`, and don't enable this for sources that aren't escaped;
+    /// a title can contain text like `&amp;` itself.
+    /// Only references ending in `;` are decoded, and titles in urls are not.
+    ///
+    /// ```
+    /// use om_wikiparser::wm::{ParseOptions, Title};
+    ///
+    /// let options = ParseOptions { html_entities: true, ..Default::default() };
+    /// let title = Title::from_osm_tag_with("en:Ben &amp; Jerry&#x27;s", &options).unwrap();
+    /// assert_eq!("en:Ben_&_Jerry's", title.to_string());
+    /// assert_eq!("en:Ben_&amp;_Jerry's", Title::from_osm_tag("en:Ben &amp; Jerry's").unwrap().to_string());
+    /// ```
+    pub html_entities: bool,
+}
+
+impl ParseOptions {
+    /// The default options: only `lang:Title` tags and urls, with no flags set.
+    pub fn strict() -> Self {
+        Self::default()
+    }
+
+    /// Recover as many titles as possible, for reviewing and fixing the tags of OSM objects.
+    ///
+    /// Sets [ParseOptions::trailing_lang], [ParseOptions::interwiki_prefix], and [ParseOptions::html_entities].
+    /// Tags without a language prefix that end in a disambiguator like `Ur (ur)` are then read in that language, so check the results instead of extracting them directly.
+    pub fn osm_cleanup() -> Self {
+        Self {
+            trailing_lang: true,
+            interwiki_prefix: true,
+            html_entities: true,
+            ..Self::default()
+        }
+    }
+
+    /// Recover the titles of tags that are unambiguous, for the titles to extract from the dumps.
+    ///
+    /// Only sets [ParseOptions::interwiki_prefix], as the other recoveries can change a valid title.
+    pub fn production_extract() -> Self {
+        Self {
+            interwiki_prefix: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Decode the HTML character references like `&amp;`, `&#39;`, and `&#x27;` in `s`.
+///
+/// References that aren't terminated by `;` or aren't known are kept as they are.
+fn decode_entities(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // The longest named reference is `&CounterClockwiseContourIntegral;`.
+        let reference = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 32)
+            .and_then(|end| Some((decode_reference(&rest[1..end + 2])?, end + 2)));
+        match reference {
+            Some((c, len)) => {
+                decoded.extend(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+/// The characters of a reference without its `&`, e.g. `amp;` or `#39;`.
+fn decode_reference(reference: &str) -> Option<impl Iterator<Item = char>> {
+    let code = |digits: &str, radix| {
+        u32::from_str_radix(digits, radix)
+            .ok()
+            .and_then(char::from_u32)
+    };
+    let name = reference.strip_suffix(';')?;
+    let (first, second) = if let Some(number) = name.strip_prefix('#') {
+        let c = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => code(hex, 16)?,
+            None => code(number, 10)?,
+        };
+        (c, None)
+    } else {
+        let &(first, second) = markup5ever::data::NAMED_ENTITIES.get(reference)?;
+        // Prefixes of longer names are in the map without a character.
+        let first = char::from_u32(first).filter(|&c| c != '\0')?;
+        (first, char::from_u32(second).filter(|&c| c != '\0'))
+    };
+    Some(std::iter::once(first).chain(second))
+}
+
+/// Check if `lang` only contains characters allowed by [Title::from_title].
+fn is_lang_like(lang: &str) -> bool {
+    let lang = lang.trim();
+    !lang.is_empty() && lang.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+}
+
+/// Check if `lang` is a language code that [Title::from_title] accepts, e.g. `en` or `zh-hans`.
+pub(crate) fn is_valid_lang(lang: &str) -> bool {
+    is_lang_like(lang) && lang.trim() == lang && is_lang_shaped(lang)
+}
+
+/// Check if `lang`, of only letters and `-`, is shaped like a BCP-47 language tag, e.g. `en`, `zh-hans`, or `be-x-old`.
+///
+/// The first subtag has 2 to 8 letters, and each following subtag 1 to 8.
+/// The codes of some wikis, like `zh-classical`, have longer subtags and are allowed too.
+fn is_lang_shaped(lang: &str) -> bool {
+    /// Codes of wikis that don't fit the BCP-47 shape.
+    const NONSTANDARD: &[&str] = &["zh-classical"];
+
+    let mut subtags = lang.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    ((2..=8).contains(&primary.len()) && subtags.all(|subtag| (1..=8).contains(&subtag.len())))
+        || NONSTANDARD
+            .iter()
+            .any(|code| code.eq_ignore_ascii_case(lang))
+}
+
+/// Remove angle brackets or quotes around a whole tag, like `<en:Berlin>` or `"en:Berlin"`, left by some OSM exports.
+///
+/// Titles can't contain angle brackets, so they are always removed.
+/// Quotes can be part of a title, e.g. `en:"Heroes"`, so they are only removed if they are outside of a language prefix,
+/// i.e. the tag starts with one and the text within them starts with a language and `:` or is a url.
+fn strip_wrapping(mut tag: &str) -> &str {
+    const QUOTES: &[(char, char)] = &[
+        ('"', '"'),
+        ('\'', '\''),
+        ('“', '”'),
+        ('„', '“'),
+        ('‘', '’'),
+        ('«', '»'),
+    ];
+    loop {
+        let inner = if let Some(inner) = tag.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+            inner
+        } else if let Some(inner) = QUOTES.iter().find_map(|&(open, close)| {
+            tag.strip_prefix(open)
+                .and_then(|t| t.strip_suffix(close))
+                .filter(|inner| {
+                    let inner = inner.trim().trim_start_matches('<');
+                    inner.starts_with("http")
+                        || inner
+                            .split_once(':')
+                            .is_some_and(|(lang, _)| is_lang_like(lang))
+                })
+        }) {
+            inner
+        } else {
+            return tag;
+        };
+        tag = inner.trim();
+    }
+}
+
+/// Remove a leading `w:` or `wikipedia:` from a `lang:title` tag.
+fn strip_interwiki_prefix(tag: &str) -> &str {
+    let Some((prefix, rest)) = tag.split_once(':') else {
+        return tag;
+    };
+    let prefix = prefix.trim();
+    if (prefix.eq_ignore_ascii_case("w") || prefix.eq_ignore_ascii_case("wikipedia"))
+        && rest.contains(':')
+    {
+        rest.trim_start()
+    } else {
+        tag
+    }
+}
+
+/// Split `Title (lang)` into the title and language, if the parenthetical looks like a language code.
+fn split_trailing_lang(tag: &str) -> Option<(&str, &str)> {
+    let (title, lang) = tag.strip_suffix(')')?.rsplit_once('(')?;
+    let title = title.trim_end();
+    let lang = lang.trim();
+
+    let mut subtags = lang.split('-');
+    let primary = subtags.next()?;
+    if !(2..=3).contains(&primary.len())
+        || !primary.bytes().all(|b| b.is_ascii_lowercase())
+        || !subtags.all(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric()))
+    {
+        return None;
+    }
+
+    if title.is_empty() {
+        return None;
+    }
+
+    Some((title, lang))
+}
+
+/// Canonical names of the namespaces that are not articles, which are recognized on every wiki.
+///
+/// See <https://en.wikipedia.org/wiki/Wikipedia:Namespace>.
+pub const NAMESPACES: &[&str] = &[
+    "Talk",
+    "User",
+    "User talk",
+    "Wikipedia",
+    "Wikipedia talk",
+    "Project",
+    "Project talk",
+    "File",
+    "File talk",
+    "Image",
+    "Image talk",
+    "MediaWiki",
+    "MediaWiki talk",
+    "Template",
+    "Template talk",
+    "Help",
+    "Help talk",
+    "Category",
+    "Category talk",
+    "Portal",
+    "Portal talk",
+    "Draft",
+    "Draft talk",
+    "Module",
+    "Module talk",
+    "Special",
+    "Media",
+];
+
+/// Localized names of [NAMESPACES] on the wikis of some languages, and the canonical names they stand for.
+///
+/// Only the namespaces that are most often tagged by mistake are listed, see the `namespacealiases` of each wiki's siteinfo API for the rest.
+pub const LOCALIZED_NAMESPACES: &[(&str, &[(&str, &str)])] = &[
+    (
+        "de",
+        &[
+            ("Datei", "File"),
+            ("Bild", "File"),
+            ("Kategorie", "Category"),
+            ("Vorlage", "Template"),
+            ("Hilfe", "Help"),
+            ("Benutzer", "User"),
+            ("Diskussion", "Talk"),
+            ("Spezial", "Special"),
+        ],
+    ),
+    (
+        "es",
+        &[
+            ("Archivo", "File"),
+            ("Categoría", "Category"),
+            ("Plantilla", "Template"),
+            ("Ayuda", "Help"),
+            ("Usuario", "User"),
+            ("Discusión", "Talk"),
+            ("Especial", "Special"),
+        ],
+    ),
+    (
+        "fr",
+        &[
+            ("Fichier", "File"),
+            ("Catégorie", "Category"),
+            ("Modèle", "Template"),
+            ("Aide", "Help"),
+            ("Utilisateur", "User"),
+            ("Discussion", "Talk"),
+            ("Spécial", "Special"),
+            ("Portail", "Portal"),
+            ("Wikipédia", "Wikipedia"),
+        ],
+    ),
+    (
+        "it",
+        &[
+            ("Categoria", "Category"),
+            ("Aiuto", "Help"),
+            ("Utente", "User"),
+            ("Discussione", "Talk"),
+            ("Speciale", "Special"),
+            ("Portale", "Portal"),
+        ],
+    ),
+    (
+        "nl",
+        &[
+            ("Bestand", "File"),
+            ("Categorie", "Category"),
+            ("Sjabloon", "Template"),
+            ("Gebruiker", "User"),
+            ("Overleg", "Talk"),
+            ("Speciaal", "Special"),
+            ("Portaal", "Portal"),
+        ],
+    ),
+    (
+        "pl",
+        &[
+            ("Plik", "File"),
+            ("Kategoria", "Category"),
+            ("Szablon", "Template"),
+            ("Pomoc", "Help"),
+            ("Wikipedysta", "User"),
+            ("Dyskusja", "Talk"),
+            ("Specjalna", "Special"),
+        ],
+    ),
+    (
+        "pt",
+        &[
+            ("Ficheiro", "File"),
+            ("Arquivo", "File"),
+            ("Categoria", "Category"),
+            ("Predefinição", "Template"),
+            ("Ajuda", "Help"),
+            ("Usuário", "User"),
+            ("Discussão", "Talk"),
+            ("Especial", "Special"),
+        ],
+    ),
+    (
+        "ru",
+        &[
+            ("Файл", "File"),
+            ("Категория", "Category"),
+            ("Шаблон", "Template"),
+            ("Справка", "Help"),
+            ("Участник", "User"),
+            ("Обсуждение", "Talk"),
+            ("Служебная", "Special"),
+            ("Портал", "Portal"),
+            ("Википедия", "Wikipedia"),
+        ],
+    ),
+    (
+        "uk",
+        &[
+            ("Файл", "File"),
+            ("Категорія", "Category"),
+            ("Шаблон", "Template"),
+            ("Довідка", "Help"),
+            ("Користувач", "User"),
+            ("Обговорення", "Talk"),
+            ("Спеціальна", "Special"),
+            ("Портал", "Portal"),
+            ("Вікіпедія", "Wikipedia"),
+        ],
+    ),
+    (
+        "ja",
+        &[
+            ("ファイル", "File"),
+            ("カテゴリ", "Category"),
+            ("ヘルプ", "Help"),
+            ("利用者", "User"),
+            ("ノート", "Talk"),
+            ("特別", "Special"),
+        ],
+    ),
+];
+
+/// The entry of [NAMESPACES] that `title` starts with, ignoring case and the separator before the colon.
+///
+/// Names in [LOCALIZED_NAMESPACES] are only recognized in their own `lang`, and return the canonical name.
+fn namespace(title: &str, lang: &str) -> Option<&'static str> {
+    let (prefix, _) = title.split_once(':')?;
+    let prefix = prefix.trim();
+    // Compared in place, as `_` and ` ` are the same in titles.
+    let matches = |ns: &str| {
+        ns.chars().flat_map(char::to_lowercase).eq(prefix
+            .chars()
+            .map(|c| if c == '_' { ' ' } else { c })
+            .flat_map(char::to_lowercase))
+    };
+    if let Some(ns) = NAMESPACES.iter().find(|ns| matches(ns)) {
+        return Some(ns);
+    }
+    let (_, localized) = LOCALIZED_NAMESPACES
+        .iter()
+        .find(|(l, _)| l.eq_ignore_ascii_case(lang))?;
+    localized
+        .iter()
+        .find(|(alias, _)| matches(alias))
+        .map(|(_, ns)| *ns)
+}
+
 #[derive(Debug, PartialEq, Eq, thiserror::Error)]
 pub enum ParseTitleError {
     #[error("value is empty or whitespace")]
@@ -157,8 +1362,14 @@ pub enum ParseTitleError {
     NoLang,
     #[error("lang contains character that is not alphabetic or '-'")]
     LangBadChar,
+    /// The lang has empty subtags, like `en-` or `en--us`, or subtags that are too long or short, see [Title::from_title].
+    #[error("lang is not shaped like a language code, e.g. 'en' or 'zh-hans'")]
+    LangMalformed,
     #[error("no ':' separating lang and title")]
     MissingColon,
+    /// The title is a page in a namespace that is not an article, e.g. `Category:Lighthouses`.
+    #[error("title is in the {0:?} namespace, not an article")]
+    Namespace(String),
 
     // url-specific
     #[error("cannot parse url")]
@@ -169,10 +1380,666 @@ pub enum ParseTitleError {
     NoHost,
     #[error("no subdomain in url")]
     NoSubdomain,
-    #[error("url base domain is not wikipedia.org")]
+    #[error("url has the mobile subdomain 'm' but no language subdomain")]
+    MobileNoLang,
+    #[error("url base domain is not wikipedia.org or wikivoyage.org")]
     BadDomain,
-    #[error("url base path is not /wiki/")]
+    #[error("url is for the Wikimedia Enterprise API, not a wikipedia article")]
+    EnterpriseApi,
+    #[error("url is for a wikimedia.org project, not a wikipedia article")]
+    WikimediaProject,
+    #[error("url base path is not /wiki/ or /w/index.php")]
     BadPath,
+    #[error("url of /w/index.php has no title parameter")]
+    NoTitleParam,
     #[error("path has less than 2 segments")]
     ShortPath,
+    /// The title of a url still has percent escapes after decoding it twice, see [Title::from_url].
+    #[error("url title is still percent-encoded after decoding it twice")]
+    AmbiguousEncoding,
+}
+
+/// The errors of each attempt of [Title::parse_any].
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub struct ParseAnyError {
+    /// The error of [Title::from_url].
+    pub url: ParseTitleError,
+    /// The error of [Title::from_osm_tag].
+    pub tag: ParseTitleError,
+    /// The error of [Title::from_title], if it was tried with a default lang.
+    pub title: Option<ParseTitleError>,
+}
+
+impl Display for ParseAnyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cannot parse as a url ({}), a tag ({}), or a title",
+            self.url, self.tag
+        )?;
+        match &self.title {
+            Some(e) => write!(f, " ({e})"),
+            None => write!(f, " (no default lang)"),
+        }
+    }
+}
+
+/// Check for a `%` followed by two hex digits, like those of percent-encoding.
+pub(crate) fn has_percent_escape(s: &str) -> bool {
+    s.as_bytes()
+        .windows(3)
+        .any(|w| w[0] == b'%' && w[1].is_ascii_hexdigit() && w[2].is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Sample of `wikipedia` tag values from OSM.
+    const TAGS: &[&str] = &[
+        "en:Spatial database",
+        "de:Breil/Brigels",
+        "de:Bahnstrecke Bassum–Herford",
+        "fr:Église Saint-Étienne de Caen",
+        "ru:Крымские горы",
+        "ko:서울특별시",
+        "zh-yue:香港",
+        "en:AC/DC",
+        "en: Thoor Ballylee ",
+        "en:https://en.wikipedia.org/wiki/Crimean_Mountains",
+        "https://de.m.wikipedia.org/wiki/K%C3%B6ln#Geschichte",
+    ];
+
+    #[test]
+    fn normalize_tag_to_path_matches_title() {
+        let base = Path::new("/base");
+        for layout in [Layout::Flat, Layout::Sharded] {
+            for tag in TAGS {
+                let title = Title::from_osm_tag(tag).unwrap();
+                let expected = layout.title_dir(base.to_path_buf(), &title);
+                let path = normalize_tag_to_path(tag, base, layout).unwrap();
+                assert_eq!(expected, path, "{tag:?} {layout:?}");
+
+                let tag = path_to_tag(&path, base, layout).unwrap();
+                assert_eq!(
+                    title,
+                    Title::from_osm_tag(&tag).unwrap(),
+                    "{tag:?} {layout:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stray_slashes() {
+        let title = |t| Title::from_title(t, "de").unwrap();
+        assert_eq!(title("Foo"), title("Foo/"));
+        assert_eq!(title("Foo/Bar"), title("/Foo/Bar/"));
+        assert_eq!(title("Foo"), title(" Foo / "));
+        assert_eq!("Breil/Brigels", title("Breil/Brigels").name);
+        assert_eq!(
+            title("Foo"),
+            Title::from_url("https://de.wikipedia.org/wiki/Foo/").unwrap()
+        );
+        assert_eq!(
+            Path::new("/base/de.wikipedia.org/wiki/Foo/Bar"),
+            title("Foo/Bar/").get_dir("/base".into())
+        );
+
+        assert_eq!(Err(ParseTitleError::NoTitle), Title::from_title("/", "de"));
+        assert_eq!(
+            Err(ParseTitleError::NoTitle),
+            Title::from_osm_tag("de: // ")
+        );
+    }
+
+    #[test]
+    fn path_to_tag_rejects_other_paths() {
+        let base = Path::new("/base");
+        for path in [
+            "/base/wikidata/Q12345",
+            "/base/en.wikipedia.org/wiki",
+            "/base/en.wikipedia.org/w/Article",
+            "/other/en.wikipedia.org/wiki/Article",
+        ] {
+            assert_eq!(
+                None,
+                path_to_tag(Path::new(path), base, Layout::Flat),
+                "{path}"
+            );
+        }
+        assert_eq!(
+            None,
+            path_to_tag(
+                Path::new("/base/en.wikipedia.org/wiki/Article"),
+                base,
+                Layout::Sharded
+            )
+        );
+    }
+
+    #[test]
+    fn wikimedia_hosts() {
+        use ParseTitleError::*;
+        for (url, expected) in [
+            (
+                "https://api.enterprise.wikimedia.com/v2/articles/Berlin",
+                EnterpriseApi,
+            ),
+            ("https://enterprise.wikimedia.com/docs/", EnterpriseApi),
+            (
+                "https://auth.enterprise.wikimedia.com/v1/login",
+                EnterpriseApi,
+            ),
+            (
+                "https://commons.wikimedia.org/wiki/File:Berlin.jpg",
+                WikimediaProject,
+            ),
+            ("https://wikimedia.org/", WikimediaProject),
+            ("https://www.notwikimedia.org/wiki/Berlin", BadDomain),
+        ] {
+            assert_eq!(Err(expected), Title::from_url(url), "{url}");
+        }
+        // Tags with enterprise urls are rejected the same way.
+        assert_eq!(
+            Err(EnterpriseApi),
+            Title::from_osm_tag("en:https://api.enterprise.wikimedia.com/v2/articles/Berlin")
+        );
+    }
+
+    #[test]
+    fn encoded_spaces() {
+        let expected = Title::from_title("New York", "en").unwrap();
+        for url in [
+            "https://en.wikipedia.org/wiki/New_York",
+            "https://en.wikipedia.org/wiki/New%20York",
+            "https://en.wikipedia.org/wiki/New York",
+            "https://en.wikipedia.org/wiki/New%5FYork",
+            "https://en.m.wikipedia.org/wiki/New%20York",
+        ] {
+            assert_eq!(Ok(&expected), Title::from_url(url).as_ref(), "{url}");
+        }
+        assert_eq!("en:New_York", expected.to_string());
+
+        // Literal underscores are kept next to decoded spaces.
+        assert_eq!(
+            Title::from_url("https://en.wikipedia.org/wiki/A__B").unwrap(),
+            Title::from_url("https://en.wikipedia.org/wiki/A%20_B").unwrap()
+        );
+    }
+
+    #[test]
+    fn title_length() {
+        assert_eq!(
+            Err(ParseTitleError::TitleLong),
+            Title::from_title(&"a".repeat(300), "en")
+        );
+        assert!(Title::from_title(&"a".repeat(255), "en").is_ok());
+        // The limit is in bytes, so fewer multibyte characters reach it.
+        assert_eq!(
+            Err(ParseTitleError::TitleLong),
+            Title::from_title(&"ö".repeat(128), "de")
+        );
+        assert!(Title::from_title(&"ö".repeat(127), "de").is_ok());
+    }
+
+    #[test]
+    fn wrapped_tags() {
+        let expected = Title::from_osm_tag("en:Berlin").unwrap();
+        for tag in [
+            "<en:Berlin>",
+            "\"en:Berlin\"",
+            "'en:Berlin'",
+            "“en:Berlin”",
+            "„de:Köln“",
+            " < en:Berlin > ",
+            "\"<en:Berlin>\"",
+            "<https://en.wikipedia.org/wiki/Berlin>",
+        ] {
+            let expected = match tag {
+                "„de:Köln“" => Title::from_osm_tag("de:Köln").unwrap(),
+                _ => expected.clone(),
+            };
+            assert_eq!(Ok(expected), Title::from_osm_tag(tag), "{tag}");
+        }
+
+        // Quotes that can be part of the title are kept.
+        assert_eq!(
+            "en:\"Heroes\"",
+            Title::from_osm_tag("en:\"Heroes\"").unwrap().to_string()
+        );
+        let options = ParseOptions {
+            default_lang: Some("en".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "en:\"Heroes\"",
+            Title::from_osm_tag_with("\"Heroes\"", &options)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "nl:'s-Hertogenbosch'",
+            Title::from_osm_tag("nl:'s-Hertogenbosch'")
+                .unwrap()
+                .to_string()
+        );
+        // Unbalanced wrapping is not removed.
+        assert!(Title::from_osm_tag("\"en:Berlin").is_err());
+        assert!(Title::from_osm_tag("<>").is_err());
+    }
+
+    #[test]
+    fn malformed_langs() {
+        for lang in ["en-", "-us", "en--us", "-", "e", "toolongcode-x"] {
+            assert_eq!(
+                Err(ParseTitleError::LangMalformed),
+                Title::from_title("Foo", lang),
+                "{lang}"
+            );
+        }
+        assert_eq!(
+            Err(ParseTitleError::LangMalformed),
+            Title::from_osm_tag("en-:Foo")
+        );
+        for lang in [
+            "zh-hans",
+            "en",
+            "simple",
+            "be-x-old",
+            "zh-min-nan",
+            "zh-classical",
+        ] {
+            assert!(Title::from_title("Foo", lang).is_ok(), "{lang}");
+        }
+    }
+
+    #[test]
+    fn mobile_without_lang() {
+        for url in [
+            "https://m.wikipedia.org/wiki/Foo",
+            "https://m.wikivoyage.org/wiki/Foo",
+            "http://m.wikipedia.org/wiki/Foo",
+        ] {
+            assert_eq!(
+                Err(ParseTitleError::MobileNoLang),
+                Title::from_url(url),
+                "{url}"
+            );
+        }
+        assert_eq!(
+            Err(ParseTitleError::MobileNoLang),
+            Title::from_osm_tag("https://m.wikipedia.org/wiki/Foo")
+        );
+        assert_eq!(
+            Title::from_title("Foo", "en").unwrap(),
+            Title::from_url("https://en.m.wikipedia.org/wiki/Foo").unwrap()
+        );
+    }
+
+    #[test]
+    fn percent_signs() {
+        let expected = Title::from_title("100% Pure", "en").unwrap();
+        for url in [
+            "https://en.wikipedia.org/wiki/100%25_Pure",
+            "https://en.wikipedia.org/wiki/100%_Pure",
+            "https://en.wikipedia.org/wiki/100%2525_Pure",
+        ] {
+            let title = Title::from_url(url).unwrap();
+            assert_eq!(expected, title, "{url}");
+            assert_eq!("100%_Pure", title.original_name(), "{url}");
+        }
+        assert_eq!(
+            Title::from_title("25%", "en").unwrap(),
+            Title::from_url("https://en.wikipedia.org/wiki/25%25").unwrap()
+        );
+
+        // Encoded twice.
+        assert_eq!(
+            Title::from_title("Köln", "de").unwrap(),
+            Title::from_url("https://de.wikipedia.org/wiki/K%25C3%25B6ln").unwrap()
+        );
+        for url in [
+            // Encoded three times.
+            "https://de.wikipedia.org/wiki/K%252525C3%252525B6ln",
+            // The second decoding isn't UTF-8.
+            "https://de.wikipedia.org/wiki/K%25F6ln",
+        ] {
+            assert_eq!(
+                Err(ParseTitleError::AmbiguousEncoding),
+                Title::from_url(url),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn encoded_fragment() {
+        let expected = Title::from_title("Berlin", "en").unwrap();
+        for url in [
+            "https://en.wikipedia.org/wiki/Berlin%23History",
+            "https://en.wikipedia.org/wiki/Berlin%23",
+            "https://en.wikipedia.org/wiki/Berlin%23History#Culture",
+        ] {
+            let title = Title::from_url(url).unwrap();
+            assert_eq!(expected, title, "{url}");
+            assert_eq!("Berlin", title.original_name(), "{url}");
+        }
+        assert_eq!(
+            Err(ParseTitleError::NoTitle),
+            Title::from_url("https://en.wikipedia.org/wiki/%23History")
+        );
+    }
+
+    #[test]
+    fn verbatim_titles() {
+        let normalized = Title::from_title(" Article Title ", "EN").unwrap();
+        let verbatim = Title::from_title_verbatim(" Article Title ", "EN").unwrap();
+        assert_eq!("Article_Title", normalized.name);
+        assert_eq!("Article Title", verbatim.name);
+        assert_eq!("en", verbatim.lang());
+        assert_eq!(normalized.original_name(), verbatim.original_name());
+        assert_ne!(normalized, verbatim);
+        assert_eq!(
+            verbatim,
+            Title::from_title_verbatim("Article Title", "en").unwrap()
+        );
+
+        // Without spaces, only the stray slashes differ.
+        assert_eq!(
+            Title::from_title("Breil/Brigels", "de").unwrap(),
+            Title::from_title_verbatim("Breil/Brigels", "de").unwrap()
+        );
+        assert_eq!(
+            "Foo/",
+            Title::from_title_verbatim("Foo/", "de").unwrap().name
+        );
+
+        // Titles are still validated.
+        assert_eq!(
+            Err(ParseTitleError::NoTitle),
+            Title::from_title_verbatim("  ", "en")
+        );
+        assert_eq!(
+            Err(ParseTitleError::Namespace("Category".into())),
+            Title::from_title_verbatim("Category:Lighthouses", "en")
+        );
+    }
+
+    #[test]
+    fn namespaces() {
+        for (tag, namespace) in [
+            ("en:Category:Lighthouses in Denmark", "Category"),
+            ("en:category:Lighthouses_in_Denmark", "Category"),
+            ("de:File:Photo.jpg", "File"),
+            ("en:Template talk:Infobox", "Template talk"),
+            ("en:User_talk:Example", "User talk"),
+            ("https://en.wikipedia.org/wiki/Special:Random", "Special"),
+            ("Template:Infobox", "Template"),
+            ("Category:Rivers_of_France", "Category"),
+            ("fr:Fichier:Photo.jpg", "File"),
+            ("de:kategorie:Leuchtturm in Europa", "Category"),
+            ("ru:категория:Маяки", "Category"),
+            (
+                "https://es.wikipedia.org/wiki/Categor%C3%ADa:Faros",
+                "Category",
+            ),
+        ] {
+            assert_eq!(
+                Err(ParseTitleError::Namespace(namespace.to_owned())),
+                Title::from_osm_tag(tag),
+                "{tag}"
+            );
+        }
+        for tag in [
+            "en:Star Wars: Episode IV",
+            "en:Dungeons_&_Dragons:_Honor_Among_Thieves",
+            "en:Spain: A History",
+            "en:Categories: A Novel",
+            // Localized names are only namespaces in their own language.
+            "en:Fichier:Photo.jpg",
+        ] {
+            assert!(Title::from_osm_tag(tag).is_ok(), "{tag}");
+        }
+        // The language is checked first, as it doesn't allocate.
+        assert_eq!(
+            Err(ParseTitleError::LangBadChar),
+            Title::from_osm_tag("en_US:Category:Lighthouses")
+        );
+    }
+
+    #[test]
+    fn url_and_tag_round_trip() {
+        let tags = TAGS.iter().copied().chain([
+            "en:100% Pure",
+            "en:Ben & Jerry's",
+            "en:C++",
+            "en:Who? (album)",
+            "voy:de:Köln",
+        ]);
+        for tag in tags {
+            let title = Title::from_osm_tag(tag).unwrap();
+            assert_eq!(title, Title::from_url(&title.to_url()).unwrap(), "{tag}");
+            assert_eq!(
+                title,
+                Title::from_osm_tag(&title.to_osm_tag()).unwrap(),
+                "{tag}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_any() {
+        let cologne = Title::from_title("Köln", "de").unwrap();
+        for value in [
+            "https://de.wikipedia.org/wiki/K%C3%B6ln",
+            "de:Köln",
+            " Köln ",
+        ] {
+            assert_eq!(
+                Ok(&cologne),
+                Title::parse_any(value, Some("de")).as_ref(),
+                "{value}"
+            );
+        }
+
+        let e = Title::parse_any("Category:Köln", Some("de")).unwrap_err();
+        assert_eq!(
+            ParseAnyError {
+                // Parsed as a url with the scheme `category`.
+                url: ParseTitleError::NoHost,
+                tag: ParseTitleError::Namespace("Category".into()),
+                title: Some(ParseTitleError::Namespace("Category".into())),
+            },
+            e
+        );
+        assert_eq!(
+            "cannot parse as a url (no host in url), a tag (title is in the \"Category\" namespace, not an article), or a title (title is in the \"Category\" namespace, not an article)",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn percent_escapes() {
+        let cafe = Title::from_title("Café", "en").unwrap();
+        for tag in [
+            "en:Caf%C3%A9",
+            "en:Caf%c3%a9",
+            "https://en.wikipedia.org/wiki/Caf%C3%A9",
+        ] {
+            assert_eq!(cafe, Title::from_osm_tag(tag).unwrap(), "{tag}");
+        }
+        // Escapes that can't be decoded are part of the name.
+        for (tag, name) in [
+            ("en:Caf%FF", "Caf%FF"),
+            ("en:100%", "100%"),
+            ("en:50%_off", "50%_off"),
+        ] {
+            assert_eq!(name, Title::from_osm_tag(tag).unwrap().name, "{tag}");
+        }
+        assert_eq!(
+            "Caf%C3%A9",
+            Title::from_title_verbatim("Caf%C3%A9", "en").unwrap().name
+        );
+
+        // The lang is checked before decoding, and the length after.
+        assert_eq!(
+            Err(ParseTitleError::LangBadChar),
+            Title::from_osm_tag("en_US:Caf%C3%A9")
+        );
+        let escaped = "%C3%A9".repeat(50);
+        assert!(escaped.len() >= 256);
+        assert_eq!(
+            format!("É{}", "é".repeat(49)),
+            Title::from_title(&escaped, "fr").unwrap().name
+        );
+        assert_eq!(
+            Err(ParseTitleError::Namespace("Category".into())),
+            Title::from_osm_tag("en:Category%3ALighthouses")
+        );
+    }
+
+    #[test]
+    fn permalinks() {
+        let berlin = Title::from_osm_tag("en:Berlin").unwrap();
+        for url in [
+            "https://en.wikipedia.org/w/index.php?title=Berlin&oldid=123456",
+            "https://en.m.wikipedia.org/w/index.php?oldid=123456&title=Berlin#History",
+        ] {
+            let title = Title::from_url(url).unwrap();
+            assert_eq!((&berlin, Some(123456)), (&title, title.oldid()), "{url}");
+        }
+
+        let title =
+            Title::from_url("https://de.wikipedia.org/w/index.php?title=K%C3%B6ln+Hbf").unwrap();
+        assert_eq!(Title::from_osm_tag("de:Köln Hbf").unwrap(), title);
+        assert_eq!(None, title.oldid());
+
+        assert_eq!(
+            Err(ParseTitleError::NoTitleParam),
+            Title::from_url("https://en.wikipedia.org/w/index.php?oldid=123456")
+        );
+        assert_eq!(
+            Err(ParseTitleError::BadPath),
+            Title::from_url("https://en.wikipedia.org/w/api.php?title=Berlin")
+        );
+    }
+
+    #[test]
+    fn invisible_characters() {
+        let title = Title::from_osm_tag("de:Köln").unwrap();
+        for tag in [
+            "de:Kö\u{200B}ln",
+            "\u{A0}de:Köln\u{A0}",
+            "\u{FEFF}de:\u{200B}Köln",
+            "\u{200F}https://de.wikipedia.org/wiki/K%C3%B6ln\u{200F}",
+            "\u{202B}https://de.wikipedia.org/wiki/K%C3%B6ln\u{202C}",
+            "https://de.wikipedia.org/wiki/K%E2%80%8E%C3%B6ln",
+        ] {
+            assert_eq!(Ok(&title), Title::from_osm_tag(tag).as_ref(), "{tag:?}");
+        }
+        assert_eq!(
+            Ok(title),
+            Title::from_title("\u{200E}Köln\u{200E}", "\u{200B}de")
+        );
+
+        // Values of only invisible characters are empty.
+        assert_eq!(
+            Err(ParseTitleError::Empty),
+            Title::from_osm_tag("\u{200B}\u{A0}\u{200F}")
+        );
+        assert_eq!(Err(ParseTitleError::Empty), Title::from_url("\u{FEFF}"));
+        assert_eq!(
+            Err(ParseTitleError::NoTitle),
+            Title::from_title("\u{200B}", "en")
+        );
+    }
+
+    #[test]
+    fn html_entities() {
+        let options = ParseOptions {
+            html_entities: true,
+            ..Default::default()
+        };
+        let expected = Title::from_osm_tag("en:Ben & Jerry's").unwrap();
+        for tag in [
+            "en:Ben &amp; Jerry's",
+            "en:Ben &amp; Jerry&#39;s",
+            "en:Ben &#38; Jerry&#X27;s",
+        ] {
+            assert_eq!(
+                Ok(&expected),
+                Title::from_osm_tag_with(tag, &options).as_ref(),
+                "{tag}"
+            );
+        }
+        assert_eq!("Ben & Jerry's", expected.original_name());
+
+        assert_eq!("a&b", decode_entities("a&b"));
+        assert_eq!("AT&T", decode_entities("AT&T"));
+        assert_eq!("&nosuchentity;", decode_entities("&nosuchentity;"));
+        assert_eq!("&#xZZ;", decode_entities("&#xZZ;"));
+        assert_eq!("&amp", decode_entities("&amp"));
+        assert_eq!("&amp;", decode_entities("&amp;amp;"));
+        assert_eq!("Å", decode_entities("&angst;"));
+        assert_eq!("\u{2242}\u{338}", decode_entities("&nesim;"));
+        assert!(matches!(decode_entities("no entities"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn trailing_lang() {
+        let options = ParseOptions {
+            trailing_lang: true,
+            ..Default::default()
+        };
+        let parse = |tag| Title::from_osm_tag_with(tag, &options).map(|t| t.to_string());
+
+        assert_eq!(Ok("de:Berlin".into()), parse("Berlin (de)"));
+        assert_eq!(Ok("zh-hans:北京".into()), parse("北京 (zh-hans)"));
+        // An explicit lang is not overridden.
+        assert_eq!(Ok("de:Berlin_(en)".into()), parse("de:Berlin (en)"));
+        assert_eq!(Ok("en:Ur_(ur)".into()), parse("en:Ur (ur)"));
+        assert_eq!(
+            Ok("en:Ur_(ur)".into()),
+            parse("https://en.wikipedia.org/wiki/Ur_(ur)")
+        );
+        // Without a prefix the disambiguator can't be told apart from a lang.
+        assert_eq!(Ok("ur:Ur".into()), parse("Ur (ur)"));
+        assert_eq!(Err(ParseTitleError::MissingColon), parse("Ur (city)"));
+    }
+
+    #[test]
+    fn option_profiles() {
+        assert_eq!(
+            "ParseOptions { trailing_lang: false, interwiki_prefix: false, default_lang: None, ignore_case: false, verbatim: false, html_entities: false }",
+            format!("{:?}", ParseOptions::strict())
+        );
+        assert_eq!(
+            "ParseOptions { trailing_lang: true, interwiki_prefix: true, default_lang: None, ignore_case: false, verbatim: false, html_entities: true }",
+            format!("{:?}", ParseOptions::osm_cleanup())
+        );
+        assert_eq!(
+            "ParseOptions { trailing_lang: false, interwiki_prefix: true, default_lang: None, ignore_case: false, verbatim: false, html_entities: false }",
+            format!("{:?}", ParseOptions::production_extract())
+        );
+
+        let tag = "w:en:Ben &amp; Jerry's";
+        // `w` is read as the language, which is too short to be one.
+        assert_eq!(
+            Err(ParseTitleError::LangMalformed),
+            Title::from_osm_tag_with(tag, &ParseOptions::strict())
+        );
+        assert_eq!(
+            "en:Ben_&_Jerry's",
+            Title::from_osm_tag_with(tag, &ParseOptions::osm_cleanup())
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "en:Ben_&amp;_Jerry's",
+            Title::from_osm_tag_with(tag, &ParseOptions::production_extract())
+                .unwrap()
+                .to_string()
+        );
+    }
 }
@@ -0,0 +1,138 @@
+//! Tests of the coverage of OSM tags by the articles extracted for them.
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufReader},
+    sync::atomic::AtomicBool,
+};
+
+use om_wikiparser::{
+    extract::{self, Matcher, Options},
+    parse_osm_tag_file,
+    wm::{
+        audit::{self, BrokenReason, CoverageOptions},
+        output::Manifest,
+    },
+};
+
+mod common;
+use common::{data_path, test_dir};
+
+#[test]
+fn tags_to_articles() {
+    let dir = test_dir("tags_to_articles");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    let (mut qids, mut titles, mut errors) = (HashSet::new(), HashSet::new(), Vec::new());
+    parse_osm_tag_file(
+        File::open(data_path("tags.tsv")).unwrap(),
+        &mut qids,
+        &mut titles,
+        &mut errors,
+    )
+    .unwrap();
+    assert_eq!(2, errors.len());
+
+    let dump = ["dumps/en.ndjson", "dumps/de.ndjson"]
+        .iter()
+        .map(|name| fs::read(data_path(name)).unwrap())
+        .collect::<Vec<_>>()
+        .concat();
+    let stats = extract::run(
+        dump.as_slice(),
+        io::sink(),
+        &Matcher {
+            qids,
+            titles,
+            ..Default::default()
+        },
+        &Options {
+            output_dir: Some(output_dir.clone()),
+            write_manifest: true,
+            write_audit: Some(dir.join("audit.jsonl")),
+            skip_stubs: true,
+            ..Default::default()
+        },
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+    assert_eq!(1, stats.stubs);
+
+    let records = audit::read_tag_records(File::open(data_path("tags.tsv")).unwrap()).unwrap();
+    assert_eq!(10, records.len());
+    let opts = CoverageOptions {
+        output_dir: output_dir.clone(),
+        skips: audit::read_skips(BufReader::new(File::open(dir.join("audit.jsonl")).unwrap()))
+            .unwrap(),
+        ..Default::default()
+    };
+    let manifest = Manifest::read(&output_dir).unwrap();
+    let report = audit::coverage(&records, &manifest, &stats, &opts);
+
+    assert_eq!(60.0, report.percent());
+    assert_eq!(
+        (5, 3),
+        (report.by_lang["en"].records, report.by_lang["en"].covered)
+    );
+    // Records without a title that can be parsed.
+    assert_eq!(
+        (4, 2),
+        (report.by_lang[""].records, report.by_lang[""].covered)
+    );
+    assert_eq!(
+        (1, 1),
+        (report.by_lang["de"].records, report.by_lang["de"].covered)
+    );
+    assert_eq!(
+        vec![
+            (6, BrokenReason::Pruned),
+            (7, BrokenReason::Unmatched),
+            (8, BrokenReason::Unparsed),
+            (9, BrokenReason::Unparsed),
+        ],
+        report
+            .broken
+            .iter()
+            .map(|chain| (chain.record.line - 1, chain.reason))
+            .collect::<Vec<_>>()
+    );
+
+    let mut tsv = Vec::new();
+    report.write_tsv(&mut tsv).unwrap();
+    let tsv = String::from_utf8(tsv).unwrap();
+    assert_eq!(5, tsv.lines().count());
+    assert!(
+        tsv.contains("node/6\tQ4973715\ten:Brockenhurst Halt\tpruned\tstub"),
+        "{tsv}"
+    );
+
+    let mut json = Vec::new();
+    report.write_json(&mut json).unwrap();
+    let read: audit::CoverageReport = serde_json::from_slice(&json).unwrap();
+    assert_eq!(report, read);
+
+    // Koeln and Q365 both lead to the same file.
+    fs::remove_file(output_dir.join("wikidata/Q365/de.html")).unwrap();
+    let report = audit::coverage(&records, &manifest, &stats, &opts);
+    assert_eq!(40.0, report.percent());
+    assert_eq!(
+        2,
+        report
+            .broken
+            .iter()
+            .filter(|chain| chain.reason == BrokenReason::MissingFile)
+            .count()
+    );
+
+    let sampled = audit::coverage(
+        &records,
+        &manifest,
+        &stats,
+        &CoverageOptions {
+            sample: Some(5),
+            ..opts
+        },
+    );
+    assert_eq!(5, sampled.total.records);
+}
@@ -0,0 +1,58 @@
+//! Helpers shared by the integration tests.
+// Each test crate only uses some of them.
+#![allow(dead_code)]
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
+};
+
+use om_wikiparser::extract::{self, DumpStats, Matcher, Options};
+
+/// Create an empty directory for a single test's files, within a directory of the test crate.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(env!("CARGO_CRATE_NAME"))
+        .join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Path of a file in `tests/data`.
+pub fn data_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+/// Path of a fixture dump in `tests/data/dumps`.
+pub fn dump_path(name: &str) -> PathBuf {
+    data_path("dumps").join(name)
+}
+
+pub fn dump(name: &str) -> File {
+    File::open(dump_path(name)).unwrap()
+}
+
+/// Run [extract::run] over the concatenated fixture `dumps`, without passthrough or cancelling.
+pub fn run_fixture(
+    dumps: &[&str],
+    matcher: &Matcher,
+    options: &Options,
+) -> anyhow::Result<DumpStats> {
+    let mut input: Box<dyn Read> = Box::new(io::empty());
+    for name in dumps {
+        input = Box::new(input.chain(dump(name)));
+    }
+    extract::run(
+        BufReader::new(input),
+        io::sink(),
+        matcher,
+        options,
+        &AtomicBool::new(false),
+    )
+}
@@ -0,0 +1,84 @@
+//! Tests of `download.sh` with a stub `wget` that doesn't touch the network.
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::{Command, Output},
+};
+
+mod common;
+use common::test_dir;
+
+/// Run `download.sh` with a `wget` on the `PATH` that logs its arguments to `wget.log` in `dir` and then runs `body`.
+fn download(dir: &Path, user_agent: Option<&str>, body: &str) -> Output {
+    let bin = dir.join("bin");
+    fs::create_dir(&bin).unwrap();
+    let wget = bin.join("wget");
+    fs::write(
+        &wget,
+        format!(
+            "#!/usr/bin/env bash\nprintf '%s\\n' \"$@\" >> {:?}\n{body}\n",
+            dir.join("wget.log")
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&wget, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let dumps = dir.join("dumps");
+    fs::create_dir(&dumps).unwrap();
+    let mut command = Command::new("bash");
+    command
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("download.sh"))
+        .arg(&dumps)
+        .env(
+            "PATH",
+            format!("{}:{}", bin.display(), std::env::var("PATH").unwrap()),
+        )
+        .env("LANGUAGES", "en")
+        .env_remove("MIRROR")
+        .env_remove("USER_AGENT");
+    if let Some(user_agent) = user_agent {
+        command.env("USER_AGENT", user_agent);
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn user_agent_is_required() {
+    let dir = test_dir("user_agent_is_required");
+    let output = download(&dir, None, "exit 0");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(Some(1), output.status.code(), "{stderr}");
+    assert!(stderr.contains("USER_AGENT is required"), "{stderr}");
+    assert!(!dir.join("wget.log").exists());
+}
+
+#[test]
+fn forbidden_request() {
+    let dir = test_dir("forbidden_request");
+    let output = download(
+        &dir,
+        Some("TestBot/1.0 (test@example.org)"),
+        "echo 'https://dumps.wikimedia.org/other/enterprise_html/runs/:\nERROR 403: Forbidden.' >&2\nexit 8",
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(Some(17), output.status.code(), "{stderr}");
+    assert!(stderr.contains("was forbidden"), "{stderr}");
+    assert!(
+        stderr.contains("USER_AGENT 'TestBot/1.0 (test@example.org)'"),
+        "{stderr}"
+    );
+
+    // The only request is sent with the User-Agent and retry options.
+    let args = fs::read_to_string(dir.join("wget.log")).unwrap();
+    let args: Vec<&str> = args.lines().collect();
+    assert_eq!(1, args.iter().filter(|&&a| a == "--user-agent").count());
+    for (option, value) in [
+        ("--user-agent", "TestBot/1.0 (test@example.org) wget"),
+        ("--tries", "3"),
+        ("--wait", "1"),
+    ] {
+        let i = args.iter().position(|&a| a == option).unwrap();
+        assert_eq!(value, args[i + 1], "{option}");
+    }
+}
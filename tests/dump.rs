@@ -0,0 +1,149 @@
+//! Tests of indexing the fixture dumps and reading single pages from them.
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use om_wikiparser::wm::dump::{find_entry, get_page, write_index, IndexKey};
+
+mod common;
+use common::{dump_path, test_dir};
+
+fn index(dir: &Path, dump: &Path) -> PathBuf {
+    let index = dir.join("index.tsv");
+    let (pages, skipped) = write_index(
+        BufReader::new(File::open(dump).unwrap()),
+        File::create(&index).unwrap(),
+        1 << 20,
+    )
+    .unwrap();
+    assert_eq!(4, pages);
+    assert_eq!(0, skipped);
+    index
+}
+
+fn key(s: &str) -> IndexKey {
+    s.parse().unwrap()
+}
+
+#[test]
+fn get_pages() {
+    let dir = test_dir("get_pages");
+    let dump = dump_path("en.ndjson");
+    let index = index(&dir, &dump);
+
+    let page = get_page(&dump, &index, &key("en:Spatial database"))
+        .unwrap()
+        .unwrap();
+    assert_eq!("Spatial database", page.name);
+
+    let page = get_page(&dump, &index, &key("Q64")).unwrap().unwrap();
+    assert_eq!("Berlin", page.name);
+    assert!(page.article_body.html.contains("capital"));
+
+    let page = get_page(&dump, &index, &key("https://en.wikipedia.org/wiki/Berlin"))
+        .unwrap()
+        .unwrap();
+    assert_eq!("Berlin", page.name);
+
+    assert!(get_page(&dump, &index, &key("en:Nowhere"))
+        .unwrap()
+        .is_none());
+    assert!(get_page(&dump, &index, &key("Q1")).unwrap().is_none());
+}
+
+/// Reader that counts the bytes read from it.
+struct Counted<R> {
+    inner: R,
+    read: usize,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Counted<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn binary_search() {
+    let page = |i: usize| {
+        format!(
+            r#"{{"name": "Page {i}", "identifier": {i}, "date_modified": "", "in_language": {{"identifier": "en"}}, "main_entity": {{"identifier": "Q{}"}}, "article_body": {{"html": ""}}}}"#,
+            i + 1
+        )
+    };
+    let mut dump = String::new();
+    for i in 0..2000 {
+        dump.push_str(&page(i));
+        dump.push('\n');
+    }
+    // Too long to index.
+    dump.push_str(&format!(
+        "{}\n",
+        page(2000).replace(
+            r#""html": """#,
+            &format!(r#""html": "{}""#, "x".repeat(1 << 20))
+        )
+    ));
+
+    let mut index = Vec::new();
+    assert_eq!(
+        (2000, 1),
+        write_index(dump.as_bytes(), &mut index, 1 << 20).unwrap()
+    );
+
+    let find = |key: &str| {
+        let mut reader = BufReader::with_capacity(
+            256,
+            Counted {
+                inner: Cursor::new(&index),
+                read: 0,
+            },
+        );
+        let entry = find_entry(&mut reader, &key.parse().unwrap()).unwrap();
+        // Only a few rows are read, not the whole index.
+        let read = reader.get_ref().read;
+        assert!(
+            read < index.len() / 20,
+            "read {read} of {} bytes",
+            index.len()
+        );
+        entry.map(|entry| entry.id.unwrap())
+    };
+    for i in [0, 1, 2, 10, 999, 1000, 1999] {
+        assert_eq!(Some(i as u64), find(&format!("en:Page {i}")), "en:Page {i}");
+        assert_eq!(Some(i as u64), find(&format!("Q{}", i + 1)), "Q{}", i + 1);
+    }
+    assert_eq!(None, find("en:Page 2000"));
+    assert_eq!(None, find("Q2001"));
+    assert_eq!(None, find("en:Page"));
+    assert_eq!(None, find("en:Zzz"));
+    assert_eq!(None, find("de:Page 1"));
+}
+
+#[test]
+fn changed_dump() {
+    let dir = test_dir("changed_dump");
+    let dump = dir.join("en.ndjson");
+    fs::copy(dump_path("en.ndjson"), &dump).unwrap();
+    let index = index(&dir, &dump);
+
+    let mut contents = fs::read(&dump).unwrap();
+    let first_line = contents.iter().position(|&b| b == b'\n').unwrap() + 1;
+    contents.drain(..first_line);
+    fs::write(&dump, contents).unwrap();
+
+    let Err(e) = get_page(&dump, &index, &key("Q64")) else {
+        panic!("read a page from the changed dump");
+    };
+    assert!(format!("{e:#}").contains(&format!("{:?}", dump)), "{e:#}");
+}
@@ -0,0 +1,2080 @@
+//! Tests that run the extraction pipeline as a library over small fixture dumps.
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use om_wikiparser::{
+    extract::{
+        self, Article, BudgetOrder, Degradation, DuplicatePolicy, LinkDirection, Matcher, Options,
+        PageWriter,
+    },
+    wm::{
+        output::{MatchedBy, MANIFEST_FILE},
+        LanguageMap, Qid, Title,
+    },
+};
+
+mod common;
+use common::{dump, dump_path, run_fixture, test_dir};
+
+/// Reader that sets the token when it is reached, without producing any data.
+struct Cancel<'a>(&'a AtomicBool);
+
+impl Read for Cancel<'_> {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        self.0.store(true, Ordering::Relaxed);
+        Ok(0)
+    }
+}
+
+#[test]
+fn cancel_stops_processing() {
+    let dir = test_dir("cancel_stops_processing");
+    let qids: HashSet<_> = ["Q64", "Q365", "Q1116061"]
+        .into_iter()
+        .map(|q| Qid::from_str(q).unwrap())
+        .collect();
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_manifest: true,
+        ..Default::default()
+    };
+
+    // Cancel once the english dump has been read, before the german one.
+    let cancel = AtomicBool::new(false);
+    let input = BufReader::new(
+        dump("en.ndjson")
+            .chain(Cancel(&cancel))
+            .chain(dump("de.ndjson")),
+    );
+    let stats = extract::run(
+        input,
+        io::sink(),
+        &Matcher {
+            qids,
+            ..Default::default()
+        },
+        &options,
+        &cancel,
+    )
+    .unwrap();
+
+    assert!(stats.cancelled);
+    assert_eq!(4, stats.pages);
+    assert_eq!(2, stats.written);
+    assert_eq!(1, stats.missing_qids);
+    assert_eq!(vec!["en"], stats.langs.keys().collect::<Vec<_>>());
+
+    assert!(dir.join("wikidata/Q64/en.html").is_file());
+    assert!(!dir.join("wikidata/Q64/de.html").exists());
+    let manifest = om_wikiparser::wm::output::Manifest::read(&dir).unwrap();
+    assert_eq!(2, manifest.entries.len());
+}
+
+/// Records the written articles instead of storing them.
+struct RecordingWriter<'a> {
+    written: &'a mut Vec<(String, String, MatchedBy, Vec<String>)>,
+    finished: &'a mut bool,
+}
+
+impl PageWriter for RecordingWriter<'_> {
+    fn write(&mut self, article: &Article) -> anyhow::Result<()> {
+        assert!(!*self.finished);
+        assert!(!article.html.is_empty());
+        self.written.push((
+            article.page.in_language.identifier.clone(),
+            article.page.name.clone(),
+            article.matched_by,
+            article.requesters.clone(),
+        ));
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        *self.finished = true;
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_writer() {
+    let mut written = Vec::new();
+    let mut finished = false;
+    let matcher = Matcher {
+        qids: [Qid::from_str("Q64").unwrap()].into(),
+        titles: [Title::from_osm_tag("en:Spatial database").unwrap()].into(),
+        ..Default::default()
+    };
+
+    let input = BufReader::new(dump("en.ndjson").chain(dump("de.ndjson")));
+    let stats = extract::run_with(
+        input,
+        io::sink(),
+        &matcher,
+        &Options::default(),
+        RecordingWriter {
+            written: &mut written,
+            finished: &mut finished,
+        },
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+
+    assert!(finished);
+    assert_eq!(4, stats.written);
+    let s = |s: &str| s.to_owned();
+    assert_eq!(
+        vec![
+            (s("en"), s("Berlin"), MatchedBy::Qid, vec![s("Q64")]),
+            (
+                s("en"),
+                s("Spatial database"),
+                MatchedBy::Title,
+                vec![s("en:Spatial_database")]
+            ),
+            (s("de"), s("Berlin"), MatchedBy::Qid, vec![s("Q64")]),
+            (
+                s("de"),
+                s("Geodatenbank"),
+                MatchedBy::Expanded,
+                vec![s("en:Spatial_database")]
+            ),
+        ],
+        written
+    );
+}
+
+#[test]
+fn match_policies() {
+    use extract::MatchPolicy::*;
+
+    let matcher = Matcher {
+        qids: ["Q64", "Q999999"]
+            .into_iter()
+            .map(|q| Qid::from_str(q).unwrap())
+            .collect(),
+        titles: ["en:Berlin", "en:Spatial database"]
+            .into_iter()
+            .map(|t| Title::from_osm_tag(t).unwrap())
+            .collect(),
+        ..Default::default()
+    };
+    let run = |match_policy| {
+        let mut written = Vec::new();
+        let mut finished = false;
+        let options = Options {
+            match_policy,
+            ..Default::default()
+        };
+        let stats = extract::run_with(
+            BufReader::new(dump("en.ndjson").chain(dump("de.ndjson"))),
+            io::sink(),
+            &matcher,
+            &options,
+            RecordingWriter {
+                written: &mut written,
+                finished: &mut finished,
+            },
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        let written: Vec<_> = written
+            .into_iter()
+            .map(|(lang, name, _, _)| format!("{lang}:{name}"))
+            .collect();
+        (written, stats.policy_matches)
+    };
+    let counts = |either, qid_only, title_only, both| {
+        BTreeMap::from([
+            (Either, either),
+            (QidOnly, qid_only),
+            (TitleOnly, title_only),
+            (Both, both),
+        ])
+    };
+
+    let (written, matches) = run(Either);
+    assert_eq!(
+        vec![
+            "en:Berlin",
+            "en:Spatial database",
+            "en:Unrequested Article",
+            "de:Berlin",
+            "de:Geodatenbank"
+        ],
+        written
+    );
+    assert_eq!(counts(5, 3, 3, 1), matches);
+
+    // Without the title match, the German article is not expanded to.
+    let (written, matches) = run(QidOnly);
+    assert_eq!(
+        vec!["en:Berlin", "en:Unrequested Article", "de:Berlin"],
+        written
+    );
+    assert_eq!(counts(4, 3, 2, 1), matches);
+
+    let (written, matches) = run(TitleOnly);
+    assert_eq!(
+        vec!["en:Berlin", "en:Spatial database", "de:Geodatenbank"],
+        written
+    );
+    assert_eq!(counts(5, 3, 3, 1), matches);
+
+    let (written, matches) = run(Both);
+    assert_eq!(vec!["en:Berlin"], written);
+    assert_eq!(counts(4, 3, 2, 1), matches);
+}
+
+#[test]
+fn not_cancelled() {
+    let dir = test_dir("not_cancelled");
+    let qids: HashSet<_> = [Qid::from_str("Q64").unwrap()].into();
+    let options = Options {
+        output_dir: Some(dir),
+        ..Default::default()
+    };
+
+    let stats = run_fixture(
+        &["en.ndjson", "de.ndjson"],
+        &Matcher {
+            qids,
+            ..Default::default()
+        },
+        &options,
+    )
+    .unwrap();
+
+    assert!(!stats.cancelled);
+    assert_eq!(7, stats.pages);
+    assert_eq!(2, stats.written);
+}
+
+/// Run the duplicates fixture with `policy`, returning the stats and html of the article.
+fn duplicates(policy: DuplicatePolicy) -> anyhow::Result<(extract::DumpStats, String)> {
+    let dir = test_dir(&format!("duplicates_{policy:?}"));
+    let qids: HashSet<_> = [Qid::from_str("Q64").unwrap()].into();
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        duplicates: policy,
+        write_duplicates: Some(dir.join("duplicates.txt")),
+        ..Default::default()
+    };
+    let stats = run_fixture(
+        &["duplicates.ndjson"],
+        &Matcher {
+            qids,
+            ..Default::default()
+        },
+        &options,
+    )?;
+    assert_eq!(
+        "en:Berlin\nen:Berlin\n",
+        fs::read_to_string(dir.join("duplicates.txt")).unwrap()
+    );
+    let html = fs::read_to_string(dir.join("wikidata/Q64/en.html")).unwrap();
+    Ok((stats, html))
+}
+
+#[test]
+fn duplicates_keep_first() {
+    let (stats, html) = duplicates(DuplicatePolicy::KeepFirst).unwrap();
+    assert!(html.contains("First export"), "{html}");
+    assert_eq!(2, stats.duplicates);
+    assert_eq!(0, stats.replaced);
+    assert_eq!(1, stats.written);
+    assert_eq!(1, stats.langs["en"].matches);
+}
+
+#[test]
+fn duplicates_keep_latest() {
+    let (stats, html) = duplicates(DuplicatePolicy::KeepLatest).unwrap();
+    assert!(html.contains("Edited export"), "{html}");
+    assert_eq!(2, stats.duplicates);
+    assert_eq!(1, stats.replaced);
+    assert_eq!(1, stats.written);
+    assert_eq!(1, stats.langs["en"].matches);
+    assert_eq!(html.len(), stats.langs["en"].bytes);
+}
+
+#[test]
+fn duplicates_error() {
+    let dir = test_dir("duplicates_error");
+    let qids: HashSet<_> = [Qid::from_str("Q64").unwrap()].into();
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        duplicates: DuplicatePolicy::Error,
+        ..Default::default()
+    };
+    let result = run_fixture(
+        &["duplicates.ndjson"],
+        &Matcher {
+            qids,
+            ..Default::default()
+        },
+        &options,
+    );
+    assert!(result.is_err());
+    let html = fs::read_to_string(dir.join("wikidata/Q64/en.html")).unwrap();
+    assert!(html.contains("First export"), "{html}");
+}
+
+#[test]
+fn matcher_from_files() {
+    let dir = test_dir("matcher_from_files");
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nnot a qid\nQ365\n").unwrap();
+    let titles = dir.join("titles.txt");
+    fs::write(
+        &titles,
+        "https://en.wikipedia.org/wiki/Thames_Barrier\nen:Spatial database\n",
+    )
+    .unwrap();
+    let tags = dir.join("tags.tsv");
+    fs::write(
+        &tags,
+        "@id\t@otype\twikidata\twikipedia\n1\t0\tQ64\tde:Berlin\n2\t1\t\tfr:Pont du Gard\n3\t2\tQ1\t\n4\t1\tbad\t\n",
+    )
+    .unwrap();
+
+    let (matcher, errors) = Matcher::from_files([&qids], [&titles], [&tags]).unwrap();
+
+    let expected_qids: HashSet<_> = ["Q64", "Q365", "Q1"]
+        .into_iter()
+        .map(|q| Qid::from_str(q).unwrap())
+        .collect();
+    assert_eq!(expected_qids, matcher.qids);
+    let expected_titles: HashSet<_> = [
+        "en:Thames Barrier",
+        "en:Spatial database",
+        "de:Berlin",
+        "fr:Pont du Gard",
+    ]
+    .into_iter()
+    .map(|t| Title::from_osm_tag(t).unwrap())
+    .collect();
+    assert_eq!(expected_titles, matcher.titles);
+
+    let errors: Vec<_> = errors
+        .iter()
+        .map(|e| (&e.path, e.error.text.as_str()))
+        .collect();
+    assert_eq!(vec![(&qids, "not a qid"), (&tags, "bad")], errors);
+}
+
+#[test]
+fn split_by_lang() {
+    use std::os::unix::fs::MetadataExt;
+
+    use om_wikiparser::wm::output::{self, Manifest};
+
+    let dir = test_dir("split_by_lang");
+    let base = dir.join("output");
+    let packs = dir.join("packs");
+    fs::create_dir_all(&base).unwrap();
+
+    let qids: HashSet<_> = ["Q64", "Q365", "Q1116061"]
+        .into_iter()
+        .map(|q| Qid::from_str(q).unwrap())
+        .collect();
+    let titles: HashSet<_> = [
+        "en:Berlin",
+        "en:Berlin, Germany",
+        "de:Koeln",
+        "voy:en:Berlin",
+    ]
+    .into_iter()
+    .map(|t| Title::from_osm_tag(t).unwrap())
+    .collect();
+    let options = Options {
+        output_dir: Some(base.clone()),
+        write_manifest: true,
+        ..Default::default()
+    };
+    run_fixture(
+        &["en.ndjson", "de.ndjson", "wikivoyage.ndjson"],
+        &Matcher {
+            qids,
+            titles,
+            ..Default::default()
+        },
+        &options,
+    )
+    .unwrap();
+
+    // A Wikivoyage redirect, and one recorded where symlinks couldn't be created.
+    std::os::unix::fs::symlink(
+        "Berlin",
+        base.join("en.wikivoyage.org/wiki/Berlin_(Germany)"),
+    )
+    .unwrap();
+    fs::write(
+        base.join(output::LINKS_FILE),
+        r#"{"link":"de.wikipedia.org/wiki/Köln","target":"wikidata/Q365"}"#,
+    )
+    .unwrap();
+
+    let manifest = Manifest::read(&base).unwrap();
+    let stats = output::split_by_lang(&base, &manifest, &packs).unwrap();
+    assert_eq!(vec!["de", "en"], stats.keys().collect::<Vec<_>>());
+    assert_eq!(3, stats["de"].articles);
+    // The Wikivoyage pages of the QIDs are in the pack of their lang.
+    assert_eq!(4, stats["en"].articles);
+    assert_eq!(7, stats["de"].linked + stats["en"].linked);
+    assert_eq!(0, stats["de"].copied + stats["en"].copied);
+    assert_eq!(3, stats["en"].redirects);
+    assert_eq!(2, stats["de"].redirects);
+
+    // Articles are hard links of the originals.
+    let en = packs.join("en");
+    let berlin = en.join("wikidata/Q64/en.html");
+    assert_eq!(
+        fs::metadata(base.join("wikidata/Q64/en.html"))
+            .unwrap()
+            .ino(),
+        fs::metadata(&berlin).unwrap().ino()
+    );
+    assert!(!en.join("wikidata/Q64/de.html").exists());
+    assert!(!en.join("wikidata/Q365").exists());
+
+    // Redirects are in the pack of their article, and link to the pack's copy relative to the link.
+    let redirect = en.join("en.wikipedia.org/wiki/Berlin,_Germany");
+    assert_eq!(
+        Path::new("../../wikidata/Q64"),
+        fs::read_link(&redirect).unwrap()
+    );
+    assert!(redirect.join("en.html").is_file());
+    assert!(!en.join("de.wikipedia.org").exists());
+    let guide = en.join("en.wikivoyage.org/wiki/Berlin_(Germany)");
+    assert_eq!(
+        Path::new("../../en.wikivoyage.org/wiki/Berlin"),
+        fs::read_link(&guide).unwrap()
+    );
+    assert!(guide.join("en.html").is_file());
+    let de = packs.join("de");
+    assert!(de.join("de.wikipedia.org/wiki/Koeln/de.html").is_file());
+    assert!(de.join("de.wikipedia.org/wiki/Köln/de.html").is_file());
+    assert!(!de.join("en.wikipedia.org").exists());
+
+    // The redirects still resolve after moving the pack.
+    let moved = dir.join("moved");
+    fs::rename(&en, &moved).unwrap();
+    assert!(moved
+        .join("en.wikipedia.org/wiki/Berlin,_Germany/en.html")
+        .is_file());
+    fs::rename(&moved, &en).unwrap();
+
+    // Each pack's manifest only lists the pack's articles, which all exist.
+    for (lang, stats) in &stats {
+        let pack = packs.join(lang);
+        let manifest = Manifest::read(&pack).unwrap();
+        assert_eq!(stats.articles, manifest.entries.len());
+        for entry in &manifest.entries {
+            assert_eq!(lang, &entry.lang);
+            assert!(pack.join(&entry.path).is_file(), "{:?}", entry.path);
+        }
+    }
+}
+
+#[test]
+fn compress_html() {
+    use flate2::read::GzDecoder;
+    use om_wikiparser::{extract::HtmlCompression, wm::output::Manifest};
+
+    let dir = test_dir("compress_html");
+    let plain = dir.join("plain");
+    let compressed = dir.join("compressed");
+    for (output_dir, compress_html) in [(&plain, None), (&compressed, Some(HtmlCompression::Gzip))]
+    {
+        fs::create_dir_all(output_dir).unwrap();
+        let options = Options {
+            output_dir: Some(output_dir.clone()),
+            write_manifest: true,
+            compress_html,
+            ..Default::default()
+        };
+        run_fixture(
+            &["en.ndjson"],
+            &Matcher {
+                qids: [Qid::from_str("Q64").unwrap()].into(),
+                ..Default::default()
+            },
+            &options,
+        )
+        .unwrap();
+    }
+
+    let manifest = Manifest::read(&compressed).unwrap();
+    assert_eq!(1, manifest.entries.len());
+    let path = &manifest.entries[0].path;
+    assert_eq!(Path::new("wikidata/Q64/en.html.gz"), path);
+    assert!(!compressed.join("wikidata/Q64/en.html").exists());
+
+    let mut html = String::new();
+    GzDecoder::new(File::open(compressed.join(path)).unwrap())
+        .read_to_string(&mut html)
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(plain.join("wikidata/Q64/en.html")).unwrap(),
+        html
+    );
+}
+
+#[test]
+fn near_miss_titles() {
+    let dir = test_dir("near_miss_titles");
+    let titles: HashSet<_> = [
+        "en:King’s Cross railway station",
+        "de:Baden–Baden",
+        // Matches the second page exactly, and the first by skeleton.
+        "en:St. Mary’s Church",
+        "en:Nonexistent Article",
+    ]
+    .into_iter()
+    .map(|t| Title::from_osm_tag(t).unwrap())
+    .collect();
+    let matcher = Matcher {
+        titles,
+        ..Default::default()
+    };
+    let run = |near_miss_titles| {
+        let options = Options {
+            output_dir: Some(dir.clone()),
+            near_miss_titles,
+            ..Default::default()
+        };
+        run_fixture(&["near_miss.ndjson"], &matcher, &options).unwrap()
+    };
+
+    let stats = run(false);
+    assert_eq!(1, stats.title_matches);
+    assert_eq!(0, stats.near_miss_matches);
+    assert_eq!(3, stats.missing_titles);
+
+    let stats = run(true);
+    assert_eq!(1, stats.title_matches);
+    assert_eq!(2, stats.near_miss_matches);
+    assert_eq!(3, stats.written);
+    assert_eq!(1, stats.missing_titles);
+    assert_eq!(
+        [
+            ("de:Baden–Baden", "de:Baden-Baden"),
+            (
+                "en:King’s_Cross_railway_station",
+                "en:King's_Cross_railway_station"
+            ),
+        ]
+        .map(|(a, b)| (a.to_owned(), b.to_owned()))
+        .into_iter()
+        .collect::<BTreeMap<_, _>>(),
+        stats.near_misses
+    );
+
+    // The requested spelling links to the recovered article.
+    assert_eq!(
+        dir.join("wikidata/Q720102"),
+        fs::read_link(dir.join("en.wikipedia.org/wiki/King’s_Cross_railway_station")).unwrap()
+    );
+    // The exact match is kept.
+    assert_eq!(
+        dir.join("wikidata/Q1000002"),
+        fs::read_link(dir.join("en.wikipedia.org/wiki/St._Mary’s_Church")).unwrap()
+    );
+    assert!(!dir.join("wikidata/Q1000001").exists());
+}
+
+#[test]
+fn memory_ceiling() {
+    let matcher = Matcher {
+        titles: ["en:King’s Cross railway station", "de:Baden–Baden"]
+            .into_iter()
+            .map(|t| Title::from_osm_tag(t).unwrap())
+            .collect(),
+        ..Default::default()
+    };
+    // An unrequested page larger than the shrunk page buffer.
+    let filler = format!(
+        r#"{{"name": "Filler", "date_modified": "2023-07-01T12:00:00Z", "in_language": {{"identifier": "en"}}, "article_body": {{"html": "{}"}}}}"#,
+        "x".repeat(2 << 20)
+    );
+    let run = |name, memory_ceiling| {
+        let dir = test_dir(name);
+        let options = Options {
+            output_dir: Some(dir.clone()),
+            write_manifest: true,
+            near_miss_titles: true,
+            memory_ceiling,
+            ..Default::default()
+        };
+        let input = format!(
+            "{filler}\n{}",
+            fs::read_to_string(dump_path("near_miss.ndjson")).unwrap()
+        );
+        let stats = extract::run(
+            input.as_bytes(),
+            io::sink(),
+            &matcher,
+            &options,
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        let manifest = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        (stats, manifest)
+    };
+
+    let (unlimited, expected) = run("memory_ceiling_unlimited", None);
+    assert!(unlimited.degradations.is_empty());
+    assert_eq!(2, unlimited.near_miss_matches);
+
+    let (stats, manifest) = run("memory_ceiling", Some(1));
+    assert_eq!(
+        vec![
+            Degradation::SpillNearMissPages,
+            Degradation::ShrinkPageBuffer
+        ],
+        stats
+            .degradations
+            .iter()
+            .map(|d| d.degradation)
+            .collect::<Vec<_>>()
+    );
+    // The buffer is only shrunk after the large first page is read.
+    assert_eq!(1, stats.degradations[0].line);
+    assert_eq!(2, stats.degradations[1].line);
+
+    // Output is unchanged.
+    assert_eq!(unlimited.near_misses, stats.near_misses);
+    assert_eq!(unlimited.written, stats.written);
+    assert_eq!(expected, manifest);
+}
+
+#[test]
+fn relative_links() {
+    let matcher = Matcher {
+        titles: ["en:Spatial database", "en:Geodatabase"]
+            .into_iter()
+            .map(|t| Title::from_osm_tag(t).unwrap())
+            .collect(),
+        ..Default::default()
+    };
+    let run = |name, link_direction| {
+        let dir = test_dir(name);
+        let options = Options {
+            output_dir: Some(dir.clone()),
+            link_direction,
+            relative_links: true,
+            ..Default::default()
+        };
+        run_fixture(&["en.ndjson"], &matcher, &options).unwrap();
+        dir
+    };
+
+    let dir = run("relative_links_title_to_qid", LinkDirection::TitleToQid);
+    for title in ["Spatial_database", "Geodatabase"] {
+        let link = dir.join("en.wikipedia.org/wiki").join(title);
+        assert_eq!(
+            Path::new("../../wikidata/Q1116061"),
+            fs::read_link(&link).unwrap()
+        );
+        assert!(link.join("en.html").is_file());
+    }
+
+    let dir = run("relative_links_qid_to_title", LinkDirection::QidToTitle);
+    let main = dir.join("en.wikipedia.org/wiki/Spatial_database");
+    assert!(main.join("en.html").is_file() && !main.is_symlink());
+    assert_eq!(
+        Path::new("../../en.wikipedia.org/wiki/Spatial_database/en.html"),
+        fs::read_link(dir.join("wikidata/Q1116061/en.html")).unwrap()
+    );
+    assert_eq!(
+        Path::new("../../en.wikipedia.org/wiki/Spatial_database"),
+        fs::read_link(dir.join("en.wikipedia.org/wiki/Geodatabase")).unwrap()
+    );
+    assert!(dir.join("wikidata/Q1116061/en.html").is_file());
+}
+
+#[test]
+fn text_direction() {
+    use om_wikiparser::{html::Direction, wm::output::Manifest};
+
+    let matcher = Matcher {
+        qids: [Qid::from_str("Q64").unwrap()].into(),
+        ..Default::default()
+    };
+    let run = |name, infer_text_direction| {
+        let dir = test_dir(name);
+        let options = Options {
+            output_dir: Some(dir.clone()),
+            write_manifest: true,
+            infer_text_direction,
+            ..Default::default()
+        };
+        run_fixture(&["rtl.ndjson"], &matcher, &options).unwrap();
+        Manifest::read(&dir)
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|e| (e.lang, e.dir))
+            .collect::<Vec<_>>()
+    };
+
+    // The Hebrew article has no `dir` attribute.
+    assert_eq!(
+        vec![("ar".into(), Direction::Rtl), ("he".into(), Direction::Ltr)],
+        run("text_direction", false)
+    );
+    assert_eq!(
+        vec![("ar".into(), Direction::Rtl), ("he".into(), Direction::Rtl)],
+        run("text_direction_inferred", true)
+    );
+}
+
+#[test]
+fn altnames() {
+    use om_wikiparser::wm::output::ALTNAMES_FILE;
+
+    let matcher = Matcher {
+        titles: [Title::from_osm_tag("en:Kyiv").unwrap()].into(),
+        ..Default::default()
+    };
+    let run = |name, max_altnames| {
+        let dir = test_dir(name);
+        let options = Options {
+            output_dir: Some(dir.clone()),
+            write_altnames: true,
+            max_altnames,
+            ..Default::default()
+        };
+        run_fixture(&["kyiv.ndjson"], &matcher, &options).unwrap();
+        fs::read_to_string(dir.join(ALTNAMES_FILE)).unwrap()
+    };
+
+    // Repeated redirects and redirects to the article's own title are skipped.
+    assert_eq!(
+        "\
+wikidata/Q1899/en.html\tKiev\tredirect
+wikidata/Q1899/en.html\tКиїв\tredirect
+wikidata/Q1899/en.html\tKyiv City\tredirect
+wikidata/Q1899/uk.html\tКиев\tredirect
+wikidata/Q1899/uk.html\tKyiv\tlanglink
+",
+        run("altnames", None)
+    );
+
+    assert_eq!(
+        "\
+wikidata/Q1899/en.html\tKiev\tredirect
+wikidata/Q1899/uk.html\tКиев\tredirect
+",
+        run("altnames_capped", Some(1))
+    );
+}
+
+#[test]
+fn qid_langs() {
+    use om_wikiparser::wm::output::QID_LANGS_FILE;
+
+    let dir = test_dir("qid_langs");
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_manifest: true,
+        write_qid_langs: true,
+        ..Default::default()
+    };
+    let run = |dumps: &[&str], qids: &[&str]| {
+        run_fixture(
+            dumps,
+            &Matcher {
+                qids: qids.iter().map(|q| Qid::from_str(q).unwrap()).collect(),
+                ..Default::default()
+            },
+            &options,
+        )
+        .unwrap();
+        let langs: BTreeMap<String, Vec<String>> =
+            serde_json::from_str(&fs::read_to_string(dir.join(QID_LANGS_FILE)).unwrap()).unwrap();
+        langs
+    };
+    let langs = |entries: &[(&str, &[&str])]| -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(qid, langs)| {
+                (
+                    qid.to_string(),
+                    langs.iter().map(|l| l.to_string()).collect(),
+                )
+            })
+            .collect()
+    };
+
+    // Köln is only in the german dump, and the unrequested article is not written.
+    assert_eq!(
+        langs(&[
+            ("Q64", &["de", "en"]),
+            ("Q365", &["de"]),
+            ("Q1116061", &["de", "en"]),
+        ]),
+        run(&["en.ndjson", "de.ndjson"], &["Q64", "Q365", "Q1116061"])
+    );
+
+    // Languages written by earlier runs into the same directory are kept.
+    assert_eq!(
+        langs(&[
+            ("Q64", &["ar", "de", "en", "he"]),
+            ("Q365", &["de"]),
+            ("Q1116061", &["de", "en"]),
+        ]),
+        run(&["rtl.ndjson"], &["Q64"])
+    );
+}
+
+#[test]
+fn path_collisions() {
+    use om_wikiparser::{extract::PathCollision, wm::output::Manifest};
+
+    let dir = test_dir("path_collisions");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let audit = dir.join("audit.jsonl");
+    let options = Options {
+        output_dir: Some(output_dir.clone()),
+        write_manifest: true,
+        write_audit: Some(audit.clone()),
+        ..Default::default()
+    };
+    // Both pages are Wikivoyage articles written to their title directory, and `Mitte/` is normalized to `Mitte`.
+    let stats = run_fixture(
+        &["collisions.ndjson"],
+        &Matcher {
+            qids: ["Q2013767", "Q1221196"]
+                .into_iter()
+                .map(|q| Qid::from_str(q).unwrap())
+                .collect(),
+            ..Default::default()
+        },
+        &options,
+    )
+    .unwrap();
+
+    assert_eq!(2, stats.qid_matches);
+    assert_eq!(1, stats.written);
+    assert_eq!(0, stats.errors);
+    assert_eq!(
+        vec![PathCollision {
+            path: "en.wikivoyage.org/wiki/Mitte/en.html".into(),
+            kept: "voy:en:Mitte".into(),
+            kept_id: Some(1001),
+            skipped: "voy:en:Mitte/".into(),
+            skipped_id: Some(1002),
+            line: 2,
+        }],
+        stats.path_collisions
+    );
+
+    let html = fs::read_to_string(output_dir.join("en.wikivoyage.org/wiki/Mitte/en.html")).unwrap();
+    assert!(html.contains("historic centre"), "{html}");
+
+    let manifest = Manifest::read(&output_dir).unwrap();
+    assert_eq!(1, manifest.entries.len());
+    assert_eq!(Some(1001), manifest.entries[0].page_id);
+    assert_eq!("Mitte", manifest.entries[0].title);
+
+    let audit = fs::read_to_string(audit).unwrap();
+    assert!(
+        audit.contains(r#""reason":"path collision with voy:en:Mitte""#),
+        "{audit}"
+    );
+}
+
+#[test]
+fn stage_timings() {
+    let dir = test_dir("stage_timings");
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_manifest: true,
+        ..Default::default()
+    };
+    let stats = run_fixture(
+        &["en.ndjson", "de.ndjson"],
+        &Matcher {
+            qids: [Qid::from_str("Q64").unwrap()].into(),
+            titles: [Title::from_osm_tag("de:Köln").unwrap()].into(),
+            ..Default::default()
+        },
+        &options,
+    )
+    .unwrap();
+    assert_eq!(3, stats.written);
+
+    let timings = &stats.timings;
+    for (stage, seconds) in [
+        ("read", timings.read),
+        ("parse", timings.parse),
+        ("matching", timings.matching),
+        ("simplify", timings.simplify),
+        ("write", timings.write),
+        ("total", timings.total),
+        ("pages_per_second", timings.pages_per_second),
+    ] {
+        assert!(seconds > 0.0, "{stage} is {seconds}");
+    }
+    assert!(
+        timings.stages() <= timings.total,
+        "stages took {} of {} seconds",
+        timings.stages(),
+        timings.total
+    );
+    assert!(
+        timings.stages() >= timings.total / 2.0,
+        "stages took {} of {} seconds",
+        timings.stages(),
+        timings.total
+    );
+
+    // The thread's CPU time can't be more than the wall time of any stage, except for the time between reading the clocks.
+    let cpu = &timings.cpu;
+    for (stage, seconds, wall) in [
+        ("read", cpu.read, timings.read),
+        ("parse", cpu.parse, timings.parse),
+        ("matching", cpu.matching, timings.matching),
+        ("simplify", cpu.simplify, timings.simplify),
+        ("write", cpu.write, timings.write),
+        ("total", cpu.total, timings.total),
+    ] {
+        assert!(seconds >= 0.0, "{stage} is {seconds}");
+        assert!(
+            seconds <= wall * 1.01 + 1e-3,
+            "{stage} took {seconds} CPU seconds in {wall} seconds"
+        );
+    }
+    assert!(cpu.parse > 0.0 && cpu.simplify > 0.0, "{cpu:?}");
+    assert!(cpu.stages() <= cpu.total * 1.01 + 1e-3, "{cpu:?}");
+
+    let json = serde_json::to_value(&stats).unwrap();
+    assert!(json["timings"]["simplify"].as_f64().unwrap() > 0.0);
+    assert!(json["timings"]["cpu"]["simplify"].as_f64().unwrap() > 0.0);
+}
+
+#[test]
+fn wikidata_descriptions() {
+    use om_wikiparser::wm::output::{sidecar_path, Manifest, Sidecar};
+
+    let dir = test_dir("wikidata_descriptions");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let descriptions = dir.join("wikidata.json");
+    fs::write(
+        &descriptions,
+        r#"[
+{"id":"Q64","type":"item","descriptions":{"en":{"language":"en","value":"capital and largest city of Germany"},"fr":{"language":"fr","value":"capitale de l'Allemagne"}}},
+{"id":"Q365","type":"item","descriptions":{"de":{"language":"de","value":"Stadt in Nordrhein-Westfalen"}}}
+]
+"#,
+    )
+    .unwrap();
+
+    let options = Options {
+        output_dir: Some(output_dir.clone()),
+        write_manifest: true,
+        write_sidecars: true,
+        wikidata_descriptions: Some(descriptions),
+        description_langs: vec!["en".into(), "de".into()],
+        ..Default::default()
+    };
+    run_fixture(
+        &["en.ndjson", "de.ndjson"],
+        &Matcher {
+            qids: ["Q64", "Q1116061"]
+                .into_iter()
+                .map(|q| Qid::from_str(q).unwrap())
+                .collect(),
+            ..Default::default()
+        },
+        &options,
+    )
+    .unwrap();
+
+    let manifest = Manifest::read(&output_dir).unwrap();
+    let descriptions: BTreeMap<_, _> = manifest
+        .entries
+        .iter()
+        .map(|e| (e.path.to_str().unwrap(), e.descriptions.clone()))
+        .collect();
+    let q64 = BTreeMap::from([("en".into(), "capital and largest city of Germany".into())]);
+    assert_eq!(
+        BTreeMap::from([
+            ("wikidata/Q1116061/de.html", BTreeMap::new()),
+            ("wikidata/Q1116061/en.html", BTreeMap::new()),
+            ("wikidata/Q64/de.html", q64.clone()),
+            ("wikidata/Q64/en.html", q64.clone()),
+        ]),
+        descriptions
+    );
+
+    let sidecar: Sidecar = serde_json::from_slice(
+        &fs::read(sidecar_path(&output_dir.join("wikidata/Q64/de.html"))).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(q64, sidecar.descriptions);
+}
+
+#[test]
+fn wikivoyage() {
+    use om_wikiparser::wm::{output::Manifest, Project};
+
+    let dir = test_dir("wikivoyage");
+    let tags = dir.join("tags.tsv");
+    fs::write(
+        &tags,
+        "@id\t@otype\twikidata\twikipedia\twikivoyage\n1\t2\tQ64\ten:Berlin\ten:Berlin\n2\t1\t\ten:Spatial database\t\n",
+    )
+    .unwrap();
+    let (matcher, errors) = Matcher::from_files(None::<&Path>, None::<&Path>, [&tags]).unwrap();
+    assert!(errors.is_empty());
+    assert!(matcher
+        .titles
+        .contains(&Title::from_osm_tag("voy:en:Berlin").unwrap()));
+
+    let base = dir.join("output");
+    fs::create_dir(&base).unwrap();
+    let options = Options {
+        output_dir: Some(base.clone()),
+        write_manifest: true,
+        ..Default::default()
+    };
+    let stats = run_fixture(&["en.ndjson", "wikivoyage.ndjson"], &matcher, &options).unwrap();
+
+    // The Wikipedia article keeps its QID directory.
+    let wikipedia = fs::read_to_string(base.join("wikidata/Q64/en.html")).unwrap();
+    assert!(
+        wikipedia.contains("capital and largest city"),
+        "{wikipedia}"
+    );
+    assert!(base.join("en.wikipedia.org/wiki/Berlin").is_symlink());
+
+    // The travel guide with the same QID is written to its own title directory.
+    let guide_dir = base.join("en.wikivoyage.org/wiki/Berlin");
+    assert!(guide_dir.is_dir() && !guide_dir.is_symlink());
+    let guide = fs::read_to_string(guide_dir.join("en.html")).unwrap();
+    assert!(guide.contains("Pergamon Museum"), "{guide}");
+    assert!(!guide.contains("Berlin banner"), "{guide}");
+
+    // Wikipedia titles and their expansions don't match Wikivoyage pages.
+    assert!(!base
+        .join("en.wikivoyage.org/wiki/Spatial_database")
+        .exists());
+
+    assert_eq!(3, stats.written);
+    assert_eq!(2, stats.langs["en"].written);
+    let voyage = &stats.other_projects[&Project::Wikivoyage]["en"];
+    assert_eq!((1, 1, 1), (voyage.titles, voyage.matches, voyage.written));
+    assert!(stats.to_string().contains("voy:en"));
+
+    let manifest = Manifest::read(&base).unwrap();
+    let projects: BTreeMap<_, _> = manifest
+        .entries
+        .iter()
+        .map(|e| (e.path.to_str().unwrap(), e.project))
+        .collect();
+    assert_eq!(
+        BTreeMap::from([
+            ("en.wikivoyage.org/wiki/Berlin/en.html", Project::Wikivoyage),
+            ("wikidata/Q1116061/en.html", Project::Wikipedia),
+            ("wikidata/Q64/en.html", Project::Wikipedia),
+        ]),
+        projects
+    );
+}
+
+#[test]
+fn oversized_page_skipped() {
+    let qids: HashSet<_> = [Qid::from_str("Q64").unwrap()].into();
+    let options = Options {
+        max_page_bytes: Some(1 << 20),
+        ..Default::default()
+    };
+
+    // A corrupted line far larger than the limit, followed by the real pages.
+    let garbage = io::repeat(b'x').take(64 << 20).chain(&b"\n"[..]);
+    let input = BufReader::new(garbage.chain(dump("en.ndjson")));
+    let stats = extract::run(
+        input,
+        io::sink(),
+        &Matcher {
+            qids,
+            ..Default::default()
+        },
+        &options,
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+
+    assert_eq!(1, stats.oversized_pages);
+    assert_eq!(4, stats.pages);
+    assert_eq!(1, stats.qid_matches);
+}
+
+#[test]
+fn recover_corrupt() {
+    let dump = fs::read(dump_path("en.ndjson")).unwrap();
+    let lines: Vec<&[u8]> = dump.split_inclusive(|&b| b == b'\n').collect();
+    // The end of the second page and its line ending are lost, and a mangled line follows the third.
+    let garbage = b"\x00\xff{\"nam\x1f\n";
+    let corrupt = [&lines[1][..100], lines[2], garbage, lines[3]].concat();
+    let input = [lines[0], &corrupt].concat();
+
+    let matcher = Matcher {
+        qids: ["Q64", "Q1116061", "Q999999", "Q4973715"]
+            .into_iter()
+            .map(|q| Qid::from_str(q).unwrap())
+            .collect(),
+        ..Default::default()
+    };
+    let run = |input: &[u8], recover_corrupt| {
+        let mut written = Vec::new();
+        let mut finished = false;
+        let options = Options {
+            recover_corrupt,
+            ..Default::default()
+        };
+        extract::run_with(
+            input,
+            io::sink(),
+            &matcher,
+            &options,
+            RecordingWriter {
+                written: &mut written,
+                finished: &mut finished,
+            },
+            &AtomicBool::new(false),
+        )
+        .map(|stats| {
+            let names: Vec<_> = written.into_iter().map(|(_, name, _, _)| name).collect();
+            (stats, names)
+        })
+    };
+
+    let e = run(&input, false).unwrap_err();
+    assert!(e.to_string().contains("on line 2"), "{e:#}");
+
+    let (stats, names) = run(&input, true).unwrap();
+    assert_eq!(
+        vec!["Berlin", "Unrequested Article", "Brockenhurst Halt"],
+        names
+    );
+    assert_eq!(3, stats.pages);
+    let start = lines[0].len() + 1;
+    let regions: Vec<_> = stats
+        .skipped_regions
+        .iter()
+        .map(|r| (r.line, r.start, r.end))
+        .collect();
+    let garbage_start = start + 100 + lines[2].len();
+    assert_eq!(
+        vec![
+            (2, start, start + 100),
+            (3, garbage_start, garbage_start + garbage.len())
+        ],
+        regions
+    );
+
+    // A dump that is corrupt throughout is not skipped entirely.
+    let broken = garbage.repeat(extract::MAX_CONSECUTIVE_RECOVERIES + 1);
+    let e = run(&[lines[0], &broken[..], lines[3]].concat(), true).unwrap_err();
+    assert!(e.to_string().contains("consecutive corrupt lines"), "{e:#}");
+}
+
+#[test]
+fn manifest_dedup_by_path() {
+    let dir = test_dir("manifest_dedup_by_path");
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_manifest: true,
+        ..Default::default()
+    };
+    let run = |matcher: Matcher| run_fixture(&["en.ndjson"], &matcher, &options).unwrap();
+
+    // Two runs with different lists that both request the same article.
+    run(Matcher {
+        qids: [Qid::from_str("Q64").unwrap()].into(),
+        ..Default::default()
+    });
+    run(Matcher {
+        titles: [Title::from_osm_tag("en:Berlin").unwrap()].into(),
+        ..Default::default()
+    });
+
+    let lines = fs::read_to_string(dir.join(om_wikiparser::wm::output::MANIFEST_FILE)).unwrap();
+    assert_eq!(2, lines.lines().count());
+
+    let manifest = om_wikiparser::wm::output::Manifest::read(&dir).unwrap();
+    assert_eq!(1, manifest.entries.len());
+    let entry = &manifest.entries[0];
+    assert_eq!(Path::new("wikidata/Q64/en.html"), entry.path);
+    assert_eq!(vec!["Q64", "en:Berlin"], entry.requesters);
+}
+
+#[test]
+fn sidecars() {
+    use om_wikiparser::wm::output::{check_sidecars, Manifest, MatchedBy, Sidecar};
+
+    let dir = test_dir("sidecars");
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_manifest: true,
+        write_sidecars: true,
+        ..Default::default()
+    };
+    let matcher = Matcher {
+        qids: [Qid::from_str("Q64").unwrap()].into(),
+        titles: [Title::from_osm_tag("en:Geodatabase").unwrap()].into(),
+        ..Default::default()
+    };
+    run_fixture(&["en.ndjson"], &matcher, &options).unwrap();
+
+    let read = |path: &str| -> Sidecar {
+        serde_json::from_str(&fs::read_to_string(dir.join(path)).unwrap()).unwrap()
+    };
+
+    let by_qid = read("wikidata/Q64/en.meta.json");
+    assert_eq!(MatchedBy::Qid, by_qid.matched_by);
+    assert_eq!(vec!["Q64"], by_qid.requesters);
+    assert_eq!(
+        ("en", "Berlin"),
+        (by_qid.lang.as_str(), by_qid.title.as_str())
+    );
+    assert_eq!(1, by_qid.line);
+    assert_eq!(1, by_qid.byte);
+    assert!(by_qid.written_at > 0);
+
+    let by_redirect = read("wikidata/Q1116061/en.meta.json");
+    assert_eq!(MatchedBy::Redirect, by_redirect.matched_by);
+    assert_eq!(vec!["en:Geodatabase"], by_redirect.requesters);
+    assert_eq!(Some(Qid::from_str("Q1116061").unwrap()), by_redirect.qid);
+    assert_eq!(2, by_redirect.line);
+    assert!(by_redirect.byte > 1);
+
+    let manifest = Manifest::read(&dir).unwrap();
+    assert_eq!(2, manifest.entries.len());
+    let mismatches = check_sidecars(&dir, &manifest);
+    assert!(mismatches.is_empty(), "{mismatches:#?}");
+
+    fs::remove_file(dir.join("wikidata/Q64/en.meta.json")).unwrap();
+    let mismatches = check_sidecars(&dir, &manifest);
+    assert_eq!(1, mismatches.len());
+    assert_eq!(Path::new("wikidata/Q64/en.html"), mismatches[0].path);
+}
+
+#[test]
+fn iter_articles_layouts() {
+    use om_wikiparser::wm::{
+        output::{iter_articles, MANIFEST_FILE},
+        Layout,
+    };
+
+    let dir = test_dir("iter_articles_layouts");
+    let matcher = Matcher {
+        qids: [Qid::from_str("Q64").unwrap()].into(),
+        titles: [Title::from_osm_tag("en:Geodatabase").unwrap()].into(),
+        ..Default::default()
+    };
+    let extract = |layout, compress_html| {
+        let output_dir = dir.join(format!("{layout:?}"));
+        fs::create_dir(&output_dir).unwrap();
+        let options = Options {
+            output_dir: Some(output_dir.clone()),
+            write_manifest: true,
+            layout,
+            compress_html,
+            ..Default::default()
+        };
+        run_fixture(&["en.ndjson", "de.ndjson"], &matcher, &options).unwrap();
+        output_dir
+    };
+    let flat = extract(Layout::Flat, None);
+    let sharded = extract(Layout::Sharded, Some(extract::HtmlCompression::Gzip));
+
+    // Everything except the paths and sizes.
+    let contents = |base: &Path| {
+        let mut articles: Vec<_> = iter_articles(base)
+            .map(|article| {
+                let article = article.unwrap();
+                assert!(article.bytes > 0);
+                (
+                    article.lang.clone(),
+                    article.title.as_ref().map(Title::to_string),
+                    article.qid,
+                    article.read_html().unwrap(),
+                )
+            })
+            .collect();
+        articles.sort();
+        articles
+    };
+
+    let from_manifest = contents(&flat);
+    assert_eq!(
+        vec![
+            (
+                "de".into(),
+                Some("de:Berlin".into()),
+                Some(Qid::from_str("Q64").unwrap())
+            ),
+            (
+                "de".into(),
+                Some("de:Geodatenbank".into()),
+                Some(Qid::from_str("Q1116061").unwrap())
+            ),
+            (
+                "en".into(),
+                Some("en:Berlin".into()),
+                Some(Qid::from_str("Q64").unwrap())
+            ),
+            (
+                "en".into(),
+                Some("en:Spatial_database".into()),
+                Some(Qid::from_str("Q1116061").unwrap())
+            ),
+        ],
+        from_manifest
+            .iter()
+            .map(|(lang, title, qid, _)| (lang.clone(), title.clone(), *qid))
+            .collect::<Vec<(String, Option<String>, Option<Qid>)>>()
+    );
+    assert_eq!(from_manifest, contents(&sharded));
+
+    // Only the article linked from a requested title has a known title.
+    fs::remove_file(flat.join(MANIFEST_FILE)).unwrap();
+    fs::remove_file(sharded.join(MANIFEST_FILE)).unwrap();
+    let inferred = contents(&flat);
+    assert_eq!(
+        vec![None, None, None, Some("en:Geodatabase".to_owned())],
+        inferred
+            .iter()
+            .map(|(_, title, _, _)| title.clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(inferred, contents(&sharded));
+}
+
+#[test]
+fn loose_titles() {
+    let dir = test_dir("loose_titles");
+    let matcher = Matcher {
+        // `Berlin, Germany` is a redirect to `Berlin`.
+        titles: [Title::from_osm_tag("en:berlin,  GERMANY").unwrap()].into(),
+        ..Default::default()
+    };
+    let run = |loose_titles| {
+        let options = Options {
+            output_dir: Some(dir.clone()),
+            loose_titles,
+            ..Default::default()
+        };
+        run_fixture(&["en.ndjson"], &matcher, &options).unwrap()
+    };
+
+    let stats = run(false);
+    assert_eq!(0, stats.title_matches);
+    assert_eq!(1, stats.missing_titles);
+
+    let stats = run(true);
+    assert_eq!(1, stats.title_matches);
+    assert_eq!(0, stats.missing_titles);
+    assert!(dir.join("wikidata/Q64/en.html").is_file());
+    // Linked from the requested spelling.
+    assert_eq!(
+        dir.join("wikidata/Q64"),
+        fs::read_link(dir.join("en.wikipedia.org/wiki/Berlin,__GERMANY")).unwrap()
+    );
+}
+
+#[test]
+fn post_processor() {
+    use std::sync::Arc;
+
+    let dir = test_dir("post_processor");
+    let audit = dir.join("audit.jsonl");
+    let matcher = Matcher {
+        qids: ["Q64", "Q365"]
+            .into_iter()
+            .map(|q| Qid::from_str(q).unwrap())
+            .collect(),
+        ..Default::default()
+    };
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_audit: Some(audit.clone()),
+        post_processor: Some(Arc::new(|title: &Title, html: String| {
+            if title.to_string() == "de:Köln" {
+                anyhow::bail!("rejected {title}");
+            }
+            Ok(html + "<!-- post-processed -->")
+        })),
+        ..Default::default()
+    };
+    let stats = run_fixture(&["en.ndjson", "de.ndjson"], &matcher, &options).unwrap();
+
+    assert_eq!(3, stats.qid_matches);
+    assert_eq!(2, stats.written);
+    assert_eq!(1, stats.errors);
+    assert_eq!(1, stats.langs["de"].errors);
+    assert_eq!(
+        BTreeMap::from([("de:Köln".to_owned(), "rejected de:Köln".to_owned())]),
+        stats.post_process_errors
+    );
+
+    for lang in ["en", "de"] {
+        let html = fs::read_to_string(dir.join(format!("wikidata/Q64/{lang}.html"))).unwrap();
+        assert!(html.ends_with("<!-- post-processed -->"), "{html}");
+    }
+    assert!(!dir.join("wikidata/Q365/de.html").exists());
+
+    let audit = fs::read_to_string(audit).unwrap();
+    assert!(
+        audit.contains("post-processing: rejected de:Köln"),
+        "{audit}"
+    );
+}
+
+#[test]
+fn page_filter() {
+    use om_wikiparser::wm::Page;
+    use std::sync::Arc;
+
+    let dir = test_dir("page_filter");
+    let matcher = Matcher {
+        qids: ["Q64", "Q1116061", "Q4973715"]
+            .into_iter()
+            .map(|q| Qid::from_str(q).unwrap())
+            .collect(),
+        ..Default::default()
+    };
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_missing: Some(dir.clone()),
+        skip_stubs: true,
+        page_filter: Some(Arc::new(|page: &Page| page.name != "Berlin")),
+        ..Default::default()
+    };
+    let stats = run_fixture(&["en.ndjson"], &matcher, &options).unwrap();
+
+    // Both the stub check and the filter apply.
+    assert_eq!(
+        (3, 1, 1, 1),
+        (
+            stats.qid_matches,
+            stats.stubs,
+            stats.filtered,
+            stats.written
+        )
+    );
+    assert!(!dir.join("wikidata/Q64").exists());
+    assert!(dir.join("wikidata/Q1116061/en.html").is_file());
+    assert_eq!(0, stats.missing_qids);
+}
+
+#[test]
+fn profile() {
+    let matcher = Matcher {
+        qids: ["Q64", "Q365", "Q1116061"]
+            .into_iter()
+            .map(|q| Qid::from_str(q).unwrap())
+            .collect(),
+        ..Default::default()
+    };
+    let run = |profile| {
+        extract::run_with(
+            BufReader::new(dump("en.ndjson").chain(dump("de.ndjson"))),
+            io::sink(),
+            &matcher,
+            &Options {
+                profile,
+                ..Default::default()
+            },
+            RecordingWriter {
+                written: &mut Vec::new(),
+                finished: &mut false,
+            },
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+    };
+
+    assert!(run(None).slowest.is_empty());
+
+    let stats = run(Some(2));
+    assert_eq!(5, stats.written);
+    assert_eq!(2, stats.slowest.len());
+    assert!(
+        stats.slowest[0].seconds >= stats.slowest[1].seconds,
+        "{:?}",
+        stats.slowest
+    );
+    for slow in &stats.slowest {
+        assert!(slow.seconds > 0.0, "{slow:?}");
+        assert!(slow.bytes > 0, "{slow:?}");
+    }
+
+    let stats = run(Some(10));
+    assert_eq!(5, stats.slowest.len());
+    assert!(stats
+        .slowest
+        .windows(2)
+        .all(|w| w[0].seconds >= w[1].seconds));
+    let mut titles: Vec<_> = stats.slowest.iter().map(|s| s.title.as_str()).collect();
+    titles.sort();
+    assert_eq!(
+        vec![
+            "de:Berlin",
+            "de:Geodatenbank",
+            "de:Köln",
+            "en:Berlin",
+            "en:Spatial database"
+        ],
+        titles
+    );
+}
+
+#[test]
+fn option_profiles() {
+    // The options each profile changes from the defaults.
+    let changes = |options: &Options| {
+        let default = serde_json::to_value(Options::default()).unwrap();
+        let options = serde_json::to_value(options).unwrap();
+        options
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(key, value)| default[key.as_str()] != **value)
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec![r#"duplicates="error""#], changes(&Options::strict()));
+    assert_eq!(
+        vec![r#"duplicates="keep-latest""#, "recover_corrupt=true"],
+        changes(&Options::production_extract())
+    );
+}
+
+#[test]
+fn long_paths() {
+    use om_wikiparser::extract::MAX_PATH_BYTES;
+
+    let dir = test_dir("long_paths");
+    // An output directory that leaves room for QID directories, but not for long titles.
+    let mut output_dir = dir.join("output");
+    while output_dir.as_os_str().len() < MAX_PATH_BYTES - 100 {
+        let remaining = MAX_PATH_BYTES - 100 - output_dir.as_os_str().len();
+        output_dir.push("d".repeat(remaining.clamp(1, 200)));
+    }
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let long_title = "Subpage/".repeat(30);
+    let page = |name: &str, qid: Option<&str>, redirect: Option<&str>| {
+        serde_json::json!({
+            "name": name,
+            "date_modified": "2023-07-01T12:00:00Z",
+            "in_language": {"identifier": "en"},
+            "main_entity": qid.map(|qid| serde_json::json!({"identifier": qid})),
+            "article_body": {"html": "<html><body><section><p>Text of the article.</p></section></body></html>"},
+            "redirects": redirect.map(|name| vec![serde_json::json!({"name": name, "url": ""})]).unwrap_or_default(),
+        })
+        .to_string()
+    };
+    let dump = [
+        page(&long_title, None, None),
+        page("Short", Some("Q1"), Some(&format!("{long_title}Redirect"))),
+    ]
+    .join("\n");
+
+    let titles = [
+        long_title.clone(),
+        "Short".into(),
+        format!("{long_title}Redirect"),
+    ]
+    .map(|title| Title::from_title(&title, "en").unwrap());
+    let stats = extract::run(
+        dump.as_bytes(),
+        io::sink(),
+        &Matcher {
+            titles: titles.into_iter().collect(),
+            ..Default::default()
+        },
+        &Options {
+            output_dir: Some(output_dir.clone()),
+            write_audit: Some(dir.join("audit.jsonl")),
+            ..Default::default()
+        },
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+
+    assert_eq!(2, stats.title_matches);
+    assert_eq!(1, stats.written);
+    assert_eq!(1, stats.errors);
+    let audit = fs::read_to_string(dir.join("audit.jsonl")).unwrap();
+    assert!(
+        audit.contains(&format!("path of \\\"en:{long_title}\\\""))
+            && audit.contains("longer than the limit"),
+        "{audit}"
+    );
+    // The page with a QID is written, linked from its short title but not its long redirect.
+    assert!(output_dir.join("wikidata/Q1/en.html").is_file());
+    assert!(output_dir.join("en.wikipedia.org/wiki/Short").is_symlink());
+    assert_eq!(
+        vec![PathBuf::from("Short")],
+        fs::read_dir(output_dir.join("en.wikipedia.org/wiki"))
+            .unwrap()
+            .map(|e| PathBuf::from(e.unwrap().file_name()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn min_substance() {
+    let dir = test_dir("min_substance");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let page = |name: &str, html: &str| {
+        serde_json::json!({
+            "name": name,
+            "date_modified": "2023-07-01T12:00:00Z",
+            "in_language": {"identifier": "en"},
+            "article_body": {"html": html},
+        })
+        .to_string()
+    };
+    let dump = [
+        page(
+            "Thoor Ballylee",
+            include_str!("./data/Q4185820-en/original.html"),
+        ),
+        page(
+            "Foo",
+            r#"<html><body><section><p>Foo is a village.</p><div class="stub"><p>This article is a stub.</p></div></section></body></html>"#,
+        ),
+        page(
+            "Bar",
+            r#"<html><body><section><table class="infobox"><tr><td><p>Population 12</p></td></tr></table></section></body></html>"#,
+        ),
+    ]
+    .join("\n");
+    let titles =
+        ["Thoor Ballylee", "Foo", "Bar"].map(|title| Title::from_title(title, "en").unwrap());
+
+    let stats = extract::run(
+        dump.as_bytes(),
+        io::sink(),
+        &Matcher {
+            titles: titles.into_iter().collect(),
+            ..Default::default()
+        },
+        &Options {
+            output_dir: Some(output_dir.clone()),
+            write_audit: Some(dir.join("audit.jsonl")),
+            min_text_chars: Some(100),
+            min_paragraphs: Some(2),
+            protect_selectors: vec![".infobox".into()],
+            ..Default::default()
+        },
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+
+    assert_eq!(3, stats.title_matches);
+    assert_eq!(2, stats.insubstantial);
+    assert_eq!(1, stats.written);
+    assert!(output_dir
+        .join("en.wikipedia.org/wiki/Thoor_Ballylee/en.html")
+        .is_file());
+    let audit = fs::read_to_string(dir.join("audit.jsonl")).unwrap();
+    assert!(
+        audit.contains("insubstantial: 14 characters, 1 paragraphs\"")
+            && audit.contains("insubstantial: 0 characters, 0 paragraphs, only an infobox"),
+        "{audit}"
+    );
+}
+
+#[test]
+fn print_paths() {
+    use om_wikiparser::extract::PathPrinter;
+
+    let base = Path::new("/does/not/exist");
+    let titles = ["en:Berlin", "en:Spatial database"].map(|tag| Title::from_osm_tag(tag).unwrap());
+    let matcher = Matcher {
+        titles: titles.iter().cloned().collect(),
+        ..Default::default()
+    };
+    let print = |options: &Options| {
+        let mut out = Vec::new();
+        let stats = extract::run_with(
+            BufReader::new(dump("en.ndjson")),
+            io::sink(),
+            &matcher,
+            options,
+            PathPrinter::new(base, options, &mut out),
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert_eq!(2, stats.written);
+        String::from_utf8(out).unwrap()
+    };
+
+    let options = Options {
+        link_direction: LinkDirection::QidToTitle,
+        ..Default::default()
+    };
+    let expected: String = titles
+        .iter()
+        .map(|title| {
+            format!(
+                "{}\n",
+                title.get_dir(base.to_owned()).join("en.html").display()
+            )
+        })
+        .collect();
+    assert_eq!(expected, print(&options));
+    assert!(!base.exists());
+
+    let expected: String = ["Q64", "Q1116061"]
+        .map(|qid| {
+            let qid = Qid::from_str(qid).unwrap();
+            format!(
+                "{}\n",
+                qid.get_dir(base.to_owned()).join("en.html").display()
+            )
+        })
+        .concat();
+    assert_eq!(expected, print(&Options::default()));
+}
+
+#[test]
+fn sidecar_metadata() {
+    use om_wikiparser::wm::output::{sidecar_path, Sidecar, SIDECAR_SCHEMA_VERSION};
+
+    let dir = test_dir("sidecar_metadata");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let dump = serde_json::json!({
+        "name": "Crimean Mountains",
+        "identifier": 1234,
+        "version": {"identifier": 1147024636},
+        "date_modified": "2023-07-01T12:00:00Z",
+        "in_language": {"identifier": "en"},
+        "main_entity": {"identifier": "Q748282"},
+        "article_body": {"html": include_str!("./data/Q748282-en/original.html")},
+    })
+    .to_string();
+
+    let stats = extract::run(
+        dump.as_bytes(),
+        io::sink(),
+        &Matcher {
+            qids: [Qid::from_str("Q748282").unwrap()].into(),
+            ..Default::default()
+        },
+        &Options {
+            output_dir: Some(output_dir.clone()),
+            write_sidecars: true,
+            ..Default::default()
+        },
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+    assert_eq!(1, stats.written);
+
+    let path = sidecar_path(&output_dir.join("wikidata/Q748282/en.html"));
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    for field in [
+        "schema_version",
+        "lang",
+        "title",
+        "qid",
+        "revision",
+        "word_count",
+        "coordinates",
+        "short_description",
+        "categories",
+    ] {
+        assert!(
+            json.get(field).is_some(),
+            "{field} is missing from {json:#}"
+        );
+    }
+    // Left-to-right is omitted.
+    assert_eq!(None, json.get("dir"));
+
+    let sidecar: Sidecar = serde_json::from_value(json).unwrap();
+    assert_eq!(SIDECAR_SCHEMA_VERSION, sidecar.schema_version);
+    assert_eq!(Some(1147024636), sidecar.revision);
+    let coordinates = sidecar.coordinates.unwrap();
+    assert_eq!((44.61306, 34.24333), (coordinates.lat, coordinates.lon));
+    assert_eq!(
+        Some("Mountain range along the southeastern coast of Crimea"),
+        sidecar.short_description.as_deref()
+    );
+    assert!(sidecar
+        .categories
+        .contains(&"Articles with short description".to_owned()));
+    assert!(sidecar.word_count > 100, "{}", sidecar.word_count);
+
+    // Sidecars written before the schema version was added are version 1.
+    let old: Sidecar = serde_json::from_str(
+        r#"{"lang": "en", "title": "Berlin", "matched_by": "qid", "line": 1, "byte": 1, "written_at": 0}"#,
+    )
+    .unwrap();
+    assert_eq!(1, old.schema_version);
+}
+
+#[test]
+fn untitled_qid_match() {
+    // One record with a blank title, and one without any.
+    let dump = [
+        serde_json::json!({
+            "name": " ",
+            "date_modified": "",
+            "in_language": {"identifier": "en"},
+            "main_entity": {"identifier": "Q64"},
+            "article_body": {"html": "<p>Berlin is the capital of Germany.</p>"},
+        }),
+        serde_json::json!({
+            "date_modified": "",
+            "in_language": {"identifier": "de"},
+            "main_entity": {"identifier": "Q64"},
+            "article_body": {"html": "<p>Berlin ist die Hauptstadt Deutschlands.</p>"},
+        }),
+    ]
+    .map(|page| page.to_string() + "\n")
+    .concat();
+    let matcher = Matcher {
+        qids: [Qid::from_str("Q64").unwrap()].into(),
+        ..Default::default()
+    };
+
+    for link_direction in [LinkDirection::TitleToQid, LinkDirection::QidToTitle] {
+        let dir = test_dir(&format!("untitled_qid_match_{link_direction:?}"));
+        let stats = extract::run(
+            dump.as_bytes(),
+            io::sink(),
+            &matcher,
+            &Options {
+                output_dir: Some(dir.clone()),
+                link_direction,
+                ..Default::default()
+            },
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert_eq!((2, 2, 0), (stats.written, stats.untitled, stats.errors));
+        for lang in ["en", "de"] {
+            let file = dir.join("wikidata/Q64").join(lang).with_extension("html");
+            assert!(file.is_file() && !file.is_symlink());
+        }
+        assert!(!dir.join("en.wikipedia.org").exists());
+    }
+}
+
+#[test]
+fn language_map() {
+    let dir = test_dir("language_map");
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        language_map: LanguageMap::from_reader("en\ten-GB\n".as_bytes()).unwrap(),
+        ..Default::default()
+    };
+    let stats = run_fixture(
+        &["en.ndjson"],
+        &Matcher {
+            titles: [Title::from_osm_tag("en:Spatial database").unwrap()].into(),
+            ..Default::default()
+        },
+        &options,
+    )
+    .unwrap();
+    assert_eq!((1, 1), (stats.title_matches, stats.written));
+
+    assert!(dir.join("wikidata/Q1116061/en-GB.html").is_file());
+    assert!(dir
+        .join("en-GB.wikipedia.org/wiki/Spatial_database/en-GB.html")
+        .is_file());
+    assert!(!dir.join("wikidata/Q1116061/en.html").exists());
+    assert!(!dir.join("en.wikipedia.org").exists());
+}
+
+#[test]
+fn encoded_html() {
+    let dir = test_dir("encoded_html");
+    let qids = ["Q64", "Q365", "Q1055"];
+    let stats = run_fixture(
+        &["encoded.ndjson"],
+        &Matcher {
+            qids: qids.iter().map(|qid| Qid::from_str(qid).unwrap()).collect(),
+            ..Default::default()
+        },
+        &Options {
+            output_dir: Some(dir.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!((3, 0), (stats.written, stats.errors));
+
+    // Declared base64, detected base64, and an escaped JSON string.
+    for (qid, text) in qids.iter().zip([
+        "Berlin is the capital and largest city of Germany.",
+        "Köln is the fourth-largest city of Germany.",
+        "Hamburg is the second-largest city of Germany.",
+    ]) {
+        let html = fs::read_to_string(dir.join("wikidata").join(qid).join("en.html")).unwrap();
+        assert!(html.contains(&format!("<p>{text}</p>")), "{qid}: {html}");
+    }
+}
+
+#[test]
+fn link_index() {
+    use om_wikiparser::wm::output::read_link_index;
+
+    let dir = test_dir("link_index");
+    let matcher = Matcher {
+        qids: ["Q64", "Q365"]
+            .map(|qid| Qid::from_str(qid).unwrap())
+            .into(),
+        ..Default::default()
+    };
+    let options = Options {
+        output_dir: Some(dir.clone()),
+        write_link_index: true,
+        ..Default::default()
+    };
+    for name in ["en.ndjson", "de.ndjson"] {
+        run_fixture(&[name], &matcher, &options).unwrap();
+    }
+
+    let index = read_link_index(&dir).unwrap();
+    // The titles of both runs, and their redirects, resolve to the article.
+    for (title, path) in [
+        ("en:Berlin", "wikidata/Q64/en.html"),
+        ("en:Berlin,_Germany", "wikidata/Q64/en.html"),
+        ("de:Berlin", "wikidata/Q64/de.html"),
+        ("de:Köln", "wikidata/Q365/de.html"),
+        ("de:Koeln", "wikidata/Q365/de.html"),
+    ] {
+        assert_eq!(
+            Some(Path::new(path)),
+            index.get(title).map(PathBuf::as_path),
+            "{title}"
+        );
+        assert!(dir.join(path).is_file());
+    }
+    assert_eq!(None, index.get("en:Spatial_database"));
+}
+
+#[test]
+fn publish_staged_output() {
+    use om_wikiparser::extract::{staging_dir, Publish};
+
+    let dir = test_dir("publish_staged_output");
+    let output_dir = dir.join("articles");
+    let matcher = Matcher {
+        qids: [Qid::from_str("Q64").unwrap()].into(),
+        ..Default::default()
+    };
+    let run = |name, publish, duplicates| {
+        run_fixture(
+            &[name],
+            &matcher,
+            &Options {
+                output_dir: Some(output_dir.clone()),
+                publish: Some(publish),
+                duplicates,
+                write_manifest: true,
+                ..Default::default()
+            },
+        )
+    };
+    let en = output_dir.join("wikidata/Q64/en.html");
+
+    run("en.ndjson", Publish::Error, DuplicatePolicy::default()).unwrap();
+    let html = fs::read_to_string(&en).unwrap();
+    assert!(!staging_dir(&output_dir).exists());
+
+    let e = run("de.ndjson", Publish::Error, DuplicatePolicy::default()).unwrap_err();
+    assert!(e.to_string().contains("already exists"), "{e:#}");
+
+    // The run fails after writing the first duplicate to the staging directory.
+    assert!(run("duplicates.ndjson", Publish::Swap, DuplicatePolicy::Error).is_err());
+    assert_eq!(html, fs::read_to_string(&en).unwrap());
+    assert!(staging_dir(&output_dir)
+        .join("wikidata/Q64/en.html")
+        .is_file());
+
+    run("de.ndjson", Publish::Swap, DuplicatePolicy::default()).unwrap();
+    assert!(output_dir.join("wikidata/Q64/de.html").is_file());
+    assert!(!en.exists());
+    assert!(!staging_dir(&output_dir).exists());
+    assert_eq!(
+        vec!["articles"],
+        fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn output_budget() {
+    let qids: HashSet<_> = ["Q64", "Q1116061", "Q999999", "Q4973715"]
+        .into_iter()
+        .map(|q| Qid::from_str(q).unwrap())
+        .collect();
+    let matcher = Matcher {
+        qids,
+        ..Default::default()
+    };
+    let run = |name: &str, max_total_bytes, budget_order| {
+        let dir = test_dir(name);
+        let options = Options {
+            output_dir: Some(dir.clone()),
+            max_total_bytes,
+            budget_order,
+            ..Default::default()
+        };
+        let stats = run_fixture(&["en.ndjson"], &matcher, &options).unwrap();
+        let sizes: BTreeMap<String, u64> = fs::read_dir(dir.join("wikidata"))
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                let size = fs::metadata(e.path().join("en.html")).unwrap().len();
+                (e.file_name().into_string().unwrap(), size)
+            })
+            .collect();
+        (stats, sizes)
+    };
+
+    let (stats, sizes) = run("output_budget_unlimited", None, Default::default());
+    assert_eq!(4, stats.written);
+    assert_eq!(0, stats.over_budget);
+    let size = |qid: &str| sizes[qid] as usize;
+
+    // Extraction stops at the third article in the dump, which is one byte too large.
+    let max = size("Q64") + size("Q1116061") + size("Q999999") - 1;
+    let (stats, written) = run("output_budget_dump", Some(max), BudgetOrder::Dump);
+    assert_eq!(2, stats.written);
+    assert_eq!(2, stats.over_budget);
+    assert_eq!(vec!["Q1116061", "Q64"], written.keys().collect::<Vec<_>>());
+    assert!(written.values().sum::<u64>() as usize <= max);
+
+    // The lowest QIDs are kept, wherever they are in the dump.
+    let max = size("Q64") + size("Q999999");
+    let (stats, written) = run("output_budget_qid", Some(max), BudgetOrder::Qid);
+    assert_eq!(2, stats.written);
+    assert_eq!(2, stats.over_budget);
+    assert_eq!(vec!["Q64", "Q999999"], written.keys().collect::<Vec<_>>());
+    assert_eq!(max, stats.langs["en"].bytes);
+}
@@ -0,0 +1,648 @@
+//! Tests that run the `get-articles` subcommand over small fixture dumps.
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+mod common;
+use common::{dump_path, test_dir};
+
+/// Run `get-articles` with the concatenated fixture `dumps` connected to stdin.
+fn get_articles(dumps: &[&str], args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_om-wikiparser"))
+        .arg("get-articles")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    for dump in dumps {
+        stdin
+            .write_all(&fs::read(dump_path(dump)).unwrap())
+            .unwrap();
+    }
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn missing_report() {
+    let dir = test_dir("missing_report");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nQ12345\n").unwrap();
+    let urls = dir.join("urls.txt");
+    fs::write(
+        &urls,
+        [
+            "https://en.wikipedia.org/wiki/Berlin,_Germany",
+            // Matched through a redirect.
+            "https://en.wikipedia.org/wiki/Geodatabase",
+            "https://en.wikipedia.org/wiki/Nonexistent_Article",
+            "https://de.wikipedia.org/wiki/Berlin",
+        ]
+        .join("\n"),
+    )
+    .unwrap();
+
+    let output = get_articles(
+        &["en.ndjson"],
+        &[
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+            "--write-missing",
+            dir.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+
+    assert_eq!(
+        "Q12345\n",
+        fs::read_to_string(dir.join("missing_qids.txt")).unwrap()
+    );
+    assert_eq!(
+        "de:Berlin\nen:Nonexistent_Article\n",
+        fs::read_to_string(dir.join("missing_titles.txt")).unwrap()
+    );
+
+    // The report can be read back in as input.
+    let output = get_articles(
+        &["en.ndjson"],
+        &[
+            "--wikidata-qids",
+            dir.join("missing_qids.txt").to_str().unwrap(),
+            "--wikipedia-urls",
+            dir.join("missing_titles.txt").to_str().unwrap(),
+            "--write-missing",
+            dir.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        "de:Berlin\nen:Nonexistent_Article\n",
+        fs::read_to_string(dir.join("missing_titles.txt")).unwrap()
+    );
+}
+
+#[test]
+fn per_language_stats() {
+    let dir = test_dir("per_language_stats");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nQ365\n").unwrap();
+    let urls = dir.join("urls.txt");
+    fs::write(
+        &urls,
+        "https://en.wikipedia.org/wiki/Spatial_database\nhttps://de.wikipedia.org/wiki/Koeln\nhttps://de.wikipedia.org/wiki/Hamburg\n",
+    )
+    .unwrap();
+    let stats = dir.join("stats.json");
+
+    let output = get_articles(
+        &["en.ndjson", "de.ndjson"],
+        &[
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+            "--write-stats",
+            stats.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+
+    let stats: serde_json::Value = serde_json::from_slice(&fs::read(stats).unwrap()).unwrap();
+    assert_eq!(7, stats["pages"]);
+    assert_eq!(5, stats["written"]);
+
+    let en = &stats["langs"]["en"];
+    assert_eq!(1, en["titles"]);
+    assert_eq!(2, en["matches"]);
+    assert_eq!(2, en["written"]);
+    assert!(en["bytes"].as_u64().unwrap() > 0);
+
+    let de = &stats["langs"]["de"];
+    assert_eq!(2, de["titles"]);
+    assert_eq!(3, de["matches"]);
+    assert_eq!(3, de["written"]);
+    assert_eq!(0, de["errors"]);
+}
+
+#[test]
+fn matched_by_qid_and_title_written_once() {
+    let dir = test_dir("matched_by_qid_and_title_written_once");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\n").unwrap();
+    let urls = dir.join("urls.txt");
+    fs::write(
+        &urls,
+        "https://en.wikipedia.org/wiki/Berlin\nhttps://en.wikipedia.org/wiki/Berlin,_Germany\n",
+    )
+    .unwrap();
+    let stats = dir.join("stats.json");
+
+    // The same record is read twice.
+    let output = get_articles(
+        &["en.ndjson", "en.ndjson"],
+        &[
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+            "--write-stats",
+            stats.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+
+    let stats: serde_json::Value = serde_json::from_slice(&fs::read(stats).unwrap()).unwrap();
+    assert_eq!(1, stats["qid_matches"]);
+    assert_eq!(1, stats["duplicates"]);
+    assert_eq!(1, stats["written"]);
+    assert!(output_dir.join("wikidata/Q64/en.html").is_file());
+}
+
+#[test]
+fn expand_titles_to_later_dumps() {
+    let dir = test_dir("expand_titles_to_later_dumps");
+    let urls = dir.join("urls.txt");
+    fs::write(&urls, "https://en.wikipedia.org/wiki/Geodatabase\n").unwrap();
+
+    // Geodatenbank only matches by QID of the english article.
+    let output_dir = dir.join("expanded");
+    fs::create_dir(&output_dir).unwrap();
+    let output = get_articles(
+        &["en.ndjson", "de.ndjson"],
+        &[
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    assert!(output_dir.join("wikidata/Q1116061/en.html").is_file());
+    assert!(output_dir.join("wikidata/Q1116061/de.html").is_file());
+
+    let output_dir = dir.join("not_expanded");
+    fs::create_dir(&output_dir).unwrap();
+    let output = get_articles(
+        &["en.ndjson", "de.ndjson"],
+        &[
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+            "--no-expand-titles",
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    assert!(output_dir.join("wikidata/Q1116061/en.html").is_file());
+    assert!(!output_dir.join("wikidata/Q1116061/de.html").exists());
+}
+
+#[test]
+fn write_indexes() {
+    use om_wikiparser::wm::output::{write_indexes, Manifest};
+
+    let dir = test_dir("write_indexes");
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nQ365\nQ1116061\n").unwrap();
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    let output = get_articles(
+        &["en.ndjson", "de.ndjson"],
+        &[
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--write-manifest",
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+
+    let manifest = Manifest::read(&output_dir).unwrap();
+    assert_eq!(5, manifest.entries.len());
+
+    write_indexes(&output_dir, &manifest, 2.try_into().unwrap()).unwrap();
+
+    let root = fs::read_to_string(output_dir.join("index.html")).unwrap();
+    assert!(root.contains(r#"<li><a href="index/de-1.html">de</a> (3)</li>"#));
+    assert!(root.contains(r#"<li><a href="index/en-1.html">en</a> (2)</li>"#));
+
+    let de1 = fs::read_to_string(output_dir.join("index/de-1.html")).unwrap();
+    assert!(de1.contains(r#"<li><a href="../wikidata/Q64/de.html">Berlin</a></li>"#));
+    assert!(de1.contains(r#"<li><a href="../wikidata/Q1116061/de.html">Geodatenbank</a></li>"#));
+    assert!(de1.contains(r#"page 1 of 2 <a href="de-2.html">next</a>"#));
+
+    let de2 = fs::read_to_string(output_dir.join("index/de-2.html")).unwrap();
+    assert!(de2.contains(r#"<li><a href="../wikidata/Q365/de.html">Köln</a></li>"#));
+    assert!(de2.contains(r#"<a href="de-1.html">previous</a> page 2 of 2"#));
+    assert!(!output_dir.join("index/de-3.html").exists());
+
+    let en1 = fs::read_to_string(output_dir.join("index/en-1.html")).unwrap();
+    assert!(en1.contains("page 1 of 1"));
+    assert!(!output_dir.join("index/en-2.html").exists());
+}
+
+#[test]
+fn parse_errors_file() {
+    let dir = test_dir("parse_errors_file");
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nnot a qid\nQ365\n").unwrap();
+    let urls = dir.join("urls.txt");
+    fs::write(
+        &urls,
+        "https://en.wikipedia.org/wiki/Berlin\nhttps://example.com/wiki/Berlin\n",
+    )
+    .unwrap();
+    let tags = dir.join("tags.tsv");
+    fs::write(&tags, "@id\twikidata\twikipedia\n1\tQ64\t\n2\tQ\tBerlin\n").unwrap();
+    let errors = dir.join("errors.log");
+
+    let output = get_articles(
+        &["en.ndjson"],
+        &[
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+            "--osm-tags",
+            tags.to_str().unwrap(),
+            "--parse-errors",
+            errors.to_str().unwrap(),
+            "--passthrough",
+            "match",
+        ],
+    );
+    assert!(output.status.success());
+
+    let errors = fs::read_to_string(errors).unwrap();
+    let errors: Vec<_> = errors.lines().collect();
+    assert_eq!(
+        vec![
+            format!("{}: on line 2: title \"https://example.com/wiki/Berlin\": url base domain is not wikipedia.org or wikivoyage.org", urls.display()),
+            format!("{}: on line 2: QID \"not a qid\": invalid digit found in string", qids.display()),
+            format!("{}: on line 3 (2): QID \"Q\": cannot parse integer from empty string", tags.display()),
+            format!("{}: on line 3 (2): title \"Berlin\": no ':' separating lang and title", tags.display()),
+        ],
+        errors
+    );
+}
+
+#[test]
+fn skip_stubs() {
+    let dir = test_dir("skip_stubs");
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nQ4973715\n").unwrap();
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let stats = dir.join("stats.json");
+
+    let output = get_articles(
+        &["en.ndjson"],
+        &[
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--skip-stubs",
+            "--write-stats",
+            stats.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+
+    let stats: serde_json::Value = serde_json::from_slice(&fs::read(stats).unwrap()).unwrap();
+    assert_eq!(1, stats["stubs"]);
+    assert!(output_dir.join("wikidata/Q64/en.html").is_file());
+    assert!(!output_dir.join("wikidata/Q4973715").exists());
+}
+
+#[test]
+fn write_audit() {
+    let dir = test_dir("write_audit");
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nnot a qid\nQ4973715\n").unwrap();
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let audit = dir.join("audit.jsonl");
+
+    let output = get_articles(
+        &["en.ndjson"],
+        &[
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--skip-stubs",
+            "--write-audit",
+            audit.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+
+    let entries: Vec<serde_json::Value> = fs::read_to_string(audit)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        vec![
+            serde_json::json!({
+                "event": "parse_error",
+                "file": qids,
+                "line": 2,
+                "kind": "QID",
+                "text": "not a qid",
+                "reason": "invalid digit found in string",
+            }),
+            serde_json::json!({
+                "event": "skip",
+                "line": 4,
+                "lang": "en",
+                "title": "Brockenhurst Halt",
+                "qid": "Q4973715",
+                "reason": "stub",
+            }),
+        ],
+        entries
+    );
+}
+
+#[test]
+fn missing_report_groups_raw_titles() {
+    let dir = test_dir("missing_report_groups_raw_titles");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    let urls = dir.join("urls.txt");
+    fs::write(
+        &urls,
+        [
+            "https://en.wikipedia.org/wiki/Nonexistent_Article",
+            "https://en.m.wikipedia.org/wiki/Nonexistent_Article",
+            "https://en.wikipedia.org/wiki/Nonexistent_Article#History",
+            "https://en.wikipedia.org/wiki/Nonexistent%20Article",
+            "en:Nonexistent Article",
+            "https://en.m.wikipedia.org/wiki/Nonexistent_Article",
+            "https://en.wikipedia.org/wiki/Berlin",
+            "https://en.m.wikipedia.org/wiki/Berlin",
+        ]
+        .join("\n"),
+    )
+    .unwrap();
+
+    let output = get_articles(
+        &["en.ndjson"],
+        &[
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+            "--write-missing",
+            dir.to_str().unwrap(),
+            "--capture-raw-titles",
+            output_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+
+    assert_eq!(
+        "en:Nonexistent_Article\n",
+        fs::read_to_string(dir.join("missing_titles.txt")).unwrap()
+    );
+    let report = fs::read_to_string(dir.join("missing_titles_raw.jsonl")).unwrap();
+    let entries: Vec<serde_json::Value> = report
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        vec![serde_json::json!({
+            "title": "en:Nonexistent_Article",
+            "count": 6,
+            "raw": {
+                "en:Nonexistent Article": 1,
+                "https://en.m.wikipedia.org/wiki/Nonexistent_Article": 2,
+                "https://en.wikipedia.org/wiki/Nonexistent%20Article": 1,
+                "https://en.wikipedia.org/wiki/Nonexistent_Article": 1,
+                "https://en.wikipedia.org/wiki/Nonexistent_Article#History": 1,
+            },
+        })],
+        entries
+    );
+}
+
+#[test]
+fn validate_only() {
+    let dir = test_dir("validate_only");
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nnot a qid\nQ365\n").unwrap();
+    let urls = dir.join("urls.txt");
+    fs::write(&urls, "en:Berlin\nen:Category:Cities\n").unwrap();
+
+    let validate = |extra: &[&str]| {
+        let mut args = vec![
+            "--validate-only",
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--wikipedia-urls",
+            urls.to_str().unwrap(),
+        ];
+        args.extend(extra);
+        get_articles(&[], &args)
+    };
+
+    let output = validate(&[]);
+    assert!(!output.status.success());
+    assert_eq!(
+        format!(
+            "{}: 1 valid, 0 invalid, 1 namespace titles\n{}: 2 valid, 1 invalid, 0 namespace titles\n",
+            urls.display(),
+            qids.display()
+        ),
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let output = validate(&["--allow-invalid"]);
+    assert!(output.status.success());
+
+    fs::write(&qids, "Q64\nQ365\n").unwrap();
+    let output = validate(&[]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn max_missing() {
+    let dir = test_dir("max_missing");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let stats = dir.join("stats.json");
+
+    // Only one of the four is in the dump.
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nQ12345\nQ23456\nQ34567\n").unwrap();
+    let run = |max| {
+        get_articles(
+            &["en.ndjson"],
+            &[
+                "--wikidata-qids",
+                qids.to_str().unwrap(),
+                "--max-missing",
+                max,
+                "--write-stats",
+                stats.to_str().unwrap(),
+                output_dir.to_str().unwrap(),
+            ],
+        )
+    };
+
+    assert!(run("0.75").status.success());
+
+    let output = run("0.5");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("3 of 4 requested articles (75.0%)"),
+        "{stderr}"
+    );
+    assert!(stats.is_file());
+
+    assert!(!run("2").status.success());
+}
+
+#[test]
+fn bare_titles_in_dump_lang() {
+    let dir = test_dir("bare_titles_in_dump_lang");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    let titles = dir.join("titles.txt");
+    fs::write(&titles, "Spatial database\nBerlin, Germany\nde:Köln\n").unwrap();
+    let run = |args: &[&str]| {
+        let mut all = vec!["--wikipedia-urls", titles.to_str().unwrap()];
+        all.extend(args);
+        all.extend(["--write-missing", dir.to_str().unwrap()]);
+        all.push(output_dir.to_str().unwrap());
+        get_articles(&["en.ndjson"], &all)
+    };
+
+    // Without a language, the bare names can't be parsed.
+    assert!(run(&[]).status.success());
+    assert_eq!(
+        "de:Köln\n",
+        fs::read_to_string(dir.join("missing_titles.txt")).unwrap()
+    );
+    assert!(!output_dir.join("wikidata").exists());
+
+    assert!(run(&["--dump-lang", "en"]).status.success());
+    for qid in ["Q64", "Q1116061"] {
+        assert!(output_dir
+            .join("wikidata")
+            .join(qid)
+            .join("en.html")
+            .is_file());
+    }
+    assert_eq!(
+        "de:Köln\n",
+        fs::read_to_string(dir.join("missing_titles.txt")).unwrap()
+    );
+}
+
+#[test]
+fn report_format() {
+    let dir = test_dir("report_format");
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nQ1116061\n").unwrap();
+
+    let run = |format| {
+        get_articles(
+            &["en.ndjson"],
+            &[
+                "--wikidata-qids",
+                qids.to_str().unwrap(),
+                "--profile",
+                "1",
+                "--report-format",
+                format,
+                output_dir.to_str().unwrap(),
+            ],
+        )
+    };
+
+    // Each report is on its own line between the logs.
+    let output = run("json");
+    assert!(output.status.success());
+    let reports: Vec<serde_json::Value> = String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        vec!["langs", "slowest"],
+        reports
+            .iter()
+            .map(|r| r["report"].as_str().unwrap())
+            .collect::<Vec<_>>()
+    );
+    let langs = reports[0]["rows"].as_array().unwrap();
+    assert_eq!(1, langs.len());
+    assert_eq!("en", langs[0]["lang"]);
+    assert_eq!(2, langs[0]["matches"]);
+    assert_eq!(2, langs[0]["written"]);
+    assert!(langs[0]["bytes"].as_u64().unwrap() > 0);
+    let slowest = reports[1]["rows"].as_array().unwrap();
+    assert_eq!(1, slowest.len());
+    for key in ["title", "seconds", "bytes", "line"] {
+        assert!(slowest[0].get(key).is_some(), "{key} {slowest:?}");
+    }
+
+    let output = run("csv");
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("lang,titles,matches,written,bytes,errors\nen,0,2,2,"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("\n\ntitle,seconds,bytes,line\n"),
+        "{stderr}"
+    );
+
+    let output = get_articles(
+        &[],
+        &[
+            "--validate-only",
+            "--wikidata-qids",
+            qids.to_str().unwrap(),
+            "--report-format",
+            "json",
+        ],
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        serde_json::json!({
+            "report": "files",
+            "rows": [{"path": qids, "valid": 2, "invalid": 0, "namespaces": 0}],
+        }),
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    );
+}
@@ -3,7 +3,9 @@
 //! To update the expected output, run the test again with the env variable
 //! `UPDATE_EXPECT=1` set.
 //! See https://docs.rs/expect-test/ for more information.
-use om_wikiparser::html::{detect_lang, pretty_print, process, process_str, HtmlError};
+use om_wikiparser::html::{
+    detect_lang, pretty_print, process, process_str, substance, truncate_html, HtmlError,
+};
 
 use expect_test::{expect_file, ExpectFile};
 use scraper::Html;
@@ -59,3 +61,69 @@ fn is_empty_bahnstrecke_bassum_herford() {
     let article = include_str!("./data/redirects/Bahnstrecke%20Bassum%FF%FF%FFHerford.html");
     assert_eq!(Err(HtmlError::NoText), process_str(article, "en"));
 }
+
+/// Check that `html` parses and serializes back to itself.
+fn assert_well_formed(html: &str) {
+    let fragment = Html::parse_fragment(html);
+    assert_eq!(html, fragment.root_element().inner_html());
+}
+
+#[test]
+fn truncate_within_budget() {
+    let article = process_str(include_str!("./data/Q748282-en/original.html"), "en").unwrap();
+    assert_well_formed(&article);
+    assert_eq!(article, truncate_html(&article, article.len(), "en"));
+
+    for max_bytes in (0..article.len()).step_by(7) {
+        let truncated = truncate_html(&article, max_bytes, "en");
+        assert!(
+            truncated.len() <= max_bytes,
+            "{} > {max_bytes}: {truncated}",
+            truncated.len()
+        );
+        assert_well_formed(&truncated);
+        if !truncated.is_empty() {
+            assert!(truncated.ends_with("<p><i>This article has been shortened.</i></p>"));
+        }
+    }
+}
+
+#[test]
+fn truncate_large_first_block() {
+    let html = "<p>First sentence. Second <b>sentence</b>. Third sentence.</p><p>More text that does not fit.</p>";
+    assert_eq!(
+        "<p>First sentence. Second sentence.</p><p><i>Dieser Artikel wurde gekürzt.</i></p>",
+        truncate_html(html, 90, "de")
+    );
+}
+
+#[test]
+fn truncate_smaller_than_header() {
+    let html =
+        "<h2>A long section header</h2><p>Some text that is long enough to need truncating.</p>";
+    assert_eq!(
+        "<p><i>This article has been shortened.</i></p>",
+        truncate_html(html, 60, "en")
+    );
+    assert_eq!("", truncate_html(html, 20, "en"));
+    // Languages without a marker use English.
+    assert_eq!(
+        "<p><i>This article has been shortened.</i></p>",
+        truncate_html(html, 60, "xx")
+    );
+}
+
+#[test]
+fn substance_thoor_ballylee() {
+    let article = include_str!("./data/Q4185820-en/original.html");
+    let html = process(Html::parse_document(article), "en").unwrap();
+    let simplified = substance(&html);
+    assert!(simplified.text_chars > 1000, "{simplified:?}");
+    assert!(simplified.paragraphs > 10, "{simplified:?}");
+    assert!(!simplified.infobox);
+
+    // Before simplification, the infobox is still there but not counted.
+    let original = substance(&Html::parse_document(article));
+    assert!(original.infobox && !original.only_infobox());
+    assert!(original.paragraphs >= simplified.paragraphs);
+}
@@ -0,0 +1,83 @@
+//! Tests of reading QID and title list files with the line endings of different platforms.
+use std::str::FromStr;
+
+use om_wikiparser::{
+    parse_wikidata_file_with, parse_wikipedia_file_raw,
+    wm::{Qid, Title},
+    ListFileOptions, ParseLineError,
+};
+
+fn qids(input: &str) -> (Vec<Qid>, Vec<ParseLineError>) {
+    let mut qids = Vec::new();
+    let mut errors = Vec::new();
+    parse_wikidata_file_with(
+        input.as_bytes(),
+        &mut qids,
+        &mut errors,
+        &ListFileOptions::default(),
+    )
+    .unwrap();
+    (qids, errors)
+}
+
+fn titles(input: &str) -> (Vec<(Title, String)>, Vec<ParseLineError>) {
+    let mut titles = Vec::new();
+    let mut errors = Vec::new();
+    parse_wikipedia_file_raw(
+        input.as_bytes(),
+        &mut titles,
+        &mut errors,
+        &ListFileOptions::default(),
+    )
+    .unwrap();
+    (titles, errors)
+}
+
+#[test]
+fn qid_line_endings() {
+    let expected: Vec<Qid> = ["Q1", "Q64", "Q365"]
+        .into_iter()
+        .map(|q| Qid::from_str(q).unwrap())
+        .collect();
+    for input in [
+        "Q1\nQ64\nQ365\n",
+        "Q1\r\nQ64\r\nQ365\r\n",
+        "Q1\rQ64\rQ365\r",
+        "Q1\rQ64\r\nQ365",
+    ] {
+        let (qids, errors) = qids(input);
+        assert_eq!(expected, qids, "{input:?}");
+        assert!(errors.is_empty(), "{input:?}: {errors:?}");
+    }
+
+    let (_, errors) = qids("Q1\rnot a qid\r\nQ2\r");
+    assert_eq!(1, errors.len());
+    assert_eq!(2, errors[0].line);
+    assert_eq!("not a qid", errors[0].text);
+}
+
+#[test]
+fn title_line_endings() {
+    for input in [
+        "en:Berlin\r\nde:Köln\r\n",
+        "en:Berlin\rde:Köln\r",
+        "en:Berlin \r\n\rde:Köln",
+    ] {
+        let (titles, _) = titles(input);
+        assert_eq!(
+            vec![
+                (
+                    Title::from_osm_tag("en:Berlin").unwrap(),
+                    "en:Berlin".into()
+                ),
+                (Title::from_osm_tag("de:Köln").unwrap(), "de:Köln".into()),
+            ],
+            titles,
+            "{input:?}"
+        );
+        for (title, raw) in &titles {
+            assert!(!title.to_string().contains('\r'), "{title:?}");
+            assert!(!raw.contains('\r'), "{raw:?}");
+        }
+    }
+}
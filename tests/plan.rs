@@ -0,0 +1,222 @@
+//! Tests of planning and executing a run over the fixture dumps.
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::AtomicBool,
+};
+
+use om_wikiparser::{
+    extract::Options,
+    wm::{
+        output::{ManifestEntry, MANIFEST_FILE},
+        plan::{self, RunConfig, RunPlan},
+        Qid, Title,
+    },
+};
+
+mod common;
+use common::{dump_path, test_dir};
+
+/// Copy the fixture dumps to `dir`, so they can be modified.
+fn copy_dumps(dir: &Path) -> Vec<PathBuf> {
+    ["en.ndjson", "de.ndjson"]
+        .into_iter()
+        .map(|name| {
+            let path = dir.join(name);
+            fs::copy(dump_path(name), &path).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn config(dir: &Path) -> RunConfig {
+    let qids = dir.join("qids.txt");
+    fs::write(&qids, "Q64\nQ365\nnot a qid\n").unwrap();
+    let titles = dir.join("titles.txt");
+    fs::write(&titles, "en:Spatial database\n").unwrap();
+
+    let output_dir = dir.join("output");
+    fs::create_dir(&output_dir).unwrap();
+
+    RunConfig {
+        dumps: copy_dumps(dir),
+        wikidata_qids: vec![qids],
+        wikipedia_urls: vec![titles],
+        osm_tags: Vec::new(),
+        options: Options {
+            output_dir: Some(output_dir),
+            write_manifest: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn entry(path: &str, lang: &str, title: &str, qid: &str) -> ManifestEntry {
+    ManifestEntry {
+        path: path.into(),
+        lang: lang.to_owned(),
+        title: title.to_owned(),
+        qid: Some(Qid::from_str(qid).unwrap()),
+        page_id: None,
+        requesters: Vec::new(),
+        project: Default::default(),
+        dir: Default::default(),
+        descriptions: Default::default(),
+    }
+}
+
+#[test]
+fn build_and_execute() {
+    let dir = test_dir("build_and_execute");
+    let config = config(&dir);
+    let output_dir = config.options.output_dir.clone().unwrap();
+
+    // A previous run that extracted an article that is no longer requested.
+    let kept = entry("wikidata/Q64/en.html", "en", "Berlin", "Q64");
+    let pruned = entry("wikidata/Q42/en.html", "en", "Douglas Adams", "Q42");
+    let mut manifest = fs::File::create(output_dir.join(MANIFEST_FILE)).unwrap();
+    for e in [&kept, &pruned] {
+        manifest.write_all(e.to_line().as_bytes()).unwrap();
+    }
+    drop(manifest);
+
+    let plan = plan::build(&config).unwrap();
+    assert_eq!(2, plan.dumps.len());
+    assert_eq!(
+        fs::metadata(&config.dumps[0]).unwrap().len(),
+        plan.dumps[0].bytes
+    );
+    assert_eq!(
+        ["Q64", "Q365"].map(|q| Qid::from_str(q).unwrap()).to_vec(),
+        plan.qids.iter().copied().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![Title::from_osm_tag("en:Spatial database").unwrap()],
+        plan.titles.iter().cloned().collect::<Vec<_>>()
+    );
+    assert_eq!(Some(&1), plan.titles_by_lang.get("en"));
+    assert_eq!(
+        vec![
+            (["en"].map(String::from).into(), 1),
+            (["de"].map(String::from).into(), 0)
+        ],
+        plan.dumps
+            .iter()
+            .map(|d| (d.langs.clone(), d.titles))
+            .collect::<Vec<(BTreeSet<String>, usize)>>()
+    );
+    assert_eq!(1, plan.parse_errors);
+    assert_eq!(Some(1), plan.estimated_articles);
+    assert_eq!(vec![pruned], plan.pruned);
+
+    // The reviewed JSON is what runs.
+    let json = serde_json::to_string_pretty(&plan).unwrap();
+    let plan: RunPlan = serde_json::from_str(&json).unwrap();
+    assert_eq!(json, serde_json::to_string_pretty(&plan).unwrap());
+
+    let stats = plan::execute(&plan, io::sink(), &AtomicBool::new(false)).unwrap();
+    assert_eq!(7, stats.pages);
+    assert_eq!(3, stats.qid_matches);
+    assert_eq!(1, stats.title_matches);
+    assert_eq!(1, stats.expanded_matches);
+    assert_eq!(5, stats.written);
+    assert!(output_dir.join("wikidata/Q365/de.html").is_file());
+    assert!(output_dir.join("wikidata/Q1116061/de.html").is_file());
+}
+
+#[test]
+fn dump_without_trailing_newline() {
+    let dir = test_dir("dump_without_trailing_newline");
+    let config = config(&dir);
+    let en = fs::read_to_string(&config.dumps[0]).unwrap();
+    fs::write(&config.dumps[0], en.trim_end()).unwrap();
+
+    let plan = plan::build(&config).unwrap();
+    let stats = plan::execute(&plan, io::sink(), &AtomicBool::new(false)).unwrap();
+    assert_eq!(7, stats.pages);
+    assert_eq!(5, stats.written);
+}
+
+#[test]
+fn changed_dump_is_rejected() {
+    let dir = test_dir("changed_dump_is_rejected");
+    let config = config(&dir);
+    let plan = plan::build(&config).unwrap();
+
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&config.dumps[1])
+        .unwrap()
+        .write_all(b"\n")
+        .unwrap();
+
+    let e = plan::execute(&plan, io::sink(), &AtomicBool::new(false)).unwrap_err();
+    assert!(e.to_string().contains("when the plan was built"), "{e:#}");
+    assert!(!config
+        .options
+        .output_dir
+        .unwrap()
+        .join(MANIFEST_FILE)
+        .exists());
+}
+
+#[test]
+fn expected_lang_mismatch() {
+    let dir = test_dir("expected_lang_mismatch");
+    let mut config = config(&dir);
+    config
+        .expected_langs
+        .insert(config.dumps[1].clone(), "de".into());
+    assert!(plan::build(&config).is_ok());
+
+    config
+        .expected_langs
+        .insert(config.dumps[0].clone(), "de".into());
+    let e = plan::build(&config).unwrap_err();
+    assert!(e.to_string().contains("expected to be in \"de\""), "{e:#}");
+}
+
+#[test]
+fn file_name_lang_mismatch() {
+    let dir = test_dir("file_name_lang_mismatch");
+    let mut config = config(&dir);
+    let renamed = dir.join("enwiki-NS0-20230701-ENTERPRISE-HTML.json");
+    fs::rename(&config.dumps[1], &renamed).unwrap();
+    config.dumps[1] = renamed;
+
+    let e = plan::build(&config).unwrap_err();
+    assert!(e.to_string().contains("named like a \"en\" dump"), "{e:#}");
+}
+
+#[test]
+fn mixed_langs() {
+    let dir = test_dir("mixed_langs");
+    let mut config = config(&dir);
+    let mixed = dir.join("mixed.ndjson");
+    let mut contents = fs::read(&config.dumps[0]).unwrap();
+    contents.extend(fs::read(&config.dumps[1]).unwrap());
+    fs::write(&mixed, contents).unwrap();
+    config.dumps = vec![mixed];
+
+    let e = plan::build(&config).unwrap_err();
+    assert!(e.to_string().contains("more than one language"), "{e:#}");
+
+    // Pages are then matched against the titles of their own language.
+    config.allow_mixed_langs = true;
+    let plan = plan::build(&config).unwrap();
+    assert_eq!(
+        ["de", "en"]
+            .map(String::from)
+            .into_iter()
+            .collect::<BTreeSet<_>>(),
+        plan.dumps[0].langs
+    );
+    assert_eq!(1, plan.dumps[0].titles);
+    let stats = plan::execute(&plan, io::sink(), &AtomicBool::new(false)).unwrap();
+    assert_eq!(1, stats.title_matches);
+    assert_eq!(5, stats.written);
+}
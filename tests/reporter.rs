@@ -0,0 +1,163 @@
+//! Tests of [LogReporter] and [LogThrottle] with a logger that captures all messages.
+//!
+//! The logger is global, so all tests that log are run from a single test.
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use om_wikiparser::{parse_wikidata_file_with, ListFileOptions, LogReporter, LogThrottle};
+
+static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+struct CapturingLogger(Mutex<Vec<(Level, String)>>);
+
+impl CapturingLogger {
+    fn take(&self) -> Vec<(Level, String)> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+fn parse(input: &str, reporter: &mut LogReporter) {
+    let mut qids = Vec::new();
+    parse_wikidata_file_with(
+        input.as_bytes(),
+        &mut qids,
+        reporter,
+        &ListFileOptions::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn reporter() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Warn);
+
+    suppression();
+    below_threshold();
+    no_periodic_summary();
+    throttle();
+}
+
+fn suppression() {
+    let mut input = String::from("Q1\n");
+    for i in 0..25 {
+        input.push_str(&format!("Z{i}\n"));
+    }
+    input.push_str("Q99999999999\n");
+
+    let mut reporter = LogReporter::new(3, 10);
+    parse(&input, &mut reporter);
+    assert_eq!(26, reporter.total());
+    assert_eq!(
+        vec![25, 1],
+        reporter.counts().values().copied().collect::<Vec<_>>()
+    );
+    drop(reporter);
+
+    let messages = LOGGER.take();
+    assert!(messages.iter().all(|(level, _)| *level == Level::Warn));
+    let messages: Vec<_> = messages.into_iter().map(|(_, m)| m).collect();
+
+    let individual: Vec<_> = messages
+        .iter()
+        .filter(|m| m.starts_with("Parse error"))
+        .collect();
+    // The first 3 of the 25 `Z` lines, and the single overflowing QID.
+    assert_eq!(4, individual.len(), "{messages:#?}");
+    assert!(individual[..3]
+        .iter()
+        .zip(["Z0", "Z1", "Z2"])
+        .all(|(m, text)| m.contains(&format!("{text:?}"))));
+    assert!(individual[3].contains("\"Q99999999999\""));
+
+    let suppressed: Vec<_> = messages
+        .iter()
+        .filter(|m| m.starts_with("Suppressed"))
+        .collect();
+    assert_eq!(2, suppressed.len(), "{messages:#?}");
+    assert!(suppressed[0].starts_with("Suppressed 10 similar"));
+    assert!(suppressed[1].starts_with("Suppressed 20 similar"));
+
+    let totals: Vec<_> = messages.iter().filter(|m| m.contains("in total")).collect();
+    assert_eq!(1, totals.len(), "{messages:#?}");
+    assert!(totals[0].starts_with("25 parse errors in total (22 suppressed)"));
+}
+
+fn below_threshold() {
+    let mut reporter = LogReporter::new(3, 10);
+    parse("Z1\nQ1\nZ2\n", &mut reporter);
+    assert_eq!(2, reporter.total());
+    drop(reporter);
+
+    let messages = LOGGER.take();
+    assert_eq!(2, messages.len(), "{messages:#?}");
+    assert!(messages.iter().all(|(_, m)| m.starts_with("Parse error")));
+}
+
+fn no_periodic_summary() {
+    let mut reporter = LogReporter::new(0, 0);
+    let input = "Z\n".repeat(1000);
+    parse(&input, &mut reporter);
+    assert_eq!(1000, reporter.total());
+    drop(reporter);
+
+    let messages = LOGGER.take();
+    assert_eq!(1, messages.len(), "{messages:#?}");
+    assert!(messages[0]
+        .1
+        .starts_with("1000 parse errors in total (1000 suppressed)"));
+}
+
+fn throttle() {
+    let throttle = LogThrottle::new(5, 1000);
+    // Errors of every kind and from every thread count towards the same limit.
+    std::thread::scope(|s| {
+        for prefix in ["Z", "Q99999999999"] {
+            let mut throttle = &throttle;
+            s.spawn(move || {
+                let input: String = (0..1000).map(|i| format!("{prefix}{i}\n")).collect();
+                parse_wikidata_file_with(
+                    input.as_bytes(),
+                    &mut Vec::new(),
+                    &mut throttle,
+                    &ListFileOptions::default(),
+                )
+                .unwrap();
+            });
+        }
+    });
+    assert_eq!(2000, throttle.total());
+    drop(throttle);
+
+    let messages: Vec<_> = LOGGER.take().into_iter().map(|(_, m)| m).collect();
+    assert_eq!(
+        5,
+        messages
+            .iter()
+            .filter(|m| m.starts_with("Parse error"))
+            .count(),
+        "{messages:#?}"
+    );
+    assert_eq!(
+        vec![
+            "Suppressed 1000 further parse errors",
+            "2000 parse errors in total (1995 suppressed)"
+        ],
+        messages[5..]
+    );
+}